@@ -0,0 +1,204 @@
+//! The settings kept in a TOML file of their own, where they can be read and
+//! edited by hand. The file wins over the settings saved with the model: it's
+//! read on startup, read again whenever it's edited, and rewritten whenever
+//! the settings change in afx.
+
+use crate::model::Settings;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const HEADER: &str = "\
+# The settings of afx, read again whenever this file is saved.
+# Settings missing from here are taken from the ones saved with the library.
+";
+
+/// The settings file under the configuration directory of the user.
+pub fn default_config_path() -> Option<PathBuf> {
+    let dirs = directories_next::ProjectDirs::from("", "", "afx")?;
+    Some(dirs.config_dir().join("settings.toml"))
+}
+
+/// Read the settings from a file. Whatever the file leaves out is taken from
+/// the given settings, so that older files stay usable.
+pub fn load(path: &Path, defaults: &Settings) -> Result<Settings> {
+    let text = std::fs::read_to_string(path)?;
+    let mut merged = toml::Table::try_from(defaults)?;
+    merge(&mut merged, toml::from_str(&text)?);
+    Ok(merged.try_into()?)
+}
+
+/// Override the entries of a table with those of another, down into the
+/// tables they both have, so that a table in the file only needs the keys it
+/// changes.
+fn merge(table: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(overrides)) => {
+                merge(table, overrides)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Write the settings to a file, replacing it only once written in full.
+pub fn save(path: &Path, settings: &Settings) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let text = format!("{}\n{}", HEADER, toml::to_string_pretty(settings)?);
+    let temp = path.with_extension("toml.tmp");
+    std::fs::write(&temp, text)?;
+    std::fs::rename(temp, path)?;
+    Ok(())
+}
+
+/// Keeps the settings file and the settings of the model in step.
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    /// When the file was last read or written, to notice outside edits.
+    modified: Option<SystemTime>,
+    /// The settings as last read or written.
+    settings: Option<Settings>,
+}
+
+impl ConfigFile {
+    pub fn new(path: PathBuf) -> ConfigFile {
+        ConfigFile {
+            path,
+            modified: None,
+            settings: None,
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    /// Take on the settings from the file if it was edited since it was last
+    /// looked at, or write the settings out if they changed instead. Returns
+    /// whether the settings were changed. A file that doesn't parse is left
+    /// alone until it's edited again or the settings change.
+    pub fn sync(&mut self, settings: &mut Settings) -> Result<bool> {
+        let modified = self.modified();
+        if modified.is_some() && modified != self.modified {
+            self.modified = modified;
+            self.settings = Some(settings.clone());
+            let loaded = load(&self.path, settings)?;
+            let changed = loaded != *settings;
+            *settings = loaded;
+            self.settings = Some(settings.clone());
+            return Ok(changed);
+        }
+
+        if self.settings.as_ref() != Some(settings) {
+            save(&self.path, settings)?;
+            self.modified = self.modified();
+            self.settings = Some(settings.clone());
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::midi::{MidiBinding, MidiTarget, MidiTrigger};
+    use crate::model::{ColourRule, RuleField, ORANGE};
+    use crate::speech::SpeechEngine;
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("afx").join("settings.toml");
+        let mut settings = Settings {
+            touch_mode: true,
+            ui_scale: Some(1.5),
+            speech_engine: SpeechEngine::Command("say {output}".to_string()),
+            ..Settings::default()
+        };
+        settings.colour_rules.push(ColourRule {
+            field: RuleField::Folder,
+            pattern: "music".to_string(),
+            colour: ORANGE,
+        });
+        settings.midi.bindings.push(MidiBinding {
+            device: None,
+            trigger: MidiTrigger::Control {
+                channel: 1,
+                controller: 7,
+            },
+            target: MidiTarget::ItemVolume(3),
+        });
+
+        save(&path, &settings)?;
+        assert_eq!(load(&path, &Settings::default())?, settings);
+
+        // left out settings are kept
+        std::fs::write(&path, "touch_mode = false\n")?;
+        let loaded = load(&path, &settings)?;
+        assert_eq!(
+            loaded,
+            Settings {
+                touch_mode: false,
+                ..settings.clone()
+            }
+        );
+
+        // as are the ones left out of a table
+        std::fs::write(&path, "[sync]\nport = 9000\n")?;
+        let mut sync = settings.sync.clone();
+        sync.primary = "stage.local".to_string();
+        let loaded = load(&path, &Settings { sync, ..settings })?;
+        assert_eq!(loaded.sync.port, 9000);
+        assert_eq!(loaded.sync.primary, "stage.local");
+        Ok(())
+    }
+
+    #[test]
+    fn syncing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut file = ConfigFile::new(dir.path().join("settings.toml"));
+        let mut settings = Settings::default();
+
+        // written out when missing, and once changed
+        assert!(!file.sync(&mut settings)?);
+        assert_eq!(load(&file.path, &Settings::default())?, settings);
+        settings.split_channels = true;
+        assert!(!file.sync(&mut settings)?);
+        assert!(load(&file.path, &Settings::default())?.split_channels);
+
+        // edited by hand, with a later modification time than the save
+        let text = std::fs::read_to_string(&file.path)?;
+        std::fs::write(
+            &file.path,
+            text.replace("split_channels = true", "split_channels = false"),
+        )?;
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&file.path)?
+            .set_modified(later)?;
+        assert!(file.sync(&mut settings)?);
+        assert!(!settings.split_channels);
+        assert!(!file.sync(&mut settings)?);
+
+        // broken edits are reported once and don't touch the settings
+        std::fs::write(&file.path, "split_channels = ")?;
+        std::fs::File::options()
+            .write(true)
+            .open(&file.path)?
+            .set_modified(later + std::time::Duration::from_secs(5))?;
+        assert!(file.sync(&mut settings).is_err());
+        assert!(!file.sync(&mut settings)?);
+        assert!(!settings.split_channels);
+        assert_eq!(std::fs::read_to_string(&file.path)?, "split_channels = ");
+        Ok(())
+    }
+}
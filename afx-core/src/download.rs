@@ -0,0 +1,162 @@
+//! Fetching the audio of online videos with yt-dlp, so that it can be
+//! imported like any other file. The downloads are kept with the rest of the
+//! data of afx, since the items refer to them once imported.
+
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The program doing the downloading, looked up on the path.
+const PROGRAM: &str = "yt-dlp";
+/// How many lines of the diagnostics of a failed download are kept.
+const ERROR_LINES: usize = 3;
+
+/// Download the audio of the video at the given address, reporting how far
+/// along the download is, from 0 to 1, and return the file holding it.
+pub fn download(url: &str, progress: impl FnMut(f32)) -> Result<PathBuf> {
+    let dirs =
+        directories_next::ProjectDirs::from("", "", "afx").context("there's no data directory")?;
+    download_in(PROGRAM, url, &dirs.data_dir().join("downloads"), progress)
+}
+
+fn download_in(
+    program: &str,
+    url: &str,
+    dir: &Path,
+    mut progress: impl FnMut(f32),
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    // FLAC, since the decoders don't cover the codecs of most video sites
+    let mut child = Command::new(program)
+        .args([
+            "--no-playlist",
+            "--extract-audio",
+            "--audio-format",
+            "flac",
+            "--quiet",
+            "--progress",
+            "--newline",
+            "--print",
+            "after_move:filepath",
+            "--output",
+        ])
+        .arg(dir.join("%(title)s [%(id)s].%(ext)s"))
+        .arg("--")
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => {
+                anyhow::anyhow!("{} isn't installed, or isn't on the path", program)
+            }
+            _ => anyhow::Error::new(err).context(format!("couldn't start {}", program)),
+        })?;
+
+    // yt-dlp reports progress alongside its diagnostics once it's quiet,
+    // leaving the standard output for the path
+    let mut stdout = child.stdout.take().unwrap();
+    let output = std::thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+    let mut diagnostics = vec![];
+    for line in BufReader::new(child.stderr.take().unwrap()).lines() {
+        let line = line?;
+        match parse_progress(&line) {
+            Some(fraction) => progress(fraction),
+            None if !line.trim().is_empty() => diagnostics.push(line),
+            None => (),
+        }
+    }
+    let output = output.join().unwrap()?;
+    if !child.wait()?.success() {
+        let start = diagnostics.len().saturating_sub(ERROR_LINES);
+        bail!(
+            "the download failed: {}",
+            diagnostics[start..].join("\n").trim()
+        );
+    }
+
+    match output
+        .lines()
+        .map(PathBuf::from)
+        .rfind(|path| path.is_file())
+    {
+        Some(path) => Ok(path),
+        None => bail!("{} didn't say where it saved the audio", program),
+    }
+}
+
+/// The fraction done in a progress line such as
+/// `[download]  42.3% of 3.52MiB at 1.2MiB/s ETA 00:02`.
+fn parse_progress(line: &str) -> Option<f32> {
+    let rest = line.trim().strip_prefix("[download]")?;
+    let (percent, _) = rest.trim_start().split_once('%')?;
+    let percent: f32 = percent.parse().ok()?;
+    Some((percent / 100.0).clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn progress_lines() {
+        assert_eq!(
+            parse_progress("[download]  42.5% of 3.52MiB at 1.2MiB/s ETA 00:02"),
+            Some(0.425)
+        );
+        assert_eq!(parse_progress("[download] 100% of 3.52MiB"), Some(1.0));
+        assert_eq!(parse_progress("[download] Destination: a.webm"), None);
+        assert_eq!(parse_progress("[ExtractAudio] 50% of nothing"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn downloads() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let saved = dir.path().join("rain [abc].flac");
+        std::fs::write(&saved, [])?;
+        let program = dir.path().join("yt-dlp");
+        std::fs::write(
+            &program,
+            format!(
+                "#!/bin/sh\n\
+                 echo '[download]  50.0% of 1MiB' >&2\n\
+                 echo '[download] 100% of 1MiB' >&2\n\
+                 echo '{}'\n",
+                saved.display()
+            ),
+        )?;
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755))?;
+
+        let mut reported = vec![];
+        let path = download_in(
+            &program.display().to_string(),
+            "https://example.com/watch?v=abc",
+            dir.path(),
+            |fraction| reported.push(fraction),
+        )?;
+        assert_eq!(path, saved);
+        assert_eq!(reported, vec![0.5, 1.0]);
+
+        let failing = dir.path().join("failing");
+        std::fs::write(
+            &failing,
+            "#!/bin/sh\necho 'ERROR: no such video' >&2\nexit 1\n",
+        )?;
+        std::fs::set_permissions(&failing, std::fs::Permissions::from_mode(0o755))?;
+        let err = download_in(&failing.display().to_string(), "x", dir.path(), |_| ())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("no such video"), "{}", err);
+
+        assert!(download_in("afx-no-such-program", "x", dir.path(), |_| ()).is_err());
+        Ok(())
+    }
+}
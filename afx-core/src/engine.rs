@@ -0,0 +1,2587 @@
+//! The playback thread. The engine owns the kira audio manager and carries
+//! out [`ControlMessage`]s, reporting the outcome back through the shared
+//! [`Model`].
+
+use crate::generator::{GeneratorData, GeneratorHandle};
+#[cfg(any(test, feature = "headless"))]
+use crate::headless::HeadlessBackend;
+use crate::import::classify_from_file_err;
+use crate::model::*;
+use crate::output::{self, DeviceBackend, DeviceSettings, Output};
+use crate::radio;
+use crate::spectrum::{Analyser, AnalyserBuilder};
+use anyhow::{anyhow, bail, Result};
+use kira::clock::{ClockHandle, ClockTime};
+use kira::dsp::Frame;
+use kira::manager::backend::cpal::CpalBackend;
+use kira::manager::backend::Backend;
+use kira::manager::{AudioManager, AudioManagerSettings};
+use kira::sound::static_sound::{
+    PlaybackState, StaticSoundData, StaticSoundHandle, StaticSoundSettings,
+};
+use kira::sound::streaming::{StreamingSoundData, StreamingSoundHandle, StreamingSoundSettings};
+use kira::sound::FromFileError;
+use kira::track::{TrackBuilder, TrackHandle, TrackRoutes};
+use kira::tween::Tween;
+use kira::{ClockSpeed, CommandError, LoopBehavior, StartTime};
+use parking_lot::RwLock;
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, trace_span, warn};
+
+/// How often, in milliseconds, [`ControlMessage::SyncPlaybackStatus`] should
+/// be sent for the item positions in the model to stay current.
+pub const PLAYBACK_SYNC_INTERVAL: u64 = 50;
+
+/// How often, in milliseconds, volume changes should be sent while a fader is
+/// moved. Each change glides over this long, so that the steps between them
+/// are inaudible.
+pub const VOLUME_UPDATE_INTERVAL: u64 = 50;
+
+/// How many control messages can wait to be passed on to the playback thread
+/// before new ones are dropped.
+pub const CONTROL_QUEUE_CAPACITY: usize = 1024;
+
+/// How many control messages the channel into the playback thread holds. The
+/// rest wait in [`forward`], where redundant ones are coalesced.
+pub const ENGINE_CHANNEL_CAPACITY: usize = 64;
+
+/// How long [`forward`] waits before trying again to pass messages on to a
+/// busy playback thread.
+const FORWARD_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// How long the items of other playlists take to fade out when a playlist is
+/// started.
+const PLAYLIST_CHANGE_FADE: Duration = Duration::from_millis(500);
+/// How fast the clock items started together are scheduled on ticks.
+const CLOCK_TICKS_PER_SECOND: f64 = 200.0;
+/// How many ticks ahead items started together are scheduled, leaving the
+/// audio thread time to take in all of them before the first starts.
+const CLOCK_LEAD_TICKS: u64 = 2;
+
+fn volume_tween() -> Tween {
+    Tween {
+        duration: Duration::from_millis(VOLUME_UPDATE_INTERVAL),
+        ..Default::default()
+    }
+}
+
+/// Run the engine on the output device from the settings until the channel
+/// is closed. Errors are reported as notifications.
+pub fn process_control_messages(
+    tx: Sender<ControlMessage>,
+    rx: Receiver<ControlMessage>,
+    notifications: Sender<Notification>,
+    model: Arc<RwLock<Model>>,
+) {
+    let (host, device) = {
+        let settings = &model.read().settings;
+        (settings.audio_host, settings.audio_device.clone())
+    };
+    model.write().engine = output_status(host, device.as_deref());
+    let open_output: fn(&str, usize) -> Result<Box<dyn Output>> = match host {
+        AudioHost::Default => |device, pair| output::open(AudioHost::Default, device, pair),
+        AudioHost::Jack => |device, pair| output::open(AudioHost::Jack, device, pair),
+        AudioHost::Asio => |device, pair| output::open(AudioHost::Asio, device, pair),
+    };
+    if host == AudioHost::Default && device.is_none() {
+        // kira's own backend follows changes of the default device
+        let reopen = || {
+            Ok(AudioManager::<CpalBackend>::new(
+                AudioManagerSettings::default(),
+            )?)
+        };
+        start(tx, rx, notifications, model, Box::new(reopen), open_output);
+    } else {
+        let settings = DeviceSettings {
+            host,
+            device,
+            pair: 0,
+        };
+        let reopen = move || {
+            AudioManager::<DeviceBackend>::new(AudioManagerSettings {
+                backend_settings: settings.clone(),
+                ..Default::default()
+            })
+        };
+        start(tx, rx, notifications, model, Box::new(reopen), open_output);
+    }
+}
+
+/// Run the engine on the manager opened by `reopen`, which opens it again
+/// whenever the output stops working, with the other outputs opened by
+/// `open_output`.
+fn start<B: Backend>(
+    tx: Sender<ControlMessage>,
+    rx: Receiver<ControlMessage>,
+    notifications: Sender<Notification>,
+    model: Arc<RwLock<Model>>,
+    reopen: Reopen<B>,
+    open_output: fn(&str, usize) -> Result<Box<dyn Output>>,
+) {
+    let manager = match reopen() {
+        Ok(manager) => manager,
+        Err(err) => {
+            warn!("Failed to create audio manager: {}", err);
+            let _ = notifications.send(Notification {
+                message: format!("Failed to create audio manager: {}", err),
+                item: None,
+            });
+            return;
+        }
+    };
+
+    let mut playback = Playback::new(manager);
+    playback.reopen = reopen;
+    playback.open_output = open_output;
+    supervise(&tx, &rx, &notifications, &model, &mut playback);
+}
+
+/// Carry out control messages until the channel is closed. Should handling
+/// one panic, the audio output is reopened and the loop restarted, rather
+/// than leaving the rest of the session without playback.
+fn supervise<B: Backend>(
+    tx: &Sender<ControlMessage>,
+    rx: &Receiver<ControlMessage>,
+    notifications: &Sender<Notification>,
+    model: &Arc<RwLock<Model>>,
+    playback: &mut Playback<B>,
+) {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // messages are drained into a local queue so that its depth can be
+    // reported, and so that the ones after a panic still get their turn
+    let mut queue = VecDeque::new();
+    while let Err(panic) = catch_unwind(AssertUnwindSafe(|| {
+        run(tx, rx, notifications, model, playback, &mut queue)
+    })) {
+        let reason = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        error!("The playback thread panicked: {}", reason);
+        let recovered = process_message(ControlMessage::RecoverOutput, tx, playback, model);
+        let _ = notifications.send(Notification {
+            message: match recovered {
+                Ok(()) => format!("Playback was restarted after an internal error: {}", reason),
+                Err(err) => format!(
+                    "Playback was restarted after an internal error ({}), \
+                    but the audio output couldn't be reopened: {}",
+                    reason, err
+                ),
+            },
+            item: None,
+        });
+    }
+}
+
+fn run<B: Backend>(
+    tx: &Sender<ControlMessage>,
+    rx: &Receiver<ControlMessage>,
+    notifications: &Sender<Notification>,
+    model: &Arc<RwLock<Model>>,
+    playback: &mut Playback<B>,
+    queue: &mut VecDeque<ControlMessage>,
+) {
+    let mut priorities = None;
+    loop {
+        while let Some(msg) = queue.pop_front() {
+            if matches!(msg, ControlMessage::SyncPlaybackStatus) {
+                model.write().engine.queue_depth = queue.len();
+            } else {
+                model.write().revision += 1;
+            }
+            let item = msg.item_id();
+            let _span = trace_span!("playback").entered();
+            let res = process_message(msg, tx, playback, model);
+            if let Err(err) = res {
+                warn!("Failed to process control message: {}", err);
+                let _ = notifications.send(Notification {
+                    message: err.to_string(),
+                    item,
+                });
+            }
+        }
+
+        let Ok(msg) = rx.recv() else {
+            return;
+        };
+        queue.push_back(msg);
+        queue.extend(rx.try_iter());
+        apply_priorities(&mut priorities, model);
+    }
+}
+
+/// Describe the output of the engine: the named device of the host of the
+/// audio API, or its default device without a name.
+fn output_status(api: AudioHost, device: Option<&str>) -> EngineStatus {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    let status = EngineStatus {
+        host: api,
+        output_device: device.map(str::to_string),
+        hosts: output::hosts(),
+        outputs: output::output_devices(api),
+        ..Default::default()
+    };
+    let host = match output::host(api) {
+        Ok(host) => host,
+        Err(err) => {
+            return EngineStatus {
+                backend: format!("cpal ({})", err),
+                ..status
+            }
+        }
+    };
+    let device = match device {
+        Some(name) => host
+            .output_devices()
+            .ok()
+            .and_then(|mut devices| devices.find(|device| device.name().is_ok_and(|n| n == name))),
+        None => host.default_output_device(),
+    };
+    EngineStatus {
+        backend: format!("cpal ({})", host.id().name()),
+        device: device.as_ref().and_then(|device| device.name().ok()),
+        sample_rate: device
+            .and_then(|device| device.default_output_config().ok())
+            .map(|config| config.sample_rate().0),
+        ..status
+    }
+}
+
+/// Opens the main manager, or a replacement once its output stops working.
+type Reopen<B> = Box<dyn Fn() -> Result<AudioManager<B>> + Send>;
+
+/// State owned by the playback thread.
+pub struct Playback<B: Backend> {
+    manager: AudioManager<B>,
+    handles: HashMap<u64, Handle>,
+    /// Mixer sub-tracks for items started from a playlist, keyed by playlist
+    /// id. Created lazily on first use.
+    buses: HashMap<u64, TrackHandle>,
+    /// Managers of the other output devices and channel pairs items are
+    /// routed to, keyed by device name and pair. Opened lazily on first use.
+    outputs: HashMap<(String, usize), Box<dyn Output>>,
+    /// Opens the manager of a pair of channels of an output device, replaced
+    /// by a mock in tests.
+    open_output: fn(&str, usize) -> Result<Box<dyn Output>>,
+    /// Playlists waiting to fire their next cue by themselves.
+    follow_ons: Vec<(u64, Trigger)>,
+    /// Creates a replacement for the manager, once its output stops working.
+    reopen: Reopen<B>,
+    /// The file of the standby cue of the playing playlist, opened ahead of
+    /// time so that the cue starts without delay. Keyed by item id and path,
+    /// and kept empty if the file couldn't be opened.
+    preloaded: Option<(u64, String, Option<StreamingSoundData<FromFileError>>)>,
+    /// The sounds of the items kept in memory along with their paths, empty
+    /// while they're loading or if they couldn't be loaded.
+    pinned: HashMap<u64, (String, Option<StaticSoundData>)>,
+    /// The whole sounds of the items looping round a region that ends before
+    /// their end, along with their paths. They're played cut at the end of
+    /// the region, so that kira loops round it to the frame.
+    looping: HashMap<u64, (String, StaticSoundData)>,
+    /// Sounds loaded into memory on background threads.
+    loaded: (Sender<Loaded>, Receiver<Loaded>),
+    /// The tracks of the items showing their live spectrum, each feeding
+    /// into the bus of the item, keyed by item id.
+    analysers: HashMap<u64, (TrackHandle, Analyser)>,
+    /// The clock of the main manager items started together are scheduled
+    /// on. Created on first use.
+    clock: Option<ClockHandle>,
+    /// The tick the items being started together start on, while they're
+    /// being started.
+    together: Option<ClockTime>,
+    /// Whether files are loaded into memory whole rather than streamed, so
+    /// that no decoding thread races a simulated clock.
+    in_memory: bool,
+    /// The time in a simulation, which only passes as it's advanced.
+    simulated: Option<Instant>,
+    /// The items paused by global pauses, along with their status before,
+    /// waiting for a global resume.
+    paused_together: Vec<(u64, ItemStatus)>,
+}
+
+/// The sound of a pinned item, with its id and path.
+type Loaded = (u64, String, StaticSoundData);
+
+/// A sound being played, either streamed from its file or from memory.
+enum Handle {
+    Streaming(StreamingSoundHandle<FromFileError>),
+    Static(StaticSoundHandle),
+    Generated(GeneratorHandle),
+}
+
+impl Handle {
+    fn state(&self) -> PlaybackState {
+        match self {
+            Handle::Streaming(handle) => handle.state(),
+            Handle::Static(handle) => handle.state(),
+            Handle::Generated(handle) => handle.state(),
+        }
+    }
+
+    fn position(&self) -> f64 {
+        match self {
+            Handle::Streaming(handle) => handle.position(),
+            Handle::Static(handle) => handle.position(),
+            Handle::Generated(handle) => handle.position(),
+        }
+    }
+
+    fn pause(&mut self, tween: Tween) -> Result<(), CommandError> {
+        match self {
+            Handle::Streaming(handle) => handle.pause(tween),
+            Handle::Static(handle) => handle.pause(tween),
+            Handle::Generated(handle) => {
+                handle.pause(tween);
+                Ok(())
+            }
+        }
+    }
+
+    fn resume(&mut self, tween: Tween) -> Result<(), CommandError> {
+        match self {
+            Handle::Streaming(handle) => handle.resume(tween),
+            Handle::Static(handle) => handle.resume(tween),
+            Handle::Generated(handle) => {
+                handle.resume(tween);
+                Ok(())
+            }
+        }
+    }
+
+    fn stop(&mut self, tween: Tween) -> Result<(), CommandError> {
+        match self {
+            Handle::Streaming(handle) => handle.stop(tween),
+            Handle::Static(handle) => handle.stop(tween),
+            Handle::Generated(handle) => {
+                handle.stop(tween);
+                Ok(())
+            }
+        }
+    }
+
+    /// Change how fast the sound plays, which test signals and streams
+    /// ignore.
+    fn set_playback_rate(&mut self, rate: f64, tween: Tween) -> Result<(), CommandError> {
+        match self {
+            Handle::Streaming(handle) => handle.set_playback_rate(rate, tween),
+            Handle::Static(handle) => handle.set_playback_rate(rate, tween),
+            Handle::Generated(_) => Ok(()),
+        }
+    }
+
+    fn seek_to(&mut self, position: f64) -> Result<(), CommandError> {
+        match self {
+            Handle::Streaming(handle) => handle.seek_to(position),
+            Handle::Static(handle) => handle.seek_to(position),
+            Handle::Generated(handle) => {
+                handle.seek_to(position);
+                Ok(())
+            }
+        }
+    }
+
+    /// What went wrong, if the sound stopped early.
+    fn error(&self) -> Option<String> {
+        match self {
+            Handle::Generated(handle) => handle.error(),
+            _ => None,
+        }
+    }
+
+    fn set_volume(&mut self, volume: f64, tween: Tween) -> Result<(), CommandError> {
+        match self {
+            Handle::Streaming(handle) => handle.set_volume(volume, tween),
+            Handle::Static(handle) => handle.set_volume(volume, tween),
+            Handle::Generated(handle) => {
+                handle.set_volume(volume, tween);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// When a cue list follows on to its next cue.
+#[derive(Debug, Clone, Copy)]
+enum Trigger {
+    At(Instant),
+    /// Once the item stops playing.
+    End(u64),
+}
+
+impl<B: Backend> Playback<B> {
+    pub fn new(manager: AudioManager<B>) -> Self {
+        Self {
+            manager,
+            handles: HashMap::new(),
+            buses: HashMap::new(),
+            outputs: HashMap::new(),
+            open_output: |device, pair| output::open(AudioHost::Default, device, pair),
+            follow_ons: vec![],
+            reopen: Box::new(|| bail!("the audio output can't be reopened")),
+            preloaded: None,
+            pinned: HashMap::new(),
+            looping: HashMap::new(),
+            loaded: std::sync::mpsc::channel(),
+            analysers: HashMap::new(),
+            clock: None,
+            together: None,
+            in_memory: false,
+            simulated: None,
+            paused_together: vec![],
+        }
+    }
+
+    /// The current time, as far as playback is concerned.
+    fn now(&self) -> Instant {
+        self.simulated.unwrap_or_else(Instant::now)
+    }
+
+    /// The tick of the main manager's clock items can be scheduled on to start
+    /// together, starting the clock if it isn't running yet.
+    fn next_tick(&mut self) -> Result<ClockTime> {
+        let clock = match &mut self.clock {
+            Some(clock) => clock,
+            None => {
+                let clock = self
+                    .manager
+                    .add_clock(ClockSpeed::TicksPerSecond(CLOCK_TICKS_PER_SECOND))?;
+                clock.start()?;
+                self.clock.insert(clock)
+            }
+        };
+        Ok(clock.time() + CLOCK_LEAD_TICKS)
+    }
+
+    /// Get the bus of a playlist, creating it with the given volume if it
+    /// doesn't exist yet.
+    fn bus(&mut self, playlist_id: u64, volume: f64) -> Result<&TrackHandle> {
+        if !self.buses.contains_key(&playlist_id) {
+            let track = self
+                .manager
+                .add_sub_track(TrackBuilder::new().volume(volume))?;
+            self.buses.insert(playlist_id, track);
+        }
+        Ok(&self.buses[&playlist_id])
+    }
+
+    /// Get the manager of a pair of channels of an output device, opening it
+    /// with the given volume if it isn't open yet.
+    fn output(&mut self, device: &str, pair: usize, volume: f64) -> Result<&mut Box<dyn Output>> {
+        let key = (device.to_string(), pair);
+        if !self.outputs.contains_key(&key) {
+            let mut output = (self.open_output)(device, pair)?;
+            output.set_volume(volume)?;
+            self.outputs.insert(key.clone(), output);
+        }
+        Ok(self.outputs.get_mut(&key).unwrap())
+    }
+}
+
+#[cfg(any(test, feature = "headless"))]
+impl Playback<HeadlessBackend> {
+    /// Playback without audio hardware, rendering only as it's advanced, and
+    /// with time standing still in between. Items routed to other outputs
+    /// play on managers of their own, which never advance.
+    pub fn headless() -> Result<Self> {
+        let mut playback = Self::new(AudioManager::new(AudioManagerSettings::default())?);
+        playback.open_output = |_, _| {
+            let settings = AudioManagerSettings::default();
+            Ok(Box::new(AudioManager::<HeadlessBackend>::new(settings)?))
+        };
+        playback.reopen = Box::new(|| Ok(AudioManager::new(AudioManagerSettings::default())?));
+        playback.in_memory = true;
+        playback.simulated = Some(Instant::now());
+        Ok(playback)
+    }
+
+    /// Let the given length of time pass on the main manager, returning
+    /// what it played.
+    pub fn advance(&mut self, duration: Duration) -> Vec<Frame> {
+        self.simulated = Some(self.now() + duration);
+        self.manager.backend_mut().advance(duration)
+    }
+}
+
+/// Queue a control message, replacing an earlier one it makes redundant, such
+/// as a seek of the same item. Returns `false` if the queue is full and the
+/// message was dropped.
+pub fn coalesce(queue: &mut VecDeque<ControlMessage>, msg: ControlMessage) -> bool {
+    use ControlMessage::*;
+
+    let global = matches!(msg, SyncPlaybackStatus | SetMasterVolume(_) | Crossfade(_));
+    if global {
+        let same_kind = |earlier: &ControlMessage| {
+            std::mem::discriminant(earlier) == std::mem::discriminant(&msg)
+        };
+        if let Some(position) = queue.iter().position(same_kind) {
+            queue.remove(position);
+        }
+    } else if let Some(id) = msg.item_id() {
+        // only the latest message about an item can be replaced without
+        // changing what the ones after it do
+        let latest = queue
+            .iter_mut()
+            .rev()
+            .find(|earlier| earlier.item_id() == Some(id));
+        if let Some(latest) = latest {
+            if matches!(
+                (&*latest, &msg),
+                (Seek(..), Seek(..))
+                    | (SetVolume(..), SetVolume(..))
+                    | (SetPitch(..), SetPitch(..))
+                    | (SetLoopRegion(..), SetLoopRegion(..))
+            ) {
+                *latest = msg;
+                return true;
+            }
+        }
+    }
+
+    if queue.len() >= CONTROL_QUEUE_CAPACITY {
+        return false;
+    }
+    queue.push_back(msg);
+    true
+}
+
+/// Pass control messages on to the playback thread through its bounded
+/// channel, calling `on_message` on each of them first. Messages wait here
+/// while the playback thread is busy, coalesced and up to
+/// [`CONTROL_QUEUE_CAPACITY`] of them, so that a stuck playback thread can't
+/// make them pile up without end. The backlog and the number of dropped
+/// messages are reported with every [`ControlMessage::SyncPlaybackStatus`].
+pub fn forward(
+    rx: Receiver<ControlMessage>,
+    engine: SyncSender<ControlMessage>,
+    model: &Arc<RwLock<Model>>,
+    mut on_message: impl FnMut(&ControlMessage),
+) {
+    let mut pending = VecDeque::new();
+    let mut dropped = 0;
+    loop {
+        // only wait for messages indefinitely with nothing left to pass on
+        let first = if pending.is_empty() {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_timeout(FORWARD_RETRY_INTERVAL)
+        };
+        let disconnected = first == Err(RecvTimeoutError::Disconnected);
+        for msg in first.into_iter().chain(rx.try_iter()) {
+            on_message(&msg);
+            if msg == ControlMessage::SyncPlaybackStatus {
+                let mut model = model.write();
+                model.engine.backlog = pending.len();
+                model.engine.dropped_messages = dropped;
+                model.engine.sync_received = Some(Instant::now());
+            }
+            if !coalesce(&mut pending, msg) {
+                dropped += 1;
+                warn!("the playback thread is behind, dropping a control message");
+            }
+        }
+
+        while let Some(msg) = pending.pop_front() {
+            let result = if disconnected {
+                engine
+                    .send(msg)
+                    .map_err(|err| TrySendError::Disconnected(err.0))
+            } else {
+                engine.try_send(msg)
+            };
+            match result {
+                Ok(()) => (),
+                Err(TrySendError::Full(msg)) => {
+                    pending.push_front(msg);
+                    break;
+                }
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+        if disconnected {
+            return;
+        }
+    }
+}
+
+/// Set the priority of the current thread.
+pub fn set_priority(priority: Priority) -> Result<()> {
+    use thread_priority::*;
+
+    set_current_thread_priority(match priority {
+        Priority::Low => ThreadPriority::Min,
+        Priority::Normal => ThreadPriority::Crossplatform(50.try_into().unwrap()),
+        Priority::High => ThreadPriority::Max,
+    })
+    .map_err(|err| match err {
+        // the codes mean more to people once described
+        Error::OS(code) => std::io::Error::from_raw_os_error(code).into(),
+        err => err.into(),
+    })
+}
+
+/// Apply the thread priorities from the settings once they change, to the
+/// playback thread, which has to be the current one, and to the import
+/// workers.
+fn apply_priorities(applied: &mut Option<Priorities>, model: &Arc<RwLock<Model>>) {
+    let priorities = model.read().settings.priorities;
+    if *applied == Some(priorities) {
+        return;
+    }
+    let previous = applied.replace(priorities);
+    info!("setting thread priorities to {:?}", priorities);
+
+    let error = set_priority(priorities.playback)
+        .err()
+        .map(|err| format!("couldn't set the priority of playback: {}", err));
+    model.write().engine.priority_error = error;
+    if previous.map(|previous| previous.import) != Some(priorities.import) {
+        let model = model.clone();
+        rayon::spawn_broadcast(move |_| {
+            if let Err(err) = set_priority(priorities.import) {
+                model.write().engine.priority_error.get_or_insert_with(|| {
+                    format!("couldn't set the priority of imports: {}", err)
+                });
+            }
+        });
+    }
+}
+
+/// Carry out a single control message.
+pub fn process_message<B: Backend>(
+    msg: ControlMessage,
+    tx: &Sender<ControlMessage>,
+    playback: &mut Playback<B>,
+    model: &Arc<RwLock<Model>>,
+) -> Result<()> {
+    // string return value because lol no lambda generics :(
+    let edit_item = |id: u64, f: &mut dyn FnMut(&mut Item) -> String| {
+        let mut model = model.write();
+        model.items.iter_mut().find(|item| item.id == id).map(f)
+    };
+
+    match msg {
+        ControlMessage::Play(id) => {
+            if let Some(handle) = playback.handles.get_mut(&id) {
+                handle.resume(Tween::default())?;
+                model.write().record(PlaybackEvent::Resume, Some(id));
+            } else {
+                edit_item(id, &mut |item| {
+                    item.rotate_stem();
+                    String::new()
+                });
+                let (crossfaded, crossfade) = crossfaded_by(&model.read(), id, playback);
+                let handle = begin_playback(model, id, edit_item, playback)?;
+                let fade = Tween {
+                    duration: Duration::from_secs_f64(crossfade),
+                    ..Default::default()
+                };
+                for other in crossfaded {
+                    stop_item(other, fade, playback, model)?;
+                }
+                playback.handles.insert(id, handle);
+                model.write().record(PlaybackEvent::Start, Some(id));
+            }
+            // we ignore the option here - the edit may not go through
+            // if the item was deleted in the meantime
+            edit_item(id, &mut |item| {
+                item.status = ItemStatus::Playing;
+                String::new()
+            });
+            Ok(())
+        }
+        ControlMessage::Pause(id) => {
+            if let Some(handle) = playback.handles.get_mut(&id) {
+                handle.pause(Tween::default())?;
+                model.write().record(PlaybackEvent::Pause, Some(id));
+                edit_item(id, &mut |item| {
+                    item.status = ItemStatus::Paused;
+                    String::new()
+                });
+            }
+            Ok(())
+        }
+        ControlMessage::Stop(id) => stop_item(id, Tween::default(), playback, model),
+        ControlMessage::ChangeStem(_, _) => bail!("changing stems isn't supported yet"),
+        ControlMessage::SyncPlaybackStatus => {
+            let voices = playback.manager.num_sounds()
+                + playback
+                    .outputs
+                    .values()
+                    .map(|output| output.num_sounds())
+                    .sum::<usize>();
+            // all items are updated under a single lock, so that the sync
+            // doesn't queue up behind other threads once per playing item
+            let mut model_guard = model.write();
+            model_guard.engine.voices = voices;
+            if let Some(received) = model_guard.engine.sync_received.take() {
+                model_guard.engine.control_latency = Some(received.elapsed());
+            }
+            let mut to_remove = vec![];
+            let mut ended = vec![];
+            let mut result = Ok(());
+            for (&id, handle) in playback
+                .handles
+                .iter_mut()
+                .filter(|(_, h)| h.state() != PlaybackState::Paused)
+            {
+                let Some(fade_out) = model_guard
+                    .items
+                    .iter()
+                    .find(|item| item.id == id)
+                    .map(|item| model_guard.fades(item).1)
+                else {
+                    continue;
+                };
+                let item = model_guard.items.iter_mut().find(|item| item.id == id);
+                let item = item.expect("the item was just found");
+                item.target_position = handle.position();
+
+                // sounds cut at the end of their loop region never get there,
+                // but streamed ones are sent back once they overshoot it
+                let (loop_start, loop_end) = item.loop_bounds();
+                if item.looped() && loop_end < item.duration && handle.position() >= loop_end {
+                    match handle.seek_to(loop_start) {
+                        Ok(()) => item.target_position = loop_start,
+                        Err(err) => result = Err(err.into()),
+                    }
+                }
+
+                // fade out ahead of the end of the trimmed part, which
+                // streams don't have
+                let live = item.is_stream();
+                let trim_end = item.trim_bounds().1;
+                if !item.looped()
+                    && !live
+                    && item.target_position >= trim_end - fade_out
+                    && handle.state() == PlaybackState::Playing
+                {
+                    // the positions are in the time of the recording
+                    let fade = Tween {
+                        duration: Duration::from_secs_f64(
+                            (trim_end - item.target_position).max(0.0) / item.playback_rate(),
+                        ),
+                        ..Default::default()
+                    };
+                    if let Err(err) = handle.stop(fade) {
+                        result = Err(err.into());
+                    }
+                }
+
+                let silent = !item.looped() && item.silent_at(item.target_position);
+                if (item.position >= item.duration && !live)
+                    || handle.state() == PlaybackState::Stopped
+                    || silent
+                {
+                    item.target_position = 0.0;
+                    if let Some(err) = handle.error() {
+                        item.report((IssueType::PlaybackProblem, err));
+                    }
+
+                    to_remove.push(id);
+                    if item.looped() {
+                        // FIXME this is a hack, since looping behaviour
+                        // can't be changed via a handle
+                        item.status = ItemStatus::Loading;
+                        // the receiving end is on this very thread
+                        let _ = tx.send(ControlMessage::Play(id));
+                    } else {
+                        item.status = ItemStatus::Stopped;
+                        if let Err(err) = handle.stop(Tween::default()) {
+                            result = Err(err.into());
+                        }
+                        ended.push(id);
+                    }
+                }
+            }
+            for id in to_remove {
+                playback.handles.remove(&id);
+                model_guard.record_end(id);
+            }
+            for id in ended {
+                if let Some(msg) = model_guard.end_of(id) {
+                    let _ = tx.send(msg);
+                }
+            }
+            let handles = &playback.handles;
+            playback.analysers.retain(|id, _| handles.contains_key(id));
+            playback.looping.retain(|id, _| handles.contains_key(id));
+            for item in model_guard.items.iter_mut() {
+                let spectrum = match playback.analysers.get(&item.id) {
+                    Some((_, analyser)) => analyser.bands(),
+                    None => vec![],
+                };
+                if item.spectrum != spectrum {
+                    item.spectrum = spectrum;
+                }
+            }
+            drop(model_guard);
+            sequence(tx, playback, model)?;
+            result
+        }
+        ControlMessage::Seek(id, target) => {
+            model.write().record(PlaybackEvent::Seek, Some(id));
+            let mut defer_to_sync = false;
+            if let Some(handle) = playback.handles.get_mut(&id) {
+                handle.seek_to(target)?;
+                if handle.state() == PlaybackState::Playing {
+                    defer_to_sync = true;
+                }
+            }
+
+            // FIXME there's still the issue of seeking a paused handle and then
+            // letting it play. Leads to glitchy behaviour.
+            if !defer_to_sync {
+                edit_item(id, &mut |item| {
+                    item.target_position = target;
+                    String::new()
+                });
+            }
+            Ok(())
+        }
+        ControlMessage::SeekRelative(id, offset) => {
+            let target = {
+                let model = model.read();
+                let item = model
+                    .items
+                    .iter()
+                    .find(|item| item.id == id)
+                    .ok_or_else(|| anyhow!("the item to seek no longer exists"))?;
+                if item.is_stream() {
+                    // live streams can't be skipped through
+                    return Ok(());
+                }
+                let position = match playback.handles.get(&id) {
+                    Some(handle) => handle.position(),
+                    None => item.target_position,
+                };
+                (position + offset).clamp(0.0, item.duration)
+            };
+            process_message(ControlMessage::Seek(id, target), tx, playback, model)
+        }
+        ControlMessage::Loop(id, _do_loop) => {
+            if let Some(_handle) = playback.handles.get_mut(&id) {
+                // TODO: implement looping via handles once it's supported
+            }
+            Ok(())
+        }
+        ControlMessage::SetLoopRegion(id, region) => {
+            let mut looped_bounds = None;
+            edit_item(id, &mut |item| {
+                item.loop_region = region;
+                looped_bounds = Some(item.loop_bounds()).filter(|_| item.looped());
+                String::new()
+            });
+            if let (Some(handle), Some((start, end))) =
+                (playback.handles.get_mut(&id), looped_bounds)
+            {
+                if playback.looping.contains_key(&id) {
+                    // the sound has to be cut anew at the end of the region,
+                    // and takes over from the old one without a fade
+                    let position = handle.position();
+                    let paused = handle.state() == PlaybackState::Paused;
+                    handle.stop(Tween {
+                        duration: Duration::ZERO,
+                        ..Default::default()
+                    })?;
+                    edit_item(id, &mut |item| {
+                        item.position = if position >= end { start } else { position };
+                        String::new()
+                    });
+                    let mut handle = begin_playback(model, id, edit_item, playback)?;
+                    if paused {
+                        handle.pause(Tween::default())?;
+                    }
+                    playback.handles.insert(id, handle);
+                } else if handle.position() >= end {
+                    handle.seek_to(start)?;
+                }
+            }
+            Ok(())
+        }
+        ControlMessage::Mute(id, mute) => {
+            if let Some(handle) = playback.handles.get_mut(&id) {
+                let model = model.read();
+                let item = model
+                    .items
+                    .iter()
+                    .find(|item| item.id == id)
+                    .ok_or_else(|| anyhow!("the muted item no longer exists"))?;
+                let volume = item.volume * model.item_gain(item);
+                handle.set_volume(if mute { 0.0 } else { volume }, Tween::default())?;
+            }
+            Ok(())
+        }
+        ControlMessage::SetVolume(id, volume) => {
+            if let Some(handle) = playback.handles.get_mut(&id) {
+                let model = model.read();
+                let gain = model
+                    .items
+                    .iter()
+                    .find(|item| item.id == id)
+                    .map_or(1.0, |item| model.item_gain(item));
+                handle.set_volume(volume * gain, volume_tween())?;
+            }
+            Ok(())
+        }
+        ControlMessage::SetPitch(id, semitones) => {
+            if let Some(handle) = playback.handles.get_mut(&id) {
+                handle.set_playback_rate(semitones_to_rate(semitones), volume_tween())?;
+            }
+            Ok(())
+        }
+        ControlMessage::SetMasterVolume(volume) => {
+            playback
+                .manager
+                .main_track()
+                .set_volume(volume, volume_tween())?;
+            for output in playback.outputs.values_mut() {
+                output.set_volume(volume)?;
+            }
+            Ok(())
+        }
+        ControlMessage::Retry(id) => {
+            let path = model
+                .read()
+                .items
+                .iter()
+                .find(|item| item.id == id && item.signal.is_none())
+                .map(|item| item.stems[item.current_stem].path.clone());
+            let Some(path) = path else {
+                return Ok(());
+            };
+            // streams are tried again when they're next played
+            if radio::is_stream_url(&path) {
+                edit_item(id, &mut |item| {
+                    item.issues.clear();
+                    String::new()
+                });
+                return Ok(());
+            }
+            match StreamingSoundData::from_file(&path, StreamingSoundSettings::new()) {
+                Ok(_) => {
+                    info!("{} loads again", path);
+                    edit_item(id, &mut |item| {
+                        item.issues.clear();
+                        String::new()
+                    });
+                    Ok(())
+                }
+                Err(err) => {
+                    let (msg, typ) = classify_from_file_err(&err);
+                    edit_item(id, &mut |item| {
+                        item.report((typ.clone(), msg.clone()));
+                        String::new()
+                    });
+                    Err(err.into())
+                }
+            }
+        }
+        ControlMessage::Delete(id) => {
+            if let Some(mut handle) = playback.handles.remove(&id) {
+                handle.stop(Tween::default())?;
+            }
+            let mut model = model.write();
+            model.items.retain(|item| item.id != id);
+            model.library_pins.retain(|&pin| pin != id);
+            model.playlists.iter_mut().for_each(|playlist| {
+                playlist.remove_item(id);
+            });
+            Ok(())
+        }
+        ControlMessage::AddToPlaylist {
+            item_id,
+            playlist_id,
+        } => {
+            let mut model = model.write();
+            if !model.items.iter().any(|item| item.id == item_id) {
+                bail!("the item added to a playlist no longer exists");
+            }
+            let playlist = model
+                .playlists
+                .iter_mut()
+                .find(|playlist| playlist.id == playlist_id)
+                .ok_or_else(|| anyhow!("the playlist no longer exists"))?;
+            playlist.items.push(item_id);
+            Ok(())
+        }
+        ControlMessage::RemoveFromPlaylist {
+            pos_within_playlist,
+            playlist_id,
+        } => {
+            let mut model = model.write();
+            let playlist = model
+                .playlists
+                .iter_mut()
+                .find(|playlist| playlist.id == playlist_id)
+                .ok_or_else(|| anyhow!("the playlist no longer exists"))?;
+            if pos_within_playlist >= playlist.items.len() {
+                bail!("the item is no longer in the playlist");
+            }
+            playlist.remove(pos_within_playlist);
+            Ok(())
+        }
+        ControlMessage::PlayFromPlaylist(id) => {
+            let stopped = {
+                let mut model = model.write();
+                let Some(playlist) = model.playlists.iter_mut().find(|p| p.id == id) else {
+                    return Ok(());
+                };
+                if playlist.items.is_empty() {
+                    return Ok(());
+                }
+                playlist.standby = 0;
+                let stopped = playlists_stopped_by(&model, id);
+                playback
+                    .follow_ons
+                    .retain(|(playlist_id, _)| !stopped(Some(*playlist_id)));
+                playback
+                    .handles
+                    .keys()
+                    .copied()
+                    .filter(|item_id| {
+                        let item = model.items.iter().find(|item| item.id == *item_id);
+                        stopped(item.and_then(|item| item.source_playlist))
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let fade = Tween {
+                duration: PLAYLIST_CHANGE_FADE,
+                ..Default::default()
+            };
+            for item_id in stopped {
+                stop_item(item_id, fade, playback, model)?;
+            }
+
+            let cue = model.write().fire_cue(id);
+            match cue {
+                Some(cue) => process_message(cue, tx, playback, model),
+                None => Ok(()),
+            }
+        }
+        ControlMessage::GlobalPause => {
+            let mut model = model.write();
+            model.record(PlaybackEvent::PauseAll, None);
+            for (id, handle) in playback.handles.iter_mut() {
+                // items paused on their own stay paused once resumed
+                if handle.state() == PlaybackState::Paused {
+                    continue;
+                }
+                handle.pause(Tween::default())?;
+                if let Some(item) = model.items.iter_mut().find(|item| item.id == *id) {
+                    playback.paused_together.push((*id, item.status.clone()));
+                    item.status = ItemStatus::Paused;
+                }
+            }
+            model.engine.paused_together =
+                playback.paused_together.iter().map(|&(id, _)| id).collect();
+            Ok(())
+        }
+        ControlMessage::GlobalResume => {
+            model.write().record(PlaybackEvent::ResumeAll, None);
+            model.write().engine.paused_together.clear();
+            let mut result = Ok(());
+            for (id, status) in std::mem::take(&mut playback.paused_together) {
+                let paused = model
+                    .read()
+                    .items
+                    .iter()
+                    .any(|item| item.id == id && item.status == ItemStatus::Paused);
+                if !paused {
+                    continue;
+                }
+                match playback.handles.get_mut(&id) {
+                    Some(handle) => {
+                        if let Err(err) = handle.resume(Tween::default()) {
+                            result = Err(err.into());
+                            continue;
+                        }
+                        edit_item(id, &mut |item| {
+                            item.status = status.clone();
+                            String::new()
+                        });
+                    }
+                    // the output was reopened since, so it starts over from
+                    // where it was
+                    None => {
+                        if let Err(err) =
+                            process_message(ControlMessage::Play(id), tx, playback, model)
+                        {
+                            result = Err(err);
+                        }
+                    }
+                }
+            }
+            result
+        }
+        ControlMessage::GlobalStop | ControlMessage::GlobalFadeOut(_) => {
+            let fade = match msg {
+                ControlMessage::GlobalFadeOut(seconds) => Tween {
+                    duration: Duration::from_secs_f64(seconds.max(0.0)),
+                    ..Default::default()
+                },
+                _ => Tween::default(),
+            };
+            let mut model = model.write();
+            model.record(PlaybackEvent::StopAll, None);
+            // the sounds fade out by themselves, with nothing following on
+            for (id, handle) in playback.handles.iter_mut() {
+                handle.stop(fade)?;
+                model.record_end(*id);
+                if let Some(item) = model.items.iter_mut().find(|item| item.id == *id) {
+                    item.status = ItemStatus::Stopped;
+                    item.target_position = 0.0;
+                }
+            }
+            playback.handles.clear();
+            playback.follow_ons.clear();
+            playback.paused_together.clear();
+            model.engine.paused_together.clear();
+            Ok(())
+        }
+        ControlMessage::Crossfade(crossfader) => {
+            for (&playlist_id, bus) in playback.buses.iter() {
+                bus.set_volume(crossfader.gain_for(playlist_id), Tween::default())?;
+            }
+            Ok(())
+        }
+        ControlMessage::RecoverOutput => {
+            info!("reopening the audio output");
+            let manager = (playback.reopen)()?;
+            // the handles die with the old manager, so the items pick up from
+            // where they were once played again
+            {
+                let mut model = model.write();
+                for (&id, handle) in playback.handles.iter() {
+                    if let Some(item) = model.items.iter_mut().find(|item| item.id == id) {
+                        item.position = handle.position();
+                        item.target_position = handle.position();
+                        item.status = ItemStatus::Paused;
+                    }
+                }
+                playback.handles.clear();
+                playback.buses.clear();
+                playback.analysers.clear();
+                playback.outputs.clear();
+                playback.follow_ons.clear();
+                playback.clock = None;
+                playback.together = None;
+                playback.manager = manager;
+                let volume = db_to_amplitude(model.master_gain_db);
+                playback
+                    .manager
+                    .main_track()
+                    .set_volume(volume, Tween::default())?;
+            }
+            Ok(())
+        }
+        ControlMessage::PlayTogether(ids) => play_together(playback, |playback| {
+            ids.into_iter()
+                .try_for_each(|id| process_message(ControlMessage::Play(id), tx, playback, model))
+        }),
+        ControlMessage::Cue {
+            playlist_id,
+            position,
+        } => {
+            let (item_id, follow) = {
+                let model = model.read();
+                let Some(playlist) = model.playlists.iter().find(|p| p.id == playlist_id) else {
+                    return Ok(());
+                };
+                let Some(&item_id) = playlist.items.get(position) else {
+                    return Ok(());
+                };
+                (item_id, playlist.follow(position))
+            };
+            model.write().record(PlaybackEvent::Cue, Some(item_id));
+            if matches!(follow, Follow::After(seconds) if seconds <= 0.0) {
+                // fire the next cue right away so that both start together
+                return play_together(playback, |playback| {
+                    process_message(ControlMessage::Play(item_id), tx, playback, model)?;
+                    let next = model.write().fire_cue(playlist_id);
+                    match next {
+                        Some(next) => process_message(next, tx, playback, model),
+                        None => Ok(()),
+                    }
+                });
+            }
+            process_message(ControlMessage::Play(item_id), tx, playback, model)?;
+
+            let trigger = match follow {
+                Follow::Manual => return Ok(()),
+                Follow::After(seconds) => {
+                    Trigger::At(playback.now() + Duration::from_secs_f64(seconds))
+                }
+                Follow::End => Trigger::End(item_id),
+            };
+            playback.follow_ons.push((playlist_id, trigger));
+            Ok(())
+        }
+    }
+}
+
+/// Start the items played by `play` on the same tick of the clock of the main
+/// manager, so that they line up to the sample. Calls nest, with the
+/// outermost one picking the tick.
+fn play_together<B: Backend>(
+    playback: &mut Playback<B>,
+    play: impl FnOnce(&mut Playback<B>) -> Result<()>,
+) -> Result<()> {
+    if playback.together.is_some() {
+        return play(playback);
+    }
+    playback.together = Some(playback.next_tick()?);
+    let result = play(playback);
+    playback.together = None;
+    result
+}
+
+/// Tell whether starting a playlist stops the items played from another,
+/// given by its id or `None` for the library. Soundboards and the library
+/// are left alone unless the settings say otherwise.
+fn playlists_stopped_by(model: &Model, started: u64) -> impl Fn(Option<u64>) -> bool + '_ {
+    move |source| {
+        let pads = model.settings.stop_pads_with_playlists;
+        match source {
+            Some(id) if id == started => false,
+            Some(id) => {
+                pads || model
+                    .playlists
+                    .iter()
+                    .find(|playlist| playlist.id == id)
+                    .is_none_or(|playlist| playlist.grid.is_none())
+            }
+            None => pads,
+        }
+    }
+}
+
+/// The items an item started from a playlist that crossfades takes over
+/// from, which are the others playing from it, along with how long the
+/// crossfade takes.
+fn crossfaded_by<B: Backend>(model: &Model, id: u64, playback: &Playback<B>) -> (Vec<u64>, f64) {
+    let Some(item) = model.items.iter().find(|item| item.id == id) else {
+        return (vec![], 0.0);
+    };
+    let crossfade = model.crossfade(item);
+    if crossfade <= 0.0 {
+        return (vec![], 0.0);
+    }
+    let others = playback
+        .handles
+        .iter()
+        .filter(|(other, handle)| **other != id && handle.state() == PlaybackState::Playing)
+        .filter(|(other, _)| {
+            let other = model.items.iter().find(|item| item.id == **other);
+            other.is_some_and(|other| other.source_playlist == item.source_playlist)
+        })
+        .map(|(other, _)| *other)
+        .collect();
+    (others, crossfade)
+}
+
+fn stop_item<B: Backend>(
+    id: u64,
+    tween: Tween,
+    playback: &mut Playback<B>,
+    model: &Arc<RwLock<Model>>,
+) -> Result<()> {
+    let mut model = model.write();
+    if let Some(mut handle) = playback.handles.remove(&id) {
+        handle.stop(tween)?;
+        model.record(PlaybackEvent::Stop, Some(id));
+        model.record_end(id);
+    }
+    if let Some(item) = model.items.iter_mut().find(|item| item.id == id) {
+        item.status = ItemStatus::Stopped;
+        item.position = 0.0;
+        item.target_position = 0.0;
+    }
+    Ok(())
+}
+
+/// Fire the cues whose follow-on is due, and report the ones still waiting.
+fn sequence<B: Backend>(
+    tx: &Sender<ControlMessage>,
+    playback: &mut Playback<B>,
+    model: &Arc<RwLock<Model>>,
+) -> Result<()> {
+    let now = playback.now();
+    let (due, waiting): (Vec<_>, Vec<_>) = {
+        let model = model.read();
+        // a looped item restarts rather than ending
+        let ended = |item_id| {
+            model
+                .items
+                .iter()
+                .find(|item| item.id == item_id)
+                .is_none_or(|item| item.status == ItemStatus::Stopped)
+        };
+        playback
+            .follow_ons
+            .drain(..)
+            .partition(|(_, trigger)| match *trigger {
+                Trigger::At(at) => at <= now,
+                Trigger::End(item_id) => ended(item_id),
+            })
+    };
+    playback.follow_ons = waiting;
+
+    for (playlist_id, _) in due {
+        let next = model.write().fire_cue(playlist_id);
+        if let Some(next) = next {
+            process_message(next, tx, playback, model)?;
+        }
+    }
+
+    let now = playback.now();
+    model.write().engine.follows = playback
+        .follow_ons
+        .iter()
+        .map(|(playlist_id, trigger)| match *trigger {
+            Trigger::At(at) => (
+                *playlist_id,
+                Some(at.saturating_duration_since(now).as_secs_f64()),
+            ),
+            Trigger::End(_) => (*playlist_id, None),
+        })
+        .collect();
+    preload(playback, model);
+    pin(playback, model);
+    Ok(())
+}
+
+/// Load the sounds of newly pinned items into memory on background threads
+/// and drop those no longer pinned.
+fn pin<B: Backend>(playback: &mut Playback<B>, model: &Arc<RwLock<Model>>) {
+    let wanted: HashMap<u64, String> = model
+        .read()
+        .items
+        .iter()
+        .filter(|item| item.pinned && item.signal.is_none() && !item.is_stream())
+        .map(|item| (item.id, item.stems[item.current_stem].path.clone()))
+        .collect();
+    for (id, path, sound) in playback.loaded.1.try_iter() {
+        if let Some((pinned_path, pinned)) = playback.pinned.get_mut(&id) {
+            if *pinned_path == path {
+                *pinned = Some(sound);
+            }
+        }
+    }
+    playback
+        .pinned
+        .retain(|id, (path, _)| wanted.get(id) == Some(path));
+
+    for (id, path) in wanted {
+        if playback.pinned.contains_key(&id) {
+            continue;
+        }
+        info!("loading {} into memory", path);
+        playback.pinned.insert(id, (path.clone(), None));
+        let loaded = playback.loaded.0.clone();
+        std::thread::spawn(move || {
+            match StaticSoundData::from_file(&path, StaticSoundSettings::new()) {
+                Ok(sound) => loaded.send((id, path, sound)).ok(),
+                // the error is reported once the item is played from disk
+                Err(err) => {
+                    warn!("failed to load {} into memory: {}", path, err);
+                    None
+                }
+            };
+        });
+    }
+
+    model.write().engine.pinned_memory = playback
+        .pinned
+        .values()
+        .filter_map(|(_, sound)| sound.as_ref())
+        .map(|sound| sound.frames.len() * std::mem::size_of::<Frame>())
+        .sum();
+}
+
+/// Open the file of the standby cue of the playing playlist, unless it's
+/// already open.
+fn preload<B: Backend>(playback: &mut Playback<B>, model: &Arc<RwLock<Model>>) {
+    let next = {
+        let model = model.read();
+        model
+            .playing_playlist
+            .and_then(|playlist_id| model.cue(playlist_id))
+            .and_then(|(_, _, item_id)| model.items.iter().find(|item| item.id == item_id))
+            .filter(|item| item.status == ItemStatus::Stopped && item.signal.is_none())
+            .filter(|item| !item.is_stream())
+            .map(|item| (item.id, item.stems[item.current_stem].path.clone()))
+    };
+    let Some((item_id, path)) = next else {
+        playback.preloaded = None;
+        return;
+    };
+    if matches!(&playback.preloaded, Some((id, file, _)) if *id == item_id && *file == path) {
+        return;
+    }
+
+    info!("preloading {}", path);
+    let sound = StreamingSoundData::from_file(&path, StreamingSoundSettings::new())
+        // the error is reported once the cue is actually played
+        .map_err(|err| warn!("failed to preload {}: {}", path, err))
+        .ok();
+    playback.preloaded = Some((item_id, path, sound));
+}
+
+fn begin_playback<B: Backend>(
+    model: &Arc<RwLock<Model>>,
+    id: u64,
+    mut edit_item: impl FnMut(u64, &mut dyn FnMut(&mut Item) -> String) -> Option<String>,
+    playback: &mut Playback<B>,
+) -> Result<Handle> {
+    let (
+        file,
+        signal,
+        stream,
+        duration,
+        position,
+        looped,
+        (loop_start, loop_end),
+        trim_start,
+        fade_in,
+        muted,
+        volume,
+        rate,
+        bus,
+        output,
+        live_spectrum,
+    ) = {
+        let model = model.read();
+        let item = model
+            .items
+            .iter()
+            .find(|item| item.id == id)
+            .ok_or_else(|| anyhow!("the item no longer exists"))?;
+        let path = item.stems[item.current_stem].path.clone();
+        let bus = item
+            .source_playlist
+            .map(|playlist_id| (playlist_id, model.crossfader.gain_for(playlist_id)));
+        let output = model.output_of(item).map(|(device, pair)| {
+            let master_volume = db_to_amplitude(model.master_gain_db);
+            (device.to_string(), pair, master_volume)
+        });
+        (
+            path,
+            item.signal,
+            item.is_stream(),
+            item.duration,
+            item.position,
+            item.looped(),
+            item.loop_bounds(),
+            item.trim_bounds().0,
+            // fading over from the other items of the playlist
+            match crossfaded_by(&model, id, playback) {
+                (others, crossfade) if !others.is_empty() => model.fades(item).0.max(crossfade),
+                _ => model.fades(item).0,
+            },
+            item.muted,
+            item.volume * model.item_gain(item),
+            item.playback_rate(),
+            bus,
+            output,
+            model.settings.live_spectrum,
+        )
+    };
+    let volume = if muted { 0.0 } else { volume };
+    // items only fade in when they start from the top
+    let fade_in = (position <= trim_start && fade_in > 0.0).then(|| Tween {
+        duration: Duration::from_secs_f64(fade_in / rate),
+        ..Default::default()
+    });
+    let position = position.max(trim_start);
+    // the other outputs have clocks of their own
+    let start_time = match (playback.together, &output) {
+        (Some(tick), None) => StartTime::ClockTime(tick),
+        _ => StartTime::Immediate,
+    };
+    let loop_behavior = looped.then_some(LoopBehavior {
+        start_position: loop_start,
+    });
+    let cut_at = (looped && loop_end < duration).then_some(loop_end);
+    // the buses live on the main manager, so routed items bypass them
+    let track = match (bus, &output) {
+        (Some((playlist_id, bus_volume)), None) => {
+            Some(playback.bus(playlist_id, bus_volume)?.id())
+        }
+        _ => None,
+    };
+    let track = if live_spectrum && output.is_none() {
+        let mut builder = TrackBuilder::new();
+        if let Some(bus) = track {
+            builder = builder.routes(TrackRoutes::parent(bus));
+        }
+        let analyser = builder.add_effect(AnalyserBuilder);
+        let handle = playback.manager.add_sub_track(builder)?;
+        let track = handle.id();
+        playback.analysers.insert(id, (handle, analyser));
+        Some(track)
+    } else {
+        track
+    };
+
+    if let Some(signal) = signal {
+        info!("generating {}", signal);
+        let mut settings = StreamingSoundSettings::new()
+            .start_position(position)
+            .volume(volume)
+            .loop_behavior(loop_behavior)
+            .fade_in_tween(fade_in)
+            .start_time(start_time);
+        if let Some(track) = track {
+            settings = settings.track(track);
+        }
+        let sound = GeneratorData::new(signal, duration, settings);
+        return Ok(Handle::Generated(match output {
+            Some((device, pair, master_volume)) => playback
+                .output(&device, pair, master_volume)?
+                .play_generated(sound)?,
+            None => playback.manager.play(sound)?,
+        }));
+    }
+
+    if stream {
+        info!("tuning in to {}", file);
+        let mut settings = StreamingSoundSettings::new()
+            .volume(volume)
+            .fade_in_tween(fade_in)
+            .start_time(start_time);
+        if let Some(track) = track {
+            settings = settings.track(track);
+        }
+        let source = Box::new(radio::open(&file));
+        let sound = GeneratorData::from_source(source, f64::INFINITY, settings);
+        return Ok(Handle::Generated(match output {
+            Some((device, pair, master_volume)) => playback
+                .output(&device, pair, master_volume)?
+                .play_generated(sound)?,
+            None => playback.manager.play(sound)?,
+        }));
+    }
+
+    let looping = playback.looping.get(&id).map(|(path, sound)| (path, sound));
+    let pinned = playback.pinned.get(&id);
+    let pinned = looping
+        .into_iter()
+        .chain(pinned.and_then(|(path, sound)| Some((path, sound.as_ref()?))))
+        .find(|(path, _)| **path == file)
+        .map(|(_, sound)| sound.clone())
+        // files that fail to load are reported once streamed below
+        .or_else(|| {
+            let settings = StaticSoundSettings::new();
+            let loaded = (playback.in_memory || cut_at.is_some())
+                .then(|| StaticSoundData::from_file(&file, settings));
+            loaded.and_then(Result::ok)
+        });
+    match (&pinned, cut_at) {
+        (Some(sound), Some(_)) => {
+            playback.looping.insert(id, (file.clone(), sound.clone()));
+        }
+        _ => {
+            playback.looping.remove(&id);
+        }
+    }
+    if let Some(sound) = pinned {
+        let sound = match cut_at {
+            Some(end) => cut(&sound, end),
+            None => sound,
+        };
+        info!("playing {} from memory", file);
+        let mut settings = StaticSoundSettings::new()
+            .start_position(position)
+            .volume(volume)
+            .playback_rate(rate)
+            .loop_behavior(loop_behavior)
+            .fade_in_tween(fade_in)
+            .start_time(start_time);
+        if let Some(track) = track {
+            settings = settings.track(track);
+        }
+        let sound = sound.with_settings(settings);
+        return Ok(Handle::Static(match output {
+            Some((device, pair, master_volume)) => playback
+                .output(&device, pair, master_volume)?
+                .play_static(sound)?,
+            None => playback.manager.play(sound)?,
+        }));
+    }
+
+    info!("loading {}", file);
+    let mut settings = StreamingSoundSettings::new()
+        .start_position(position)
+        .volume(volume)
+        .playback_rate(rate)
+        .loop_behavior(loop_behavior)
+        .fade_in_tween(fade_in)
+        .start_time(start_time);
+    if let Some(track) = track {
+        settings = settings.track(track);
+    }
+    let preloaded = match playback.preloaded.take() {
+        Some((item_id, path, Some(sound))) if item_id == id && path == file => Some(sound),
+        other => {
+            playback.preloaded = other;
+            None
+        }
+    };
+    let sound = match preloaded {
+        Some(mut sound) => {
+            info!("using the preloaded {}", file);
+            sound.settings = settings;
+            Ok(sound)
+        }
+        None => StreamingSoundData::from_file(&file, settings),
+    };
+    let sound = match sound {
+        Ok(sound) => sound,
+        Err(err) => {
+            edit_item(id, &mut |item| {
+                item.status = ItemStatus::Stopped;
+                let (msg, typ) = classify_from_file_err(&err);
+                item.report((typ, msg));
+                String::new()
+            });
+            return Err(err.into());
+        }
+    };
+    info!("passing {} to manager", file);
+    Ok(Handle::Streaming(match output {
+        Some((device, pair, master_volume)) => {
+            playback.output(&device, pair, master_volume)?.play(sound)?
+        }
+        None => playback.manager.play(sound)?,
+    }))
+}
+
+/// The sound up to the given position, in seconds, which kira loops round
+/// once it plays out.
+fn cut(sound: &StaticSoundData, end: f64) -> StaticSoundData {
+    let frames = (end * sound.sample_rate as f64).round() as usize;
+    let frames = frames.clamp(1, sound.frames.len());
+    StaticSoundData {
+        sample_rate: sound.sample_rate,
+        frames: Arc::new(sound.frames[..frames].to_vec()),
+        settings: sound.settings,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::headless::{Simulation, TestModel, SAMPLE};
+    use std::sync::mpsc::channel;
+
+    fn mock_playback() -> Playback<kira::manager::backend::mock::MockBackend> {
+        Playback::new(AudioManager::new(AudioManagerSettings::default()).unwrap())
+    }
+
+    #[test]
+    fn saved_output() {
+        let mut model = Model::default();
+        model.settings.audio_host = AudioHost::Jack;
+        model.settings.audio_device = Some("system".to_string());
+        let model = Arc::new(RwLock::new(model));
+        let (tx, _relayed) = channel();
+        let (notifications, _notified) = channel();
+        // the engine stops as soon as it's done with the messages
+        let (_, rx) = channel();
+
+        process_control_messages(tx, rx, notifications, model.clone());
+        let engine = &model.read().engine;
+        assert_eq!(engine.host, AudioHost::Jack);
+        assert_eq!(engine.output_device.as_deref(), Some("system"));
+    }
+
+    #[test]
+    fn file_not_found() -> Result<()> {
+        // create a temporary directory and try to play a nonexistent file from it
+        let path = {
+            let tempdir = tempfile::tempdir()?;
+            let path = tempdir
+                .path()
+                .join("nonexistent.wav")
+                .to_str()
+                .unwrap()
+                .to_string();
+            tempdir.close()?;
+            path
+        };
+        let model = {
+            let mut m = TestModel::new(3, 1.0).playing(SAMPLE).build();
+            m.items[0].stems[0].path = path;
+            m
+        };
+        let mut playback = mock_playback();
+
+        let msg = ControlMessage::Play(0);
+
+        let model = Arc::new(RwLock::new(model));
+        let (rx, _tx) = channel();
+        #[allow(unused_must_use)]
+        {
+            process_message(msg, &rx, &mut playback, &model);
+        }
+
+        let model = &*model.read();
+
+        assert_eq!(model.items[0].status, ItemStatus::Stopped);
+        assert_eq!(model.items[0].issues.len(), 1);
+        assert_eq!(model.items[0].issues[0].0, IssueType::MissingFile);
+        Ok(())
+    }
+
+    #[test]
+    fn retry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("unplugged.wav");
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        let sample = std::mem::replace(
+            &mut model.items[0].stems[0].path,
+            path.display().to_string(),
+        );
+        let model = Arc::new(RwLock::new(model));
+        let mut playback = mock_playback();
+        let (tx, _rx) = channel();
+
+        assert!(process_message(ControlMessage::Play(0), &tx, &mut playback, &model).is_err());
+        assert!(process_message(ControlMessage::Retry(0), &tx, &mut playback, &model).is_err());
+        // the same problem is only reported once
+        assert_eq!(model.read().items[0].issues.len(), 1);
+
+        std::fs::copy(sample, &path)?;
+        process_message(ControlMessage::Retry(0), &tx, &mut playback, &model)?;
+        assert!(model.read().items[0].issues.is_empty());
+        assert_eq!(model.read().items[0].status, ItemStatus::Stopped);
+        Ok(())
+    }
+
+    #[test]
+    fn play_and_pause() -> Result<()> {
+        let mut sim = Simulation::new(TestModel::new(3, 1.0).playing(SAMPLE).build())?;
+
+        sim.send(ControlMessage::Play(0))?;
+        sim.advance(Duration::from_millis(100))?;
+        assert_eq!(sim.model.read().items[0].status, ItemStatus::Playing);
+
+        sim.send(ControlMessage::Pause(0))?;
+        sim.advance(Duration::from_millis(100))?;
+        assert_eq!(sim.model.read().items[0].status, ItemStatus::Paused);
+
+        Ok(())
+    }
+
+    #[test]
+    fn play_out() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        model.items[0].trim = Some((0.25, 0.75));
+        model.items[0].fade_out = 0.25;
+        model.items[0].on_end = OnEnd::Play(1);
+        model.items[1].on_end = OnEnd::Loop;
+        model.items[1].loop_region = Some((0.25, 0.5));
+        let mut sim = Simulation::new(model)?;
+        let item = |sim: &Simulation, id: usize| sim.model.read().items[id].clone();
+
+        // the trimmed part starts a quarter of a second in
+        sim.send(ControlMessage::Play(0))?;
+        let mix = sim.advance(Duration::from_millis(300))?;
+        assert!(mix.iter().any(|frame| frame.left != 0.0));
+        assert_eq!(item(&sim, 0).status, ItemStatus::Playing);
+        assert!((item(&sim, 0).position - 0.55).abs() < 0.06);
+
+        // then fades out before its end, and hands over to the next item
+        let mix = sim.advance(Duration::from_millis(300))?;
+        let quiet = mix.len() / 2;
+        let peak = |frames: &[Frame]| frames.iter().map(|f| f.left.abs()).fold(0.0, f32::max);
+        assert!(peak(&mix[quiet - 2400..quiet]) < peak(&mix[..2400]));
+        assert_eq!(item(&sim, 0).status, ItemStatus::Stopped);
+        assert_eq!(item(&sim, 1).status, ItemStatus::Playing);
+
+        // which loops round its region for good
+        for _ in 0..10 {
+            sim.advance(Duration::from_millis(200))?;
+            assert_eq!(item(&sim, 1).status, ItemStatus::Playing);
+            assert!(item(&sim, 1).position < 0.55);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stop_paused() -> Result<()> {
+        let model = Arc::new(RwLock::new(TestModel::new(3, 1.0).playing(SAMPLE).build()));
+        let mut playback = mock_playback();
+        let (tx, _rx) = channel();
+
+        process_message(ControlMessage::Play(0), &tx, &mut playback, &model)?;
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        process_message(ControlMessage::Pause(0), &tx, &mut playback, &model)?;
+        model.write().items[0].position = 1.5;
+
+        process_message(ControlMessage::Stop(0), &tx, &mut playback, &model)?;
+        let item = model.read().items[0].clone();
+        assert_eq!(item.status, ItemStatus::Stopped);
+        assert_eq!((item.position, item.target_position), (0.0, 0.0));
+        assert!(playback.handles.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn play_and_stop() -> Result<()> {
+        let model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        let mut playback = mock_playback();
+
+        let model = Arc::new(RwLock::new(model));
+        let (rx, _tx) = channel();
+
+        process_message(ControlMessage::Play(0), &rx, &mut playback, &model)?;
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(model.read().items[0].status, ItemStatus::Playing);
+
+        process_message(ControlMessage::Stop(0), &rx, &mut playback, &model)?;
+        assert_eq!(model.read().items[0].status, ItemStatus::Stopped);
+        assert_eq!(model.read().items[0].target_position, 0.0);
+        assert!(playback.handles.is_empty());
+
+        let events: Vec<_> = model
+            .read()
+            .history
+            .iter()
+            .map(|entry| (entry.event, entry.item, entry.name.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (PlaybackEvent::Start, Some(0), "test 0".to_string()),
+                (PlaybackEvent::Stop, Some(0), "test 0".to_string()),
+            ]
+        );
+        assert!(model.read().history[0].duration.unwrap() >= 0.1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn preload_standby_cue() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        model
+            .playlists
+            .push(Playlist::new(5, "show".to_string(), vec![0, 1]));
+        model.selected_playlist = Some(5);
+        let mut playback = mock_playback();
+
+        let model = Arc::new(RwLock::new(model));
+        let (tx, _rx) = channel();
+        let sync = |playback: &mut Playback<_>| {
+            process_message(ControlMessage::SyncPlaybackStatus, &tx, playback, &model)
+        };
+
+        // nothing is preloaded before the playlist plays
+        sync(&mut playback)?;
+        assert!(playback.preloaded.is_none());
+
+        let go = model.write().fire_standby().unwrap();
+        process_message(go, &tx, &mut playback, &model)?;
+        sync(&mut playback)?;
+        assert!(matches!(playback.preloaded, Some((1, _, Some(_)))));
+
+        // the next cue takes the preloaded file, leaving nothing on standby
+        let go = model.write().fire_standby().unwrap();
+        process_message(go, &tx, &mut playback, &model)?;
+        assert!(playback.preloaded.is_none());
+        assert_eq!(model.read().items[1].status, ItemStatus::Playing);
+        sync(&mut playback)?;
+        assert!(playback.preloaded.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn play_pinned_from_memory() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        model.items[0].pinned = true;
+        let model = Arc::new(RwLock::new(model));
+        let mut playback = mock_playback();
+        let (tx, _rx) = channel();
+        let sync = |playback: &mut Playback<_>| {
+            process_message(ControlMessage::SyncPlaybackStatus, &tx, playback, &model)
+        };
+
+        for _ in 0..100 {
+            sync(&mut playback)?;
+            if model.read().engine.pinned_memory > 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert!(model.read().engine.pinned_memory > 0);
+
+        process_message(ControlMessage::Play(0), &tx, &mut playback, &model)?;
+        process_message(ControlMessage::Play(1), &tx, &mut playback, &model)?;
+        assert!(matches!(playback.handles[&0], Handle::Static(_)));
+        assert!(matches!(playback.handles[&1], Handle::Streaming(_)));
+
+        model.write().items[0].pinned = false;
+        sync(&mut playback)?;
+        assert!(playback.pinned.is_empty());
+        assert_eq!(model.read().engine.pinned_memory, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn coalescing() {
+        use ControlMessage::*;
+
+        let mut queue = VecDeque::new();
+        for msg in [
+            SyncPlaybackStatus,
+            Seek(1, 1.0),
+            SetVolume(2, 0.5),
+            Seek(1, 2.0),
+            SetMasterVolume(0.5),
+            SyncPlaybackStatus,
+            SetVolume(2, 0.25),
+            Play(1),
+            Seek(1, 3.0),
+            SetMasterVolume(1.0),
+        ] {
+            assert!(coalesce(&mut queue, msg));
+        }
+        assert_eq!(
+            Vec::from(queue.clone()),
+            vec![
+                Seek(1, 2.0),
+                SetVolume(2, 0.25),
+                SyncPlaybackStatus,
+                Play(1),
+                Seek(1, 3.0),
+                SetMasterVolume(1.0),
+            ]
+        );
+
+        while queue.len() < CONTROL_QUEUE_CAPACITY {
+            queue.push_back(Play(1));
+        }
+        assert!(!coalesce(&mut queue, Stop(1)));
+        assert!(coalesce(&mut queue, SyncPlaybackStatus));
+    }
+
+    #[test]
+    fn forwarding() {
+        let model = Arc::new(RwLock::new(Model::default()));
+        let (tx, rx) = channel();
+        let (engine_tx, engine_rx) = std::sync::mpsc::sync_channel(2);
+
+        // the playback thread is busy until the whole lot has been sent
+        for position in 0..5 {
+            tx.send(ControlMessage::Seek(1, position as f64)).unwrap();
+        }
+        tx.send(ControlMessage::Play(2)).unwrap();
+        tx.send(ControlMessage::SyncPlaybackStatus).unwrap();
+        drop(tx);
+        let forwarder = {
+            let model = model.clone();
+            std::thread::spawn(move || {
+                let mut seen = 0;
+                forward(rx, engine_tx, &model, |_| seen += 1);
+                seen
+            })
+        };
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(
+            engine_rx.iter().collect::<Vec<_>>(),
+            vec![
+                ControlMessage::Seek(1, 4.0),
+                ControlMessage::Play(2),
+                ControlMessage::SyncPlaybackStatus,
+            ]
+        );
+        assert_eq!(forwarder.join().unwrap(), 7);
+    }
+
+    #[test]
+    fn recover_output() -> Result<()> {
+        let model = Arc::new(RwLock::new(TestModel::new(3, 1.0).playing(SAMPLE).build()));
+        let mut playback = mock_playback();
+        let (tx, _rx) = channel();
+
+        let recover = ControlMessage::RecoverOutput;
+        assert!(process_message(recover.clone(), &tx, &mut playback, &model).is_err());
+
+        playback.reopen = Box::new(|| {
+            AudioManager::new(AudioManagerSettings::default())
+                .map_err(|_| anyhow::anyhow!("failed to create a mock manager"))
+        });
+        process_message(ControlMessage::Play(0), &tx, &mut playback, &model)?;
+        process_message(recover, &tx, &mut playback, &model)?;
+        assert_eq!(model.read().items[0].status, ItemStatus::Paused);
+        assert!(playback.handles.is_empty());
+
+        process_message(ControlMessage::Play(0), &tx, &mut playback, &model)?;
+        assert_eq!(model.read().items[0].status, ItemStatus::Playing);
+        assert_eq!(playback.handles.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_targets() -> Result<()> {
+        let model = Arc::new(RwLock::new(TestModel::new(3, 1.0).playing(SAMPLE).build()));
+        let mut playback = mock_playback();
+        let (tx, _rx) = channel();
+
+        process_message(ControlMessage::Play(0), &tx, &mut playback, &model)?;
+        model.write().items.retain(|item| item.id == 2);
+        for msg in [
+            ControlMessage::Mute(0, true),
+            ControlMessage::SeekRelative(1, 5.0),
+            ControlMessage::Play(1),
+            ControlMessage::AddToPlaylist {
+                item_id: 2,
+                playlist_id: 5,
+            },
+            ControlMessage::RemoveFromPlaylist {
+                pos_within_playlist: 0,
+                playlist_id: 5,
+            },
+        ] {
+            assert!(process_message(msg, &tx, &mut playback, &model).is_err());
+        }
+        process_message(ControlMessage::GlobalPause, &tx, &mut playback, &model)?;
+        process_message(ControlMessage::GlobalStop, &tx, &mut playback, &model)?;
+        assert!(playback.handles.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn watchdog() {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        model.items[0].output = Some("broken".to_string());
+        let model = Arc::new(RwLock::new(model));
+        let (tx, rx) = channel();
+        let (notifications, notified) = channel();
+        let engine = {
+            let model = model.clone();
+            std::thread::spawn(move || {
+                let mut playback = mock_playback();
+                playback.open_output = |_, _| panic!("the output exploded");
+                playback.reopen = Box::new(|| {
+                    AudioManager::new(AudioManagerSettings::default())
+                        .map_err(|_| anyhow::anyhow!("failed to create a mock manager"))
+                });
+                let (own_tx, _own_rx) = channel();
+                supervise(&own_tx, &rx, &notifications, &model, &mut playback);
+                playback.handles.len()
+            })
+        };
+
+        tx.send(ControlMessage::Play(1)).unwrap();
+        tx.send(ControlMessage::Play(0)).unwrap();
+        tx.send(ControlMessage::Play(2)).unwrap();
+        drop(tx);
+        // item 1 was paused when the output was reopened, item 2 played after
+        assert_eq!(engine.join().unwrap(), 1);
+        let notification = notified.try_recv().unwrap();
+        assert!(notification.message.contains("the output exploded"));
+        let model = model.read();
+        assert_eq!(model.items[1].status, ItemStatus::Paused);
+        assert_eq!(model.items[2].status, ItemStatus::Playing);
+    }
+
+    #[test]
+    fn play_together() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        model.items[2].output = Some("monitor".to_string());
+        let mut playback = mock_playback();
+        playback.open_output = |_, _| {
+            let manager = AudioManager::<kira::manager::backend::mock::MockBackend>::new(
+                AudioManagerSettings::default(),
+            );
+            Ok(Box::new(manager.unwrap()))
+        };
+        let model = Arc::new(RwLock::new(model));
+        let (tx, _rx) = channel();
+
+        let msg = ControlMessage::PlayTogether(vec![0, 1, 2]);
+        process_message(msg, &tx, &mut playback, &model)?;
+        assert_eq!(playback.handles.len(), 3);
+        assert!(playback.clock.is_some());
+        assert!(playback.together.is_none());
+        // the mock clock never ticks, so the items are still waiting to start
+        assert!(playback
+            .handles
+            .values()
+            .all(|handle| handle.position() == 0.0));
+        let model = model.read();
+        assert!(model
+            .items
+            .iter()
+            .all(|item| item.status == ItemStatus::Playing));
+        Ok(())
+    }
+
+    #[test]
+    fn loop_region() -> Result<()> {
+        let model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        let mut playback = mock_playback();
+
+        let model = Arc::new(RwLock::new(model));
+        let (rx, _tx) = channel();
+
+        let region = Some((0.25, 0.5));
+        let msg = ControlMessage::SetLoopRegion(0, region);
+        process_message(msg, &rx, &mut playback, &model)?;
+        assert_eq!(model.read().items[0].loop_region, region);
+        assert_eq!(model.read().items[0].loop_bounds(), (0.25, 0.5));
+
+        let msg = ControlMessage::SetLoopRegion(0, None);
+        process_message(msg, &rx, &mut playback, &model)?;
+        assert_eq!(model.read().items[0].loop_bounds(), (0.0, 1.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn play_many() -> Result<()> {
+        let mut sim = Simulation::new(TestModel::new(3, 1.0).playing(SAMPLE).build())?;
+        let model = sim.model.clone();
+
+        sim.send(ControlMessage::Play(0))?;
+        sim.send(ControlMessage::Play(1))?;
+        sim.send(ControlMessage::Play(2))?;
+        sim.advance(Duration::from_millis(100))?;
+        assert_eq!(model.read().items[0].status, ItemStatus::Playing);
+        assert_eq!(model.read().items[1].status, ItemStatus::Playing);
+        assert_eq!(model.read().items[2].status, ItemStatus::Playing);
+
+        sim.send(ControlMessage::GlobalPause)?;
+        sim.advance(Duration::from_millis(100))?;
+        assert_eq!(model.read().items[0].status, ItemStatus::Paused);
+        assert_eq!(model.read().items[1].status, ItemStatus::Paused);
+        assert_eq!(model.read().items[2].status, ItemStatus::Paused);
+
+        sim.send(ControlMessage::GlobalStop)?;
+        sim.advance(Duration::from_millis(100))?;
+        assert_eq!(model.read().items[0].status, ItemStatus::Stopped);
+        assert_eq!(model.read().items[1].status, ItemStatus::Stopped);
+        assert_eq!(model.read().items[2].status, ItemStatus::Stopped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn global_resume() -> Result<()> {
+        let mut sim = Simulation::new(TestModel::new(3, 1.0).playing(SAMPLE).build())?;
+        let model = sim.model.clone();
+        let status = |id: usize| model.read().items[id].status.clone();
+
+        for id in 0..3 {
+            sim.send(ControlMessage::Play(id))?;
+        }
+        sim.send(ControlMessage::Pause(1))?;
+        sim.advance(Duration::from_millis(100))?;
+        sim.send(ControlMessage::GlobalPause)?;
+        let mut paused = model.read().engine.paused_together.clone();
+        paused.sort();
+        assert_eq!(paused, vec![0, 2]);
+        // pausing again, say as the system goes to sleep, forgets nothing
+        sim.send(ControlMessage::GlobalPause)?;
+        sim.advance(Duration::from_millis(100))?;
+
+        sim.send(ControlMessage::GlobalResume)?;
+        sim.advance(Duration::from_millis(100))?;
+        assert_eq!(status(0), ItemStatus::Playing);
+        assert_eq!(status(1), ItemStatus::Paused);
+        assert_eq!(status(2), ItemStatus::Playing);
+        assert!(model.read().engine.paused_together.is_empty());
+
+        // items pick up where they were after the output was reopened
+        sim.send(ControlMessage::GlobalPause)?;
+        sim.send(ControlMessage::RecoverOutput)?;
+        assert!(sim.playback.handles.is_empty());
+        sim.send(ControlMessage::GlobalResume)?;
+        assert_eq!(status(0), ItemStatus::Playing);
+        assert_eq!(status(1), ItemStatus::Paused);
+        assert_eq!(sim.playback.handles.len(), 2);
+
+        // stopping everything leaves nothing to resume
+        sim.send(ControlMessage::GlobalPause)?;
+        sim.send(ControlMessage::GlobalStop)?;
+        sim.send(ControlMessage::GlobalResume)?;
+        assert!(sim.playback.handles.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn fade_out_all() -> Result<()> {
+        let mut sim = Simulation::new(TestModel::new(3, 1.0).playing(SAMPLE).build())?;
+        sim.send(ControlMessage::Play(0))?;
+        sim.advance(Duration::from_millis(100))?;
+
+        sim.send(ControlMessage::GlobalFadeOut(0.5))?;
+        assert_eq!(sim.model.read().items[0].status, ItemStatus::Stopped);
+        assert!(sim.playback.handles.is_empty());
+        // still audible while fading
+        let mix = sim.advance(Duration::from_millis(200))?;
+        assert!(mix.iter().any(|frame| frame.left != 0.0));
+        sim.advance(Duration::from_millis(400))?;
+        let mix = sim.advance(Duration::from_millis(100))?;
+        assert!(mix.iter().all(|frame| frame.left == 0.0));
+        Ok(())
+    }
+
+    #[test]
+    fn starting_a_playlist_stops_others() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        model.playlists = vec![
+            Playlist::new(7, "act 1".to_string(), vec![0]),
+            Playlist::new(8, "act 2".to_string(), vec![1]),
+            Playlist::new(9, "empty".to_string(), vec![]),
+        ];
+        let mut pads = Playlist::new(10, "pads".to_string(), vec![2]);
+        pads.grid = Some(Grid::new(4));
+        model.playlists.push(pads);
+        model.items[2].source_playlist = Some(10);
+        let model = Arc::new(RwLock::new(model));
+        let mut playback = mock_playback();
+        let (tx, _rx) = channel();
+
+        let status = |id: usize| model.read().items[id].status.clone();
+        process_message(
+            ControlMessage::PlayFromPlaylist(7),
+            &tx,
+            &mut playback,
+            &model,
+        )?;
+        process_message(ControlMessage::Play(2), &tx, &mut playback, &model)?;
+        assert_eq!(model.read().playing_playlist, Some(7));
+        assert_eq!(status(0), ItemStatus::Playing);
+
+        // an empty playlist changes nothing
+        process_message(
+            ControlMessage::PlayFromPlaylist(9),
+            &tx,
+            &mut playback,
+            &model,
+        )?;
+        assert_eq!(model.read().playing_playlist, Some(7));
+        assert_eq!(status(0), ItemStatus::Playing);
+
+        process_message(
+            ControlMessage::PlayFromPlaylist(8),
+            &tx,
+            &mut playback,
+            &model,
+        )?;
+        assert_eq!(model.read().playing_playlist, Some(8));
+        assert_eq!(status(0), ItemStatus::Stopped);
+        assert_eq!(status(1), ItemStatus::Playing);
+        // the soundboard carries on
+        assert_eq!(status(2), ItemStatus::Playing);
+
+        model.write().settings.stop_pads_with_playlists = true;
+        process_message(
+            ControlMessage::PlayFromPlaylist(7),
+            &tx,
+            &mut playback,
+            &model,
+        )?;
+        assert_eq!(status(0), ItemStatus::Playing);
+        assert_eq!(status(1), ItemStatus::Stopped);
+        assert_eq!(status(2), ItemStatus::Stopped);
+        assert_eq!(playback.handles.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn playlists_play_through_buses() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        model.items[0].source_playlist = Some(7);
+        model.crossfader = Crossfader {
+            deck_a: Some(7),
+            deck_b: Some(8),
+            position: 0.25,
+            curve: CrossfadeCurve::Linear,
+        };
+        let mut playback = mock_playback();
+
+        let model = Arc::new(RwLock::new(model));
+        let (rx, _tx) = channel();
+
+        process_message(ControlMessage::Play(0), &rx, &mut playback, &model)?;
+        process_message(ControlMessage::Play(1), &rx, &mut playback, &model)?;
+        assert_eq!(playback.buses.len(), 1);
+        assert!(playback.buses.contains_key(&7));
+
+        let crossfader = model.read().crossfader.clone();
+        assert_eq!(crossfader.gain_for(7), 0.75);
+        assert_eq!(crossfader.gain_for(8), 0.25);
+        assert_eq!(crossfader.gain_for(9), 1.0);
+        let msg = ControlMessage::Crossfade(crossfader);
+        process_message(msg, &rx, &mut playback, &model)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn crossfading_playlists() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        let mut playlist = Playlist::new(7, "music".to_string(), vec![0, 1]);
+        playlist.crossfade = 1.0;
+        model.playlists.push(playlist);
+        model.items[0].source_playlist = Some(7);
+        model.items[1].source_playlist = Some(7);
+        let model = Arc::new(RwLock::new(model));
+        let mut playback = mock_playback();
+        let (tx, _rx) = channel();
+
+        process_message(ControlMessage::Play(0), &tx, &mut playback, &model)?;
+        process_message(ControlMessage::Play(2), &tx, &mut playback, &model)?;
+        process_message(ControlMessage::Play(1), &tx, &mut playback, &model)?;
+        let mut playing: Vec<_> = playback.handles.keys().copied().collect();
+        playing.sort();
+        assert_eq!(playing, vec![1, 2]);
+        assert_eq!(model.read().items[0].status, ItemStatus::Stopped);
+        Ok(())
+    }
+
+    #[test]
+    fn live_spectrum() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        model.settings.live_spectrum = true;
+        model.items[1].source_playlist = Some(7);
+        model
+            .playlists
+            .push(Playlist::new(7, "music".to_string(), vec![1]));
+        let model = Arc::new(RwLock::new(model));
+        let mut playback = mock_playback();
+        let (tx, _rx) = channel();
+
+        process_message(ControlMessage::Play(0), &tx, &mut playback, &model)?;
+        process_message(ControlMessage::Play(1), &tx, &mut playback, &model)?;
+        assert_eq!(playback.analysers.len(), 2);
+        assert_eq!(playback.buses.len(), 1);
+
+        model.write().items[0].spectrum = vec![1, 2, 3];
+        process_message(ControlMessage::Stop(0), &tx, &mut playback, &model)?;
+        process_message(
+            ControlMessage::SyncPlaybackStatus,
+            &tx,
+            &mut playback,
+            &model,
+        )?;
+        assert_eq!(playback.analysers.keys().collect::<Vec<_>>(), vec![&1]);
+        assert!(model.read().items[0].spectrum.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn items_route_to_outputs() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        model.items[0].output = Some("monitor".to_string());
+        model.items[1].source_playlist = Some(7);
+        let mut playlist = Playlist::new(7, "music".to_string(), vec![1]);
+        playlist.output = Some("stream".to_string());
+        playlist.output_pair = 1;
+        model.playlists.push(playlist);
+        let mut playback = mock_playback();
+        playback.open_output = |_, _| {
+            let manager = AudioManager::<kira::manager::backend::mock::MockBackend>::new(
+                AudioManagerSettings::default(),
+            );
+            Ok(Box::new(manager.unwrap()))
+        };
+
+        let model = Arc::new(RwLock::new(model));
+        let (rx, _tx) = channel();
+
+        process_message(ControlMessage::Play(0), &rx, &mut playback, &model)?;
+        process_message(ControlMessage::Play(1), &rx, &mut playback, &model)?;
+        process_message(ControlMessage::Play(2), &rx, &mut playback, &model)?;
+        assert_eq!(playback.handles.len(), 3);
+        let mut outputs: Vec<_> = playback.outputs.keys().cloned().collect();
+        outputs.sort();
+        let key = |device: &str, pair| (device.to_string(), pair);
+        assert_eq!(outputs, vec![key("monitor", 0), key("stream", 1)]);
+        assert!(playback.buses.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn seek_relative() -> Result<()> {
+        use approx::assert_relative_eq;
+
+        let mut sim = Simulation::new(TestModel::new(3, 1.0).playing(SAMPLE).build())?;
+        let position = |sim: &Simulation| sim.model.read().items[0].target_position;
+
+        sim.send(ControlMessage::Play(0))?;
+        sim.advance(Duration::from_millis(100))?;
+        sim.send(ControlMessage::SeekRelative(0, 0.5))?;
+        sim.advance(Duration::from_millis(100))?;
+        assert_relative_eq!(position(&sim), 0.7, epsilon = 0.02);
+
+        // skipping back from the start stays there
+        sim.send(ControlMessage::SeekRelative(0, -5.0))?;
+        sim.advance(Duration::from_millis(100))?;
+        assert_relative_eq!(position(&sim), 0.1, epsilon = 0.02);
+
+        // a paused item moves right away
+        sim.send(ControlMessage::Pause(0))?;
+        sim.advance(Duration::from_millis(100))?;
+        sim.send(ControlMessage::SeekRelative(0, 0.25))?;
+        assert_relative_eq!(position(&sim), 0.35, epsilon = 0.03);
+        Ok(())
+    }
+
+    #[test]
+    fn seek() -> Result<()> {
+        use approx::assert_relative_eq;
+
+        let model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        let mut playback = Playback::headless()?;
+
+        let model = Arc::new(RwLock::new(model));
+        let (rx, _tx) = channel();
+
+        process_message(ControlMessage::Play(0), &rx, &mut playback, &model)?;
+        playback.advance(Duration::from_millis(100));
+        assert_eq!(model.read().items[0].status, ItemStatus::Playing);
+
+        process_message(ControlMessage::Seek(0, 1.5), &rx, &mut playback, &model)?;
+        let mix = playback.advance(Duration::from_millis(500));
+        assert!(mix.iter().any(|frame| frame.left != 0.0));
+        process_message(
+            ControlMessage::SyncPlaybackStatus,
+            &rx,
+            &mut playback,
+            &model,
+        )?;
+        assert_eq!(model.read().items[0].status, ItemStatus::Playing);
+        assert_relative_eq!(model.read().items[0].target_position, 2.0, epsilon = 0.02);
+
+        process_message(ControlMessage::Pause(0), &rx, &mut playback, &model)?;
+        playback.advance(Duration::from_millis(500));
+        process_message(
+            ControlMessage::SyncPlaybackStatus,
+            &rx,
+            &mut playback,
+            &model,
+        )?;
+        assert_eq!(model.read().items[0].status, ItemStatus::Paused);
+        assert_relative_eq!(model.read().items[0].target_position, 2.0, epsilon = 0.05);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cues_follow_on() -> Result<()> {
+        let mut model = TestModel::new(3, 1.0).playing(SAMPLE).build();
+        let mut extra = model.items[2].clone();
+        extra.id = 3;
+        model.items.push(extra);
+        let mut playlist = Playlist::new(5, "show".to_string(), vec![0, 1, 2, 3]);
+        playlist.follows = vec![Follow::After(0.0), Follow::End, Follow::After(0.05)];
+        model.playlists.push(playlist);
+        model.selected_playlist = Some(5);
+        let mut sim = Simulation::new(model)?;
+        let model = sim.model.clone();
+        let status = |id: usize| model.read().items[id].status.clone();
+
+        // the first two cues fire together, the third waits for the second
+        let go = model.write().fire_standby().unwrap();
+        sim.send(go)?;
+        assert_eq!(status(0), ItemStatus::Playing);
+        assert_eq!(status(1), ItemStatus::Playing);
+        assert_eq!(status(2), ItemStatus::Stopped);
+        sim.send(ControlMessage::SyncPlaybackStatus)?;
+        assert_eq!(model.read().engine.follows, vec![(5, None)]);
+
+        sim.send(ControlMessage::Stop(1))?;
+        sim.send(ControlMessage::SyncPlaybackStatus)?;
+        assert_eq!(status(2), ItemStatus::Playing);
+        assert_eq!(model.read().playlists[0].standby, 3);
+        let follows = model.read().engine.follows.clone();
+        assert!(matches!(follows[..], [(5, Some(wait))] if (wait - 0.05).abs() < 1e-9));
+
+        sim.advance(Duration::from_millis(60))?;
+        assert_eq!(status(3), ItemStatus::Playing);
+        assert!(model.read().engine.follows.is_empty());
+
+        // stopping everything cancels the sequence
+        model.write().playlists[0].standby = 2;
+        let go = model.write().fire_standby().unwrap();
+        sim.send(go)?;
+        sim.send(ControlMessage::GlobalStop)?;
+        sim.advance(Duration::from_millis(60))?;
+        assert_eq!(status(3), ItemStatus::Stopped);
+        assert_eq!(model.read().playlists[0].standby, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn loop_end_to_the_frame() -> Result<()> {
+        // a ramp, so that every frame played tells where in the file it's from
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ramp.wav");
+        let frames: Vec<i16> = (0..28_800).map(|i| i as i16).collect();
+        let mut wav = b"RIFF".to_vec();
+        wav.extend((36 + 2 * frames.len() as u32).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(48_000u32.to_le_bytes());
+        wav.extend((2 * 48_000u32).to_le_bytes());
+        wav.extend(2u16.to_le_bytes());
+        wav.extend(16u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend((2 * frames.len() as u32).to_le_bytes());
+        wav.extend(frames.iter().flat_map(|frame| frame.to_le_bytes()));
+        std::fs::write(&path, wav)?;
+
+        let path = path.display().to_string();
+        let mut item = Item::with_default_stem(0, "ramp".to_string(), path, Colour::BLACK, 0.6);
+        item.on_end = OnEnd::Loop;
+        item.loop_region = Some((0.25, 0.5));
+        let mut sim = Simulation::new(Model {
+            items: vec![item],
+            ..Model::default()
+        })?;
+        sim.send(ControlMessage::Play(0))?;
+        let mix = sim.advance(Duration::from_millis(1200))?;
+
+        // playback jumps from the last frame of the region to its first
+        let jumps: Vec<_> = mix
+            .windows(2)
+            .filter(|pair| pair[1].left < pair[0].left)
+            .collect();
+        assert_eq!(jumps.len(), 3);
+        for pair in jumps {
+            let frame = pair[0].left / pair[1].left * 12_000.0;
+            assert!((frame - 23_999.0).abs() <= 1.0, "looped at frame {}", frame);
+        }
+
+        // and the region can be moved while it plays
+        sim.send(ControlMessage::SetLoopRegion(0, Some((0.25, 0.4))))?;
+        sim.advance(Duration::from_millis(50))?;
+        let mix = sim.advance(Duration::from_millis(600))?;
+        let jumps: Vec<_> = mix
+            .windows(2)
+            .filter(|pair| pair[1].left < pair[0].left)
+            .collect();
+        assert_eq!(jumps.len(), 4);
+        for pair in jumps {
+            let frame = pair[0].left / pair[1].left * 12_000.0;
+            assert!((frame - 19_199.0).abs() <= 1.0, "looped at frame {}", frame);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+//! Sounds generated on the fly rather than read from a file: test signals,
+//! for checking lines and tuning a venue without hunting for test files, and
+//! internet radio. A [`GeneratorData`] is played like any other sound and
+//! controlled through the [`GeneratorHandle`] it returns.
+
+use crate::model::Signal;
+use kira::clock::clock_info::{ClockInfoProvider, WhenToStart};
+use kira::dsp::Frame;
+use kira::sound::static_sound::PlaybackState;
+use kira::sound::streaming::StreamingSoundSettings;
+use kira::sound::{Sound, SoundData};
+use kira::track::TrackId;
+use kira::tween::{Tween, Tweener};
+use kira::{StartTime, Volume};
+use parking_lot::Mutex;
+use std::convert::Infallible;
+use std::f64::consts::TAU;
+use std::sync::Arc;
+
+/// The RMS level of every signal, in dBFS. A common alignment level, which
+/// leaves plenty of headroom on any desk.
+pub const LEVEL_DB: f64 = -18.0;
+/// The frequency of a new tone, in hertz.
+pub const DEFAULT_FREQUENCY: f64 = 1000.0;
+/// How long a new generated item plays for, in seconds.
+pub const DEFAULT_DURATION: f64 = 60.0;
+/// The RMS level of the pink noise filter fed with full scale white noise.
+const PINK_RMS: f64 = 1.71;
+
+enum Command {
+    SetVolume(Volume, Tween),
+    Pause(Tween),
+    Resume(Tween),
+    Stop(Tween),
+    SeekTo(f64),
+}
+
+/// The commands waiting for the audio thread and what it last reported
+/// back. The audio thread only ever tries the lock, so that it never waits.
+struct Shared {
+    commands: Vec<Command>,
+    state: PlaybackState,
+    position: f64,
+    error: Option<String>,
+}
+
+/// Where the samples of a generated sound come from.
+pub(crate) trait Source: Send {
+    /// Called before each batch of samples, for work that shouldn't be done
+    /// for every sample.
+    fn prepare(&mut self) {}
+
+    /// The next frame, `dt` seconds after the last one.
+    fn next(&mut self, dt: f64) -> Frame;
+
+    /// Whether the source has run dry for good.
+    fn ended(&self) -> bool {
+        false
+    }
+
+    /// What went wrong, if the source ended early.
+    fn error(&self) -> Option<String> {
+        None
+    }
+}
+
+/// A sound ready to be played. Of the settings, the start time and
+/// position, the volume, the fade-in, the loop and the track are honoured.
+pub struct GeneratorData {
+    source: Box<dyn Source>,
+    /// How long the sound plays for, in seconds. Sounds that can't be sought
+    /// through, such as radio streams, go on for ever.
+    duration: f64,
+    pub settings: StreamingSoundSettings,
+}
+
+impl GeneratorData {
+    pub fn new(signal: Signal, duration: f64, settings: StreamingSoundSettings) -> Self {
+        let tone = Tone {
+            signal,
+            phase: 0.0,
+            noise: Noise::default(),
+        };
+        Self::from_source(Box::new(tone), duration, settings)
+    }
+
+    pub(crate) fn from_source(
+        source: Box<dyn Source>,
+        duration: f64,
+        settings: StreamingSoundSettings,
+    ) -> Self {
+        Self {
+            source,
+            duration,
+            settings,
+        }
+    }
+}
+
+impl SoundData for GeneratorData {
+    type Error = Infallible;
+    type Handle = GeneratorHandle;
+
+    fn into_sound(self) -> Result<(Box<dyn Sound>, GeneratorHandle), Infallible> {
+        let shared = Arc::new(Mutex::new(Shared {
+            commands: vec![],
+            state: PlaybackState::Playing,
+            position: self.settings.start_position,
+            error: None,
+        }));
+        let fade = match self.settings.fade_in_tween {
+            Some(tween) => {
+                let mut fade = Tweener::new(0.0);
+                fade.set(1.0, tween);
+                fade
+            }
+            None => Tweener::new(1.0),
+        };
+        let generator = Generator {
+            source: self.source,
+            duration: self.duration,
+            loop_start: self
+                .settings
+                .loop_behavior
+                .map(|behavior| behavior.start_position),
+            track: self.settings.track,
+            start_time: self.settings.start_time,
+            started: false,
+            state: PlaybackState::Playing,
+            reported: false,
+            position: self.settings.start_position,
+            volume: Tweener::new(self.settings.volume),
+            fade,
+            shared: shared.clone(),
+        };
+        Ok((Box::new(generator), GeneratorHandle(shared)))
+    }
+}
+
+/// Controls a playing signal, much like the handles of kira's own sounds.
+pub struct GeneratorHandle(Arc<Mutex<Shared>>);
+
+impl GeneratorHandle {
+    pub fn state(&self) -> PlaybackState {
+        self.0.lock().state
+    }
+
+    /// The position within the signal, in seconds.
+    pub fn position(&self) -> f64 {
+        self.0.lock().position
+    }
+
+    /// What went wrong, if the sound stopped early.
+    pub fn error(&self) -> Option<String> {
+        self.0.lock().error.clone()
+    }
+
+    pub fn set_volume(&mut self, volume: impl Into<Volume>, tween: Tween) {
+        self.send(Command::SetVolume(volume.into(), tween));
+    }
+
+    pub fn pause(&mut self, tween: Tween) {
+        self.send(Command::Pause(tween));
+    }
+
+    pub fn resume(&mut self, tween: Tween) {
+        self.send(Command::Resume(tween));
+    }
+
+    pub fn stop(&mut self, tween: Tween) {
+        self.send(Command::Stop(tween));
+    }
+
+    pub fn seek_to(&mut self, position: f64) {
+        self.send(Command::SeekTo(position));
+    }
+
+    fn send(&mut self, command: Command) {
+        self.0.lock().commands.push(command);
+    }
+}
+
+/// White noise from a xorshift generator, and pink noise filtered out of it
+/// with Paul Kellet's refined filter.
+struct Noise {
+    seed: u64,
+    pink: [f64; 7],
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            seed: 0x2545_f491_4f6c_dd1d,
+            pink: [0.0; 7],
+        }
+    }
+}
+
+impl Noise {
+    /// The next sample of white noise, between -1 and 1.
+    fn white(&mut self) -> f64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    fn pink(&mut self) -> f64 {
+        let white = self.white();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b.iter().sum::<f64>() + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink
+    }
+}
+
+struct Generator {
+    source: Box<dyn Source>,
+    duration: f64,
+    loop_start: Option<f64>,
+    track: TrackId,
+    start_time: StartTime,
+    started: bool,
+    state: PlaybackState,
+    /// Whether the handle has been told the signal stopped, after which it
+    /// can be dropped.
+    reported: bool,
+    position: f64,
+    volume: Tweener<Volume>,
+    /// Goes down to silence on pausing and stopping, and back up on resuming.
+    fade: Tweener,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Generator {
+    fn apply(&mut self, command: Command) {
+        match command {
+            Command::SetVolume(volume, tween) => self.volume.set(volume, tween),
+            Command::Pause(tween) if self.state != PlaybackState::Stopped => {
+                self.state = PlaybackState::Pausing;
+                self.fade.set(0.0, tween);
+            }
+            Command::Resume(tween) if self.state != PlaybackState::Stopped => {
+                self.state = PlaybackState::Playing;
+                self.fade.set(1.0, tween);
+            }
+            Command::Stop(tween) if self.state != PlaybackState::Stopped => {
+                self.state = PlaybackState::Stopping;
+                self.fade.set(0.0, tween);
+            }
+            Command::SeekTo(position) if self.duration.is_finite() => {
+                self.position = position.clamp(0.0, self.duration)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Generates a test signal.
+struct Tone {
+    signal: Signal,
+    /// How far through its cycle the sine tone is, from 0 to 1.
+    phase: f64,
+    noise: Noise,
+}
+
+impl Source for Tone {
+    fn next(&mut self, dt: f64) -> Frame {
+        let scale = 10f64.powf(LEVEL_DB / 20.0);
+        let sample = match self.signal {
+            Signal::Sine(frequency) => {
+                let sample = (self.phase * TAU).sin();
+                self.phase = (self.phase + frequency * dt).fract();
+                sample * scale * 2f64.sqrt()
+            }
+            Signal::WhiteNoise => self.noise.white() * scale * 3f64.sqrt(),
+            Signal::PinkNoise => self.noise.pink() * scale / PINK_RMS,
+        };
+        Frame::from_mono(sample as f32)
+    }
+}
+
+impl Sound for Generator {
+    fn track(&mut self) -> TrackId {
+        self.track
+    }
+
+    fn on_start_processing(&mut self) {
+        self.source.prepare();
+        if self.source.ended() && self.state != PlaybackState::Stopped {
+            self.state = PlaybackState::Stopped;
+        }
+        let Some(mut shared) = self.shared.try_lock() else {
+            return;
+        };
+        let commands = std::mem::take(&mut shared.commands);
+        shared.state = self.state;
+        shared.position = self.position;
+        if self.state == PlaybackState::Stopped && !self.reported {
+            shared.error = self.source.error();
+        }
+        self.reported = self.state == PlaybackState::Stopped;
+        drop(shared);
+        for command in commands {
+            self.apply(command);
+        }
+    }
+
+    fn process(&mut self, dt: f64, clock_info_provider: &ClockInfoProvider) -> Frame {
+        if !self.started {
+            match clock_info_provider.when_to_start(self.start_time) {
+                WhenToStart::Now => self.started = true,
+                WhenToStart::Later => return Frame::ZERO,
+                WhenToStart::Never => self.state = PlaybackState::Stopped,
+            }
+        }
+        if self.state == PlaybackState::Stopped {
+            return Frame::ZERO;
+        }
+
+        self.volume.update(dt, clock_info_provider);
+        if self.fade.update(dt, clock_info_provider) {
+            match self.state {
+                PlaybackState::Pausing => self.state = PlaybackState::Paused,
+                PlaybackState::Stopping => self.state = PlaybackState::Stopped,
+                _ => (),
+            }
+        }
+        if matches!(self.state, PlaybackState::Paused | PlaybackState::Stopped) {
+            return Frame::ZERO;
+        }
+
+        let gain = self.volume.value().as_amplitude() * self.fade.value();
+        let frame = self.source.next(dt) * gain as f32;
+        self.position += dt;
+        if self.position >= self.duration {
+            match self.loop_start {
+                Some(start) if start < self.duration => {
+                    self.position = start + (self.position - self.duration)
+                }
+                _ => self.state = PlaybackState::Stopped,
+            }
+        }
+        frame
+    }
+
+    fn finished(&self) -> bool {
+        self.reported
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kira::clock::clock_info::MockClockInfoProviderBuilder;
+    use kira::LoopBehavior;
+
+    fn render(data: GeneratorData, frames: usize) -> (Vec<f32>, GeneratorHandle) {
+        let dt = 1.0 / 48_000.0;
+        let clock_info_provider = MockClockInfoProviderBuilder::new(0).build();
+        let (mut sound, handle) = data.into_sound().unwrap();
+        let samples = (0..frames)
+            .map(|_| sound.process(dt, &clock_info_provider).left)
+            .collect();
+        sound.on_start_processing();
+        (samples, handle)
+    }
+
+    fn rms_db(samples: &[f32]) -> f64 {
+        let power = samples.iter().map(|s| (s * s) as f64).sum::<f64>() / samples.len() as f64;
+        10.0 * power.log10()
+    }
+
+    #[test]
+    fn levels() {
+        for signal in [Signal::Sine(1000.0), Signal::WhiteNoise, Signal::PinkNoise] {
+            let data = GeneratorData::new(signal, 10.0, StreamingSoundSettings::new());
+            let (samples, handle) = render(data, 96_000);
+            assert!(
+                (rms_db(&samples) - LEVEL_DB).abs() < 0.5,
+                "{signal} is at {} dB",
+                rms_db(&samples)
+            );
+            assert!(samples.iter().all(|s| s.abs() < 1.0));
+            assert_eq!(handle.state(), PlaybackState::Playing);
+            assert!((handle.position() - 2.0).abs() < 1e-6);
+        }
+
+        // a 1 kHz tone crosses zero twice a millisecond
+        let data = GeneratorData::new(Signal::Sine(1000.0), 1.0, StreamingSoundSettings::new());
+        let (samples, _) = render(data, 48_000);
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count();
+        assert!((1999..=2001).contains(&crossings));
+    }
+
+    #[test]
+    fn ends() {
+        let settings = StreamingSoundSettings::new().volume(0.5);
+        let data = GeneratorData::new(Signal::WhiteNoise, 0.5, settings);
+        let (samples, handle) = render(data, 48_000);
+        assert!(samples[24_000..].iter().all(|s| *s == 0.0));
+        assert_eq!(handle.state(), PlaybackState::Stopped);
+
+        let settings = StreamingSoundSettings::new().loop_behavior(LoopBehavior {
+            start_position: 0.25,
+        });
+        let data = GeneratorData::new(Signal::WhiteNoise, 0.5, settings);
+        let (samples, handle) = render(data, 48_000);
+        assert!(samples[24_000..].iter().any(|s| *s != 0.0));
+        assert_eq!(handle.state(), PlaybackState::Playing);
+        assert!((handle.position() - 0.25).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,178 @@
+//! Playback without audio hardware, for tests and machines without a sound
+//! card. Time only passes as [`HeadlessBackend::advance`] renders it, so
+//! playback states and positions come out the same on every run. A
+//! [`Simulation`] drives the whole engine that way, follow-ons, loops and
+//! fades included. [`TestModel`] builds the models such tests start from.
+
+use crate::engine::{process_message, Playback, PLAYBACK_SYNC_INTERVAL};
+use crate::model::{Colour, ControlMessage, Item, Model, Playlist};
+use anyhow::Result;
+use kira::dsp::Frame;
+use kira::manager::backend::{Backend, Renderer};
+use parking_lot::RwLock;
+use std::convert::Infallible;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many frames are rendered between checks for new commands, like the
+/// buffer of a sound card.
+const BATCH: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadlessSettings {
+    pub sample_rate: u32,
+}
+
+impl Default for HeadlessSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48_000,
+        }
+    }
+}
+
+/// A backend rendering audio only when asked to.
+pub struct HeadlessBackend {
+    sample_rate: u32,
+    renderer: Option<Renderer>,
+    /// How many frames have been rendered so far.
+    rendered: usize,
+}
+
+impl Backend for HeadlessBackend {
+    type Settings = HeadlessSettings;
+    type Error = Infallible;
+
+    fn setup(settings: HeadlessSettings) -> Result<(Self, u32), Infallible> {
+        let backend = Self {
+            sample_rate: settings.sample_rate,
+            renderer: None,
+            rendered: 0,
+        };
+        Ok((backend, settings.sample_rate))
+    }
+
+    fn start(&mut self, renderer: Renderer) -> Result<(), Infallible> {
+        self.renderer = Some(renderer);
+        Ok(())
+    }
+}
+
+impl HeadlessBackend {
+    /// Render the given length of audio, returning the mix.
+    pub fn advance(&mut self, duration: Duration) -> Vec<Frame> {
+        let frames = (duration.as_secs_f64() * self.sample_rate as f64).round() as usize;
+        let Some(renderer) = &mut self.renderer else {
+            return vec![Frame::ZERO; frames];
+        };
+        let mut mix = Vec::with_capacity(frames);
+        for _ in 0..frames {
+            if self.rendered.is_multiple_of(BATCH) {
+                renderer.on_start_processing();
+            }
+            mix.push(renderer.process());
+            self.rendered += 1;
+        }
+        mix
+    }
+}
+
+/// The playback thread and the interface, played out in simulated time.
+pub struct Simulation {
+    pub playback: Playback<HeadlessBackend>,
+    pub model: Arc<RwLock<Model>>,
+    tx: Sender<ControlMessage>,
+    rx: Receiver<ControlMessage>,
+}
+
+impl Simulation {
+    pub fn new(model: Model) -> Result<Self> {
+        let (tx, rx) = channel();
+        Ok(Self {
+            playback: Playback::headless()?,
+            model: Arc::new(RwLock::new(model)),
+            tx,
+            rx,
+        })
+    }
+
+    /// Process a message, and those the engine sends itself in turn.
+    pub fn send(&mut self, msg: ControlMessage) -> Result<()> {
+        process_message(msg, &self.tx, &mut self.playback, &self.model)?;
+        while let Ok(msg) = self.rx.try_recv() {
+            process_message(msg, &self.tx, &mut self.playback, &self.model)?;
+        }
+        Ok(())
+    }
+
+    /// Let time pass, syncing every [`PLAYBACK_SYNC_INTERVAL`] milliseconds
+    /// and catching the item positions up with playback like the interface
+    /// does. Returns what the main manager played.
+    pub fn advance(&mut self, duration: Duration) -> Result<Vec<Frame>> {
+        let step = Duration::from_millis(PLAYBACK_SYNC_INTERVAL);
+        let mut mix = vec![];
+        let mut left = duration;
+        while !left.is_zero() {
+            let elapsed = left.min(step);
+            left -= elapsed;
+            mix.extend(self.playback.advance(elapsed));
+            self.send(ControlMessage::SyncPlaybackStatus)?;
+            for item in self.model.write().items.iter_mut() {
+                item.position = item.target_position;
+            }
+        }
+        Ok(mix)
+    }
+}
+
+/// The recording tests play, a robin whistling for a second.
+pub const SAMPLE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../samples/416529__inspectorj__bird-whistling-single-robin-a.wav"
+);
+
+/// Builds the models tests start from, with items numbered from zero and
+/// named after their ids.
+pub struct TestModel {
+    model: Model,
+}
+
+impl TestModel {
+    /// A model of the given number of items, each lasting `duration`
+    /// seconds, with no file to play.
+    pub fn new(items: u64, duration: f64) -> Self {
+        let mut model = Model::default();
+        for id in 0..items {
+            let name = format!("test {}", id);
+            let item = Item::with_default_stem(id, name, String::new(), Colour::BLACK, duration);
+            model.items.push(item);
+        }
+        Self { model }
+    }
+
+    /// Have all the items play the given file.
+    pub fn playing(mut self, path: &str) -> Self {
+        for item in &mut self.model.items {
+            item.stems[0].path = path.to_string();
+        }
+        self
+    }
+
+    /// Add a playlist of the given items.
+    pub fn playlist(mut self, id: u64, items: Vec<u64>) -> Self {
+        let playlist = Playlist::new(id, "cues".to_string(), items);
+        self.model.playlists.push(playlist);
+        self
+    }
+
+    /// Select the playlist with the given id.
+    pub fn select(mut self, playlist: u64) -> Self {
+        self.model.selected_playlist = Some(playlist);
+        self
+    }
+
+    pub fn build(self) -> Model {
+        self.model
+    }
+}
@@ -0,0 +1,629 @@
+//! Turning audio files into items: decoding them for the waveform overview,
+//! tempo, key and loudness and probing their technical details.
+
+use crate::model::*;
+use crate::{key, loudness, tempo};
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+use kira::sound::FromFileError;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use tracing::{debug, trace_span, warn};
+
+/// Sounds peaking below this level, in dBFS, count as silent.
+const SILENCE_THRESHOLD_DB: f64 = -50.0;
+/// The resolution of the silence detection, in seconds.
+const SILENCE_WINDOW: f64 = 0.05;
+/// The shortest stretch of silence noted, in seconds.
+const MIN_SILENCE: f64 = 0.5;
+
+/// How many files are being read, counting the loads abandoned on timeout
+/// that are still stuck, so that they hold up later imports rather than
+/// piling up.
+static LOADS: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+
+/// A place among the [`LOADS`], given up once the file is read.
+struct LoadSlot;
+
+impl LoadSlot {
+    /// Wait for a place among fewer than `limit` loads, for up to `timeout`.
+    fn take(limit: usize, timeout: Duration) -> Option<Self> {
+        let (loads, freed) = &LOADS;
+        let (mut running, _) = freed
+            .wait_timeout_while(loads.lock().unwrap(), timeout, |running| *running >= limit)
+            .unwrap();
+        (*running < limit).then(|| {
+            *running += 1;
+            LoadSlot
+        })
+    }
+}
+
+impl Drop for LoadSlot {
+    fn drop(&mut self) {
+        let (loads, freed) = &LOADS;
+        *loads.lock().unwrap() -= 1;
+        freed.notify_one();
+    }
+}
+
+/// Import files in parallel, reporting progress on the channel. Files that
+/// fail to load, or that take longer than the limits allow, are reported and
+/// left out of the returned items.
+pub fn import_paths(
+    tx: Sender<ImportMessage>,
+    mut fresh_id: impl FnMut() -> u64,
+    paths: Vec<PathBuf>,
+    colour_rules: &[ColourRule],
+    limits: ImportLimits,
+) -> Vec<Item> {
+    use rayon::prelude::*;
+
+    let queue: Mutex<VecDeque<_>> = paths
+        .into_iter()
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let path = path.display().to_string();
+            let id = fresh_id();
+            tx.send(ImportMessage::Update(
+                id,
+                ItemImportStatus::Queued(name.clone()),
+            ))
+            .unwrap();
+
+            (name, path, id)
+        })
+        .enumerate()
+        .collect::<VecDeque<_>>()
+        .into();
+
+    // rather than fanning out over the whole pool, only as many workers as
+    // the limit allows take files off the queue
+    let (queue, timeout) = (&queue, Duration::from_secs(limits.timeout));
+    let concurrency = limits.concurrency.max(1);
+    let mut items: Vec<_> = (0..concurrency)
+        .into_par_iter()
+        .flat_map_iter(|_| {
+            let tx = tx.clone();
+            std::iter::from_fn(move || queue.lock().unwrap().pop_front()).filter_map(
+                move |(index, (name, path, id))| {
+                    let limits = (concurrency, timeout);
+                    create_item(tx.clone(), id, path, name, colour_rules, limits)
+                        .map(|item| (index, item))
+                },
+            )
+        })
+        .collect();
+    // keep the order the files were given in
+    items.sort_by_key(|&(index, _)| index);
+    items.into_iter().map(|(_, item)| item).collect()
+}
+
+fn create_item(
+    tx: Sender<ImportMessage>,
+    id: u64,
+    path: String,
+    name: String,
+    colour_rules: &[ColourRule],
+    (concurrency, timeout): (usize, Duration),
+) -> Option<Item> {
+    let _span = trace_span!("import").entered();
+    tx.send(ImportMessage::Update(id, ItemImportStatus::InProgress))
+        .unwrap();
+
+    // reading from a stalled network share can block indefinitely, so the
+    // file is loaded on a thread of its own which is abandoned on timeout,
+    // keeping its slot until it gets unstuck
+    let loaded = match LoadSlot::take(concurrency, timeout) {
+        Some(slot) => {
+            let (done_tx, done_rx) = channel();
+            let priority = thread_priority::get_current_thread_priority().ok();
+            let (loaded_path, rules) = (path.clone(), colour_rules.to_vec());
+            std::thread::spawn(move || {
+                let _slot = slot;
+                // not every platform has threads inherit the priority of the
+                // import worker
+                if let Some(priority) = priority {
+                    thread_priority::set_current_thread_priority(priority).ok();
+                }
+                done_tx.send(load_item(id, loaded_path, name, &rules)).ok();
+            });
+            done_rx.recv_timeout(timeout).unwrap_or_else(|_| {
+                Err(format!(
+                    "loading the file took longer than {} s",
+                    timeout.as_secs()
+                ))
+            })
+        }
+        None => Err("files from earlier imports are still stuck loading".to_string()),
+    };
+
+    match loaded {
+        Ok(item) => {
+            tx.send(ImportMessage::Update(id, ItemImportStatus::Finished))
+                .unwrap();
+            Some(item)
+        }
+        Err(msg) => {
+            warn!("failed to load {}: {}", path, msg);
+            tx.send(ImportMessage::Update(id, ItemImportStatus::Failed(msg)))
+                .unwrap();
+            None
+        }
+    }
+}
+
+fn load_item(
+    id: u64,
+    path: String,
+    name: String,
+    colour_rules: &[ColourRule],
+) -> Result<Item, String> {
+    let analysis = analyse(&path).map_err(|e| classify_from_file_err(&e).0)?;
+    let mut i = Item::with_default_stem(
+        id,
+        name,
+        path,
+        PALETTE[id as usize % PALETTE.len()],
+        analysis.duration,
+    );
+    analysis.apply_to(&mut i, 0);
+    apply_colour_rules(colour_rules, &mut i);
+    Ok(i)
+}
+
+/// What decoding a file tells about it.
+pub struct Analysis {
+    pub duration: f64,
+    pub bars: Vec<u8>,
+    pub rms_bars: Vec<u8>,
+    pub channel_bars: [Vec<u8>; 2],
+    pub channel_rms_bars: [Vec<u8>; 2],
+    pub tempo: Option<Tempo>,
+    pub key: Option<Key>,
+    pub loudness: Option<f64>,
+    pub silences: Vec<(f64, f64)>,
+    pub info: Option<FileInfo>,
+}
+
+/// Decode a file for its duration, waveform overview, tempo, key and loudness
+/// and probe its technical details.
+pub fn analyse(path: &str) -> Result<Analysis, FromFileError> {
+    let static_sound = StaticSoundData::from_file(path, StaticSoundSettings::new())?;
+    let count = bar_count(static_sound.frames.len(), static_sound.sample_rate);
+    let (bars, rms_bars) = visualise_samples(&static_sound.frames, count);
+    let (channel_bars, channel_rms_bars) = visualise_channels(&static_sound.frames, count);
+    Ok(Analysis {
+        duration: static_sound.frames.len() as f64 / static_sound.sample_rate as f64,
+        bars,
+        rms_bars,
+        channel_bars,
+        channel_rms_bars,
+        tempo: tempo::detect(&static_sound.frames, static_sound.sample_rate),
+        key: key::detect(&static_sound.frames, static_sound.sample_rate),
+        loudness: loudness::measure(&static_sound.frames, static_sound.sample_rate),
+        silences: find_silences(&static_sound.frames, static_sound.sample_rate),
+        info: probe_file_info(path)
+            .map_err(|e| warn!("failed to probe {}: {}", path, e))
+            .ok(),
+    })
+}
+
+impl Analysis {
+    /// Bring a stem of an item up to date, and the duration and waveform of
+    /// the item too if it's the current stem.
+    pub fn apply_to(self, item: &mut Item, stem: usize) {
+        item.stems[stem].info = self.info;
+        if stem != item.current_stem {
+            return;
+        }
+        item.duration = self.duration;
+        item.bars = self.bars;
+        item.rms_bars = self.rms_bars;
+        item.channel_bars = self.channel_bars;
+        item.channel_rms_bars = self.channel_rms_bars;
+        item.tempo = self.tempo;
+        item.key = self.key;
+        item.loudness = self.loudness;
+        item.silences = self.silences;
+        item.loop_region = item
+            .loop_region
+            .map(|(start, end)| (start, end.min(self.duration)))
+            .filter(|(start, end)| start < end);
+    }
+}
+
+/// Whether the file of a stem was replaced or edited since it was probed.
+/// Missing files don't count, they are reported once played.
+pub fn stem_changed(stem: &Stem) -> bool {
+    let (Some(info), Ok(metadata)) = (&stem.info, std::fs::metadata(&stem.path)) else {
+        return false;
+    };
+    metadata.len() != info.file_size
+        || info
+            .modified
+            .is_some_and(|modified| metadata.modified().ok() != Some(modified))
+}
+
+/// Find the stretches where the sound stays below [`SILENCE_THRESHOLD_DB`]
+/// for at least [`MIN_SILENCE`] seconds.
+fn find_silences(frames: &[kira::dsp::Frame], sample_rate: u32) -> Vec<(f64, f64)> {
+    let window = (sample_rate as f64 * SILENCE_WINDOW).round() as usize;
+    let threshold = db_to_amplitude(SILENCE_THRESHOLD_DB) as f32;
+    let mut silences = vec![];
+    let mut start = None;
+    for (i, chunk) in frames.chunks(window.max(1)).enumerate() {
+        let time = (i * window) as f64 / sample_rate as f64;
+        let quiet = chunk
+            .iter()
+            .all(|frame| frame.left.abs().max(frame.right.abs()) < threshold);
+        match (quiet, start) {
+            (true, None) => start = Some(time),
+            (false, Some(from)) => {
+                silences.push((from, time));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(from) = start {
+        silences.push((from, frames.len() as f64 / sample_rate as f64));
+    }
+    silences.retain(|(start, end)| end - start >= MIN_SILENCE);
+    silences
+}
+
+/// How many bars the waveform of a file gets: more the longer it is, but
+/// never more than it has frames.
+fn bar_count(frames: usize, sample_rate: u32) -> usize {
+    let duration = frames as f64 / sample_rate as f64;
+    let count = (duration * BARS_PER_SECOND).round() as usize;
+    count.clamp(MIN_BARS, MAX_BARS).min(frames)
+}
+
+/// The peak and RMS levels of the mix, both on the scale of the loudest peak.
+fn visualise_samples(frames: &[kira::dsp::Frame], count: usize) -> (Vec<u8>, Vec<u8>) {
+    let (peaks, rms) = bin_samples(frames, count, |sample| {
+        sample.left.abs() * 0.5 + sample.right.abs() * 0.5
+    });
+    let max = peaks.iter().copied().fold(0.0f32, f32::max);
+    (scale_bins(peaks, max), scale_bins(rms, max))
+}
+
+/// Like [`visualise_samples`], for each channel separately. Both channels
+/// are scaled alike, so that a one-sided recording shows as such.
+fn visualise_channels(frames: &[kira::dsp::Frame], count: usize) -> ([Vec<u8>; 2], [Vec<u8>; 2]) {
+    let (left, left_rms) = bin_samples(frames, count, |sample| sample.left.abs());
+    let (right, right_rms) = bin_samples(frames, count, |sample| sample.right.abs());
+    let max = left.iter().chain(&right).copied().fold(0.0f32, f32::max);
+    (
+        [scale_bins(left, max), scale_bins(right, max)],
+        [scale_bins(left_rms, max), scale_bins(right_rms, max)],
+    )
+}
+
+/// The peak and RMS level of the samples in each of `count` bins, which
+/// mustn't outnumber the frames.
+fn bin_samples(
+    frames: &[kira::dsp::Frame],
+    count: usize,
+    level: impl Fn(&kira::dsp::Frame) -> f32,
+) -> (Vec<f32>, Vec<f32>) {
+    // collect samples into bins
+    let mut peaks = vec![0.0; count];
+    let mut rms = vec![0.0; count];
+    debug!("processing {:#?} frames into {} bins", frames.len(), count);
+
+    for (i, (peak, rms)) in peaks.iter_mut().zip(&mut rms).enumerate() {
+        // spread the frames left over evenly, rather than dropping them
+        let start = i * frames.len() / count;
+        let end = (i + 1) * frames.len() / count;
+        let bin_size = end - start;
+        let (max, squares) = frames[start..end]
+            .iter()
+            .map(&level)
+            .fold((0.0f32, 0.0f32), |(max, squares), level| {
+                (max.max(level), squares + level * level)
+            });
+        *peak = max;
+        *rms = (squares / bin_size as f32).sqrt();
+    }
+    (peaks, rms)
+}
+
+fn scale_bins(bins: Vec<f32>, max: f32) -> Vec<u8> {
+    bins.into_iter()
+        .map(|bin| (255.0 * (bin / max)).round() as u8)
+        .collect()
+}
+
+/// Read the technical details of an audio file from its default track.
+pub fn probe_file_info(path: &str) -> anyhow::Result<FileInfo> {
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::probe::Hint;
+
+    let file = std::fs::File::open(path)?;
+    let metadata = file.metadata()?;
+    let mut hint = Hint::new();
+    if let Some(extension) = std::path::Path::new(path).extension() {
+        hint.with_extension(&extension.to_string_lossy());
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        MediaSourceStream::new(Box::new(file), Default::default()),
+        &Default::default(),
+        &Default::default(),
+    )?;
+    let params = &probed
+        .format
+        .default_track()
+        .ok_or_else(|| anyhow::anyhow!("the file doesn't have a default track"))?
+        .codec_params;
+
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map_or_else(|| "unknown".to_string(), |c| c.short_name.to_string());
+    Ok(FileInfo {
+        codec,
+        sample_rate: params.sample_rate,
+        channels: params.channels.map(|c| c.count()),
+        bits_per_sample: params.bits_per_sample,
+        frames: params.n_frames,
+        file_size: metadata.len(),
+        modified: metadata.modified().ok(),
+    })
+}
+
+pub fn classify_from_file_err(e: &FromFileError) -> (String, IssueType) {
+    use std::io::ErrorKind;
+    use symphonia::core::errors;
+    use IssueType::*;
+
+    fn describe_io_error(kind: ErrorKind) -> (String, IssueType) {
+        match kind {
+            ErrorKind::NotFound => ("the file could not be found".to_string(), MissingFile),
+            ErrorKind::PermissionDenied => (
+                "permission to read the file was denied".to_string(),
+                InaccessibleFile,
+            ),
+            kind => (format!("an IO error occurred: {}", kind), OtherError),
+        }
+    }
+
+    match e {
+        FromFileError::NoDefaultTrack => (
+            "the file doesn't have a default track".to_string(),
+            PlaybackProblem,
+        ),
+        FromFileError::UnknownSampleRate => (
+            "the sample rate could not be determined".to_string(),
+            PlaybackProblem,
+        ),
+        FromFileError::UnsupportedChannelConfiguration => (
+            "the channel configuration of the file is not supported".to_string(),
+            PlaybackProblem,
+        ),
+        FromFileError::IoError(io_err) => describe_io_error(io_err.kind()),
+        FromFileError::SymphoniaError(symphonia_err) => match symphonia_err {
+            errors::Error::IoError(e) => describe_io_error(e.kind()),
+            errors::Error::DecodeError(e) => (
+                format!("symphonia could not decode the file: {}", e),
+                PlaybackProblem,
+            ),
+            errors::Error::SeekError(e) => match e {
+                errors::SeekErrorKind::Unseekable => {
+                    ("this file is not seekable".to_string(), PlaybackProblem)
+                }
+                errors::SeekErrorKind::ForwardOnly => (
+                    "this file can only be seeked forward".to_string(),
+                    PlaybackProblem,
+                ),
+                errors::SeekErrorKind::OutOfRange => (
+                    "the seek timestamp is out of range".to_string(),
+                    PlaybackProblem,
+                ),
+                errors::SeekErrorKind::InvalidTrack => {
+                    ("the track ID is invalid".to_string(), PlaybackProblem)
+                }
+            },
+            errors::Error::Unsupported(e) => (
+                format!("symphonia does not support this format: {}", e),
+                PlaybackProblem,
+            ),
+            errors::Error::LimitError(e) => {
+                (format!("a limit error occurred: {}", e), PlaybackProblem)
+            }
+            errors::Error::ResetRequired => {
+                ("symphonia requires a reset".to_string(), PlaybackProblem)
+            }
+        },
+        _ => ("an unknown error occurred".to_string(), OtherError),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn silences() {
+        use kira::dsp::Frame;
+        let sample_rate = 1000;
+        let mut frames = vec![Frame::from_mono(0.5); 1000];
+        // too short to count
+        frames.extend(vec![Frame::from_mono(0.0); 300]);
+        frames.extend(vec![Frame::from_mono(-0.5); 700]);
+        frames.extend(vec![Frame::from_mono(0.001); 2000]);
+        assert_eq!(find_silences(&frames, sample_rate), vec![(2.0, 4.0)]);
+    }
+
+    #[test]
+    fn one_sided() {
+        use kira::dsp::Frame;
+        let frames: Vec<_> = (0..1280)
+            .map(|i| Frame::new(if i < 640 { 0.5 } else { 0.25 }, 0.0))
+            .collect();
+        let ([left, right], _) = visualise_channels(&frames, 128);
+        assert_eq!((left[0], left[127]), (255, 128));
+        assert!(right.iter().all(|&bar| bar == 0));
+        // the mix can't tell which side is missing
+        assert_eq!(visualise_samples(&frames, 128).0[0], 255);
+    }
+
+    #[test]
+    fn peaks_and_rms() {
+        use kira::dsp::Frame;
+        // a click in each bin of an otherwise quiet track
+        let frames: Vec<_> = (0..12_800)
+            .map(|i| {
+                let level = if i % 100 == 0 { 1.0 } else { 0.1 };
+                Frame::new(level, level)
+            })
+            .collect();
+        let (peaks, rms) = visualise_samples(&frames, 128);
+        assert!(peaks.iter().all(|&bar| bar == 255));
+        // sqrt((1 + 99 * 0.01) / 100) of the peak
+        assert!(rms.iter().all(|&bar| bar == 36));
+
+        // a steady tone is as loud on average as at its peaks
+        let frames = vec![Frame::new(0.5, 0.5); 1280];
+        let (peaks, rms) = visualise_samples(&frames, 128);
+        assert_eq!(peaks, rms);
+    }
+
+    #[test]
+    fn resolution() {
+        use kira::dsp::Frame;
+        assert_eq!(bar_count(5 * 48_000, 48_000), MIN_BARS);
+        assert_eq!(bar_count(60 * 48_000, 48_000), 480);
+        assert_eq!(bar_count(2 * 3600 * 48_000, 48_000), MAX_BARS);
+
+        // a blip shorter than the bars it would get
+        assert_eq!(bar_count(10, 48_000), 10);
+        let frames = vec![Frame::from_mono(0.5); 10];
+        assert_eq!(visualise_samples(&frames, 10).0, vec![255; 10]);
+        assert_eq!(bar_count(0, 48_000), 0);
+        assert_eq!(visualise_samples(&[], 0), (vec![], vec![]));
+
+        // frames that don't divide evenly all count
+        let mut frames = vec![Frame::from_mono(0.25); 99];
+        frames.push(Frame::from_mono(1.0));
+        let (peaks, _) = visualise_samples(&frames, 64);
+        assert_eq!((peaks.len(), peaks[63]), (64, 255));
+    }
+
+    #[test]
+    fn probe_sample() -> anyhow::Result<()> {
+        let info = probe_file_info(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../samples/416529__inspectorj__bird-whistling-single-robin-a.wav"
+        ))?;
+        assert_eq!(
+            info,
+            FileInfo {
+                codec: "pcm_s24le".to_string(),
+                sample_rate: Some(44100),
+                channels: Some(2),
+                bits_per_sample: Some(24),
+                frames: Some(232_705),
+                file_size: 1396344,
+                modified: info.modified,
+            }
+        );
+        assert_eq!(
+            info.to_string(),
+            "pcm_s24le, 44.1 kHz, 2 ch, 24 bit, 1.4 MB"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn changed_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("edited.wav");
+        std::fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../samples/416529__inspectorj__bird-whistling-single-robin-a.wav"
+            ),
+            &path,
+        )?;
+        let path = path.display().to_string();
+        let mut item = load_item(1, path.clone(), "edited".to_string(), &[]).unwrap();
+        item.loop_region = Some((1.0, 100.0));
+        assert!(!stem_changed(&item.stems[0]));
+
+        // trimming the file leaves a shorter, but still valid, recording
+        let mut bytes = std::fs::read(&path)?;
+        bytes.truncate(bytes.len() / 2);
+        std::fs::write(&path, bytes)?;
+        assert!(stem_changed(&item.stems[0]));
+
+        let (duration, bars) = (item.duration, item.bars.clone());
+        analyse(&path)?.apply_to(&mut item, 0);
+        assert!(!stem_changed(&item.stems[0]));
+        assert!(item.duration < duration);
+        assert_ne!(item.bars, bars);
+        assert_eq!(item.loop_region, Some((1.0, item.duration)));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stalled_import() -> anyhow::Result<()> {
+        // opening a named pipe blocks until someone writes to it, much like
+        // reading from an unresponsive share
+        let dir = tempfile::tempdir()?;
+        let pipe = dir.path().join("stalled.wav");
+        assert!(std::process::Command::new("mkfifo")
+            .arg(&pipe)
+            .status()?
+            .success());
+        let sample = PathBuf::from(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../samples/416529__inspectorj__bird-whistling-single-robin-a.wav"
+        ));
+
+        let import = |paths: Vec<PathBuf>, concurrency| {
+            let (tx, rx) = channel();
+            let mut id = 0;
+            let limits = ImportLimits {
+                concurrency,
+                timeout: 1,
+            };
+            let items = import_paths(
+                tx,
+                || {
+                    id += 1;
+                    id
+                },
+                paths,
+                &[],
+                limits,
+            );
+            let failed: Vec<_> = rx
+                .try_iter()
+                .filter_map(|msg| match msg {
+                    ImportMessage::Update(id, ItemImportStatus::Failed(_)) => Some(id),
+                    _ => None,
+                })
+                .collect();
+            (items.iter().map(|item| item.id).collect::<Vec<_>>(), failed)
+        };
+        assert_eq!(
+            import(vec![pipe.clone(), sample.clone()], 2),
+            (vec![2], vec![1])
+        );
+
+        // the abandoned loader still takes up the only slot
+        assert_eq!(import(vec![sample.clone()], 1), (vec![], vec![1]));
+
+        // let the abandoned loader finish
+        drop(std::fs::OpenOptions::new().write(true).open(&pipe)?);
+        assert_eq!(import(vec![sample], 1), (vec![1], vec![]));
+        Ok(())
+    }
+}
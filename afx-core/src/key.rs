@@ -0,0 +1,118 @@
+//! Key detection, so that music played back to back doesn't clash. The
+//! energy of the sound is folded into the twelve pitch classes and compared
+//! against the Krumhansl-Kessler profiles of every key.
+
+use crate::model::Key;
+use crate::spectrum::fft;
+use kira::dsp::Frame;
+
+/// How many samples each spectrum is computed from. Must be a power of two.
+const WINDOW: usize = 8192;
+/// At most this many windows are analysed, spread over the whole sound.
+const MAX_WINDOWS: usize = 128;
+/// The range of frequencies folded into pitch classes, in hertz.
+const FREQUENCIES: (f32, f32) = (55.0, 2000.0);
+/// How closely the pitch classes must follow the profile of the best key for
+/// it to be trusted.
+const MIN_CORRELATION: f32 = 0.5;
+
+/// How strongly each pitch class, counted from the tonic, belongs to a major
+/// and a minor key.
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// Find the key of a sound, if it has a clear one.
+pub fn detect(frames: &[Frame], sample_rate: u32) -> Option<Key> {
+    let windows = frames.len() / WINDOW;
+    if windows == 0 {
+        return None;
+    }
+    let step = windows.div_ceil(MAX_WINDOWS);
+
+    let mut chroma = [0.0f32; 12];
+    let (lowest, highest) = FREQUENCIES;
+    let bin_width = sample_rate as f32 / WINDOW as f32;
+    let bins = (lowest / bin_width).ceil() as usize..(highest / bin_width) as usize;
+    let mut values = vec![(0.0, 0.0); WINDOW];
+    for window in frames.chunks_exact(WINDOW).step_by(step) {
+        for (value, frame) in values.iter_mut().zip(window) {
+            *value = ((frame.left + frame.right) / 2.0, 0.0);
+        }
+        fft(&mut values);
+        for bin in bins.clone() {
+            let (re, im) = values[bin];
+            let midi_note = 69.0 + 12.0 * (bin as f32 * bin_width / 440.0).log2();
+            chroma[(midi_note.round() as usize) % 12] += (re * re + im * im).sqrt();
+        }
+    }
+
+    let (correlation, key) = (0..12u8)
+        .flat_map(|tonic| {
+            [(MAJOR_PROFILE, false), (MINOR_PROFILE, true)]
+                .into_iter()
+                .map(move |(profile, minor)| {
+                    let rotated: Vec<_> = (0..12)
+                        .map(|class| profile[(class + 12 - tonic as usize) % 12])
+                        .collect();
+                    (pearson(&chroma, &rotated), Key { tonic, minor })
+                })
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))?;
+    (correlation >= MIN_CORRELATION).then_some(key)
+}
+
+fn pearson(a: &[f32], b: &[f32]) -> f32 {
+    let mean = |values: &[f32]| values.iter().sum::<f32>() / values.len() as f32;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (a, b) in a.iter().zip(b) {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a) * (a - mean_a);
+        variance_b += (b - mean_b) * (b - mean_b);
+    }
+    if variance_a <= f32::EPSILON || variance_b <= f32::EPSILON {
+        return 0.0;
+    }
+    covariance / (variance_a * variance_b).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chord(midi_notes: &[i32], sample_rate: u32) -> Vec<Frame> {
+        (0..sample_rate * 2)
+            .map(|i| {
+                let time = i as f32 / sample_rate as f32;
+                let sample: f32 = midi_notes
+                    .iter()
+                    .map(|&note| 440.0 * 2f32.powf((note - 69) as f32 / 12.0))
+                    .map(|frequency| (time * frequency * std::f32::consts::TAU).sin())
+                    .sum();
+                Frame::from_mono(sample / midi_notes.len() as f32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chords() {
+        let sample_rate = 44_100;
+        let key = |text| Key::parse(text);
+        // C E G
+        assert_eq!(
+            detect(&chord(&[60, 64, 67], sample_rate), sample_rate),
+            key("C")
+        );
+        // A C E
+        assert_eq!(
+            detect(&chord(&[57, 60, 64], sample_rate), sample_rate),
+            key("Am")
+        );
+        let silence = vec![Frame::from_mono(0.0); sample_rate as usize];
+        assert_eq!(detect(&silence, sample_rate), None);
+    }
+}
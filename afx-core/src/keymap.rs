@@ -0,0 +1,227 @@
+//! Rebindable keyboard shortcuts.
+
+use crate::model::BANKS;
+use serde::{Deserialize, Serialize};
+
+/// Something the user can do from the keyboard.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Action {
+    Go,
+    FocusSearch,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    SelectBank(u8),
+    /// Seek the focused item by this many seconds, back if negative.
+    Skip(i16),
+    /// Start or pause the pad of this number, counted from zero in reading
+    /// order over the current bank, in performance mode.
+    TriggerPad(u8),
+    /// Scroll to the playing item, going on to the next one when repeated.
+    Locate,
+}
+
+/// How many pads can be triggered from the keyboard, see
+/// [`Action::TriggerPad`].
+pub const QUICK_TRIGGERS: usize = 10;
+
+impl Action {
+    pub fn description(&self) -> String {
+        match self {
+            Action::Go => "Fire the standby cue".to_string(),
+            Action::FocusSearch => "Search the library".to_string(),
+            Action::ZoomIn => "Zoom in".to_string(),
+            Action::ZoomOut => "Zoom out".to_string(),
+            Action::ResetZoom => "Reset zoom".to_string(),
+            Action::SelectBank(bank) => format!("Switch to bank {}", bank + 1),
+            Action::Skip(seconds) if *seconds < 0 => {
+                format!("Skip the focused item back {} s", -seconds)
+            }
+            Action::Skip(seconds) => format!("Skip the focused item forward {} s", seconds),
+            Action::TriggerPad(pad) => format!("Start or pause pad {}", pad + 1),
+            Action::Locate => "Locate the playing item".to_string(),
+        }
+    }
+}
+
+/// A key of the keyboard. The keys are named the way egui names them, so that
+/// the interface can convert them and keymaps saved with egui's keys still
+/// load.
+#[rustfmt::skip]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Key {
+    ArrowDown, ArrowLeft, ArrowRight, ArrowUp,
+    Escape, Tab, Backspace, Enter, Space,
+    Insert, Delete, Home, End, PageUp, PageDown,
+    Minus, PlusEquals,
+    Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10,
+    F11, F12, F13, F14, F15, F16, F17, F18, F19, F20,
+}
+
+/// The modifier keys held down along with a key, as egui reports them.
+/// `command` is the Command key on macOS and the Control key elsewhere.
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Modifiers {
+    pub alt: bool,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub mac_cmd: bool,
+    pub command: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers {
+        alt: false,
+        ctrl: false,
+        shift: false,
+        mac_cmd: false,
+        command: false,
+    };
+    pub const SHIFT: Modifiers = Modifiers {
+        shift: true,
+        ..Modifiers::NONE
+    };
+    pub const COMMAND: Modifiers = Modifiers {
+        command: true,
+        ..Modifiers::NONE
+    };
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Shortcut {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+impl Shortcut {
+    pub const fn new(modifiers: Modifiers, key: Key) -> Shortcut {
+        Shortcut { modifiers, key }
+    }
+}
+
+/// The keyboard shortcuts bound to each action. An action may have several
+/// shortcuts, or none at all.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Keymap {
+    pub bindings: Vec<(Action, Shortcut)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        const BANK_KEYS: [Key; BANKS as usize] = [
+            Key::F1,
+            Key::F2,
+            Key::F3,
+            Key::F4,
+            Key::F5,
+            Key::F6,
+            Key::F7,
+            Key::F8,
+        ];
+
+        // 1 to 9, and 0 for the tenth
+        const PAD_KEYS: [Key; QUICK_TRIGGERS] = [
+            Key::Num1,
+            Key::Num2,
+            Key::Num3,
+            Key::Num4,
+            Key::Num5,
+            Key::Num6,
+            Key::Num7,
+            Key::Num8,
+            Key::Num9,
+            Key::Num0,
+        ];
+
+        let mut bindings = vec![
+            (Action::Go, Shortcut::new(Modifiers::NONE, Key::Space)),
+            (
+                Action::FocusSearch,
+                Shortcut::new(Modifiers::COMMAND, Key::F),
+            ),
+            (
+                Action::ZoomIn,
+                Shortcut::new(Modifiers::COMMAND, Key::PlusEquals),
+            ),
+            (
+                Action::ZoomOut,
+                Shortcut::new(Modifiers::COMMAND, Key::Minus),
+            ),
+            (
+                Action::ResetZoom,
+                Shortcut::new(Modifiers::COMMAND, Key::Num0),
+            ),
+            (Action::Locate, Shortcut::new(Modifiers::COMMAND, Key::L)),
+            (
+                Action::Skip(-5),
+                Shortcut::new(Modifiers::NONE, Key::ArrowLeft),
+            ),
+            (
+                Action::Skip(5),
+                Shortcut::new(Modifiers::NONE, Key::ArrowRight),
+            ),
+            (
+                Action::Skip(-30),
+                Shortcut::new(Modifiers::SHIFT, Key::ArrowLeft),
+            ),
+            (
+                Action::Skip(30),
+                Shortcut::new(Modifiers::SHIFT, Key::ArrowRight),
+            ),
+        ];
+        for (bank, key) in BANK_KEYS.into_iter().enumerate() {
+            bindings.push((
+                Action::SelectBank(bank as u8),
+                Shortcut::new(Modifiers::NONE, key),
+            ));
+        }
+        for (pad, key) in PAD_KEYS.into_iter().enumerate() {
+            bindings.push((
+                Action::TriggerPad(pad as u8),
+                Shortcut::new(Modifiers::NONE, key),
+            ));
+        }
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    pub fn shortcuts(&self, action: Action) -> impl Iterator<Item = Shortcut> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(a, _)| *a == action)
+            .map(|(_, shortcut)| *shortcut)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let keymap = Keymap::default();
+        for (i, (action, shortcut)) in keymap.bindings.iter().enumerate() {
+            assert!(
+                keymap.bindings[i + 1..]
+                    .iter()
+                    .all(|(_, other)| other != shortcut),
+                "{:?} shares its shortcut",
+                action
+            );
+        }
+        let pads: Vec<_> = (0..QUICK_TRIGGERS as u8)
+            .map(|pad| {
+                keymap
+                    .shortcuts(Action::TriggerPad(pad))
+                    .next()
+                    .unwrap()
+                    .key
+            })
+            .collect();
+        assert_eq!(pads.first(), Some(&Key::Num1));
+        assert_eq!(pads.last(), Some(&Key::Num0));
+    }
+}
@@ -0,0 +1,50 @@
+//! The engine of afx, usable without its graphical interface.
+//!
+//! The state of the application is a [`model::Model`] shared behind a lock.
+//! Playback is driven by sending [`model::ControlMessage`]s to a thread
+//! running [`engine::process_control_messages`], which keeps the model up to
+//! date as long as [`model::ControlMessage::SyncPlaybackStatus`] is sent
+//! every [`engine::PLAYBACK_SYNC_INTERVAL`] milliseconds.
+//!
+//! ```no_run
+//! use afx_core::model::{ControlMessage, Model};
+//! use afx_core::{engine, persistence};
+//! use parking_lot::RwLock;
+//! use std::sync::{mpsc::channel, Arc};
+//!
+//! let project = persistence::default_project_path().unwrap();
+//! let model = Arc::new(RwLock::new(persistence::load_project(&project)?));
+//! let (notifications, _) = channel();
+//! let (tx, rx) = channel();
+//! {
+//!     let (tx, model) = (tx.clone(), model.clone());
+//!     std::thread::spawn(move || engine::process_control_messages(tx, rx, notifications, model));
+//! }
+//! tx.send(ControlMessage::Play(model.read().items[0].id))?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod config;
+pub mod download;
+pub mod engine;
+pub mod generator;
+#[cfg(any(test, feature = "headless"))]
+pub mod headless;
+pub mod import;
+pub mod key;
+pub mod keymap;
+pub mod loudness;
+pub mod merge;
+pub mod midi;
+pub mod model;
+pub mod output;
+pub mod persistence;
+pub mod profile;
+pub mod radio;
+pub mod report;
+pub mod script;
+pub mod search;
+pub mod spectrum;
+pub mod speech;
+pub mod stats;
+pub mod tempo;
@@ -0,0 +1,140 @@
+//! Integrated loudness as defined by ITU-R BS.1770, so that playlists can
+//! bring their items to a common level. The sound is K-weighted, split into
+//! overlapping blocks, and the blocks that aren't near silence are averaged.
+
+use kira::dsp::Frame;
+
+/// How long each block is, and how far apart they start, in seconds.
+const BLOCK: f64 = 0.4;
+const BLOCK_STEP: f64 = 0.1;
+/// Blocks quieter than this, in LUFS, are left out altogether.
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks quieter than the average of the rest by more than this, in LU,
+/// are left out too.
+const RELATIVE_GATE: f64 = 10.0;
+
+/// A second order IIR filter.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.state[0];
+        self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+/// The two stages of the K-weighting filter, a high shelf modelling the
+/// head and a high pass, for the given sample rate.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    use std::f64::consts::PI;
+    let rate = sample_rate as f64;
+
+    let (frequency, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * frequency / rate).tan();
+    let (vh, vb) = (
+        10f64.powf(gain_db / 20.0),
+        10f64.powf(gain_db / 20.0 * 0.4996667741545416),
+    );
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+
+    let (frequency, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * frequency / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+    [shelf, high_pass]
+}
+
+fn lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+/// The integrated loudness of a sound in LUFS, if it isn't silent.
+pub fn measure(frames: &[Frame], sample_rate: u32) -> Option<f64> {
+    let mut filters = [k_weighting(sample_rate), k_weighting(sample_rate)];
+    // the weighted power of each step, both channels summed
+    let step = (BLOCK_STEP * sample_rate as f64).round() as usize;
+    let steps: Vec<f64> = frames
+        .chunks_exact(step)
+        .map(|chunk| {
+            let mut sum = 0.0;
+            for frame in chunk {
+                for (sample, channel) in [frame.left, frame.right].into_iter().zip(&mut filters) {
+                    let weighted = channel
+                        .iter_mut()
+                        .fold(sample as f64, |value, stage| stage.process(value));
+                    sum += weighted * weighted;
+                }
+            }
+            sum / step as f64
+        })
+        .collect();
+
+    let steps_per_block = (BLOCK / BLOCK_STEP).round() as usize;
+    let blocks: Vec<f64> = steps
+        .windows(steps_per_block)
+        .map(|window| window.iter().sum::<f64>() / steps_per_block as f64)
+        .filter(|&power| lufs(power) > ABSOLUTE_GATE)
+        .collect();
+    if blocks.is_empty() {
+        return None;
+    }
+    let mean = |powers: &[f64]| powers.iter().sum::<f64>() / powers.len() as f64;
+    let gate = lufs(mean(&blocks)) - RELATIVE_GATE;
+    let loud: Vec<_> = blocks
+        .into_iter()
+        .filter(|&power| lufs(power) > gate)
+        .collect();
+    Some(lufs(mean(&loud)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tone(amplitude: f32, sample_rate: u32) -> Vec<Frame> {
+        (0..sample_rate * 3)
+            .map(|i| {
+                let time = i as f32 / sample_rate as f32;
+                Frame::from_mono(amplitude * (time * 997.0 * std::f32::consts::TAU).sin())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tones() {
+        for sample_rate in [44_100, 48_000] {
+            // a full scale 997 Hz tone in both channels is the reference
+            let loudness = measure(&tone(1.0, sample_rate), sample_rate).unwrap();
+            assert!(loudness.abs() < 0.2, "measured {}", loudness);
+            let loudness = measure(&tone(0.1, sample_rate), sample_rate).unwrap();
+            assert!((loudness + 20.0).abs() < 0.2, "measured {}", loudness);
+        }
+
+        // quiet passages don't drag the loudness down
+        let mut frames = tone(0.1, 48_000);
+        frames.extend(vec![Frame::from_mono(0.0); 48_000 * 10]);
+        let loudness = measure(&frames, 48_000).unwrap();
+        assert!((loudness + 20.0).abs() < 0.5, "measured {}", loudness);
+
+        assert_eq!(measure(&[Frame::from_mono(0.0); 48_000], 48_000), None);
+    }
+}
@@ -0,0 +1,239 @@
+//! Three-way merging of models, so that the interface can draw from a copy of
+//! its own without holding the lock on the shared model. Changes made to the
+//! copy are carried over to the shared model unless another thread changed
+//! the same field in the meantime, in which case the other thread wins.
+
+use crate::model::*;
+use std::collections::{HashMap, HashSet};
+
+pub trait Merge {
+    /// Carry over the changes from `base` to `ours` into `self`, which has
+    /// moved on from `base` by itself.
+    fn merge(&mut self, base: &Self, ours: &Self);
+}
+
+/// Things kept in lists and told apart by their id.
+trait Keyed {
+    fn key(&self) -> u64;
+}
+
+impl Keyed for Item {
+    fn key(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Keyed for Playlist {
+    fn key(&self) -> u64 {
+        self.id
+    }
+}
+
+fn merge_value<T: PartialEq + Clone>(shared: &mut T, base: &T, ours: &T) {
+    if ours != base && shared == base {
+        *shared = ours.clone();
+    }
+}
+
+/// Merge lists of things with ids: added ones are appended, removed ones are
+/// removed and the others are merged one by one.
+fn merge_keyed<T: Keyed + Merge + PartialEq + Clone>(shared: &mut Vec<T>, base: &[T], ours: &[T]) {
+    let base: HashMap<_, _> = base.iter().map(|value| (value.key(), value)).collect();
+    let kept: HashSet<_> = ours.iter().map(Keyed::key).collect();
+    shared.retain(|value| !base.contains_key(&value.key()) || kept.contains(&value.key()));
+
+    let positions: HashMap<_, _> = shared
+        .iter()
+        .enumerate()
+        .map(|(position, value)| (value.key(), position))
+        .collect();
+    for value in ours {
+        match (base.get(&value.key()), positions.get(&value.key())) {
+            (Some(&base), Some(&position)) if value != base => {
+                shared[position].merge(base, value);
+            }
+            (None, None) => shared.push(value.clone()),
+            // unchanged, or removed by another thread
+            _ => (),
+        }
+    }
+}
+
+macro_rules! merge_fields {
+    ($ty:ident, $shared:expr, $base:expr, $ours:expr; $($field:ident),*; $($keyed:ident),*) => {{
+        // naming every field makes sure that new ones aren't left out
+        let $ty { $($field: _,)* $($keyed: _,)* } = $base;
+        $(merge_value(&mut $shared.$field, &$base.$field, &$ours.$field);)*
+        $(merge_keyed(&mut $shared.$keyed, &$base.$keyed, &$ours.$keyed);)*
+    }};
+}
+
+impl Merge for Model {
+    fn merge(&mut self, base: &Self, ours: &Self) {
+        merge_fields!(
+            Model, self, base, ours;
+            search_query, compact_library, library_pins, playlist_creation_state, settings,
+            selected_playlist, views, playing_playlist, shuffle, id_counter, crossfader, master_gain_db,
+            engine, midi_devices, midi_learn, remote_url, script_draft,
+            script_error, sync_status, mirroring, history, usage, interrupted, revision;
+            items, playlists
+        );
+        if show_changed(base, ours) {
+            self.revision += 1;
+        }
+    }
+}
+
+/// Whether the interface changed the show, leaving aside the playback
+/// positions it animates every frame.
+fn show_changed(base: &Model, ours: &Model) -> bool {
+    let item_changed = |(base, ours): (&Item, &Item)| {
+        base != ours && {
+            let mut ours = ours.clone();
+            ours.status = base.status.clone();
+            ours.position = base.position;
+            ours.target_position = base.target_position;
+            *base != ours
+        }
+    };
+    base.items.len() != ours.items.len()
+        || base.items.iter().zip(&ours.items).any(item_changed)
+        || base.playlists != ours.playlists
+        || base.selected_playlist != ours.selected_playlist
+        || base.playing_playlist != ours.playing_playlist
+        || base.shuffle != ours.shuffle
+        || base.id_counter != ours.id_counter
+        || base.crossfader != ours.crossfader
+        || base.master_gain_db != ours.master_gain_db
+}
+
+impl Merge for Item {
+    fn merge(&mut self, base: &Self, ours: &Self) {
+        merge_fields!(
+            Item, self, base, ours;
+            id, name, stems, current_stem, volume, gain_db, muted, on_end, status, colour, bars,
+            rms_bars, channel_bars, channel_rms_bars, position, target_position, duration,
+            issues, source_playlist, loop_region, output, output_pair, pinned, spectrum, tempo,
+            key, loudness, silences, stop_on_silence, round_robin, icon, trim, fade_in,
+            fade_out, pitch, signal, speech;
+        );
+    }
+}
+
+impl Merge for Playlist {
+    fn merge(&mut self, base: &Self, ours: &Self) {
+        merge_fields!(
+            Playlist, self, base, ours;
+            id, name, description, items, standby, grid, output, output_pair, follows,
+            loudness_target, compact, fade_in, fade_out, crossfade;
+        );
+    }
+}
+
+/// Bring a copy of a list up to date, cloning only what differs.
+pub fn refresh<T: PartialEq + Clone>(copy: &mut Vec<T>, original: &[T]) {
+    copy.truncate(original.len());
+    for (value, original) in copy.iter_mut().zip(original) {
+        if value != original {
+            *value = original.clone();
+        }
+    }
+    let len = copy.len();
+    copy.extend_from_slice(&original[len..]);
+}
+
+macro_rules! refresh_fields {
+    ($ty:ident, $copy:expr, $original:expr; $($field:ident),*; $($list:ident),*) => {{
+        let $ty { $($field: _,)* $($list: _,)* } = $original;
+        $(
+            if $copy.$field != $original.$field {
+                $copy.$field = $original.$field.clone();
+            }
+        )*
+        $(refresh(&mut $copy.$list, &$original.$list);)*
+    }};
+}
+
+impl Model {
+    /// Bring a copy of the model up to date. Unlike cloning, this only clones
+    /// what has changed.
+    pub fn refresh_from(&mut self, original: &Model) {
+        refresh_fields!(
+            Model, self, original;
+            search_query, compact_library, library_pins, playlist_creation_state, settings,
+            selected_playlist, views, playing_playlist, shuffle, id_counter, crossfader, master_gain_db,
+            engine, midi_devices, midi_learn, remote_url, script_draft,
+            script_error, sync_status, mirroring, usage, interrupted, revision;
+            items, playlists, history
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn item(id: u64) -> Item {
+        Item::with_default_stem(
+            id,
+            format!("item {}", id),
+            String::new(),
+            Colour::BLACK,
+            1.0,
+        )
+    }
+
+    #[test]
+    fn merging() {
+        let base = Model {
+            items: vec![item(0), item(1), item(2)],
+            ..Model::default()
+        };
+
+        // the interface renames an item, adds one and removes another
+        let mut ours = base.clone();
+        ours.items[0].name = "renamed".to_string();
+        ours.items[1].status = ItemStatus::Loading;
+        ours.items.remove(2);
+        ours.items.push(item(4));
+        ours.search_query = "rain".to_string();
+
+        // meanwhile the engine starts an item and the importer adds one
+        let mut shared = base.clone();
+        shared.items[1].status = ItemStatus::Playing;
+        shared.items[1].target_position = 0.5;
+        shared.items.push(item(3));
+
+        shared.merge(&base, &ours);
+        let ids: Vec<_> = shared.items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, vec![0, 1, 3, 4]);
+        assert_eq!(shared.items[0].name, "renamed");
+        // the engine knows better
+        assert_eq!(shared.items[1].status, ItemStatus::Playing);
+        assert_eq!(shared.items[1].target_position, 0.5);
+        assert_eq!(shared.search_query, "rain");
+
+        let mut copy = base;
+        copy.refresh_from(&shared);
+        assert_eq!(copy, shared);
+    }
+
+    #[test]
+    fn counting_revisions() {
+        let base = Model {
+            items: vec![item(0)],
+            ..Model::default()
+        };
+
+        // animating the playback position doesn't change the show
+        let mut ours = base.clone();
+        ours.items[0].position = 0.2;
+        let mut shared = base.clone();
+        shared.merge(&base, &ours);
+        assert_eq!(shared.revision, 0);
+
+        ours.items[0].name = "renamed".to_string();
+        shared.merge(&base, &ours);
+        assert_eq!(shared.revision, 1);
+    }
+}
@@ -0,0 +1,490 @@
+//! Parsing MIDI input and mapping it to control messages. Reading from the
+//! devices themselves is left to the frontend.
+
+use crate::model::*;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Sender;
+
+/// A channel voice message relevant to controlling afx.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+}
+
+impl MidiMessage {
+    pub fn trigger(&self) -> MidiTrigger {
+        match *self {
+            MidiMessage::NoteOn { channel, note, .. } => MidiTrigger::Note { channel, note },
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                ..
+            } => MidiTrigger::Control {
+                channel,
+                controller,
+            },
+        }
+    }
+}
+
+/// Turns a raw MIDI byte stream into messages, keeping track of running
+/// status and skipping system exclusive and real-time messages.
+#[derive(Debug, Default)]
+pub struct MidiParser {
+    status: Option<u8>,
+    data: Vec<u8>,
+    in_sysex: bool,
+}
+
+impl MidiParser {
+    pub fn feed(&mut self, byte: u8) -> Option<MidiMessage> {
+        match byte {
+            // real-time messages may appear anywhere, even between data bytes
+            0xF8..=0xFF => return None,
+            0xF0 => {
+                self.in_sysex = true;
+                self.status = None;
+                return None;
+            }
+            0xF7 => {
+                self.in_sysex = false;
+                return None;
+            }
+            0x80..=0xEF => {
+                self.in_sysex = false;
+                self.status = Some(byte);
+                self.data.clear();
+                return None;
+            }
+            0xF1..=0xF6 => {
+                self.in_sysex = false;
+                self.status = None;
+                return None;
+            }
+            _ => (),
+        }
+        if self.in_sysex {
+            return None;
+        }
+
+        let status = self.status?;
+        self.data.push(byte);
+        let expected = match status & 0xF0 {
+            0xC0 | 0xD0 => 1,
+            _ => 2,
+        };
+        if self.data.len() < expected {
+            return None;
+        }
+
+        let data = std::mem::take(&mut self.data);
+        let channel = status & 0x0F;
+        match (status & 0xF0, data.as_slice()) {
+            // a note-on with zero velocity is a note-off
+            (0x90, &[note, velocity]) if velocity > 0 => Some(MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            }),
+            (0xB0, &[controller, value]) => Some(MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum MidiTrigger {
+    Note { channel: u8, note: u8 },
+    Control { channel: u8, controller: u8 },
+}
+
+impl std::fmt::Display for MidiTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MidiTrigger::Note { channel, note } => write!(f, "note {} (ch {})", note, channel + 1),
+            MidiTrigger::Control {
+                channel,
+                controller,
+            } => write!(f, "CC {} (ch {})", controller, channel + 1),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum MidiTarget {
+    /// Play or pause the item on note-on.
+    Item(u64),
+    /// Set the volume of the item from a controller.
+    ItemVolume(u64),
+    /// Set the master volume from a controller.
+    MasterVolume,
+    /// Switch the grid of the selected playlist to the bank, counted from
+    /// zero, on note-on.
+    Bank(u8),
+    /// Fire the standby cue of the selected playlist on note-on, like GO.
+    Go,
+}
+
+impl MidiTarget {
+    /// Whether the message can drive the target. Items, banks and GO are
+    /// triggered by notes, volumes are set by controllers.
+    pub fn accepts(&self, message: &MidiMessage) -> bool {
+        matches!(
+            (self, message),
+            (
+                MidiTarget::Item(_) | MidiTarget::Bank(_) | MidiTarget::Go,
+                MidiMessage::NoteOn { .. }
+            ) | (
+                MidiTarget::ItemVolume(_) | MidiTarget::MasterVolume,
+                MidiMessage::ControlChange { .. }
+            )
+        )
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct MidiBinding {
+    /// The device the binding listens to, or any device if unset.
+    pub device: Option<String>,
+    pub trigger: MidiTrigger,
+    pub target: MidiTarget,
+}
+
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiSettings {
+    pub bindings: Vec<MidiBinding>,
+}
+
+/// Map a controller value onto the volume fader, in decibels.
+pub fn control_to_db(value: u8) -> f64 {
+    SILENCE_DB + (MAX_GAIN_DB - SILENCE_DB) * value as f64 / 127.0
+}
+
+/// Bind the target armed in MIDI learn mode to the message, if it fits.
+/// Any previous binding of the target or of the control is replaced. Returns
+/// whether a binding was created.
+fn learn(device: &str, message: MidiMessage, model: &mut Model) -> bool {
+    let Some(target) = model.midi_learn.filter(|target| target.accepts(&message)) else {
+        return false;
+    };
+    let trigger = message.trigger();
+    let bindings = &mut model.settings.midi.bindings;
+    bindings.retain(|binding| {
+        binding.target != target
+            && !(binding.trigger == trigger && binding.device.as_deref() == Some(device))
+    });
+    bindings.push(MidiBinding {
+        device: Some(device.to_string()),
+        trigger,
+        target,
+    });
+    model.midi_learn = None;
+    true
+}
+
+/// Act on a message received from the named device according to the
+/// configured bindings, or learn a new binding if one is armed.
+pub fn handle_message(
+    device: &str,
+    message: MidiMessage,
+    model: &mut Model,
+    channel: &Sender<ControlMessage>,
+) {
+    if learn(device, message, model) {
+        return;
+    }
+
+    let targets: Vec<_> = model
+        .settings
+        .midi
+        .bindings
+        .iter()
+        .filter(|binding| binding.device.as_ref().is_none_or(|d| d == device))
+        .filter(|binding| binding.trigger == message.trigger())
+        .map(|binding| binding.target)
+        .collect();
+
+    for target in targets {
+        match (target, message) {
+            (MidiTarget::Item(id), MidiMessage::NoteOn { .. }) => {
+                if let Some(item) = model.items.iter_mut().find(|item| item.id == id) {
+                    item.toggle_playback(channel);
+                }
+            }
+            (MidiTarget::ItemVolume(id), MidiMessage::ControlChange { value, .. }) => {
+                let Some(item) = model.items.iter_mut().find(|item| item.id == id) else {
+                    continue;
+                };
+                item.volume = db_to_amplitude(control_to_db(value));
+                channel
+                    .send(ControlMessage::SetVolume(id, item.volume))
+                    .unwrap();
+            }
+            (MidiTarget::MasterVolume, MidiMessage::ControlChange { value, .. }) => {
+                model.master_gain_db = control_to_db(value);
+                channel
+                    .send(ControlMessage::SetMasterVolume(db_to_amplitude(
+                        model.master_gain_db,
+                    )))
+                    .unwrap();
+            }
+            (MidiTarget::Bank(bank), MidiMessage::NoteOn { .. }) => {
+                let selected = model.selected_playlist;
+                let grid = model
+                    .playlists
+                    .iter_mut()
+                    .find(|playlist| Some(playlist.id) == selected)
+                    .and_then(|playlist| playlist.grid.as_mut());
+                if let Some(grid) = grid.filter(|_| bank < BANKS) {
+                    grid.bank = bank;
+                    model.revision += 1;
+                }
+            }
+            (MidiTarget::Go, MidiMessage::NoteOn { .. }) => {
+                if let Some(cue) = model.fire_standby() {
+                    channel.send(cue).unwrap();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn parse_stream() {
+        let mut parser = MidiParser::default();
+        let bytes = [
+            0x91, 36, 100, // note-on
+            37, 0xF8, 90, // running status with a clock tick in between
+            38, 0, // running status note-off
+            0xF0, 1, 2, 3, 0xF7, // sysex
+            0xB0, 7, 64, // control change
+        ];
+        let messages: Vec<_> = bytes.iter().filter_map(|&b| parser.feed(b)).collect();
+        assert_eq!(
+            messages,
+            vec![
+                MidiMessage::NoteOn {
+                    channel: 1,
+                    note: 36,
+                    velocity: 100
+                },
+                MidiMessage::NoteOn {
+                    channel: 1,
+                    note: 37,
+                    velocity: 90
+                },
+                MidiMessage::ControlChange {
+                    channel: 0,
+                    controller: 7,
+                    value: 64
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bindings() {
+        let mut model = Model {
+            items: vec![Item::with_default_stem(
+                0,
+                "test".to_string(),
+                "test.wav".to_string(),
+                Colour::BLACK,
+                1.0,
+            )],
+            ..Model::default()
+        };
+        let note = MidiTrigger::Note {
+            channel: 0,
+            note: 36,
+        };
+        let control = MidiTrigger::Control {
+            channel: 0,
+            controller: 7,
+        };
+        model.settings.midi.bindings = vec![
+            MidiBinding {
+                device: Some("pads".to_string()),
+                trigger: note,
+                target: MidiTarget::Item(0),
+            },
+            MidiBinding {
+                device: None,
+                trigger: control,
+                target: MidiTarget::ItemVolume(0),
+            },
+        ];
+        let (tx, rx) = channel();
+        let note_on = MidiMessage::NoteOn {
+            channel: 0,
+            note: 36,
+            velocity: 127,
+        };
+
+        handle_message("keys", note_on, &mut model, &tx);
+        assert!(rx.try_recv().is_err());
+
+        handle_message("pads", note_on, &mut model, &tx);
+        assert_eq!(rx.try_recv(), Ok(ControlMessage::Play(0)));
+        assert_eq!(model.items[0].status, ItemStatus::Loading);
+
+        let fader_down = MidiMessage::ControlChange {
+            channel: 0,
+            controller: 7,
+            value: 0,
+        };
+        handle_message("keys", fader_down, &mut model, &tx);
+        assert_eq!(rx.try_recv(), Ok(ControlMessage::SetVolume(0, 0.0)));
+        assert_eq!(model.items[0].volume, 0.0);
+    }
+
+    #[test]
+    fn learning() {
+        let mut model = Model::default();
+        let (tx, rx) = channel();
+        let note_on = MidiMessage::NoteOn {
+            channel: 2,
+            note: 40,
+            velocity: 127,
+        };
+        let knob = MidiMessage::ControlChange {
+            channel: 0,
+            controller: 21,
+            value: 127,
+        };
+
+        model.midi_learn = Some(MidiTarget::MasterVolume);
+        // notes can't set volumes, so the note is handled as usual
+        handle_message("pads", note_on, &mut model, &tx);
+        assert_eq!(model.midi_learn, Some(MidiTarget::MasterVolume));
+        assert!(model.settings.midi.bindings.is_empty());
+
+        handle_message("pads", knob, &mut model, &tx);
+        assert_eq!(model.midi_learn, None);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(
+            model.settings.midi.bindings,
+            vec![MidiBinding {
+                device: Some("pads".to_string()),
+                trigger: knob.trigger(),
+                target: MidiTarget::MasterVolume,
+            }]
+        );
+
+        // relearning replaces the previous binding
+        model.midi_learn = Some(MidiTarget::MasterVolume);
+        let other_knob = MidiMessage::ControlChange {
+            channel: 0,
+            controller: 22,
+            value: 0,
+        };
+        handle_message("pads", other_knob, &mut model, &tx);
+        assert_eq!(model.settings.midi.bindings.len(), 1);
+        assert_eq!(
+            model.settings.midi.bindings[0].trigger,
+            other_knob.trigger()
+        );
+
+        handle_message("pads", other_knob, &mut model, &tx);
+        assert_eq!(rx.try_recv(), Ok(ControlMessage::SetMasterVolume(0.0)));
+    }
+
+    #[test]
+    fn banks_and_go() {
+        let item =
+            |id| Item::with_default_stem(id, String::new(), String::new(), Colour::BLACK, 1.0);
+        let mut playlist = Playlist::new(5, "pads".to_string(), vec![0, 1]);
+        playlist.grid = Some(Grid::new(4));
+        let mut model = Model {
+            items: vec![item(0), item(1)],
+            playlists: vec![playlist],
+            selected_playlist: Some(5),
+            ..Model::default()
+        };
+        let pad = |note| MidiBinding {
+            device: None,
+            trigger: MidiTrigger::Note { channel: 9, note },
+            target: MidiTarget::Bank(note - 36),
+        };
+        model.settings.midi.bindings = vec![
+            pad(36),
+            pad(38),
+            // past the last bank
+            pad(36 + BANKS),
+            MidiBinding {
+                device: None,
+                trigger: MidiTrigger::Note {
+                    channel: 9,
+                    note: 60,
+                },
+                target: MidiTarget::Go,
+            },
+        ];
+        let (tx, rx) = channel();
+        let hit = |note| MidiMessage::NoteOn {
+            channel: 9,
+            note,
+            velocity: 100,
+        };
+        let bank = |model: &Model| model.playlists[0].grid.as_ref().unwrap().bank;
+
+        handle_message("pads", hit(38), &mut model, &tx);
+        assert_eq!(bank(&model), 2);
+        handle_message("pads", hit(36 + BANKS), &mut model, &tx);
+        assert_eq!(bank(&model), 2);
+        handle_message("pads", hit(36), &mut model, &tx);
+        assert_eq!(bank(&model), 0);
+        assert!(rx.try_recv().is_err());
+
+        handle_message("pads", hit(60), &mut model, &tx);
+        assert_eq!(
+            rx.try_recv(),
+            Ok(ControlMessage::Cue {
+                playlist_id: 5,
+                position: 0
+            })
+        );
+        assert_eq!(model.playlists[0].standby, 1);
+
+        // controllers don't switch banks
+        let knob = MidiMessage::ControlChange {
+            channel: 0,
+            controller: 21,
+            value: 127,
+        };
+        model.midi_learn = Some(MidiTarget::Bank(3));
+        handle_message("pads", knob, &mut model, &tx);
+        assert_eq!(model.midi_learn, Some(MidiTarget::Bank(3)));
+        handle_message("pads", hit(40), &mut model, &tx);
+        assert_eq!(model.midi_learn, None);
+        assert_eq!(
+            model
+                .settings
+                .midi
+                .bindings
+                .last()
+                .map(|binding| binding.target),
+            Some(MidiTarget::Bank(3))
+        );
+    }
+}
@@ -0,0 +1,2534 @@
+//! The state of afx, the messages controlling playback, and the settings.
+
+use crate::keymap::Keymap;
+use crate::midi::{MidiSettings, MidiTarget};
+use crate::speech::SpeechEngine;
+use crate::stats::Usage;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A colour in sRGB with premultiplied alpha. It's laid out and saved like
+/// egui's `Color32`, which the interface converts it to.
+#[derive(PartialEq, Eq, Hash, Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Colour(pub [u8; 4]);
+
+impl Colour {
+    pub const BLACK: Colour = Colour::from_rgb(0, 0, 0);
+    pub const GRAY: Colour = Colour::from_rgb(160, 160, 160);
+
+    pub const fn from_rgb(r: u8, g: u8, b: u8) -> Colour {
+        Colour([r, g, b, 255])
+    }
+
+    pub const fn from_rgba_premultiplied(r: u8, g: u8, b: u8, a: u8) -> Colour {
+        Colour([r, g, b, a])
+    }
+
+    pub const fn to_array(self) -> [u8; 4] {
+        self.0
+    }
+}
+
+#[rustfmt::skip]
+mod colours {
+    use super::*;
+    pub const ORANGE   : Colour = Colour::from_rgb(240, 135, 35);
+    pub const YELLOW   : Colour = Colour::from_rgb(230, 200, 50);
+    pub const PURPLE   : Colour = Colour::from_rgb(110, 60,  200);
+    pub const PINK     : Colour = Colour::from_rgb(240, 140, 170);
+    pub const BURGUNDY : Colour = Colour::from_rgb(119, 51,  85);
+    pub const SALMON   : Colour = Colour::from_rgb(220, 130, 140);
+    pub const TEAL     : Colour = Colour::from_rgb(40,  150, 190);
+    pub const BROWN    : Colour = Colour::from_rgb(102, 51,  46);
+    pub const CREAM    : Colour = Colour::from_rgb(238, 221, 170);
+    pub const RED      : Colour = Colour::from_rgb(230, 70,  70);
+    pub const GREEN    : Colour = Colour::from_rgb(70,  175, 70);
+    pub const BLUE     : Colour = Colour::from_rgb(40,  120, 220);
+}
+
+pub use colours::*;
+/// The colours given to imported items, in turn.
+pub const PALETTE: [Colour; 12] = [
+    ORANGE, YELLOW, PURPLE, PINK, BURGUNDY, SALMON, TEAL, BROWN, CREAM, RED, GREEN, BLUE,
+];
+/// Colours told apart with any kind of colour blindness, after Okabe and Ito,
+/// with grey in place of black so that it shows on the dark theme.
+#[rustfmt::skip]
+pub const ACCESSIBLE_PALETTE: [Colour; 8] = [
+    Colour::from_rgb(230, 159, 0),
+    Colour::from_rgb(86,  180, 233),
+    Colour::from_rgb(0,   158, 115),
+    Colour::from_rgb(240, 228, 66),
+    Colour::from_rgb(0,   114, 178),
+    Colour::from_rgb(213, 94,  0),
+    Colour::from_rgb(204, 121, 167),
+    Colour::from_rgb(153, 153, 153),
+];
+/// How many textures tell apart the colours of the [`PALETTE`] sharing one
+/// of the [`ACCESSIBLE_PALETTE`], the first being none.
+pub const TEXTURES: usize = 3;
+
+/// The colour of the [`ACCESSIBLE_PALETTE`] standing in for an item colour,
+/// along with a texture below [`TEXTURES`]. No two colours of the
+/// [`PALETTE`] get the same pair, and other colours get the pair of the
+/// closest one.
+pub fn accessible_colour(colour: Colour) -> (Colour, usize) {
+    let distance = |other: &Colour| {
+        let [r, g, b, _] = colour.to_array().map(i32::from);
+        let [r2, g2, b2, _] = other.to_array().map(i32::from);
+        (r - r2).pow(2) + (g - g2).pow(2) + (b - b2).pow(2)
+    };
+    let (index, _) = PALETTE
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, other)| distance(other))
+        .unwrap();
+    (
+        ACCESSIBLE_PALETTE[index % ACCESSIBLE_PALETTE.len()],
+        index % TEXTURES,
+    )
+}
+
+/// How many bars the waveform overview of an item gets for each second of
+/// it, within [`MIN_BARS`] and [`MAX_BARS`].
+pub const BARS_PER_SECOND: f64 = 8.0;
+/// The fewest bars a waveform overview has, unless the file has fewer frames.
+pub const MIN_BARS: usize = 64;
+pub const MAX_BARS: usize = 1024;
+
+#[derive(PartialEq, PartialOrd, Debug, Clone, Serialize, Deserialize)]
+pub enum ControlMessage {
+    Play(u64),
+    Pause(u64),
+    Stop(u64),
+    ChangeStem(u64, usize),
+    SyncPlaybackStatus,
+    Seek(u64, f64),
+    /// Seek by the given number of seconds from where an item is, forward or
+    /// back, but no further than its start or end.
+    SeekRelative(u64, f64),
+    Loop(u64, bool),
+    SetLoopRegion(u64, Option<(f64, f64)>),
+    Mute(u64, bool),
+    SetVolume(u64, f64),
+    /// Shift an item in pitch by the given number of semitones.
+    SetPitch(u64, f64),
+    SetMasterVolume(f64),
+    Delete(u64),
+    AddToPlaylist {
+        item_id: u64,
+        playlist_id: u64,
+    },
+    RemoveFromPlaylist {
+        pos_within_playlist: usize,
+        playlist_id: u64,
+    },
+    PlayFromPlaylist(u64),
+    GlobalPause,
+    /// Resume the items paused by global pauses since the last resume, each
+    /// as it was before.
+    GlobalResume,
+    GlobalStop,
+    /// Stop everything, fading out over the given number of seconds.
+    GlobalFadeOut(f64),
+    Crossfade(Crossfader),
+    /// Fire the cue at a position of a playlist, following on to the next
+    /// cues as they are set to.
+    Cue {
+        playlist_id: u64,
+        position: usize,
+    },
+    /// Replace the audio output, which may have stopped working after the
+    /// system woke up. Items playing through it are paused.
+    RecoverOutput,
+    /// Try loading the current stem of an item again, clearing its issues if
+    /// it loads, without playing it.
+    Retry(u64),
+    /// Start several items on the same sample, such as the layers of a
+    /// stinger. Items routed to other outputs start as soon as they can.
+    PlayTogether(Vec<u64>),
+}
+
+impl ControlMessage {
+    /// The item the message concerns, if any.
+    pub fn item_id(&self) -> Option<u64> {
+        match *self {
+            ControlMessage::Play(id)
+            | ControlMessage::Pause(id)
+            | ControlMessage::Stop(id)
+            | ControlMessage::ChangeStem(id, _)
+            | ControlMessage::Seek(id, _)
+            | ControlMessage::SeekRelative(id, _)
+            | ControlMessage::Loop(id, _)
+            | ControlMessage::SetLoopRegion(id, _)
+            | ControlMessage::Mute(id, _)
+            | ControlMessage::SetVolume(id, _)
+            | ControlMessage::SetPitch(id, _)
+            | ControlMessage::Delete(id)
+            | ControlMessage::Retry(id)
+            | ControlMessage::AddToPlaylist { item_id: id, .. } => Some(id),
+            _ => None,
+        }
+    }
+}
+
+/// A problem reported by a background thread, shown to the user as a toast.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Notification {
+    pub message: String,
+    /// The item the problem concerns, if any.
+    pub item: Option<u64>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ImportMessage {
+    Cancelled,
+    /// How far along the download of the named file to import is, from 0
+    /// to 1.
+    Downloading(String, f32),
+    Update(u64, ItemImportStatus),
+    Finished(Vec<Item>),
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+pub enum ItemImportStatus {
+    Queued(String),
+    Waiting,
+    InProgress,
+    Finished,
+    Failed(String),
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize, Deserialize)]
+pub struct Stem {
+    pub tag: String,
+    pub path: String,
+    /// Technical details of the file, probed at import.
+    pub info: Option<FileInfo>,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize, Deserialize)]
+pub struct FileInfo {
+    pub codec: String,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+    pub bits_per_sample: Option<u32>,
+    /// The length of the track, in frames, if the file tells.
+    pub frames: Option<u64>,
+    /// The size of the file, in bytes.
+    pub file_size: u64,
+    /// When the file was last modified, if the platform keeps track.
+    pub modified: Option<SystemTime>,
+}
+
+impl FileInfo {
+    /// The length of the track in seconds, if the file tells.
+    pub fn duration(&self) -> Option<f64> {
+        let (frames, rate) = (self.frames?, self.sample_rate?);
+        (rate > 0).then(|| frames as f64 / rate as f64)
+    }
+}
+
+impl std::fmt::Display for FileInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.codec)?;
+        if let Some(rate) = self.sample_rate {
+            write!(f, ", {:.1} kHz", rate as f64 / 1000.0)?;
+        }
+        if let Some(channels) = self.channels {
+            write!(f, ", {} ch", channels)?;
+        }
+        if let Some(bits) = self.bits_per_sample {
+            write!(f, ", {} bit", bits)?;
+        }
+        write!(f, ", {:.1} MB", self.file_size as f64 / 1_000_000.0)
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize, Deserialize)]
+pub enum ItemStatus {
+    Stopped,
+    Loading,
+    Playing,
+    Paused,
+}
+
+pub type Issue = (IssueType, String);
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize, Deserialize)]
+pub enum IssueType {
+    MissingFile,
+    InaccessibleFile,
+    PlaybackProblem,
+    LicensingIssue,
+    OtherError,
+    OtherWarning,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Item {
+    pub id: u64,
+    pub name: String,
+    pub stems: Vec<Stem>,
+    pub current_stem: usize,
+    /// The level of the fader, ridden live.
+    pub volume: f64,
+    /// The gain trim in decibels, set once to balance the source material
+    /// against the rest of the library, so that it doesn't take up the range
+    /// of the fader.
+    pub gain_db: f64,
+    pub muted: bool,
+    pub on_end: OnEnd,
+    pub status: ItemStatus,
+    pub colour: Colour,
+    /// The peak level of each stretch of the track.
+    pub bars: Vec<u8>,
+    /// The RMS level of the same stretches, on the scale of the peaks. Empty
+    /// for items analysed before it was introduced.
+    pub rms_bars: Vec<u8>,
+    /// The bars of the left and right channel, on the same scale as each
+    /// other. Empty for items analysed before they were introduced.
+    pub channel_bars: [Vec<u8>; 2],
+    /// The RMS levels of the left and right channel, on the scale of their
+    /// peaks.
+    pub channel_rms_bars: [Vec<u8>; 2],
+    /// The position within the track, in seconds.
+    ///
+    /// This should only ever be read, since it is animated by target_position.
+    pub position: f64,
+    /// The target (real) position within the track, in seconds.
+    ///
+    /// This is effectively owned by the playback thread.
+    /// Changes from elsewhere will be overwritten.
+    pub target_position: f64,
+    pub duration: f64,
+    pub issues: Vec<Issue>,
+    /// The playlist this item was last started from, which decides the bus
+    /// it is played through.
+    pub source_playlist: Option<u64>,
+    /// The start and end of the looped part of the track, in seconds. The
+    /// whole track is looped if unset.
+    pub loop_region: Option<(f64, f64)>,
+    /// The output device the item is routed to, overriding the output of
+    /// its playlist.
+    pub output: Option<String>,
+    /// The pair of channels of the output the item is played through,
+    /// counted from zero, so that pair 1 stands for channels 3 and 4.
+    /// Overrides the output of its playlist unless zero.
+    pub output_pair: usize,
+    /// Whether the sound is kept in memory, so that it starts without
+    /// reading from disk.
+    pub pinned: bool,
+    /// The live spectrum of the playing sound, in the same scale as the
+    /// bars. Empty unless enabled in the settings.
+    #[serde(skip)]
+    pub spectrum: Vec<u8>,
+    /// The beats of the sound, if it has a steady tempo.
+    pub tempo: Option<Tempo>,
+    /// The musical key of the sound, if it has a clear one.
+    pub key: Option<Key>,
+    /// The integrated loudness of the sound in LUFS, unless it's silent.
+    pub loudness: Option<f64>,
+    /// The stretches of near-silence in the sound, as their start and end in
+    /// seconds.
+    pub silences: Vec<(f64, f64)>,
+    /// How many seconds of near-silence stop the item, as if it had ended.
+    pub stop_on_silence: Option<f64>,
+    /// Whether each start plays the next of the stems, going round them as
+    /// variations of the same sound.
+    pub round_robin: bool,
+    /// An emoji shown before the name, to tell items apart at a glance.
+    pub icon: Option<String>,
+    /// The start and end of the part of the track that is played, in
+    /// seconds. The whole track is played if unset.
+    pub trim: Option<(f64, f64)>,
+    /// How long the item fades in from the start of its trimmed part, in
+    /// seconds.
+    pub fade_in: f64,
+    /// How long the item fades out before the end of its trimmed part, in
+    /// seconds.
+    pub fade_out: f64,
+    /// How far the sound is shifted in pitch, in semitones. Like a tape
+    /// played faster or slower, its speed changes along with it. Test
+    /// signals and streams play at their own pitch.
+    pub pitch: f64,
+    /// The test signal played in place of the stems, for items that don't
+    /// reference a file.
+    pub signal: Option<Signal>,
+    /// The text spoken by the item, whose stem is the speech rendered from
+    /// it, see [`crate::speech`].
+    pub speech: Option<String>,
+}
+
+/// Trim points and fades worked out from the silences of an item.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Suggestion {
+    pub trim: Option<(f64, f64)>,
+    pub fade_in: f64,
+    pub fade_out: f64,
+}
+
+/// The furthest an item can be shifted in pitch, in semitones.
+pub const MAX_PITCH: f64 = 12.0;
+/// The most the gain trim of an item can raise or lower it, in decibels.
+pub const MAX_GAIN_TRIM: f64 = 24.0;
+/// The shortest part of the trimmed part of an item its variations keep.
+const MIN_VARIED_TRIM: f64 = 0.6;
+
+/// How far the copies made by [`Item::varied`] stray from the original at
+/// most.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Variation {
+    /// Semitones up or down.
+    pub pitch: f64,
+    /// Decibels up or down.
+    pub gain_db: f64,
+    /// Whether the trimmed part is shortened and moved along the track.
+    pub trim: bool,
+}
+
+impl Default for Variation {
+    fn default() -> Self {
+        Self {
+            pitch: 1.0,
+            gain_db: 3.0,
+            trim: false,
+        }
+    }
+}
+
+/// Convert a shift in pitch to the playback rate sounding it.
+pub fn semitones_to_rate(semitones: f64) -> f64 {
+    2f64.powf(semitones / 12.0)
+}
+
+/// A fade just long enough to keep a cut from clicking, in seconds.
+pub const DECLICK_FADE: f64 = 0.01;
+/// The fade suggested for items cut off while still sounding, in seconds.
+/// Never more than a quarter of what is played.
+pub const CUT_OFF_FADE: f64 = 2.0;
+/// How close to either end of the track a silence must be to be trimmed off,
+/// in seconds.
+const SILENCE_EDGE: f64 = 0.1;
+
+/// What an item does once it plays to the end.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum OnEnd {
+    #[default]
+    Stop,
+    /// Start over, from the start of the loop region if there is one.
+    Loop,
+    /// Play the item after it in the playlist it was started from.
+    Next,
+    /// Play another item of the playlist it was started from, at random.
+    Random,
+    /// Play the item of this id.
+    Play(u64),
+}
+
+/// A test signal, generated rather than read from a file.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Signal {
+    /// A sine tone at the given frequency, in hertz.
+    Sine(f64),
+    WhiteNoise,
+    PinkNoise,
+}
+
+impl std::fmt::Display for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Signal::Sine(frequency) => write!(f, "{} Hz tone", frequency),
+            Signal::WhiteNoise => write!(f, "White noise"),
+            Signal::PinkNoise => write!(f, "Pink noise"),
+        }
+    }
+}
+
+/// A musical key.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Key {
+    /// The pitch class of the tonic, from 0 for C up to 11 for B.
+    pub tonic: u8,
+    pub minor: bool,
+}
+
+impl Key {
+    const NOTES: [&'static str; 12] = [
+        "C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+    ];
+
+    /// The position of the key on the Camelot wheel, from 1 to 12, along
+    /// with `'A'` for minor or `'B'` for major keys. Keys mix well with those
+    /// next to them on the wheel.
+    pub fn camelot(&self) -> (u8, char) {
+        // relative keys share a number, and the numbers go up in fifths
+        let major_tonic = if self.minor {
+            (self.tonic + 3) % 12
+        } else {
+            self.tonic
+        };
+        let number = (major_tonic * 7 + 7) % 12 + 1;
+        (number, if self.minor { 'A' } else { 'B' })
+    }
+
+    pub fn from_camelot(number: u8, letter: char) -> Option<Key> {
+        let minor = match letter.to_ascii_uppercase() {
+            'A' => true,
+            'B' => false,
+            _ => return None,
+        };
+        if !(1..=12).contains(&number) {
+            return None;
+        }
+        // seven fifths up is a semitone up, undoing the wheel
+        let major_tonic = ((number + 12 - 8) * 7) % 12;
+        let tonic = if minor {
+            (major_tonic + 9) % 12
+        } else {
+            major_tonic
+        };
+        Some(Key { tonic, minor })
+    }
+
+    /// Read a key written like `Am`, `F# major`, `Eb minor` or `8A`.
+    pub fn parse(text: &str) -> Option<Key> {
+        let text = text.trim();
+        if let Some(letter) = text.chars().last().filter(|c| c.is_ascii_alphabetic()) {
+            if let Ok(number) = text[..text.len() - 1].parse() {
+                return Key::from_camelot(number, letter);
+            }
+        }
+
+        let mut chars = text.chars();
+        let letter = chars.next()?.to_ascii_uppercase();
+        let natural = match letter {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let accidental = match chars.clone().next() {
+            Some('#' | '♯') => 1,
+            Some('b' | '♭') => 11,
+            _ => 0,
+        };
+        if accidental != 0 {
+            chars.next();
+        }
+        let (tonic, rest) = (natural + accidental, chars.as_str());
+        let minor = match rest.trim().to_lowercase().as_str() {
+            "" | "maj" | "major" => false,
+            "m" | "min" | "minor" => true,
+            _ => return None,
+        };
+        Some(Key {
+            tonic: tonic % 12,
+            minor,
+        })
+    }
+
+    /// The keys that mix well with this one, itself included: its relative
+    /// key and its neighbours on the Camelot wheel.
+    pub fn compatible(&self) -> [Key; 4] {
+        let (number, letter) = self.camelot();
+        let relative = if letter == 'A' { 'B' } else { 'A' };
+        [
+            *self,
+            Key::from_camelot(number % 12 + 1, letter).unwrap(),
+            Key::from_camelot((number + 10) % 12 + 1, letter).unwrap(),
+            Key::from_camelot(number, relative).unwrap(),
+        ]
+    }
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (number, letter) = self.camelot();
+        let mode = if self.minor { "m" } else { "" };
+        write!(
+            f,
+            "{}{} ({}{})",
+            Key::NOTES[self.tonic as usize],
+            mode,
+            number,
+            letter
+        )
+    }
+}
+
+/// Where the beats of a sound fall.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Tempo {
+    pub bpm: f64,
+    /// The time of the first beat, in seconds. Bars are counted from it.
+    pub offset: f64,
+}
+
+impl Tempo {
+    pub const BEATS_PER_BAR: i64 = 4;
+
+    pub fn beat_length(&self) -> f64 {
+        60.0 / self.bpm
+    }
+
+    /// The times of the beats from the start of the sound until the given
+    /// time, along with whether each of them starts a bar.
+    pub fn beats(&self, until: f64) -> impl Iterator<Item = (f64, bool)> + '_ {
+        let beat = self.beat_length();
+        let first = -(self.offset / beat).floor() as i64;
+        (first..)
+            .map(move |index| (self.offset + index as f64 * beat, index))
+            .take_while(move |&(time, _)| time <= until)
+            .map(|(time, index)| (time, index.rem_euclid(Self::BEATS_PER_BAR) == 0))
+    }
+
+    /// The beat closest to the given time.
+    pub fn snap(&self, time: f64) -> f64 {
+        let beat = self.beat_length();
+        self.offset + ((time - self.offset) / beat).round() * beat
+    }
+}
+
+/// Fills in the fields missing from saved items.
+impl Default for Item {
+    fn default() -> Self {
+        Item {
+            stems: vec![],
+            ..Item::with_default_stem(0, String::new(), String::new(), Colour::GRAY, 0.0)
+        }
+    }
+}
+
+impl Item {
+    pub fn with_default_stem(
+        id: u64,
+        name: String,
+        path: String,
+        colour: Colour,
+        duration: f64,
+    ) -> Item {
+        Item {
+            id,
+            name,
+            stems: vec![Stem {
+                tag: "default".to_string(),
+                path,
+                info: None,
+            }],
+            current_stem: 0,
+            volume: 1.0,
+            gain_db: 0.0,
+            muted: false,
+            on_end: OnEnd::Stop,
+            status: ItemStatus::Stopped,
+            colour,
+            bars: vec![],
+            rms_bars: vec![],
+            channel_bars: [vec![], vec![]],
+            channel_rms_bars: [vec![], vec![]],
+            position: 0.0,
+            target_position: 0.0,
+            duration,
+            issues: vec![],
+            source_playlist: None,
+            loop_region: None,
+            output: None,
+            output_pair: 0,
+            pinned: false,
+            spectrum: vec![],
+            tempo: None,
+            key: None,
+            loudness: None,
+            silences: vec![],
+            stop_on_silence: None,
+            round_robin: false,
+            icon: None,
+            trim: None,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            pitch: 0.0,
+            signal: None,
+            speech: None,
+        }
+    }
+
+    /// An item speaking the given text. It has nothing to play until the
+    /// speech is rendered into its stem.
+    pub fn speech(id: u64, text: String, colour: Colour) -> Item {
+        let name = crate::speech::speech_name(&text);
+        let mut item = Item::with_default_stem(id, name, String::new(), colour, 0.0);
+        item.stems[0].tag = "speech".to_string();
+        item.speech = Some(text);
+        item
+    }
+
+    /// An item playing the internet radio stream at the given address.
+    pub fn stream(id: u64, url: String, colour: Colour) -> Item {
+        let name = crate::radio::stream_name(&url);
+        let mut item = Item::with_default_stem(id, name, url, colour, 0.0);
+        item.stems[0].tag = "stream".to_string();
+        item
+    }
+
+    /// An item playing a test signal instead of a file.
+    pub fn generated(id: u64, signal: Signal, colour: Colour) -> Item {
+        let duration = crate::generator::DEFAULT_DURATION;
+        let mut item =
+            Item::with_default_stem(id, signal.to_string(), String::new(), colour, duration);
+        item.stems[0].tag = "generated".to_string();
+        item.signal = Some(signal);
+        item
+    }
+
+    /// Start the item if it's stopped or paused, pause it if it's playing.
+    pub fn toggle_playback(&mut self, channel: &Sender<ControlMessage>) {
+        match self.status {
+            ItemStatus::Stopped | ItemStatus::Paused => {
+                self.status = ItemStatus::Loading;
+                channel.send(ControlMessage::Play(self.id)).unwrap();
+            }
+            ItemStatus::Playing => {
+                self.status = ItemStatus::Paused;
+                channel.send(ControlMessage::Pause(self.id)).unwrap();
+            }
+            ItemStatus::Loading => (),
+        }
+    }
+
+    /// The start and end of the looped part of the track.
+    pub fn loop_bounds(&self) -> (f64, f64) {
+        self.loop_region.unwrap_or((0.0, self.duration))
+    }
+
+    /// Whether the item plays an internet radio stream, which has no duration
+    /// and can't be sought through.
+    pub fn is_stream(&self) -> bool {
+        self.signal.is_none() && crate::radio::is_stream_url(&self.stems[self.current_stem].path)
+    }
+
+    pub fn looped(&self) -> bool {
+        self.on_end == OnEnd::Loop
+    }
+
+    /// Loop the item, or stop it at the end if it was looped.
+    pub fn set_looped(&mut self, looped: bool) {
+        if looped {
+            self.on_end = OnEnd::Loop;
+        } else if self.looped() {
+            self.on_end = OnEnd::Stop;
+        }
+    }
+
+    /// The name, preceded by the icon if there is one.
+    pub fn labelled_name(&self) -> String {
+        match &self.icon {
+            Some(icon) => format!("{} {}", icon, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Switch to the next stem if the item goes round them, taking on its
+    /// duration if it's known.
+    pub fn rotate_stem(&mut self) {
+        if !self.round_robin || self.stems.len() < 2 {
+            return;
+        }
+        self.current_stem = (self.current_stem + 1) % self.stems.len();
+        let info = self.stems[self.current_stem].info.as_ref();
+        if let Some(duration) = info.and_then(FileInfo::duration) {
+            self.duration = duration;
+        }
+    }
+
+    /// The start and end of the played part of the track.
+    pub fn trim_bounds(&self) -> (f64, f64) {
+        self.trim.unwrap_or((0.0, self.duration))
+    }
+
+    /// How many times faster than recorded the sound plays, going by its
+    /// pitch.
+    pub fn playback_rate(&self) -> f64 {
+        semitones_to_rate(self.pitch)
+    }
+
+    /// A copy of the item under a new id, its pitch and gain trim set off at
+    /// random by at most the given variation, and its trimmed part shortened
+    /// and moved if asked to.
+    pub fn varied(&self, id: u64, variation: &Variation) -> Item {
+        self.varied_with(id, variation, &mut fastrand::Rng::new())
+    }
+
+    fn varied_with(&self, id: u64, variation: &Variation, rng: &mut fastrand::Rng) -> Item {
+        let mut offset = |range: f64| (rng.f64() * 2.0 - 1.0) * range;
+        let mut item = self.clone();
+        item.id = id;
+        item.name = format!("{} (variation)", self.name);
+        item.status = ItemStatus::Stopped;
+        item.position = 0.0;
+        item.target_position = 0.0;
+        item.spectrum.clear();
+        item.pitch = (self.pitch + offset(variation.pitch)).clamp(-MAX_PITCH, MAX_PITCH);
+        item.gain_db =
+            (self.gain_db + offset(variation.gain_db)).clamp(-MAX_GAIN_TRIM, MAX_GAIN_TRIM);
+
+        if variation.trim && self.duration > 0.0 {
+            let (start, end) = self.trim_bounds();
+            let shortest = MIN_VARIED_TRIM * (end - start);
+            let length = shortest + (offset(0.5) + 0.5) * (end - start - shortest);
+            let start = (offset(0.5) + 0.5) * (self.duration - length);
+            let end = start + length;
+            item.trim = Some((start, end));
+            item.fade_in = self.fade_in.min((end - start) / 2.0);
+            item.fade_out = self.fade_out.min((end - start) / 2.0);
+            item.loop_region = self
+                .loop_region
+                .filter(|&(loop_start, loop_end)| loop_start >= start && loop_end <= end);
+        }
+        item
+    }
+
+    /// Suggest trimming off the silences at either end of the track, with
+    /// fades to match. Sounds that rise out of or die away into silence only
+    /// need their cuts declicked, but sounds that start or end abruptly in
+    /// the recording are faded properly. Nothing is suggested until the
+    /// silences have been found.
+    pub fn suggestion(&self) -> Option<Suggestion> {
+        if self.bars.is_empty() || self.duration <= 0.0 {
+            return None;
+        }
+        let leading = self
+            .silences
+            .iter()
+            .find(|(start, _)| *start <= SILENCE_EDGE)
+            .map(|&(_, end)| end);
+        let trailing = self
+            .silences
+            .iter()
+            .find(|(_, end)| *end >= self.duration - SILENCE_EDGE)
+            .map(|&(start, _)| start);
+        let (start, end) = (leading.unwrap_or(0.0), trailing.unwrap_or(self.duration));
+        if start >= end {
+            // there's nothing but silence
+            return None;
+        }
+        let length = end - start;
+        Some(Suggestion {
+            trim: (leading.is_some() || trailing.is_some()).then_some((start, end)),
+            fade_in: DECLICK_FADE.min(length / 4.0),
+            fade_out: match trailing {
+                Some(_) => DECLICK_FADE,
+                None => CUT_OFF_FADE,
+            }
+            .min(length / 4.0),
+        })
+    }
+
+    pub fn apply(&mut self, suggestion: Suggestion) {
+        self.trim = suggestion.trim;
+        self.fade_in = suggestion.fade_in;
+        self.fade_out = suggestion.fade_out;
+    }
+
+    /// Whether the item has been silent long enough at the given position to
+    /// be stopped, if it stops on silence at all.
+    pub fn silent_at(&self, position: f64) -> bool {
+        let Some(wait) = self.stop_on_silence else {
+            return false;
+        };
+        self.silences
+            .iter()
+            .any(|&(start, end)| start <= position && position <= end && position - start >= wait)
+    }
+
+    /// Note a problem with the item, unless it's already known.
+    pub fn report(&mut self, issue: Issue) {
+        if !self.issues.contains(&issue) {
+            self.issues.push(issue);
+        }
+    }
+}
+
+/// Hands out ids for items and playlists. Copies of a counter share it, so
+/// the ids it hands out are unique across every copy of the model and every
+/// thread, without locking the model. Nothing should assume that ids are
+/// ordered the way their items and playlists are.
+#[derive(Debug, Clone, Default)]
+pub struct IdCounter(Arc<AtomicU64>);
+
+impl IdCounter {
+    pub fn new(last: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(last)))
+    }
+
+    pub fn fresh(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// The last id handed out.
+    pub fn last(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Never hand out `id` or any before it. Returns whether they could
+    /// have been handed out otherwise.
+    pub fn reserve(&self, id: u64) -> bool {
+        self.0.fetch_max(id, Ordering::SeqCst) < id
+    }
+}
+
+impl PartialEq for IdCounter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.last() == other.last()
+    }
+}
+
+impl Serialize for IdCounter {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.last().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IdCounter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(Self::new)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Model {
+    pub search_query: String,
+    /// Whether the library lists its items as single rows, see
+    /// [`Playlist::compact`].
+    pub compact_library: bool,
+    /// The items kept at the top of the library regardless of the order and
+    /// the search, in the order they were pinned.
+    pub library_pins: Vec<u64>,
+    pub items: Vec<Item>,
+    pub playlists: Vec<Playlist>,
+    pub playlist_creation_state: Option<Playlist>,
+    pub settings: Settings,
+    pub selected_playlist: Option<u64>,
+    /// The windows open next to the main one, see [`View`].
+    pub views: Vec<View>,
+    pub playing_playlist: Option<u64>,
+    pub shuffle: bool,
+    pub id_counter: IdCounter,
+    pub crossfader: Crossfader,
+    /// The gain of the main mixer track, in decibels.
+    pub master_gain_db: f64,
+    /// Reported by the playback thread, not persisted.
+    #[serde(skip)]
+    pub engine: EngineStatus,
+    /// Names of the MIDI input ports found at startup.
+    #[serde(skip)]
+    pub midi_devices: Vec<String>,
+    /// The control waiting to be bound to the next fitting MIDI message.
+    #[serde(skip)]
+    pub midi_learn: Option<MidiTarget>,
+    /// Where the web remote can be reached along with its access token, if
+    /// it's running.
+    #[serde(skip)]
+    pub remote_url: Option<String>,
+    /// The cue script being edited in the settings, until it is applied.
+    #[serde(skip)]
+    pub script_draft: Option<String>,
+    /// Why the cue script didn't load or last failed to run a command.
+    #[serde(skip)]
+    pub script_error: Option<String>,
+    /// What the primary/backup link is up to, if it's on.
+    #[serde(skip)]
+    pub sync_status: Option<String>,
+    /// Whether this is a backup currently following its primary.
+    #[serde(skip)]
+    pub mirroring: bool,
+    /// What was played during the session, recorded by the playback thread.
+    #[serde(skip)]
+    pub history: Vec<HistoryEntry>,
+    /// How much the items and playlists have been used, over all sessions.
+    pub usage: Usage,
+    /// The items left paused on startup that were playing when afx closed,
+    /// while the user is asked whether to resume them.
+    #[serde(skip)]
+    pub interrupted: Vec<u64>,
+    /// Counts the changes to the show, so that backups are only sent a new
+    /// copy of it when there is one.
+    #[serde(skip)]
+    pub revision: u64,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum PlaybackEvent {
+    /// An item started playing from the beginning.
+    Start,
+    Resume,
+    Pause,
+    Stop,
+    Seek,
+    /// A cue of a playlist was fired, by GO or by following on.
+    Cue,
+    PauseAll,
+    ResumeAll,
+    StopAll,
+}
+
+/// A line of the show report.
+#[derive(PartialEq, Debug, Clone)]
+pub struct HistoryEntry {
+    pub time: SystemTime,
+    pub event: PlaybackEvent,
+    pub item: Option<u64>,
+    /// The name of the item at the time, which may have changed since.
+    pub name: String,
+    /// How long the item played for, in seconds. Only set for starts, once
+    /// the item stops.
+    pub duration: Option<f64>,
+}
+
+/// The state of the audio engine, as last reported by the playback thread.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct EngineStatus {
+    pub backend: String,
+    /// The audio API played through.
+    pub host: AudioHost,
+    /// The output device from the settings played through, with none
+    /// standing for the default device.
+    pub output_device: Option<String>,
+    /// The audio APIs afx was built with support for.
+    pub hosts: Vec<AudioHost>,
+    /// The output device, if one was found.
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// The number of sounds the audio manager is currently playing.
+    pub voices: usize,
+    /// The number of control messages waiting to be processed.
+    pub queue_depth: usize,
+    /// The number of control messages waiting to be passed on to the
+    /// playback thread while it's busy.
+    pub backlog: usize,
+    /// The number of control messages dropped since the playback thread fell
+    /// too far behind.
+    pub dropped_messages: usize,
+    /// The output devices items can be routed to.
+    pub outputs: Vec<String>,
+    /// Playlists whose next cue will follow on by itself, along with the
+    /// seconds left until it does. Cues following on when the current one
+    /// ends have no time.
+    pub follows: Vec<(u64, Option<f64>)>,
+    /// The bytes taken by the sounds of the items kept in memory.
+    pub pinned_memory: usize,
+    /// The items a global resume would resume, unless they've been played or
+    /// stopped since.
+    pub paused_together: Vec<u64>,
+    /// Why the thread priorities from the settings couldn't be applied.
+    pub priority_error: Option<String>,
+    /// When the relay last received a [`ControlMessage::SyncPlaybackStatus`],
+    /// so that the playback thread can tell how long it took to get there.
+    pub sync_received: Option<Instant>,
+    /// How long the last status sync took to get from the relay to the
+    /// playback thread.
+    pub control_latency: Option<Duration>,
+}
+
+/// Levels at or below this many decibels are treated as silence.
+pub const SILENCE_DB: f64 = -60.0;
+/// The loudest gain an item can be boosted to, in decibels.
+pub const MAX_GAIN_DB: f64 = 6.0;
+
+/// Convert a linear amplitude, as understood by kira, to decibels.
+pub fn amplitude_to_db(amplitude: f64) -> f64 {
+    if amplitude <= 0.0 {
+        SILENCE_DB
+    } else {
+        (20.0 * amplitude.log10()).max(SILENCE_DB)
+    }
+}
+
+/// How far [`Model::normalisation_gain`] may raise quiet items, in decibels,
+/// so that they don't clip.
+pub const MAX_NORMALISATION_BOOST_DB: f64 = 12.0;
+
+/// Convert decibels to a linear amplitude. Anything at the [`SILENCE_DB`]
+/// floor becomes complete silence.
+pub fn db_to_amplitude(db: f64) -> f64 {
+    if db <= SILENCE_DB {
+        0.0
+    } else {
+        10f64.powf(db / 20.0)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Larger hit targets, long presses for context menus and swiping over
+    /// items to scroll.
+    pub touch_mode: bool,
+    /// Points to pixels ratio overriding the one reported by the system.
+    pub ui_scale: Option<f32>,
+    /// Rules deciding the colour of imported items. The first matching rule
+    /// wins.
+    pub colour_rules: Vec<ColourRule>,
+    pub keymap: Keymap,
+    pub midi: MidiSettings,
+    pub remote: RemoteSettings,
+    /// The source of the cue script, see [`crate::script`].
+    pub script: String,
+    pub sync: SyncSettings,
+    pub priorities: Priorities,
+    pub import_limits: ImportLimits,
+    /// Whether starting a playlist also stops the sounds fired from
+    /// soundboards and the library, rather than just other playlists.
+    pub stop_pads_with_playlists: bool,
+    /// Whether playing items show their live spectrum instead of their
+    /// waveform.
+    pub live_spectrum: bool,
+    /// Whether waveforms show the left channel above the right one.
+    pub split_channels: bool,
+    pub resume_playback: ResumePlayback,
+    /// What renders the speech of speech items.
+    pub speech_engine: SpeechEngine,
+    /// Whether items are drawn from the [`ACCESSIBLE_PALETTE`], with
+    /// textures telling apart the colours it merges and a bolder outline
+    /// around playing items.
+    pub colour_blind: bool,
+    /// The audio API played through, which only changes once afx restarts,
+    /// like the output device.
+    pub audio_host: AudioHost,
+    /// The output device of the audio API played through, or its default
+    /// device without one.
+    pub audio_device: Option<String>,
+}
+
+/// An audio API afx can play through. All but the default one need afx
+/// built with the cargo feature of the same name.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum AudioHost {
+    /// The default API of the platform, such as ALSA, WASAPI or CoreAudio.
+    #[default]
+    Default,
+    /// The JACK Audio Connection Kit, for routing afx into a pro-audio
+    /// session.
+    Jack,
+    /// Steinberg's ASIO on Windows, whose drivers are listed as output
+    /// devices. Often the only way to low latency with audio interfaces.
+    Asio,
+}
+
+/// What happens on startup to the items that were playing when afx closed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum ResumePlayback {
+    /// Leave them paused and ask whether to resume them.
+    #[default]
+    Ask,
+    Resume,
+    /// Leave them paused where they were.
+    Pause,
+    /// Stop them and rewind them to the start.
+    Reset,
+}
+
+/// Limits keeping imports from slow or network storage from overwhelming
+/// it.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportLimits {
+    /// How many files are imported at once.
+    pub concurrency: usize,
+    /// How long loading a file may take before it's reported as failed, in
+    /// seconds.
+    pub timeout: u64,
+}
+
+impl Default for ImportLimits {
+    fn default() -> Self {
+        ImportLimits {
+            concurrency: 4,
+            timeout: 60,
+        }
+    }
+}
+
+/// How eagerly the system schedules a thread.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    /// Raising a priority may need extra privileges, on Linux for instance.
+    High,
+}
+
+/// The priorities of the threads doing the work that competes for the CPU.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Priorities {
+    /// The thread carrying out control messages.
+    pub playback: Priority,
+    /// The workers analysing imported files.
+    pub import: Priority,
+}
+
+impl Default for Priorities {
+    fn default() -> Self {
+        Priorities {
+            playback: Priority::High,
+            import: Priority::Low,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteSettings {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for RemoteSettings {
+    fn default() -> Self {
+        RemoteSettings {
+            enabled: false,
+            port: 8340,
+        }
+    }
+}
+
+/// The part this instance plays in keeping a backup machine in sync.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum SyncRole {
+    #[default]
+    Off,
+    /// Serve the model and the playback commands to backups.
+    Primary,
+    /// Mirror a primary, carrying on alone if it goes away.
+    Backup,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncSettings {
+    pub role: SyncRole,
+    /// The port the primary listens on.
+    pub port: u16,
+    /// The host name or address of the primary, for backups.
+    pub primary: String,
+    /// The password a backup has to give before the primary lets it mirror
+    /// the show.
+    pub secret: String,
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        SyncSettings {
+            role: SyncRole::Off,
+            port: 8341,
+            primary: String::new(),
+            secret: String::new(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum RuleField {
+    Name,
+    Folder,
+    Tag,
+}
+
+/// Assigns a colour to items whose name, folder or stem tags contain the
+/// pattern, ignoring case.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct ColourRule {
+    pub field: RuleField,
+    pub pattern: String,
+    pub colour: Colour,
+}
+
+impl ColourRule {
+    pub fn matches(&self, item: &Item) -> bool {
+        let pattern = self.pattern.to_lowercase();
+        if pattern.is_empty() {
+            return false;
+        }
+        let contains = |text: &str| text.to_lowercase().contains(&pattern);
+
+        match self.field {
+            RuleField::Name => contains(&item.name),
+            RuleField::Folder => item.stems.iter().any(|stem| {
+                std::path::Path::new(&stem.path)
+                    .parent()
+                    .is_some_and(|folder| contains(&folder.to_string_lossy()))
+            }),
+            RuleField::Tag => item.stems.iter().any(|stem| contains(&stem.tag)),
+        }
+    }
+}
+
+/// Colour the item according to the first matching rule, if any.
+pub fn apply_colour_rules(rules: &[ColourRule], item: &mut Item) {
+    if let Some(rule) = rules.iter().find(|rule| rule.matches(item)) {
+        item.colour = rule.colour;
+    }
+}
+
+impl Model {
+    pub fn fresh_id(&self) -> u64 {
+        self.id_counter.fresh()
+    }
+
+    /// The gain bringing an item to the loudness target of the playlist it
+    /// was started from, as a linear amplitude. Quiet items are boosted by
+    /// at most [`MAX_NORMALISATION_BOOST_DB`].
+    pub fn normalisation_gain(&self, item: &Item) -> f64 {
+        let target = item.source_playlist.and_then(|id| {
+            let playlist = self.playlists.iter().find(|p| p.id == id)?;
+            playlist.loudness_target
+        });
+        match (target, item.loudness) {
+            (Some(target), Some(loudness)) => {
+                db_to_amplitude((target - loudness).min(MAX_NORMALISATION_BOOST_DB))
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// The gain applied to an item on top of its volume: its gain trim and
+    /// the normalisation to the loudness target of its playlist.
+    pub fn item_gain(&self, item: &Item) -> f64 {
+        db_to_amplitude(item.gain_db) * self.normalisation_gain(item)
+    }
+
+    /// The output device and pair of channels an item is routed to, either
+    /// its own or those of the playlist it was started from. `None` stands
+    /// for the first pair of the default output.
+    pub fn output_of<'a>(&'a self, item: &'a Item) -> Option<(&'a str, usize)> {
+        let playlist = item
+            .source_playlist
+            .and_then(|id| self.playlists.iter().find(|p| p.id == id));
+        let (device, pair) = match (&item.output, playlist) {
+            (Some(device), _) => (Some(device.as_str()), item.output_pair),
+            _ if item.output_pair > 0 => (None, item.output_pair),
+            (None, Some(playlist)) => (playlist.output.as_deref(), playlist.output_pair),
+            (None, None) => (None, 0),
+        };
+        match (device, pair) {
+            (Some(device), pair) => Some((device, pair)),
+            (None, 0) => None,
+            // the main manager only plays through the first pair, so other
+            // pairs of the default output are opened by name
+            (None, pair) => Some((self.engine.device.as_deref()?, pair)),
+        }
+    }
+
+    /// Add an entry to the playback history.
+    pub fn record(&mut self, event: PlaybackEvent, item: Option<u64>) {
+        let found = item.and_then(|id| self.items.iter().find(|i| i.id == id));
+        let name = found.map(|i| i.name.clone()).unwrap_or_default();
+        if let (PlaybackEvent::Start, Some(found)) = (event, found) {
+            self.usage.start(found.id, found.source_playlist);
+        }
+        self.history.push(HistoryEntry {
+            time: SystemTime::now(),
+            event,
+            item,
+            name,
+            duration: None,
+        });
+    }
+
+    /// The items started most recently, latest first and each only once, with
+    /// when they last started. Items deleted since are left out.
+    pub fn recently_played(&self, count: usize) -> Vec<(u64, SystemTime)> {
+        let mut recent: Vec<(u64, SystemTime)> = vec![];
+        for entry in self.history.iter().rev() {
+            if recent.len() == count {
+                break;
+            }
+            let Some(id) = entry.item.filter(|_| entry.event == PlaybackEvent::Start) else {
+                continue;
+            };
+            if recent.iter().all(|(seen, _)| *seen != id) && self.items.iter().any(|i| i.id == id) {
+                recent.push((id, entry.time));
+            }
+        }
+        recent
+    }
+
+    /// Note that an item stopped playing, which completes its last start.
+    pub fn record_end(&mut self, item: u64) {
+        let start = self
+            .history
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.item == Some(item) && entry.event == PlaybackEvent::Start);
+        if let Some(start) = start.filter(|start| start.duration.is_none()) {
+            let duration = start.time.elapsed().unwrap_or_default().as_secs_f64();
+            start.duration = Some(duration);
+            self.usage.played(item, duration);
+        }
+    }
+
+    /// The item keyboard shortcuts for a single item apply to unless its
+    /// details are shown: the one started last of those still playing or
+    /// paused.
+    pub fn focused_item(&self) -> Option<u64> {
+        self.history
+            .iter()
+            .rev()
+            .filter(|entry| entry.event == PlaybackEvent::Start)
+            .filter_map(|entry| entry.item)
+            .find(|&id| {
+                self.items
+                    .iter()
+                    .any(|item| item.id == id && item.status != ItemStatus::Stopped)
+            })
+    }
+
+    /// The items playing right now, the one started last first.
+    pub fn playing(&self) -> Vec<u64> {
+        let is_playing = |id: u64| {
+            self.items
+                .iter()
+                .any(|item| item.id == id && item.status == ItemStatus::Playing)
+        };
+        let mut playing: Vec<u64> = vec![];
+        let started = self
+            .history
+            .iter()
+            .rev()
+            .filter(|entry| entry.event == PlaybackEvent::Start)
+            .filter_map(|entry| entry.item);
+        // the history doesn't go back past the start of the session
+        let rest = self.items.iter().map(|item| item.id);
+        for id in started.chain(rest) {
+            if is_playing(id) && !playing.contains(&id) {
+                playing.push(id);
+            }
+        }
+        playing
+    }
+
+    /// How long the given items take to play through once, trimmed, along
+    /// with how many of them are streams, which are left out since they
+    /// don't end.
+    pub fn runtime(&self, ids: &[u64]) -> (f64, usize) {
+        let items = ids
+            .iter()
+            .filter_map(|id| self.items.iter().find(|item| item.id == *id));
+        let (streams, items): (Vec<&Item>, Vec<&Item>) = items.partition(|item| item.is_stream());
+        let seconds = items
+            .iter()
+            .map(|item| {
+                let (start, end) = item.trim_bounds();
+                (end - start).max(0.0) / item.playback_rate()
+            })
+            .sum();
+        (seconds, streams.len())
+    }
+
+    /// How long the playlist has left to play, in seconds: the rest of the
+    /// item started from it last, and the items after it, see
+    /// [`Model::runtime`]. None unless an item of the playlist is playing
+    /// from it.
+    pub fn remaining(&self, playlist_id: u64) -> Option<f64> {
+        let playlist = self.playlists.iter().find(|p| p.id == playlist_id)?;
+        let current = self.playing().into_iter().find(|&id| {
+            playlist.items.contains(&id)
+                && self
+                    .items
+                    .iter()
+                    .any(|item| item.id == id && item.source_playlist == Some(playlist_id))
+        })?;
+        let item = self.items.iter().find(|item| item.id == current)?;
+        let (_, end) = item.trim_bounds();
+        let left = (end - item.position).max(0.0) / item.playback_rate();
+        let position = playlist.items.iter().position(|&id| id == current)?;
+        let (after, _) = self.runtime(&playlist.items[position + 1..]);
+        Some(left + after)
+    }
+
+    /// How long an item fades in and out, in seconds, taking on the fades of
+    /// the playlist it was started from where it has none of its own.
+    pub fn fades(&self, item: &Item) -> (f64, f64) {
+        let playlist = item
+            .source_playlist
+            .and_then(|id| self.playlists.iter().find(|p| p.id == id));
+        let or_default = |own: f64, default: fn(&Playlist) -> f64| match playlist {
+            Some(playlist) if own <= 0.0 => default(playlist),
+            _ => own,
+        };
+        (
+            or_default(item.fade_in, |p| p.fade_in),
+            or_default(item.fade_out, |p| p.fade_out),
+        )
+    }
+
+    /// How long starting the item takes to fade over from the others started
+    /// from the same playlist, in seconds, zero if they keep playing.
+    pub fn crossfade(&self, item: &Item) -> f64 {
+        item.source_playlist
+            .and_then(|id| self.playlists.iter().find(|p| p.id == id))
+            .map_or(0.0, |playlist| playlist.crossfade)
+    }
+
+    /// How many items a global resume would resume.
+    pub fn resumable(&self) -> usize {
+        self.engine
+            .paused_together
+            .iter()
+            .filter(|&&id| {
+                self.items
+                    .iter()
+                    .any(|item| item.id == id && item.status == ItemStatus::Paused)
+            })
+            .count()
+    }
+
+    /// The cue the next GO fires: the selected playlist, the position of its
+    /// standby cue and the item there.
+    pub fn standby_cue(&self) -> Option<(u64, usize, u64)> {
+        self.cue(self.selected_playlist?)
+    }
+
+    /// The position of the standby cue of a playlist and the item there.
+    pub fn cue(&self, playlist_id: u64) -> Option<(u64, usize, u64)> {
+        let playlist = self.playlists.iter().find(|p| p.id == playlist_id)?;
+        let item_id = *playlist.items.get(playlist.standby)?;
+        Some((playlist.id, playlist.standby, item_id))
+    }
+
+    /// Work out what follows an item that played to the end, as set by its
+    /// [`OnEnd`], marking the item to play next as loading. Looped items
+    /// restart by themselves and aren't handled here.
+    pub fn end_of(&mut self, id: u64) -> Option<ControlMessage> {
+        let item = self.items.iter().find(|item| item.id == id)?;
+        let playlist = || {
+            let playlist_id = item.source_playlist?;
+            self.playlists.iter().find(|p| p.id == playlist_id)
+        };
+        let (next, source_playlist) = match item.on_end {
+            OnEnd::Stop | OnEnd::Loop => return None,
+            OnEnd::Next => {
+                let playlist = playlist()?;
+                let position = playlist.items.iter().position(|&i| i == id)?;
+                (*playlist.items.get(position + 1)?, Some(playlist.id))
+            }
+            OnEnd::Random => {
+                let playlist = playlist()?;
+                let others: Vec<_> = playlist.items.iter().filter(|&&i| i != id).collect();
+                if others.is_empty() {
+                    return None;
+                }
+                (*others[fastrand::usize(..others.len())], Some(playlist.id))
+            }
+            OnEnd::Play(next) => (next, None),
+        };
+        let item = self.items.iter_mut().find(|item| item.id == next)?;
+        item.status = ItemStatus::Loading;
+        if source_playlist.is_some() {
+            item.source_playlist = source_playlist;
+        }
+        Some(ControlMessage::Play(next))
+    }
+
+    /// Fire the standby cue of the selected playlist, see [`Model::fire_cue`].
+    pub fn fire_standby(&mut self) -> Option<ControlMessage> {
+        self.fire_cue(self.selected_playlist?)
+    }
+
+    /// Mark the standby cue of a playlist as loading and move the standby
+    /// pointer on to the next one. Returns the message firing the cue, if
+    /// there was one on standby.
+    pub fn fire_cue(&mut self, playlist_id: u64) -> Option<ControlMessage> {
+        let (playlist_id, position, item_id) = self.cue(playlist_id)?;
+        if let Some(item) = self.items.iter_mut().find(|i| i.id == item_id) {
+            item.status = ItemStatus::Loading;
+            item.source_playlist = Some(playlist_id);
+        }
+        self.playing_playlist = Some(playlist_id);
+        if let Some(playlist) = self.playlists.iter_mut().find(|p| p.id == playlist_id) {
+            playlist.standby = position + 1;
+        }
+        Some(ControlMessage::Cue {
+            playlist_id,
+            position,
+        })
+    }
+
+    /// Settle the items that were playing or loading when the model was
+    /// saved according to the settings, returning the messages resuming
+    /// them.
+    pub fn restore_playback(&mut self) -> Vec<ControlMessage> {
+        let resume = self.settings.resume_playback;
+        let mut messages = vec![];
+        for item in self.items.iter_mut() {
+            if item.status == ItemStatus::Loading {
+                item.status = ItemStatus::Stopped;
+            }
+            if item.status != ItemStatus::Playing {
+                continue;
+            }
+            item.target_position = item.position;
+            match resume {
+                ResumePlayback::Resume => {
+                    item.status = ItemStatus::Loading;
+                    messages.push(ControlMessage::Play(item.id));
+                }
+                ResumePlayback::Ask | ResumePlayback::Pause => item.status = ItemStatus::Paused,
+                ResumePlayback::Reset => {
+                    item.status = ItemStatus::Stopped;
+                    item.position = 0.0;
+                    item.target_position = 0.0;
+                }
+            }
+            if resume == ResumePlayback::Ask {
+                self.interrupted.push(item.id);
+            }
+        }
+        messages
+    }
+
+    /// Drop references to items and playlists that don't exist, which would
+    /// otherwise trip up the interface, and make sure fresh ids don't clash
+    /// with existing ones. Returns a description of each repair made.
+    pub fn repair(&mut self) -> Vec<String> {
+        let mut repairs = vec![];
+        let item_ids: HashSet<u64> = self.items.iter().map(|item| item.id).collect();
+        let playlist_ids: HashSet<u64> = self.playlists.iter().map(|p| p.id).collect();
+
+        for playlist in &mut self.playlists {
+            let dangling = playlist
+                .items
+                .iter()
+                .filter(|id| !item_ids.contains(id))
+                .count();
+            if dangling == 0 {
+                continue;
+            }
+            while let Some(position) = playlist.items.iter().position(|id| !item_ids.contains(id)) {
+                playlist.remove(position);
+            }
+            if let Some(grid) = &mut playlist.grid {
+                grid.arrange(&playlist.items);
+            }
+            repairs.push(format!(
+                "removed {} missing {} from playlist {}",
+                dangling,
+                if dangling == 1 { "item" } else { "items" },
+                playlist.name
+            ));
+        }
+
+        let exists = |id: &Option<u64>| id.is_none_or(|id| playlist_ids.contains(&id));
+        for item in &mut self.items {
+            if !exists(&item.source_playlist) {
+                item.source_playlist = None;
+                repairs.push(format!("{} was started from a missing playlist", item.name));
+            }
+        }
+        for (name, playlist) in [
+            ("selected playlist", &mut self.selected_playlist),
+            ("playing playlist", &mut self.playing_playlist),
+            ("crossfader deck A", &mut self.crossfader.deck_a),
+            ("crossfader deck B", &mut self.crossfader.deck_b),
+        ] {
+            if !exists(playlist) {
+                *playlist = None;
+                repairs.push(format!("the {} was missing", name));
+            }
+        }
+        for view in &mut self.views {
+            if !exists(&view.playlist) {
+                view.playlist = None;
+                repairs.push("a window showed a missing playlist".to_string());
+            }
+        }
+
+        let view_ids = self.views.iter().map(|view| view.id);
+        let highest = item_ids
+            .into_iter()
+            .chain(playlist_ids)
+            .chain(view_ids)
+            .max()
+            .unwrap_or(0);
+        if self.id_counter.reserve(highest) {
+            repairs.push("fresh ids would have clashed with existing ones".to_string());
+        }
+        repairs
+    }
+}
+
+/// A window showing a playlist or the library next to the main one, so that
+/// items can be dragged between them.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct View {
+    pub id: u64,
+    /// The playlist shown, or the library if there's none.
+    pub playlist: Option<u64>,
+    pub search_query: String,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Playlist {
+    pub id: u64,
+    pub name: String,
+    pub description: String,
+    pub items: Vec<u64>,
+    /// Position of the cue that will be fired by the next GO.
+    pub standby: usize,
+    /// Fixed placement of the items, if the playlist is laid out as a
+    /// soundboard grid rather than flowing.
+    pub grid: Option<Grid>,
+    /// The output device the items started from this playlist are routed to.
+    /// The default output is used if unset.
+    pub output: Option<String>,
+    /// The pair of channels of the output the items are played through, see
+    /// [`Item::output_pair`].
+    pub output_pair: usize,
+    /// What happens after each cue is fired, by position. Cues past the end
+    /// wait for the next GO.
+    pub follows: Vec<Follow>,
+    /// The loudness the items started from this playlist are brought to, in
+    /// LUFS.
+    pub loudness_target: Option<f64>,
+    /// Whether the items are listed as single rows without their waveforms.
+    pub compact: bool,
+    /// How long the items started from this playlist fade in and out, in
+    /// seconds, unless they have fades of their own.
+    pub fade_in: f64,
+    pub fade_out: f64,
+    /// How long an item started from this playlist takes to fade over from
+    /// the others still playing from it, which are stopped, in seconds. The
+    /// items play alongside each other if zero.
+    pub crossfade: f64,
+}
+
+/// Fills in the fields missing from saved playlists.
+impl Default for Playlist {
+    fn default() -> Self {
+        Playlist::new(0, String::new(), vec![])
+    }
+}
+
+impl Playlist {
+    pub fn new(id: u64, name: String, items: Vec<u64>) -> Playlist {
+        Playlist {
+            id,
+            name,
+            description: String::new(),
+            items,
+            standby: 0,
+            grid: None,
+            output: None,
+            output_pair: 0,
+            follows: vec![],
+            loudness_target: None,
+            compact: false,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            crossfade: 0.0,
+        }
+    }
+
+    /// What happens after the cue at a position is fired.
+    pub fn follow(&self, position: usize) -> Follow {
+        self.follows.get(position).copied().unwrap_or_default()
+    }
+
+    pub fn set_follow(&mut self, position: usize, follow: Follow) {
+        if self.follows.len() <= position {
+            self.follows.resize(position + 1, Follow::default());
+        }
+        self.follows[position] = follow;
+    }
+
+    /// Remove the cue at a position, keeping the standby pointer on the same
+    /// cue.
+    pub fn remove(&mut self, position: usize) {
+        self.items.remove(position);
+        if position < self.follows.len() {
+            self.follows.remove(position);
+        }
+        if position < self.standby {
+            self.standby -= 1;
+        }
+    }
+
+    /// Remove every cue of an item.
+    pub fn remove_item(&mut self, item_id: u64) {
+        while let Some(position) = self.items.iter().position(|&id| id == item_id) {
+            self.remove(position);
+        }
+    }
+}
+
+/// What a cue list does after a cue is fired.
+#[derive(PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Follow {
+    /// Wait for the next GO.
+    #[default]
+    Manual,
+    /// Fire the next cue this many seconds later, or on the same sample as
+    /// this one if zero.
+    After(f64),
+    /// Fire the next cue once this one ends.
+    End,
+}
+
+/// The number of banks (pages) of a soundboard grid.
+pub const BANKS: u8 = 8;
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Cell {
+    pub bank: u8,
+    pub column: u32,
+    pub row: u32,
+}
+
+/// A soundboard layout assigning each item of a playlist a cell on one of
+/// [`BANKS`] pages.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Grid {
+    pub columns: u32,
+    /// The bank currently shown.
+    pub bank: u8,
+    pub cells: HashMap<u64, Cell>,
+}
+
+impl Grid {
+    pub fn new(columns: u32) -> Self {
+        Self {
+            columns,
+            bank: 0,
+            cells: HashMap::new(),
+        }
+    }
+
+    /// The number of rows occupied by items in a bank.
+    pub fn rows(&self, bank: u8) -> u32 {
+        self.cells
+            .values()
+            .filter(|cell| cell.bank == bank)
+            .map(|cell| cell.row + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Forget the cells of items no longer in the playlist and put new items
+    /// (or those pushed out by shrinking the grid) into the first free cells of
+    /// the current bank.
+    pub fn arrange(&mut self, items: &[u64]) {
+        let columns = self.columns.max(1);
+        self.cells
+            .retain(|id, cell| items.contains(id) && cell.column < columns);
+
+        let mut next_free = 0;
+        for id in items {
+            if self.cells.contains_key(id) {
+                continue;
+            }
+            let cell = loop {
+                let cell = Cell {
+                    bank: self.bank,
+                    column: next_free % columns,
+                    row: next_free / columns,
+                };
+                next_free += 1;
+                if !self.cells.values().any(|&c| c == cell) {
+                    break cell;
+                }
+            };
+            self.cells.insert(*id, cell);
+        }
+    }
+
+    /// Move an item to a cell, swapping places with the item already there.
+    pub fn move_to(&mut self, id: u64, cell: Cell) {
+        let Some(&previous) = self.cells.get(&id) else {
+            return;
+        };
+        if let Some(other) = self
+            .cells
+            .iter()
+            .find(|&(_, &c)| c == cell)
+            .map(|(&other, _)| other)
+        {
+            self.cells.insert(other, previous);
+        }
+        self.cells.insert(id, cell);
+    }
+
+    /// Move an item to the first free cell of another bank.
+    pub fn move_to_bank(&mut self, id: u64, bank: u8) {
+        let columns = self.columns.max(1);
+        let cell = (0..)
+            .map(|i| Cell {
+                bank,
+                column: i % columns,
+                row: i / columns,
+            })
+            .find(|cell| !self.cells.values().any(|c| c == cell))
+            .unwrap();
+        self.move_to(id, cell);
+    }
+}
+
+#[derive(PartialEq, PartialOrd, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum CrossfadeCurve {
+    Linear,
+    /// Keeps the perceived loudness constant through the middle of the fade.
+    #[default]
+    EqualPower,
+    /// Both decks stay at full volume except near the ends of the fader.
+    Cut,
+}
+
+/// A DJ-style fader between the buses of two playlists.
+#[derive(PartialEq, PartialOrd, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Crossfader {
+    pub deck_a: Option<u64>,
+    pub deck_b: Option<u64>,
+    /// 0.0 is fully deck A, 1.0 is fully deck B.
+    pub position: f64,
+    pub curve: CrossfadeCurve,
+}
+
+impl Crossfader {
+    /// The volume of the given playlist's bus. Playlists not assigned to a
+    /// deck are unaffected by the fader.
+    pub fn gain_for(&self, playlist_id: u64) -> f64 {
+        let x = self.position.clamp(0.0, 1.0);
+        let (a, b) = match self.curve {
+            CrossfadeCurve::Linear => (1.0 - x, x),
+            CrossfadeCurve::EqualPower => {
+                let angle = x * std::f64::consts::FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+            CrossfadeCurve::Cut => ((2.0 * (1.0 - x)).min(1.0), (2.0 * x).min(1.0)),
+        };
+
+        if self.deck_a == Some(playlist_id) {
+            a
+        } else if self.deck_b == Some(playlist_id) {
+            b
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grid_arrangement() {
+        let cell = |bank, column, row| Cell { bank, column, row };
+        let mut grid = Grid::new(2);
+        grid.arrange(&[10, 11, 12]);
+        assert_eq!(grid.cells[&10], cell(0, 0, 0));
+        assert_eq!(grid.cells[&11], cell(0, 1, 0));
+        assert_eq!(grid.cells[&12], cell(0, 0, 1));
+        assert_eq!(grid.rows(0), 2);
+
+        grid.move_to(12, cell(0, 3, 5));
+        grid.move_to(10, cell(0, 1, 0));
+        assert_eq!(grid.cells[&10], cell(0, 1, 0));
+        assert_eq!(grid.cells[&11], cell(0, 0, 0));
+
+        // item 12 no longer fits, 11 was removed from the playlist
+        grid.arrange(&[10, 12, 13]);
+        assert_eq!(grid.cells.get(&11), None);
+        assert_eq!(grid.cells[&12], cell(0, 0, 0));
+        assert_eq!(grid.cells[&13], cell(0, 0, 1));
+    }
+
+    #[test]
+    fn repair() {
+        let item =
+            |id| Item::with_default_stem(id, format!("item {}", id), String::new(), ORANGE, 1.0);
+        let mut pads = Playlist::new(2, "pads".to_string(), vec![0, 9, 1]);
+        pads.standby = 2;
+        pads.grid = Some(Grid::new(4));
+        pads.grid.as_mut().unwrap().arrange(&[0, 9, 1]);
+        let mut model = Model {
+            items: vec![item(0), item(1)],
+            playlists: vec![pads],
+            selected_playlist: Some(2),
+            views: vec![View {
+                id: 3,
+                playlist: Some(5),
+                search_query: String::new(),
+            }],
+            playing_playlist: Some(5),
+            id_counter: IdCounter::new(1),
+            ..Model::default()
+        };
+        model.items[1].source_playlist = Some(5);
+
+        assert_eq!(
+            model.repair(),
+            vec![
+                "removed 1 missing item from playlist pads",
+                "item 1 was started from a missing playlist",
+                "the playing playlist was missing",
+                "a window showed a missing playlist",
+                "fresh ids would have clashed with existing ones",
+            ]
+        );
+        let pads = &model.playlists[0];
+        assert_eq!(pads.items, vec![0, 1]);
+        // still on the same cue
+        assert_eq!(pads.standby, 1);
+        assert!(!pads.grid.as_ref().unwrap().cells.contains_key(&9));
+        assert_eq!(model.items[1].source_playlist, None);
+        assert_eq!(model.selected_playlist, Some(2));
+        assert_eq!(model.playing_playlist, None);
+        assert_eq!(model.views[0].playlist, None);
+        assert_eq!(model.id_counter.last(), 3);
+        assert!(model.repair().is_empty());
+    }
+
+    #[test]
+    fn silence() {
+        let mut item = Item::with_default_stem(0, String::new(), String::new(), ORANGE, 10.0);
+        item.silences = vec![(1.0, 2.0), (6.0, 10.0)];
+        assert!(!item.silent_at(7.0));
+
+        item.stop_on_silence = Some(1.5);
+        // too short to count
+        assert!(!item.silent_at(2.0));
+        assert!(!item.silent_at(7.0));
+        assert!(item.silent_at(7.5));
+        assert!(!item.silent_at(10.5));
+    }
+
+    #[test]
+    fn focus() {
+        let item = |id| Item::with_default_stem(id, String::new(), String::new(), ORANGE, 1.0);
+        let mut model = Model {
+            items: vec![item(0), item(1), item(2)],
+            ..Model::default()
+        };
+        assert_eq!(model.focused_item(), None);
+
+        for id in [0, 1] {
+            model.items[id as usize].status = ItemStatus::Playing;
+            model.record(PlaybackEvent::Start, Some(id));
+        }
+        assert_eq!(model.focused_item(), Some(1));
+        model.items[1].status = ItemStatus::Stopped;
+        assert_eq!(model.focused_item(), Some(0));
+    }
+
+    #[test]
+    fn output_pairs() {
+        let mut model = Model {
+            items: vec![Item::with_default_stem(
+                0,
+                String::new(),
+                String::new(),
+                ORANGE,
+                1.0,
+            )],
+            playlists: vec![Playlist::new(7, String::new(), vec![0])],
+            ..Model::default()
+        };
+        let item = |model: &Model| model.items[0].clone();
+        assert_eq!(model.output_of(&item(&model)), None);
+
+        model.items[0].source_playlist = Some(7);
+        model.playlists[0].output = Some("desk".to_string());
+        model.playlists[0].output_pair = 2;
+        assert_eq!(model.output_of(&item(&model)), Some(("desk", 2)));
+
+        // the item's own pair is on the default output, unknown until probed
+        model.items[0].output_pair = 1;
+        assert_eq!(model.output_of(&item(&model)), None);
+        model.engine.device = Some("speakers".to_string());
+        assert_eq!(model.output_of(&item(&model)), Some(("speakers", 1)));
+        model.items[0].output = Some("monitor".to_string());
+        assert_eq!(model.output_of(&item(&model)), Some(("monitor", 1)));
+    }
+
+    #[test]
+    fn end_of() {
+        let mut model = Model {
+            items: (0..3)
+                .map(|id| Item::with_default_stem(id, String::new(), String::new(), ORANGE, 1.0))
+                .collect(),
+            playlists: vec![Playlist::new(7, String::new(), vec![0, 1])],
+            ..Model::default()
+        };
+        assert_eq!(model.end_of(0), None);
+        model.items[0].set_looped(true);
+        assert_eq!(model.end_of(0), None);
+        model.items[0].set_looped(false);
+        assert_eq!(model.items[0].on_end, OnEnd::Stop);
+
+        // the next item is only known once played from a playlist
+        model.items[0].on_end = OnEnd::Next;
+        assert_eq!(model.end_of(0), None);
+        model.items[0].source_playlist = Some(7);
+        assert_eq!(model.end_of(0), Some(ControlMessage::Play(1)));
+        assert_eq!(model.items[1].status, ItemStatus::Loading);
+        assert_eq!(model.items[1].source_playlist, Some(7));
+        model.items[1].on_end = OnEnd::Next;
+        assert_eq!(model.end_of(1), None);
+
+        // another item, never the one that ended
+        model.items[1].on_end = OnEnd::Random;
+        assert_eq!(model.end_of(1), Some(ControlMessage::Play(0)));
+
+        model.items[1].on_end = OnEnd::Play(2);
+        assert_eq!(model.end_of(1), Some(ControlMessage::Play(2)));
+        assert_eq!(model.items[2].source_playlist, None);
+        model.items.pop();
+        assert_eq!(model.end_of(1), None);
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut item = Item::with_default_stem(0, String::new(), "a".to_string(), ORANGE, 1.0);
+        item.rotate_stem();
+        assert_eq!(item.current_stem, 0);
+
+        let info = FileInfo {
+            codec: String::new(),
+            sample_rate: Some(48_000),
+            channels: None,
+            bits_per_sample: None,
+            frames: Some(96_000),
+            file_size: 0,
+            modified: None,
+        };
+        for (tag, info) in [("b", Some(info)), ("c", None)] {
+            item.stems.push(Stem {
+                tag: tag.to_string(),
+                path: tag.to_string(),
+                info,
+            });
+        }
+        item.rotate_stem();
+        assert_eq!(item.current_stem, 0);
+
+        item.round_robin = true;
+        item.rotate_stem();
+        assert_eq!((item.current_stem, item.duration), (1, 2.0));
+        // the duration of the last stem is kept if the next one's is unknown
+        item.rotate_stem();
+        assert_eq!((item.current_stem, item.duration), (2, 2.0));
+        item.rotate_stem();
+        assert_eq!(item.current_stem, 0);
+    }
+
+    #[test]
+    fn suggestion() {
+        let mut item = Item::with_default_stem(0, String::new(), String::new(), ORANGE, 10.0);
+        assert_eq!(item.suggestion(), None);
+
+        item.bars = vec![255; 4];
+        // cut off at both ends
+        let suggestion = item.suggestion().unwrap();
+        assert_eq!(suggestion.trim, None);
+        assert_eq!(suggestion.fade_in, DECLICK_FADE);
+        assert_eq!(suggestion.fade_out, CUT_OFF_FADE);
+
+        item.silences = vec![(0.0, 1.5), (4.0, 5.0), (8.0, 10.0)];
+        let suggestion = item.suggestion().unwrap();
+        assert_eq!(suggestion.trim, Some((1.5, 8.0)));
+        assert_eq!(suggestion.fade_out, DECLICK_FADE);
+        item.apply(suggestion);
+        assert_eq!(item.trim_bounds(), (1.5, 8.0));
+
+        item.duration = 1.0;
+        item.silences = vec![(0.0, 0.6)];
+        let suggestion = item.suggestion().unwrap();
+        assert_eq!(suggestion.trim, Some((0.6, 1.0)));
+        approx::assert_relative_eq!(suggestion.fade_out, 0.1);
+
+        item.silences = vec![(0.0, 1.0)];
+        assert_eq!(item.suggestion(), None);
+    }
+
+    #[test]
+    fn recent_items() {
+        let item = |id| Item::with_default_stem(id, String::new(), String::new(), ORANGE, 1.0);
+        let mut model = Model {
+            items: vec![item(1), item(2)],
+            ..Model::default()
+        };
+        model.record(PlaybackEvent::Start, Some(1));
+        model.record(PlaybackEvent::Start, Some(2));
+        model.record(PlaybackEvent::Pause, Some(2));
+        model.record(PlaybackEvent::Start, Some(1));
+        model.record(PlaybackEvent::StopAll, None);
+        // since deleted
+        model.record(PlaybackEvent::Start, Some(3));
+
+        let ids = |count| -> Vec<u64> {
+            model
+                .recently_played(count)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect()
+        };
+        assert_eq!(ids(5), vec![1, 2]);
+        assert_eq!(ids(1), vec![1]);
+        assert_eq!(model.recently_played(5)[0].1, model.history[3].time);
+
+        assert!(model.playing().is_empty());
+        model.items.push(item(4));
+        for item in model.items.iter_mut() {
+            item.status = ItemStatus::Playing;
+        }
+        model.items[0].status = ItemStatus::Paused;
+        assert_eq!(model.playing(), vec![2, 4]);
+    }
+
+    #[test]
+    fn variations() {
+        let mut item = Item::with_default_stem(1, "Door".to_string(), String::new(), ORANGE, 10.0);
+        item.trim = Some((1.0, 6.0));
+        item.fade_out = 4.0;
+        item.loop_region = Some((2.0, 3.0));
+        let variation = Variation {
+            trim: true,
+            ..Variation::default()
+        };
+
+        let mut rng = fastrand::Rng::with_seed(7);
+        for _ in 0..100 {
+            let copy = item.varied_with(2, &variation, &mut rng);
+            assert_eq!((copy.id, copy.name.as_str()), (2, "Door (variation)"));
+            assert!(copy.pitch.abs() <= 1.0 && copy.gain_db.abs() <= 3.0);
+            let (start, end) = copy.trim_bounds();
+            assert!(start >= 0.0 && end <= 10.0, "{:?}", copy.trim);
+            assert!((3.0..=5.0).contains(&(end - start)), "{:?}", copy.trim);
+            assert!(copy.fade_out <= (end - start) / 2.0);
+            if let Some((loop_start, loop_end)) = copy.loop_region {
+                assert!(loop_start >= start && loop_end <= end);
+            }
+        }
+
+        item.pitch = 11.5;
+        let copy = item.varied_with(3, &Variation::default(), &mut rng);
+        assert!(copy.pitch <= MAX_PITCH);
+        assert_eq!(copy.trim, item.trim);
+        assert!((semitones_to_rate(12.0) - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn remaining_time() {
+        let item = |id, duration| {
+            let mut item =
+                Item::with_default_stem(id, String::new(), String::new(), ORANGE, duration);
+            item.source_playlist = Some(9);
+            item
+        };
+        let mut model = Model {
+            items: vec![item(1, 60.0), item(2, 90.0), item(3, 30.0)],
+            playlists: vec![Playlist::new(9, String::new(), vec![1, 2, 3])],
+            ..Model::default()
+        };
+        assert_eq!(model.remaining(9), None);
+
+        model.items[1].status = ItemStatus::Playing;
+        model.items[1].position = 20.0;
+        assert_eq!(model.remaining(9), Some(100.0));
+        model.items[1].source_playlist = None;
+        assert_eq!(model.remaining(9), None);
+    }
+
+    #[test]
+    fn playlist_fades() {
+        let mut item = Item::with_default_stem(1, String::new(), String::new(), ORANGE, 10.0);
+        let mut playlist = Playlist::new(2, "Music".to_string(), vec![1]);
+        playlist.fade_in = 1.0;
+        playlist.fade_out = 3.0;
+        playlist.crossfade = 2.0;
+        let model = Model {
+            playlists: vec![playlist],
+            ..Model::default()
+        };
+
+        assert_eq!(
+            (model.fades(&item), model.crossfade(&item)),
+            ((0.0, 0.0), 0.0)
+        );
+        item.source_playlist = Some(2);
+        assert_eq!(
+            (model.fades(&item), model.crossfade(&item)),
+            ((1.0, 3.0), 2.0)
+        );
+        item.fade_out = 0.5;
+        assert_eq!(model.fades(&item), (1.0, 0.5));
+    }
+
+    #[test]
+    fn runtimes() {
+        let item = |id, duration| {
+            Item::with_default_stem(id, String::new(), format!("{}.ogg", id), ORANGE, duration)
+        };
+        let mut model = Model {
+            items: vec![item(1, 60.0), item(2, 90.0), item(3, 30.0)],
+            ..Model::default()
+        };
+        model.items[1].trim = Some((10.0, 40.0));
+        model.items[2].stems[0].path = "https://radio.example.com/stream.mp3".to_string();
+
+        assert_eq!(model.runtime(&[1, 2, 3, 4]), (90.0, 1));
+        assert_eq!(model.runtime(&[]), (0.0, 0));
+    }
+
+    #[test]
+    fn accessible_colours() {
+        let pairs: HashSet<_> = PALETTE
+            .iter()
+            .map(|&colour| accessible_colour(colour))
+            .collect();
+        assert_eq!(pairs.len(), PALETTE.len());
+        assert!(pairs.iter().all(|(_, texture)| *texture < TEXTURES));
+        assert_eq!(
+            accessible_colour(Colour::from_rgb(235, 140, 40)),
+            accessible_colour(ORANGE)
+        );
+    }
+
+    #[test]
+    fn normalisation() {
+        let mut quiet = Item::with_default_stem(0, String::new(), String::new(), ORANGE, 1.0);
+        quiet.loudness = Some(-30.0);
+        quiet.source_playlist = Some(1);
+        let mut loud = quiet.clone();
+        loud.loudness = Some(-8.0);
+        let mut model = Model {
+            playlists: vec![Playlist::new(1, String::new(), vec![0])],
+            ..Model::default()
+        };
+        assert_eq!(model.normalisation_gain(&loud), 1.0);
+
+        model.playlists[0].loudness_target = Some(-14.0);
+        approx::assert_relative_eq!(model.normalisation_gain(&loud), db_to_amplitude(-6.0));
+        approx::assert_relative_eq!(
+            model.normalisation_gain(&quiet),
+            db_to_amplitude(MAX_NORMALISATION_BOOST_DB)
+        );
+        loud.source_playlist = None;
+        assert_eq!(model.normalisation_gain(&loud), 1.0);
+
+        // the gain trim comes on top
+        loud.gain_db = -3.0;
+        approx::assert_relative_eq!(model.item_gain(&loud), db_to_amplitude(-3.0));
+        quiet.gain_db = 2.0;
+        approx::assert_relative_eq!(
+            model.item_gain(&quiet),
+            db_to_amplitude(MAX_NORMALISATION_BOOST_DB + 2.0)
+        );
+    }
+
+    #[test]
+    fn keys() {
+        let key = |text| Key::parse(text).unwrap();
+        let a_minor = Key {
+            tonic: 9,
+            minor: true,
+        };
+        assert_eq!(key("Am"), a_minor);
+        assert_eq!(key("a minor"), a_minor);
+        assert_eq!(key("8A"), a_minor);
+        assert_eq!(a_minor.to_string(), "Am (8A)");
+        assert_eq!(key("C").camelot(), (8, 'B'));
+        assert_eq!(key("G").camelot(), (9, 'B'));
+        assert_eq!(key("Eb minor").camelot(), (2, 'A'));
+        assert_eq!(key("F#").camelot(), (2, 'B'));
+        assert_eq!(key("Bb").to_string(), "Bb (6B)");
+        assert_eq!(Key::parse("H"), None);
+        assert_eq!(Key::parse("13A"), None);
+        for tonic in 0..12 {
+            for minor in [false, true] {
+                let key = Key { tonic, minor };
+                let (number, letter) = key.camelot();
+                assert_eq!(Key::from_camelot(number, letter), Some(key));
+            }
+        }
+        assert_eq!(
+            a_minor.compatible(),
+            [a_minor, key("Em"), key("Dm"), key("C")]
+        );
+    }
+
+    #[test]
+    fn beat_grid() {
+        let tempo = Tempo {
+            bpm: 120.0,
+            offset: 1.25,
+        };
+        let beats: Vec<_> = tempo.beats(2.5).collect();
+        assert_eq!(
+            beats,
+            vec![
+                (0.25, false),
+                (0.75, false),
+                (1.25, true),
+                (1.75, false),
+                (2.25, false)
+            ]
+        );
+        assert_eq!(tempo.snap(0.1), 0.25);
+        assert_eq!(tempo.snap(1.6), 1.75);
+        assert_eq!(tempo.snap(9.4), 9.25);
+    }
+
+    #[test]
+    fn id_counter() {
+        let model = Model::default();
+        let copy = model.clone();
+        let ids: HashSet<_> = std::thread::scope(|scope| {
+            let workers: Vec<_> = [&model, &copy]
+                .into_iter()
+                .map(|model| scope.spawn(|| (0..100).map(|_| model.fresh_id()).collect::<Vec<_>>()))
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        assert_eq!(ids.len(), 200);
+        assert_eq!(copy.id_counter.last(), 200);
+
+        let encoded = ron::to_string(&model.id_counter).unwrap();
+        assert_eq!(encoded, "200");
+        let decoded: IdCounter = ron::from_str(&encoded).unwrap();
+        assert_eq!(decoded, model.id_counter);
+        assert!(!decoded.reserve(150));
+        assert!(decoded.reserve(250));
+        assert_eq!(decoded.fresh(), 251);
+    }
+
+    #[test]
+    fn restore_playback() {
+        let restored = |resume| {
+            let item = |id, status, position| Item {
+                status,
+                position,
+                ..Item::with_default_stem(id, String::new(), String::new(), ORANGE, 10.0)
+            };
+            let mut model = Model {
+                items: vec![
+                    item(0, ItemStatus::Playing, 2.0),
+                    item(1, ItemStatus::Loading, 0.0),
+                    item(2, ItemStatus::Paused, 3.0),
+                ],
+                ..Model::default()
+            };
+            model.settings.resume_playback = resume;
+            let messages = model.restore_playback();
+            let items: Vec<_> = model
+                .items
+                .iter()
+                .map(|item| (item.status.clone(), item.position))
+                .collect();
+            (messages, items, model.interrupted)
+        };
+        use ItemStatus::*;
+
+        let (messages, items, interrupted) = restored(ResumePlayback::Resume);
+        assert_eq!(messages, vec![ControlMessage::Play(0)]);
+        assert_eq!(items, vec![(Loading, 2.0), (Stopped, 0.0), (Paused, 3.0)]);
+        assert!(interrupted.is_empty());
+
+        let (messages, items, interrupted) = restored(ResumePlayback::Ask);
+        assert!(messages.is_empty());
+        assert_eq!(items[0], (Paused, 2.0));
+        assert_eq!(interrupted, vec![0]);
+
+        let (_, items, interrupted) = restored(ResumePlayback::Pause);
+        assert_eq!(items[0], (Paused, 2.0));
+        assert!(interrupted.is_empty());
+
+        let (_, items, _) = restored(ResumePlayback::Reset);
+        assert_eq!(items[0], (Stopped, 0.0));
+    }
+
+    #[test]
+    fn decibels() {
+        assert_eq!(amplitude_to_db(1.0), 0.0);
+        assert_eq!(db_to_amplitude(0.0), 1.0);
+        assert_eq!(amplitude_to_db(0.0), SILENCE_DB);
+        assert_eq!(db_to_amplitude(SILENCE_DB), 0.0);
+        assert!((db_to_amplitude(MAX_GAIN_DB) - 1.995).abs() < 0.001);
+        assert!((amplitude_to_db(db_to_amplitude(-12.5)) + 12.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn colour_rules() {
+        let mut item = Item::with_default_stem(
+            0,
+            "Heavy Rain.ogg".to_string(),
+            "/sfx/Weather/Heavy Rain.ogg".to_string(),
+            Colour::BLACK,
+            1.0,
+        );
+        let rule = |field, pattern: &str, colour| ColourRule {
+            field,
+            pattern: pattern.to_string(),
+            colour,
+        };
+
+        apply_colour_rules(&[rule(RuleField::Name, "thunder", RED)], &mut item);
+        assert_eq!(item.colour, Colour::BLACK);
+
+        let rules = [
+            rule(RuleField::Tag, "", GREEN),
+            rule(RuleField::Folder, "weather", BLUE),
+            rule(RuleField::Name, "RAIN", RED),
+        ];
+        apply_colour_rules(&rules, &mut item);
+        assert_eq!(item.colour, BLUE);
+
+        apply_colour_rules(&[rule(RuleField::Tag, "default", GREEN)], &mut item);
+        assert_eq!(item.colour, GREEN);
+    }
+
+    #[test]
+    fn grid_banks() {
+        let mut grid = Grid::new(2);
+        grid.arrange(&[10, 11]);
+        grid.move_to_bank(11, 3);
+        assert_eq!(
+            grid.cells[&11],
+            Cell {
+                bank: 3,
+                column: 0,
+                row: 0
+            }
+        );
+        assert_eq!(grid.rows(0), 1);
+        assert_eq!(grid.rows(3), 1);
+
+        // new items land in the bank being shown
+        grid.bank = 3;
+        grid.arrange(&[10, 11, 12]);
+        assert_eq!(
+            grid.cells[&12],
+            Cell {
+                bank: 3,
+                column: 1,
+                row: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,437 @@
+//! Playing through output devices other than the default one.
+
+use crate::generator::{GeneratorData, GeneratorHandle};
+use crate::model::AudioHost;
+use anyhow::{anyhow, bail, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample};
+use kira::manager::backend::{Backend, Renderer};
+use kira::manager::{AudioManager, AudioManagerSettings};
+use kira::sound::static_sound::{StaticSoundData, StaticSoundHandle};
+use kira::sound::streaming::{StreamingSoundData, StreamingSoundHandle};
+use kira::sound::FromFileError;
+use kira::tween::Tween;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Weak};
+use tracing::warn;
+
+/// The id of the cpal host of an audio API, unless afx was built without
+/// support for it.
+fn host_id(api: AudioHost) -> Option<cpal::HostId> {
+    match api {
+        AudioHost::Default => Some(cpal::default_host().id()),
+        #[cfg(all(
+            feature = "jack",
+            any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "netbsd"
+            )
+        ))]
+        AudioHost::Jack => Some(cpal::HostId::Jack),
+        #[cfg(all(feature = "asio", target_os = "windows"))]
+        AudioHost::Asio => Some(cpal::HostId::Asio),
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+/// The audio APIs afx was built with support for.
+pub fn hosts() -> Vec<AudioHost> {
+    [AudioHost::Default, AudioHost::Jack, AudioHost::Asio]
+        .into_iter()
+        .filter(|&api| host_id(api).is_some())
+        .collect()
+}
+
+/// The cpal host of an audio API.
+pub fn host(api: AudioHost) -> Result<cpal::Host> {
+    let id = host_id(api).ok_or_else(|| anyhow!("afx was built without support for {:?}", api))?;
+    Ok(cpal::host_from_id(id)?)
+}
+
+/// The names of the output devices of the host of an audio API.
+pub fn output_devices(api: AudioHost) -> Vec<String> {
+    host(api)
+        .and_then(|host| Ok(host.output_devices()?))
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// An audio manager on one of the extra output devices items can be routed
+/// to. Kept behind a trait object so that its backend can differ from the
+/// backend of the main manager.
+pub trait Output {
+    fn play(
+        &mut self,
+        sound: StreamingSoundData<FromFileError>,
+    ) -> Result<StreamingSoundHandle<FromFileError>>;
+    fn play_static(&mut self, sound: StaticSoundData) -> Result<StaticSoundHandle>;
+    fn play_generated(&mut self, sound: GeneratorData) -> Result<GeneratorHandle>;
+    fn set_volume(&mut self, volume: f64) -> Result<()>;
+    fn num_sounds(&self) -> usize;
+}
+
+impl<B: Backend> Output for AudioManager<B> {
+    fn play(
+        &mut self,
+        sound: StreamingSoundData<FromFileError>,
+    ) -> Result<StreamingSoundHandle<FromFileError>> {
+        Ok(AudioManager::play(self, sound)?)
+    }
+
+    fn play_static(&mut self, sound: StaticSoundData) -> Result<StaticSoundHandle> {
+        Ok(AudioManager::play(self, sound)?)
+    }
+
+    fn play_generated(&mut self, sound: GeneratorData) -> Result<GeneratorHandle> {
+        Ok(AudioManager::play(self, sound)?)
+    }
+
+    fn set_volume(&mut self, volume: f64) -> Result<()> {
+        Ok(self.main_track().set_volume(volume, Tween::default())?)
+    }
+
+    fn num_sounds(&self) -> usize {
+        AudioManager::num_sounds(self)
+    }
+}
+
+/// Open a manager on a pair of channels of the output device with the given
+/// name, counted from zero, so that pair 1 stands for channels 3 and 4.
+pub fn open(host: AudioHost, device: &str, pair: usize) -> Result<Box<dyn Output>> {
+    let manager = AudioManager::<DeviceBackend>::new(AudioManagerSettings {
+        backend_settings: DeviceSettings {
+            host,
+            device: Some(device.to_string()),
+            pair,
+        },
+        ..Default::default()
+    })?;
+    Ok(Box::new(manager))
+}
+
+/// A change to the renderers mixed by a hub. Changes are sent to the audio
+/// callback rather than made under a lock, so that the callback never waits.
+enum Change {
+    Add {
+        id: u64,
+        first: usize,
+        renderer: Box<Renderer>,
+    },
+    Remove(u64),
+}
+
+/// The renderers of the managers playing through a hub, each with its own id
+/// and the first of its channels, owned by the audio callback.
+struct Mixer {
+    channels: usize,
+    changes: Receiver<Change>,
+    renderers: Vec<(u64, usize, Renderer)>,
+    /// The mix, before it's converted to the sample format of the device.
+    mix: Vec<f32>,
+}
+
+impl Mixer {
+    fn new(channels: usize, changes: Receiver<Change>) -> Self {
+        Self {
+            channels,
+            changes,
+            renderers: vec![],
+            mix: vec![],
+        }
+    }
+
+    fn render<T: FromSample<f32>>(&mut self, data: &mut [T]) {
+        for change in self.changes.try_iter() {
+            match change {
+                Change::Add {
+                    id,
+                    first,
+                    renderer,
+                } => self.renderers.push((id, first, *renderer)),
+                Change::Remove(id) => self.renderers.retain(|(other, _, _)| *other != id),
+            }
+        }
+
+        let channels = self.channels;
+        self.mix.clear();
+        self.mix.resize(data.len(), 0.0);
+        for (_, first, renderer) in self.renderers.iter_mut() {
+            renderer.on_start_processing();
+            for frame in self.mix.chunks_exact_mut(channels) {
+                let out = renderer.process();
+                if channels == 1 {
+                    frame[0] += (out.left + out.right) / 2.0;
+                } else {
+                    frame[*first] += out.left;
+                    frame[*first + 1] += out.right;
+                }
+            }
+        }
+        for (sample, &mixed) in data.iter_mut().zip(&self.mix) {
+            *sample = mixed.to_sample();
+        }
+    }
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut mixer: Mixer,
+) -> Result<cpal::Stream> {
+    Ok(device.build_output_stream(
+        config,
+        move |data: &mut [T], _| mixer.render(data),
+        |err| warn!("output stream error: {}", err),
+        None,
+    )?)
+}
+
+/// The stream of an output device, shared by the managers playing through
+/// its channel pairs, since a device can't always be opened more than once.
+struct Hub {
+    host: AudioHost,
+    /// The device as asked for, with none standing for the default device.
+    device: Option<String>,
+    name: String,
+    config: cpal::StreamConfig,
+    /// Passes renderers on to the [`Mixer`] of the stream.
+    changes: Sender<Change>,
+    next_id: AtomicU64,
+    /// Dropping the sender stops the stream.
+    _stop: Sender<()>,
+}
+
+/// The open streams, by host and device.
+static HUBS: Mutex<Vec<Weak<Hub>>> = parking_lot::const_mutex(Vec::new());
+
+impl Hub {
+    /// Get the stream of the named device of a host, or of its default device
+    /// without a name, opening it unless it's open.
+    fn open(api: AudioHost, device: Option<&str>) -> Result<Arc<Hub>> {
+        let mut hubs = HUBS.lock();
+        hubs.retain(|hub| hub.strong_count() > 0);
+        if let Some(hub) = hubs
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|hub| hub.host == api && hub.device.as_deref() == device)
+        {
+            return Ok(hub);
+        }
+
+        let host = host(api)?;
+        let (device, requested) = match device {
+            Some(name) => (
+                host.output_devices()?
+                    .find(|device| device.name().is_ok_and(|n| n == name))
+                    .ok_or_else(|| anyhow!("there's no output device named {}", name))?,
+                Some(name.to_string()),
+            ),
+            None => (
+                host.default_output_device()
+                    .ok_or_else(|| anyhow!("{:?} has no default output device", api))?,
+                None,
+            ),
+        };
+        let name = device.name()?;
+        let supported = device.default_output_config()?;
+        let format = supported.sample_format();
+        let config = supported.config();
+        let (changes, mixed) = channel();
+        let (stop, stopped) = channel::<()>();
+        let (started_tx, started) = channel();
+
+        // cpal streams can't always be sent between threads, so the stream
+        // lives on a thread of its own until the hub is dropped
+        let stream_config = config.clone();
+        let name_of_device = name.clone();
+        std::thread::spawn(move || {
+            let mixer = Mixer::new(stream_config.channels as usize, mixed);
+            let (device, config) = (&device, &stream_config);
+            let stream = match format {
+                SampleFormat::I8 => build_stream::<i8>(device, config, mixer),
+                SampleFormat::I16 => build_stream::<i16>(device, config, mixer),
+                SampleFormat::I32 => build_stream::<i32>(device, config, mixer),
+                SampleFormat::I64 => build_stream::<i64>(device, config, mixer),
+                SampleFormat::U8 => build_stream::<u8>(device, config, mixer),
+                SampleFormat::U16 => build_stream::<u16>(device, config, mixer),
+                SampleFormat::U32 => build_stream::<u32>(device, config, mixer),
+                SampleFormat::U64 => build_stream::<u64>(device, config, mixer),
+                SampleFormat::F32 => build_stream::<f32>(device, config, mixer),
+                SampleFormat::F64 => build_stream::<f64>(device, config, mixer),
+                format => Err(anyhow!(
+                    "{} uses the unsupported sample format {}",
+                    name_of_device,
+                    format
+                )),
+            };
+            let stream = match stream.and_then(|stream| {
+                stream.play()?;
+                Ok(stream)
+            }) {
+                Ok(stream) => stream,
+                Err(err) => {
+                    let _ = started_tx.send(Err(err));
+                    return;
+                }
+            };
+            let _ = started_tx.send(Ok(()));
+            let _ = stopped.recv();
+            drop(stream);
+        });
+        started.recv()??;
+
+        let hub = Arc::new(Hub {
+            host: api,
+            device: requested,
+            name,
+            config,
+            changes,
+            next_id: AtomicU64::new(0),
+            _stop: stop,
+        });
+        hubs.push(Arc::downgrade(&hub));
+        Ok(hub)
+    }
+}
+
+/// Where a [`DeviceBackend`] plays: a pair of channels, counted from zero, of
+/// the named output device of a host, or of its default device without a
+/// name.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceSettings {
+    pub host: AudioHost,
+    pub device: Option<String>,
+    pub pair: usize,
+}
+
+/// A kira backend playing through a pair of channels of a cpal output
+/// device. Unlike kira's own backend, it doesn't follow changes of the
+/// default device.
+pub struct DeviceBackend {
+    hub: Arc<Hub>,
+    /// The first channel played through.
+    first: usize,
+    /// The id of the renderer in the hub, once started.
+    id: Option<u64>,
+}
+
+impl Backend for DeviceBackend {
+    type Settings = DeviceSettings;
+
+    type Error = anyhow::Error;
+
+    fn setup(settings: Self::Settings) -> Result<(Self, u32)> {
+        let hub = Hub::open(settings.host, settings.device.as_deref())?;
+        let (name, pair) = (&hub.name, settings.pair);
+        let channels = hub.config.channels as usize;
+        let first = 2 * pair;
+        // mono devices play the first pair mixed down
+        if pair > 0 && first + 1 >= channels {
+            bail!(
+                "{} has {} channels, too few for channels {} and {}",
+                name,
+                channels,
+                first + 1,
+                first + 2
+            );
+        }
+        let sample_rate = hub.config.sample_rate.0;
+        Ok((
+            Self {
+                hub,
+                first,
+                id: None,
+            },
+            sample_rate,
+        ))
+    }
+
+    fn start(&mut self, renderer: Renderer) -> Result<()> {
+        if self.id.is_some() {
+            bail!("the backend was already started");
+        }
+        let id = self.hub.next_id.fetch_add(1, Ordering::Relaxed);
+        self.hub
+            .changes
+            .send(Change::Add {
+                id,
+                first: self.first,
+                renderer: Box::new(renderer),
+            })
+            .map_err(|_| anyhow!("the output stream of {} stopped", self.hub.name))?;
+        self.id = Some(id);
+        Ok(())
+    }
+}
+
+impl Drop for DeviceBackend {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let _ = self.hub.changes.send(Change::Remove(id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kira::dsp::Frame;
+    use std::convert::Infallible;
+
+    /// Hands the renderer of a manager over, the way the hubs get them.
+    struct Capture(Option<Sender<Renderer>>);
+
+    impl Backend for Capture {
+        type Settings = Option<Sender<Renderer>>;
+        type Error = Infallible;
+
+        fn setup(renderers: Self::Settings) -> Result<(Self, u32), Infallible> {
+            Ok((Capture(renderers), 48_000))
+        }
+
+        fn start(&mut self, renderer: Renderer) -> Result<(), Infallible> {
+            self.0.take().unwrap().send(renderer).unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mixing() -> Result<()> {
+        let (renderers, renderer) = channel();
+        let mut manager = AudioManager::<Capture>::new(AudioManagerSettings {
+            backend_settings: Some(renderers),
+            ..Default::default()
+        })?;
+        manager.play(StaticSoundData {
+            sample_rate: 48_000,
+            frames: Arc::new(vec![Frame::from_mono(0.5); 48_000]),
+            settings: Default::default(),
+        })?;
+
+        let (changes, mixed) = channel();
+        let mut mixer = Mixer::new(4, mixed);
+        changes
+            .send(Change::Add {
+                id: 7,
+                first: 2,
+                renderer: Box::new(renderer.recv()?),
+            })
+            .unwrap();
+        // integer devices get the mix converted, on the second pair only
+        let mut data = [0i16; 8];
+        mixer.render(&mut data);
+        assert_eq!(&data[..2], &[0, 0]);
+        assert!(data[2] > 0 && data[3] > 0);
+
+        changes.send(Change::Remove(7)).unwrap();
+        let mut data = [1.0f32; 8];
+        mixer.render(&mut data);
+        assert_eq!(data, [0.0; 8]);
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+//! Saving and loading the model. Projects are files in eframe's storage
+//! format, a map of ron strings holding the compressed model under the key
+//! `"model"`, prefixed with its checksum. Saves go to a temporary file that
+//! replaces the project once written, and the previous generation is kept
+//! next to it with a `.bak` suffix. The application saves to the
+//! [`default_project_path`] this way too, leaving eframe's own storage to
+//! the window state.
+//!
+//! Fields are stored by name along with the [`FORMAT_VERSION`], so that fields
+//! added later are filled in with their defaults. Saves from before then are
+//! read by the [`legacy`] module.
+
+mod legacy;
+
+use crate::model::*;
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+/// The version of the saved model, raised when a change can't be taken care
+/// of by filling in defaults.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<M> {
+    version: u32,
+    model: M,
+}
+
+/// Encode a value the way the model is persisted.
+pub fn serialize<T: serde::Serialize + ?Sized>(value: &T) -> Result<String> {
+    Ok(
+        BASE64.encode(lz4_flex::compress_prepend_size(&rmp_serde::to_vec_named(
+            value,
+        )?)),
+    )
+}
+
+/// Decode a value encoded by [`serialize`].
+pub fn deserialize<T: for<'de> serde::Deserialize<'de>>(saved: impl AsRef<[u8]>) -> Result<T> {
+    BASE64
+        .decode(saved)
+        .map_err(|e| anyhow!(e))
+        .and_then(|decoded| lz4_flex::decompress_size_prepended(&decoded).map_err(|e| anyhow!(e)))
+        .and_then(|decompressed| rmp_serde::from_slice(&decompressed).map_err(|e| anyhow!(e)))
+}
+
+/// The project the application saves to, which doubles as the default
+/// project for the command line.
+pub fn default_project_path() -> Option<PathBuf> {
+    let dirs = directories_next::ProjectDirs::from("", "", "afx")?;
+    Some(dirs.data_dir().join("project.ron"))
+}
+
+/// The file eframe persists the application state to, where the model was
+/// saved before the application had a project of its own.
+pub fn eframe_storage_path() -> Option<PathBuf> {
+    let dirs = directories_next::ProjectDirs::from("", "", "afx")?;
+    Some(dirs.data_dir().join("app.ron"))
+}
+
+/// Encode the model along with its checksum.
+pub fn encode_model(model: &Model) -> Result<String> {
+    let encoded = serialize(&Envelope {
+        version: FORMAT_VERSION,
+        model,
+    })?;
+    Ok(format!(
+        "{:08x}:{}",
+        crc32fast::hash(encoded.as_bytes()),
+        encoded
+    ))
+}
+
+/// Decode a model encoded by [`encode_model`], verifying its checksum. Models
+/// saved before checksums and format versions were introduced are accepted as
+/// they are.
+pub fn decode_model(saved: &str) -> Result<Model> {
+    let encoded = match saved.split_once(':') {
+        Some((checksum, encoded)) => {
+            let expected = u32::from_str_radix(checksum, 16)?;
+            if crc32fast::hash(encoded.as_bytes()) != expected {
+                return Err(anyhow!("the saved model is corrupted"));
+            }
+            encoded
+        }
+        None => saved,
+    };
+    let envelope: Envelope<Model> = match deserialize(encoded) {
+        Ok(envelope) => envelope,
+        Err(err) => {
+            let legacy: legacy::Model = deserialize(encoded).map_err(|_| err)?;
+            warn!("took on a model saved before format versions");
+            return Ok(legacy.into());
+        }
+    };
+    if envelope.version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "the model was saved by a newer version of afx (format {})",
+            envelope.version
+        ));
+    }
+    Ok(envelope.model)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Where the previous generation of a project is kept.
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// Where the model is saved when afx crashes, to be offered on the next
+/// launch.
+pub fn emergency_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".crash")
+}
+
+/// Save the model next to a project in a hurry, leaving the project and its
+/// backup alone.
+pub fn save_emergency(path: &Path, model: &Model) -> Result<()> {
+    let storage = HashMap::from([("model".to_string(), encode_model(model)?)]);
+    let emergency = emergency_path(path);
+    let temp = with_suffix(&emergency, ".tmp");
+    let file = File::create(&temp)?;
+    ron::ser::to_writer_pretty(&file, &storage, Default::default())?;
+    file.sync_all()?;
+    std::fs::rename(temp, emergency)?;
+    Ok(())
+}
+
+/// Load the model saved next to a project when afx last crashed, if any.
+pub fn load_emergency(path: &Path) -> Option<Result<Model>> {
+    let emergency = emergency_path(path);
+    emergency.exists().then(|| read_model(&emergency))
+}
+
+/// Forget the model saved when afx last crashed.
+pub fn discard_emergency(path: &Path) -> Result<()> {
+    match std::fs::remove_file(emergency_path(path)) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+fn read_storage(path: &Path) -> Result<HashMap<String, String>> {
+    Ok(ron::de::from_reader(File::open(path)?)?)
+}
+
+fn read_model(path: &Path) -> Result<Model> {
+    let storage = read_storage(path)?;
+    let saved = storage
+        .get("model")
+        .ok_or_else(|| anyhow!("{} contains no saved model", path.display()))?;
+    decode_model(saved)
+}
+
+/// Load the model from a project, i.e. a file in eframe's storage format,
+/// falling back to its backup if the project is missing or damaged.
+pub fn load_project(path: &Path) -> Result<Model> {
+    read_model(path).or_else(|err| {
+        let model = read_model(&backup_path(path)).map_err(|_| err)?;
+        warn!("{} is damaged, loaded its backup instead", path.display());
+        Ok(model)
+    })
+}
+
+/// Save the model to a project, keeping any other state eframe stored in it.
+pub fn save_project(path: &Path, model: &Model) -> Result<()> {
+    // a damaged project only loses the state eframe stored in it
+    let mut storage = read_storage(path).unwrap_or_default();
+    storage.insert("model".to_string(), encode_model(model)?);
+
+    let temp = with_suffix(path, ".tmp");
+    let file = File::create(&temp)?;
+    ron::ser::to_writer_pretty(&file, &storage, Default::default())?;
+    file.sync_all()?;
+    if read_model(path).is_ok() {
+        std::fs::rename(path, backup_path(path))?;
+    }
+    std::fs::rename(temp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn named(name: &str) -> Model {
+        Model {
+            search_query: name.to_string(),
+            ..Model::default()
+        }
+    }
+
+    #[test]
+    fn recover_from_backup() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let project = dir.path().join("show.ron");
+        save_project(&project, &named("first"))?;
+        save_project(&project, &named("second"))?;
+        assert_eq!(load_project(&project)?.search_query, "second");
+
+        // a crash in the middle of writing leaves the project truncated
+        let saved = std::fs::read_to_string(&project)?;
+        std::fs::write(&project, &saved[..saved.len() / 2])?;
+        assert_eq!(load_project(&project)?.search_query, "first");
+
+        // saving again doesn't replace the intact backup with the damaged file
+        save_project(&project, &named("third"))?;
+        assert_eq!(load_project(&project)?.search_query, "third");
+        assert_eq!(read_model(&backup_path(&project))?.search_query, "first");
+        Ok(())
+    }
+
+    #[test]
+    fn emergency() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let project = dir.path().join("show.ron");
+        save_project(&project, &named("saved"))?;
+        assert!(load_emergency(&project).is_none());
+
+        save_emergency(&project, &named("unsaved"))?;
+        assert_eq!(load_emergency(&project).unwrap()?.search_query, "unsaved");
+        assert_eq!(load_project(&project)?.search_query, "saved");
+
+        discard_emergency(&project)?;
+        assert!(load_emergency(&project).is_none());
+        discard_emergency(&project)?;
+        Ok(())
+    }
+
+    #[test]
+    fn baseline_save() -> Result<()> {
+        // saved by the first version of afx, storing fields by position
+        let project = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/baseline.ron");
+        let model = load_project(&project)?;
+        assert_eq!(model.search_query, "ro");
+        assert_eq!(model.selected_playlist, Some(3));
+        assert!(model.shuffle);
+        assert_eq!(model.id_counter.last(), 3);
+
+        let [robin, rain] = &model.items[..] else {
+            panic!("expected two items, got {:?}", model.items);
+        };
+        assert_eq!(robin.name, "robin");
+        assert_eq!(robin.stems[0].path, "samples/robin.wav");
+        assert_eq!(robin.on_end, OnEnd::Loop);
+        assert_eq!(robin.status, ItemStatus::Paused);
+        assert_eq!(robin.position, 1.25);
+        assert_eq!(robin.colour, Colour::from_rgb(200, 120, 40));
+        assert!(rain.muted);
+        assert_eq!(rain.issues[0].0, IssueType::MissingFile);
+        assert_eq!(model.playlists[0].name, "act one");
+        assert_eq!(model.playlists[0].items, vec![1, 2]);
+
+        // saved again in the current format
+        let dir = tempfile::tempdir()?;
+        let resaved = dir.path().join("show.ron");
+        save_project(&resaved, &model)?;
+        assert_eq!(load_project(&resaved)?, model);
+        Ok(())
+    }
+
+    #[test]
+    fn newer_format() -> Result<()> {
+        let encoded = serialize(&Envelope {
+            version: FORMAT_VERSION + 1,
+            model: named("future"),
+        })?;
+        assert!(decode_model(&encoded).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn checksum() -> Result<()> {
+        let encoded = encode_model(&named("intact"))?;
+        assert_eq!(decode_model(&encoded)?.search_query, "intact");
+        let (_, legacy) = encoded.split_once(':').unwrap();
+        assert_eq!(decode_model(legacy)?.search_query, "intact");
+
+        let mut damaged = encoded.into_bytes();
+        let last = damaged.len() - 3;
+        damaged[last] = if damaged[last] == b'A' { b'B' } else { b'A' };
+        assert!(decode_model(std::str::from_utf8(&damaged)?).is_err());
+        Ok(())
+    }
+}
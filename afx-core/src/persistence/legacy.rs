@@ -0,0 +1,109 @@
+//! The model as saved before saves carried a format version, with the fields
+//! of structs stored by position rather than by name. Only read once, to take
+//! on the libraries saved back then.
+
+use crate::model::{self, IdCounter, Issue, ItemStatus, OnEnd};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct Model {
+    search_query: String,
+    items: Vec<Item>,
+    playlists: Vec<Playlist>,
+    playlist_creation_state: Option<Playlist>,
+    selected_playlist: Option<u64>,
+    playing_playlist: Option<u64>,
+    shuffle: bool,
+    id_counter: u64,
+}
+
+#[derive(Deserialize)]
+struct Stem {
+    tag: String,
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    id: u64,
+    name: String,
+    stems: Vec<Stem>,
+    current_stem: usize,
+    volume: f64,
+    muted: bool,
+    looped: bool,
+    status: ItemStatus,
+    colour: [u8; 4],
+    bars: Vec<u8>,
+    position: f64,
+    target_position: f64,
+    duration: f64,
+    issues: Vec<Issue>,
+}
+
+#[derive(Deserialize)]
+struct Playlist {
+    id: u64,
+    name: String,
+    description: String,
+    items: Vec<u64>,
+}
+
+impl From<Model> for model::Model {
+    fn from(legacy: Model) -> Self {
+        model::Model {
+            search_query: legacy.search_query,
+            items: legacy.items.into_iter().map(Into::into).collect(),
+            playlists: legacy.playlists.into_iter().map(Into::into).collect(),
+            playlist_creation_state: legacy.playlist_creation_state.map(Into::into),
+            selected_playlist: legacy.selected_playlist,
+            playing_playlist: legacy.playing_playlist,
+            shuffle: legacy.shuffle,
+            id_counter: IdCounter::new(legacy.id_counter),
+            ..model::Model::default()
+        }
+    }
+}
+
+impl From<Item> for model::Item {
+    fn from(legacy: Item) -> Self {
+        model::Item {
+            id: legacy.id,
+            name: legacy.name,
+            stems: legacy
+                .stems
+                .into_iter()
+                .map(|stem| model::Stem {
+                    tag: stem.tag,
+                    path: stem.path,
+                    info: None,
+                })
+                .collect(),
+            current_stem: legacy.current_stem,
+            volume: legacy.volume,
+            muted: legacy.muted,
+            on_end: if legacy.looped {
+                OnEnd::Loop
+            } else {
+                OnEnd::Stop
+            },
+            status: legacy.status,
+            colour: model::Colour(legacy.colour),
+            bars: legacy.bars,
+            position: legacy.position,
+            target_position: legacy.target_position,
+            duration: legacy.duration,
+            issues: legacy.issues,
+            ..model::Item::default()
+        }
+    }
+}
+
+impl From<Playlist> for model::Playlist {
+    fn from(legacy: Playlist) -> Self {
+        model::Playlist {
+            description: legacy.description,
+            ..model::Playlist::new(legacy.id, legacy.name, legacy.items)
+        }
+    }
+}
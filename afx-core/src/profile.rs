@@ -0,0 +1,160 @@
+//! Mapping profiles: the keyboard shortcuts and MIDI mappings saved on their
+//! own, to carry a controller layout over to another machine. The items of
+//! another library have ids of their own, so the mappings of items are
+//! matched up by name when they're loaded.
+
+use crate::keymap::Keymap;
+use crate::midi::{MidiBinding, MidiTarget};
+use crate::model::Model;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub keymap: Keymap,
+    pub midi: Vec<ProfileBinding>,
+}
+
+/// A MIDI mapping along with the name of the item it targets, if any.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileBinding {
+    pub binding: MidiBinding,
+    pub item: Option<String>,
+}
+
+fn target_item(target: MidiTarget) -> Option<u64> {
+    match target {
+        MidiTarget::Item(id) | MidiTarget::ItemVolume(id) => Some(id),
+        MidiTarget::MasterVolume | MidiTarget::Bank(_) | MidiTarget::Go => None,
+    }
+}
+
+fn retarget(target: MidiTarget, id: u64) -> MidiTarget {
+    match target {
+        MidiTarget::Item(_) => MidiTarget::Item(id),
+        MidiTarget::ItemVolume(_) => MidiTarget::ItemVolume(id),
+        MidiTarget::MasterVolume | MidiTarget::Bank(_) | MidiTarget::Go => target,
+    }
+}
+
+impl Profile {
+    /// The mappings of the model, leaving out those of deleted items.
+    pub fn of(model: &Model) -> Profile {
+        let name_of = |id: u64| {
+            let item = model.items.iter().find(|item| item.id == id)?;
+            Some(item.name.clone())
+        };
+        let midi = model
+            .settings
+            .midi
+            .bindings
+            .iter()
+            .filter_map(|binding| {
+                let item = match target_item(binding.target) {
+                    Some(id) => Some(name_of(id)?),
+                    None => None,
+                };
+                Some(ProfileBinding {
+                    binding: binding.clone(),
+                    item,
+                })
+            })
+            .collect();
+        Profile {
+            keymap: model.settings.keymap.clone(),
+            midi,
+        }
+    }
+
+    /// Replace the mappings of the model with those of the profile. Items are
+    /// found by id if the name still matches, by name otherwise. Returns the
+    /// names of the items that couldn't be found, whose mappings are dropped.
+    pub fn apply(&self, model: &mut Model) -> Vec<String> {
+        let mut missing = vec![];
+        let mut bindings = vec![];
+        for ProfileBinding { binding, item } in &self.midi {
+            let (Some(id), Some(name)) = (target_item(binding.target), item) else {
+                bindings.push(binding.clone());
+                continue;
+            };
+            let same = model
+                .items
+                .iter()
+                .find(|item| item.id == id && item.name == *name);
+            match same.or_else(|| model.items.iter().find(|item| item.name == *name)) {
+                Some(item) => bindings.push(MidiBinding {
+                    target: retarget(binding.target, item.id),
+                    ..binding.clone()
+                }),
+                None => missing.push(name.clone()),
+            }
+        }
+        model.settings.keymap = self.keymap.clone();
+        model.settings.midi.bindings = bindings;
+        missing
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = ron::ser::to_string_pretty(self, Default::default())?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Profile> {
+        Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keymap::{Action, Key, Modifiers, Shortcut};
+    use crate::midi::MidiTrigger;
+    use crate::model::{Item, ORANGE};
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let item = |id, name: &str| {
+            Item::with_default_stem(id, name.to_string(), String::new(), ORANGE, 1.0)
+        };
+        let binding = |note, target| MidiBinding {
+            device: None,
+            trigger: MidiTrigger::Note { channel: 0, note },
+            target,
+        };
+        let mut model = Model {
+            items: vec![item(1, "Thunder"), item(2, "Rain")],
+            ..Model::default()
+        };
+        model.settings.keymap.bindings = vec![(Action::Go, Shortcut::new(Modifiers::NONE, Key::G))];
+        model.settings.midi.bindings = vec![
+            binding(36, MidiTarget::Item(1)),
+            binding(37, MidiTarget::ItemVolume(2)),
+            binding(38, MidiTarget::MasterVolume),
+            binding(39, MidiTarget::Item(9)),
+        ];
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("layout.ron");
+        Profile::of(&model).save(&path)?;
+        let profile = Profile::load(&path)?;
+        assert_eq!(profile.midi.len(), 3);
+
+        // another machine, where the rain has another id and no thunder
+        let mut other = Model {
+            items: vec![item(1, "Wind"), item(5, "Rain")],
+            ..Model::default()
+        };
+        assert_eq!(profile.apply(&mut other), vec!["Thunder".to_string()]);
+        assert_eq!(other.settings.keymap, model.settings.keymap);
+        assert_eq!(
+            other.settings.midi.bindings,
+            vec![
+                binding(37, MidiTarget::ItemVolume(5)),
+                binding(38, MidiTarget::MasterVolume)
+            ]
+        );
+        Ok(())
+    }
+}
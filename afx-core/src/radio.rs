@@ -0,0 +1,372 @@
+//! Internet radio. Items whose stem is an `http://` URL play an Icecast or
+//! Shoutcast stream, which is decoded on a thread of its own as it arrives
+//! and buffered ahead of playback to ride out a patchy connection.
+
+use crate::generator::Source;
+use anyhow::{anyhow, bail, Context, Result};
+use kira::dsp::Frame;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::io::{MediaSourceStream, ReadOnlySource};
+use symphonia::core::probe::Hint;
+use tracing::{info, warn};
+
+/// How long connecting to a station may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the station may go quiet before the stream counts as lost.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many redirects are followed to the actual stream.
+const MAX_REDIRECTS: usize = 5;
+/// How many seconds of audio are buffered before playback starts, and again
+/// whenever the buffer runs dry.
+const PREBUFFER: f64 = 2.0;
+/// How many seconds of audio are decoded ahead at most.
+const BUFFER: f64 = 10.0;
+/// How many frames are handed over to the audio thread at a time, enough for
+/// a batch of samples at any sample rate.
+const CHUNK: usize = 4096;
+/// How long the decoding thread waits for room in a full buffer.
+const FULL_BUFFER_WAIT: Duration = Duration::from_millis(50);
+
+/// Whether the path of a stem is the address of a stream rather than a file.
+pub fn is_stream_url(path: &str) -> bool {
+    let path = path.trim().to_ascii_lowercase();
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// A name for a stream, taken from its address.
+pub fn stream_name(url: &str) -> String {
+    let address = url.split_once("://").map_or(url, |(_, rest)| rest);
+    address.trim_end_matches('/').to_string()
+}
+
+/// The audio decoded from a stream, shared with the thread decoding it.
+#[derive(Default)]
+struct Buffer {
+    frames: VecDeque<Frame>,
+    sample_rate: u32,
+    ended: bool,
+    error: Option<String>,
+}
+
+/// Plays the audio of a stream as it's decoded, going silent while the
+/// buffer fills.
+pub(crate) struct Stream {
+    buffer: Arc<Mutex<Buffer>>,
+    /// The frames handed over to the audio thread.
+    local: VecDeque<Frame>,
+    sample_rate: u32,
+    /// How far between the first two local frames playback is.
+    fraction: f64,
+    buffering: bool,
+    ended: bool,
+    error: Option<String>,
+}
+
+/// Start receiving the stream at the given address on a background thread.
+pub(crate) fn open(url: &str) -> Stream {
+    let buffer = Arc::new(Mutex::new(Buffer::default()));
+    let (url, shared) = (url.trim().to_string(), buffer.clone());
+    std::thread::spawn(move || {
+        if let Err(err) = receive(&url, &shared) {
+            warn!("the stream {} failed: {:#}", url, err);
+            shared.lock().error = Some(format!("{:#}", err));
+        }
+        shared.lock().ended = true;
+    });
+    Stream {
+        buffer,
+        local: VecDeque::with_capacity(CHUNK * 2),
+        sample_rate: 0,
+        fraction: 0.0,
+        buffering: true,
+        ended: false,
+        error: None,
+    }
+}
+
+impl Source for Stream {
+    fn prepare(&mut self) {
+        let Some(mut buffer) = self.buffer.try_lock() else {
+            return;
+        };
+        self.sample_rate = buffer.sample_rate;
+        let prebuffered = buffer.sample_rate > 0
+            && buffer.frames.len() as f64 >= PREBUFFER * buffer.sample_rate as f64;
+        if self.buffering && (prebuffered || buffer.ended) {
+            self.buffering = false;
+        }
+        let wanted = CHUNK.saturating_sub(self.local.len());
+        let available = buffer.frames.len().min(wanted);
+        self.local.extend(buffer.frames.drain(..available));
+        if buffer.ended && buffer.frames.is_empty() {
+            self.ended = true;
+            // taken once, when playback stops
+            self.error = buffer.error.take();
+        }
+    }
+
+    fn next(&mut self, dt: f64) -> Frame {
+        if self.buffering {
+            return Frame::ZERO;
+        }
+        let (Some(&current), Some(&next)) = (self.local.front(), self.local.get(1)) else {
+            // out of audio, so wait for more unless there's no more to come
+            self.buffering = !self.ended;
+            return Frame::ZERO;
+        };
+        let frame = current + (next - current) * self.fraction as f32;
+        self.fraction += dt * self.sample_rate as f64;
+        while self.fraction >= 1.0 && !self.local.is_empty() {
+            self.local.pop_front();
+            self.fraction -= 1.0;
+        }
+        frame
+    }
+
+    fn ended(&self) -> bool {
+        self.ended && self.local.len() < 2
+    }
+
+    fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+}
+
+/// Decode the stream into the buffer until it ends, fails, or isn't played
+/// any more.
+fn receive(url: &str, buffer: &Arc<Mutex<Buffer>>) -> Result<()> {
+    let (reader, content_type) = connect(url)?;
+    let mut hint = Hint::new();
+    match content_type.as_deref() {
+        Some("audio/mpeg" | "audio/mp3") => hint.with_extension("mp3"),
+        Some("audio/ogg" | "application/ogg") => hint.with_extension("ogg"),
+        Some("audio/flac") => hint.with_extension("flac"),
+        Some("audio/wav" | "audio/x-wav") => hint.with_extension("wav"),
+        _ => &mut hint,
+    };
+    let stream = MediaSourceStream::new(Box::new(ReadOnlySource::new(reader)), Default::default());
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, stream, &Default::default(), &Default::default())
+        .context("the stream isn't in a supported format")?
+        .format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow!("the stream has no audio"))?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| anyhow!("the stream doesn't tell its sample rate"))?;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &Default::default())?;
+    buffer.lock().sample_rate = sample_rate;
+    info!("receiving {} at {} Hz", url, sample_rate);
+
+    let capacity = (BUFFER * sample_rate as f64) as usize;
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    loop {
+        // nobody is listening once the sound is gone
+        if Arc::strong_count(buffer) == 1 {
+            return Ok(());
+        }
+        if buffer.lock().frames.len() >= capacity {
+            std::thread::sleep(FULL_BUFFER_WAIT);
+            continue;
+        }
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(symphonia::core::errors::Error::IoError(err))
+                if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // a corrupt packet only costs a moment of audio
+            Err(symphonia::core::errors::Error::DecodeError(err)) => {
+                warn!("skipping a packet of {}: {}", url, err);
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let channels = decoded.spec().channels.count().max(1);
+        let samples = match &mut samples {
+            Some(samples) if samples.capacity() >= decoded.capacity() * channels => samples,
+            samples => samples.insert(SampleBuffer::new(
+                decoded.capacity() as u64,
+                *decoded.spec(),
+            )),
+        };
+        samples.copy_interleaved_ref(decoded);
+        let frames = samples.samples().chunks_exact(channels).map(|frame| Frame {
+            left: frame[0],
+            right: frame[1.min(channels - 1)],
+        });
+        buffer.lock().frames.extend(frames);
+    }
+}
+
+/// Request the stream at the given address, following redirects. Returns
+/// the body along with its content type.
+fn connect(url: &str) -> Result<(BufReader<TcpStream>, Option<String>)> {
+    let mut url = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let (host, path) = match url.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => rest
+                .split_once('/')
+                .map_or((rest, "/".to_string()), |(host, path)| {
+                    (host, format!("/{}", path))
+                }),
+            Some((scheme, _)) if scheme.eq_ignore_ascii_case("https") => {
+                bail!("secure streams aren't supported, try the http:// address of the station")
+            }
+            _ => bail!("{} isn't an http:// address", url),
+        };
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:80", host)
+        };
+        let address = address
+            .to_socket_addrs()
+            .with_context(|| format!("couldn't find {}", host))?
+            .next()
+            .ok_or_else(|| anyhow!("couldn't find {}", host))?;
+        let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+            .with_context(|| format!("couldn't connect to {}", host))?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        // HTTP/1.0 keeps the body free of chunked encoding, and leaving out
+        // Icy-MetaData keeps it free of song titles
+        write!(
+            stream,
+            "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: afx\r\nAccept: */*\r\n\r\n",
+            path, host
+        )?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status)?;
+        // Shoutcast answers with ICY in place of the HTTP version
+        let code: u16 = status
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| anyhow!("{} didn't answer with a stream", host))?;
+        let mut content_type = None;
+        let mut location = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                let value = value.trim().to_string();
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "content-type" => content_type = Some(value.to_ascii_lowercase()),
+                    "location" => location = Some(value),
+                    _ => (),
+                }
+            }
+        }
+        match (code, location) {
+            (200..=299, _) => return Ok((reader, content_type)),
+            (300..=399, Some(location)) => {
+                info!("{} redirects to {}", url, location);
+                url = match location.starts_with('/') {
+                    true => format!("http://{}{}", host, location),
+                    false => location,
+                };
+            }
+            _ => bail!("{} answered {}", host, status.trim()),
+        }
+    }
+    bail!("too many redirects")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn drain(stream: &mut Stream) -> Vec<Frame> {
+        let mut frames = vec![];
+        while !stream.ended() {
+            stream.prepare();
+            frames.extend(stream.local.drain(..));
+            std::thread::yield_now();
+        }
+        frames
+    }
+
+    /// Serve the given answers to one connection each.
+    fn serve(answers: Vec<Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for answer in answers {
+                let (mut connection, _) = listener.accept().unwrap();
+                let mut request = BufReader::new(connection.try_clone().unwrap());
+                let mut line = String::new();
+                while request.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                connection.write_all(&answer).ok();
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn receives() {
+        let wav = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../samples/416529__inspectorj__bird-whistling-single-robin-a.wav"
+        ))
+        .unwrap();
+        let mut stream_answer = b"ICY 200 OK\r\ncontent-type: audio/wav\r\n\r\n".to_vec();
+        stream_answer.extend(wav);
+        let url = serve(vec![
+            b"HTTP/1.0 302 Found\r\nLocation: /live\r\n\r\n".to_vec(),
+            stream_answer,
+        ]);
+
+        let mut stream = open(&url);
+        let frames = drain(&mut stream);
+        assert_eq!(stream.error(), None);
+        assert_eq!(stream.sample_rate, 44100);
+        assert_eq!(frames.len(), 232_705);
+    }
+
+    #[test]
+    fn fails() {
+        let url = serve(vec![b"HTTP/1.1 404 Not Found\r\n\r\n".to_vec()]);
+        let mut stream = open(&url);
+        assert!(drain(&mut stream).is_empty());
+        assert!(stream.error().unwrap().contains("404"));
+
+        let mut stream = open("https://radio.example/live");
+        drain(&mut stream);
+        assert!(stream.error().unwrap().contains("secure"));
+    }
+
+    #[test]
+    fn names() {
+        assert!(is_stream_url(" HTTP://radio.example/live"));
+        assert!(!is_stream_url("/music/http.mp3"));
+        assert_eq!(
+            stream_name("http://radio.example/live/"),
+            "radio.example/live"
+        );
+    }
+}
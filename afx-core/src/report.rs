@@ -0,0 +1,293 @@
+//! Show reports: the playback history of a session as CSV or JSON, for
+//! stage management, and as Audacity labels, for finding the cues in a
+//! recording of it.
+
+use crate::model::*;
+use anyhow::Result;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+impl PlaybackEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            PlaybackEvent::Start => "start",
+            PlaybackEvent::Resume => "resume",
+            PlaybackEvent::Pause => "pause",
+            PlaybackEvent::Stop => "stop",
+            PlaybackEvent::Seek => "seek",
+            PlaybackEvent::Cue => "cue",
+            PlaybackEvent::PauseAll => "pause all",
+            PlaybackEvent::ResumeAll => "resume all",
+            PlaybackEvent::StopAll => "stop all",
+        }
+    }
+}
+
+/// Format a time as an RFC 3339 timestamp in UTC, to the millisecond.
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, of_day) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // the civil date from the days since the epoch, after Howard Hinnant
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn json_string(string: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// One line per entry, with the duration in seconds.
+pub fn csv(history: &[HistoryEntry]) -> String {
+    let mut csv = String::from("time,event,item,cue,duration\n");
+    for entry in history {
+        writeln!(
+            csv,
+            "{},{},{},{},{}",
+            timestamp(entry.time),
+            entry.event.name(),
+            entry.item.map(|id| id.to_string()).unwrap_or_default(),
+            csv_field(&entry.name),
+            entry
+                .duration
+                .map(|duration| format!("{:.3}", duration))
+                .unwrap_or_default(),
+        )
+        .unwrap();
+    }
+    csv
+}
+
+/// An array of objects with the same fields as the [`csv`] columns, missing
+/// values being null.
+pub fn json(history: &[HistoryEntry]) -> String {
+    let entries: Vec<_> = history
+        .iter()
+        .map(|entry| {
+            format!(
+                "  {{\"time\": \"{}\", \"event\": \"{}\", \"item\": {}, \"cue\": {}, \"duration\": {}}}",
+                timestamp(entry.time),
+                entry.event.name(),
+                entry.item.map_or("null".to_string(), |id| id.to_string()),
+                json_string(&entry.name),
+                entry
+                    .duration
+                    .map_or("null".to_string(), |duration| format!("{:.3}", duration)),
+            )
+        })
+        .collect();
+    if entries.is_empty() {
+        "[]\n".to_string()
+    } else {
+        format!("[\n{}\n]\n", entries.join(",\n"))
+    }
+}
+
+/// Add a line of an Audacity label track: the start and end of the label in
+/// seconds, the same for a point, and its text, which can't span lines.
+fn label(labels: &mut String, start: f64, end: f64, text: &str) {
+    let text = text.replace(['\t', '\n', '\r'], " ");
+    writeln!(labels, "{:.6}\t{:.6}\t{}", start, end, text).unwrap();
+}
+
+/// Audacity labels for the markers of an item: its trim points and loop
+/// region.
+pub fn item_labels(item: &Item) -> String {
+    let mut labels = String::new();
+    if let Some((start, end)) = item.trim {
+        label(&mut labels, start, start, "trim start");
+        label(&mut labels, end, end, "trim end");
+    }
+    if let Some((start, end)) = item.loop_region {
+        label(&mut labels, start, end, "loop");
+    }
+    labels
+}
+
+/// Audacity labels for a run through the items, played one after another,
+/// each spanning the trimmed part of it.
+pub fn run_labels<'a>(items: impl IntoIterator<Item = &'a Item>) -> String {
+    let mut labels = String::new();
+    let mut time = 0.0;
+    for item in items {
+        let (start, end) = item.trim_bounds();
+        let length = (end - start).max(0.0);
+        label(&mut labels, time, time + length, &item.name);
+        time += length;
+    }
+    labels
+}
+
+/// Audacity labels for the history, timed from its first entry, so that they
+/// line up with a recording started then. Starts span as long as the item
+/// played, the other events are points.
+pub fn history_labels(history: &[HistoryEntry]) -> String {
+    let mut labels = String::new();
+    let Some(first) = history.first() else {
+        return labels;
+    };
+    for entry in history {
+        let start = entry
+            .time
+            .duration_since(first.time)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let text = match (entry.event, entry.name.is_empty()) {
+            (PlaybackEvent::Start, _) => entry.name.clone(),
+            (event, true) => event.name().to_string(),
+            (event, false) => format!("{} {}", event.name(), entry.name),
+        };
+        label(
+            &mut labels,
+            start,
+            start + entry.duration.unwrap_or(0.0),
+            &text,
+        );
+    }
+    labels
+}
+
+/// Write a report, as JSON if the file name ends in `.json`, as Audacity
+/// labels if it ends in `.txt` and as CSV otherwise.
+pub fn save(path: &Path, history: &[HistoryEntry]) -> Result<()> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    let report = match extension.as_deref() {
+        Some("json") => json(history),
+        Some("txt") => history_labels(history),
+        _ => csv(history),
+    };
+    std::fs::write(path, report)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn history() -> Vec<HistoryEntry> {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_250);
+        vec![
+            HistoryEntry {
+                time,
+                event: PlaybackEvent::Start,
+                item: Some(3),
+                name: "Storm, \"loud\"".to_string(),
+                duration: Some(12.5),
+            },
+            HistoryEntry {
+                time: time + Duration::from_secs(90_000),
+                event: PlaybackEvent::StopAll,
+                item: None,
+                name: String::new(),
+                duration: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3661);
+        assert_eq!(timestamp(leap_day), "2000-02-29T01:01:01.000Z");
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            csv(&history()),
+            "time,event,item,cue,duration\n\
+            2023-11-14T22:13:20.250Z,start,3,\"Storm, \"\"loud\"\"\",12.500\n\
+            2023-11-15T23:13:20.250Z,stop all,,,\n"
+        );
+        assert_eq!(
+            json(&history()),
+            "[\n  \
+            {\"time\": \"2023-11-14T22:13:20.250Z\", \"event\": \"start\", \"item\": 3, \
+            \"cue\": \"Storm, \\\"loud\\\"\", \"duration\": 12.500},\n  \
+            {\"time\": \"2023-11-15T23:13:20.250Z\", \"event\": \"stop all\", \"item\": null, \
+            \"cue\": \"\", \"duration\": null}\n]\n"
+        );
+        assert_eq!(json(&[]), "[]\n");
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(
+            history_labels(&history()),
+            "0.000000\t12.500000\tStorm, \"loud\"\n\
+            90000.000000\t90000.000000\tstop all\n"
+        );
+        assert_eq!(history_labels(&[]), "");
+
+        let mut item = Item::with_default_stem(
+            1,
+            "Rain\tand wind".to_string(),
+            "rain.ogg".to_string(),
+            Colour::BLACK,
+            10.0,
+        );
+        assert_eq!(item_labels(&item), "");
+        item.trim = Some((0.5, 8.0));
+        item.loop_region = Some((2.0, 4.25));
+        assert_eq!(
+            item_labels(&item),
+            "0.500000\t0.500000\ttrim start\n\
+            8.000000\t8.000000\ttrim end\n\
+            2.000000\t4.250000\tloop\n"
+        );
+
+        let mut whole = item.clone();
+        whole.name = "Thunder".to_string();
+        whole.trim = None;
+        assert_eq!(
+            run_labels([&item, &whole]),
+            "0.000000\t7.500000\tRain and wind\n\
+            7.500000\t17.500000\tThunder\n"
+        );
+    }
+}
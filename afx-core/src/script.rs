@@ -0,0 +1,683 @@
+//! Cue scripts: small programs reacting to items starting and ending.
+//!
+//! A script is a list of hooks, each a block of commands run when its event
+//! happens:
+//!
+//! ```text
+//! # fade the room out once the intro is over
+//! on end "Intro"
+//!     volume "Room tone" -12
+//!     wait 2.5
+//!     if playing "Room tone"
+//!         stop "Room tone"
+//!     else
+//!         go
+//!     end
+//! end
+//! ```
+//!
+//! Items are referred to by their quoted name or by their id. The events are
+//! `on load`, `on start <item>` and `on end <item>`; the commands are `play`
+//! (which starts several items on the same sample if given them), `pause`
+//! and `stop` (which also take `all` for pause and stop), `loop <item>
+//! on|off`, `volume <item> <dB>`, `master <dB>`, `go`, `wait <seconds>` and
+//! `log <text>`, plus `if [not] playing|paused|stopped <item>` blocks with an
+//! optional `else`.
+
+use crate::model::*;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use tracing::info;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ItemRef {
+    Id(u64),
+    Name(String),
+}
+
+impl ItemRef {
+    fn matches(&self, item: &Item) -> bool {
+        match self {
+            ItemRef::Id(id) => item.id == *id,
+            ItemRef::Name(name) => item.name == *name,
+        }
+    }
+
+    fn find<'a>(&self, model: &'a Model) -> Option<&'a Item> {
+        model.items.iter().find(|item| self.matches(item))
+    }
+}
+
+impl std::fmt::Display for ItemRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ItemRef::Id(id) => write!(f, "{}", id),
+            ItemRef::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Event {
+    Load,
+    Start(ItemRef),
+    End(ItemRef),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Command {
+    /// Start the items, all on the same sample if there are several.
+    Play(Vec<ItemRef>),
+    Pause(ItemRef),
+    Stop(ItemRef),
+    PauseAll,
+    StopAll,
+    Loop(ItemRef, bool),
+    /// Set the volume of an item, in decibels.
+    Volume(ItemRef, f64),
+    /// Set the master volume, in decibels.
+    Master(f64),
+    Go,
+    Log(String),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Condition {
+    pub negated: bool,
+    pub status: ItemStatus,
+    pub item: ItemRef,
+}
+
+impl Condition {
+    fn holds(&self, model: &Model) -> bool {
+        let status = self.item.find(model).map(|item| match item.status {
+            // an item that's loading is as good as playing
+            ItemStatus::Loading => ItemStatus::Playing,
+            ref status => status.clone(),
+        });
+        // an item that doesn't exist is neither playing nor paused
+        (status.unwrap_or(ItemStatus::Stopped) == self.status) != self.negated
+    }
+}
+
+/// A hook body is compiled to a flat list of instructions so that a running
+/// hook is just a position within it.
+#[derive(PartialEq, Debug, Clone)]
+enum Instr {
+    Command(Command, usize),
+    Wait(f64),
+    /// Jump to the instruction unless the condition holds.
+    Unless(Condition, usize),
+    Jump(usize),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Hook {
+    event: Event,
+    body: Vec<Instr>,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Script {
+    hooks: Vec<Hook>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Token {
+    Word(String),
+    Str(String),
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next().ok_or_else(|| anyhow!("unterminated string"))? {
+                    '"' => break,
+                    '\\' => {
+                        string.push(chars.next().ok_or_else(|| anyhow!("unterminated string"))?)
+                    }
+                    c => string.push(c),
+                }
+            }
+            tokens.push(Token::Str(string));
+        } else {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                word.push(c);
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+/// The tokens of a single line, consumed from the front.
+struct Line(std::collections::VecDeque<Token>);
+
+impl Line {
+    fn word(&mut self) -> Result<String> {
+        match self.0.pop_front() {
+            Some(Token::Word(word)) => Ok(word),
+            Some(Token::Str(string)) => bail!("expected a keyword, found \"{}\"", string),
+            None => bail!("unexpected end of line"),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64> {
+        let word = self.word()?;
+        word.parse()
+            .ok()
+            .filter(|n: &f64| n.is_finite())
+            .ok_or_else(|| anyhow!("expected a number, found {}", word))
+    }
+
+    /// A gain, held within the range of the faders.
+    fn decibels(&mut self) -> Result<f64> {
+        Ok(self.number()?.clamp(SILENCE_DB, MAX_GAIN_DB))
+    }
+
+    fn item(&mut self) -> Result<ItemRef> {
+        match self.0.pop_front() {
+            Some(Token::Str(name)) => Ok(ItemRef::Name(name)),
+            Some(Token::Word(word)) => word
+                .parse()
+                .map(ItemRef::Id)
+                .map_err(|_| anyhow!("expected a quoted item name or an id, found {}", word)),
+            None => bail!("missing item"),
+        }
+    }
+
+    fn peek_word(&self, expected: &str) -> bool {
+        matches!(self.0.front(), Some(Token::Word(word)) if word == expected)
+    }
+
+    fn finish(self) -> Result<()> {
+        match self.0.front() {
+            None => Ok(()),
+            Some(Token::Word(word)) => bail!("unexpected {}", word),
+            Some(Token::Str(string)) => bail!("unexpected \"{}\"", string),
+        }
+    }
+}
+
+enum Block {
+    Hook,
+    /// The position of the jump over the block.
+    If(usize),
+    /// The position of the jump over the block.
+    Else(usize),
+}
+
+impl Script {
+    /// Parse a script, reporting the first error along with its line number.
+    pub fn parse(source: &str) -> Result<Script> {
+        let mut script = Script::default();
+        let mut blocks: Vec<Block> = vec![];
+        for (n, text) in source.lines().enumerate() {
+            Self::parse_line(&mut script, &mut blocks, text, n + 1)
+                .map_err(|err| anyhow!("line {}: {}", n + 1, err))?;
+        }
+        if !blocks.is_empty() {
+            bail!("missing end");
+        }
+        Ok(script)
+    }
+
+    fn parse_line(
+        script: &mut Script,
+        blocks: &mut Vec<Block>,
+        text: &str,
+        n: usize,
+    ) -> Result<()> {
+        let mut line = Line(tokenize(text)?.into());
+        if line.0.is_empty() {
+            return Ok(());
+        }
+        let keyword = line.word()?;
+
+        if blocks.is_empty() {
+            if keyword != "on" {
+                bail!(
+                    "expected a hook such as on start \"Item\", found {}",
+                    keyword
+                );
+            }
+            let event = match line.word()?.as_str() {
+                "load" => Event::Load,
+                "start" => Event::Start(line.item()?),
+                "end" => Event::End(line.item()?),
+                other => bail!("unknown event {}", other),
+            };
+            line.finish()?;
+            script.hooks.push(Hook {
+                event,
+                body: vec![],
+            });
+            blocks.push(Block::Hook);
+            return Ok(());
+        }
+
+        let body = &mut script.hooks.last_mut().unwrap().body;
+        let instr = match keyword.as_str() {
+            "on" => bail!("hooks can't be nested"),
+            "end" => {
+                line.finish()?;
+                match blocks.pop().unwrap() {
+                    Block::Hook => (),
+                    Block::If(at) | Block::Else(at) => {
+                        let end = body.len();
+                        match &mut body[at] {
+                            Instr::Unless(_, target) | Instr::Jump(target) => *target = end,
+                            _ => unreachable!(),
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            "else" => {
+                line.finish()?;
+                let Some(Block::If(at)) = blocks.pop() else {
+                    bail!("else without if");
+                };
+                body.push(Instr::Jump(0));
+                let end = body.len();
+                if let Instr::Unless(_, target) = &mut body[at] {
+                    *target = end;
+                }
+                blocks.push(Block::Else(end - 1));
+                return Ok(());
+            }
+            "if" => {
+                let negated = line.peek_word("not");
+                if negated {
+                    line.word()?;
+                }
+                let status = match line.word()?.as_str() {
+                    "playing" => ItemStatus::Playing,
+                    "paused" => ItemStatus::Paused,
+                    "stopped" => ItemStatus::Stopped,
+                    other => bail!("unknown condition {}", other),
+                };
+                let item = line.item()?;
+                blocks.push(Block::If(body.len()));
+                Instr::Unless(
+                    Condition {
+                        negated,
+                        status,
+                        item,
+                    },
+                    0,
+                )
+            }
+            "wait" => {
+                let seconds = line.number()?;
+                if seconds < 0.0 {
+                    bail!("can't wait for {} seconds", seconds);
+                }
+                Instr::Wait(seconds)
+            }
+            "play" => {
+                let mut items = vec![line.item()?];
+                while !line.0.is_empty() {
+                    items.push(line.item()?);
+                }
+                Instr::Command(Command::Play(items), n)
+            }
+            "pause" if line.peek_word("all") => {
+                line.word()?;
+                Instr::Command(Command::PauseAll, n)
+            }
+            "pause" => Instr::Command(Command::Pause(line.item()?), n),
+            "stop" if line.peek_word("all") => {
+                line.word()?;
+                Instr::Command(Command::StopAll, n)
+            }
+            "stop" => Instr::Command(Command::Stop(line.item()?), n),
+            "loop" => {
+                let item = line.item()?;
+                let looped = match line.word()?.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    other => bail!("expected on or off, found {}", other),
+                };
+                Instr::Command(Command::Loop(item, looped), n)
+            }
+            "volume" => Instr::Command(Command::Volume(line.item()?, line.decibels()?), n),
+            "master" => Instr::Command(Command::Master(line.decibels()?), n),
+            "go" => Instr::Command(Command::Go, n),
+            "log" => {
+                let text = line
+                    .0
+                    .drain(..)
+                    .map(|token| match token {
+                        Token::Word(word) | Token::Str(word) => word,
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                Instr::Command(Command::Log(text), n)
+            }
+            other => bail!("unknown command {}", other),
+        };
+        line.finish()?;
+        body.push(instr);
+        Ok(())
+    }
+}
+
+/// A hook that is running, possibly waiting.
+#[derive(Debug)]
+struct Task {
+    hook: usize,
+    pc: usize,
+    resume_at: f64,
+}
+
+/// Runs the hooks of a script as the model changes.
+pub struct Runner {
+    script: Script,
+    tasks: Vec<Task>,
+    /// The status of each item as of the last update, to tell when items
+    /// start and end.
+    statuses: HashMap<u64, ItemStatus>,
+    loaded: bool,
+}
+
+impl Runner {
+    pub fn new(script: Script) -> Self {
+        Self {
+            script,
+            tasks: vec![],
+            statuses: HashMap::new(),
+            loaded: false,
+        }
+    }
+
+    /// Fire the hooks of whatever happened since the last update and carry on
+    /// with the waiting ones. `now` is a time in seconds. The commands are
+    /// mirrored in the model, and the messages carrying them out returned.
+    /// Commands that can't be carried out are reported as an error.
+    pub fn update(&mut self, now: f64, model: &mut Model) -> (Vec<ControlMessage>, Vec<String>) {
+        let mut events = vec![];
+        if !self.loaded {
+            self.loaded = true;
+            events.push(Event::Load);
+        }
+        for item in &model.items {
+            let previous = self.statuses.insert(item.id, item.status.clone());
+            let Some(previous) = previous else {
+                continue;
+            };
+            let started = item.status == ItemStatus::Playing
+                && matches!(previous, ItemStatus::Stopped | ItemStatus::Loading);
+            let ended = item.status == ItemStatus::Stopped && previous != ItemStatus::Stopped;
+            if started {
+                events.push(Event::Start(ItemRef::Id(item.id)));
+            }
+            if ended {
+                events.push(Event::End(ItemRef::Id(item.id)));
+            }
+        }
+        self.statuses
+            .retain(|id, _| model.items.iter().any(|item| item.id == *id));
+
+        for event in events {
+            for (i, hook) in self.script.hooks.iter().enumerate() {
+                let fires = match (&hook.event, &event) {
+                    (Event::Load, Event::Load) => true,
+                    (Event::Start(hooked), Event::Start(ItemRef::Id(id)))
+                    | (Event::End(hooked), Event::End(ItemRef::Id(id))) => model
+                        .items
+                        .iter()
+                        .any(|item| item.id == *id && hooked.matches(item)),
+                    _ => false,
+                };
+                if fires {
+                    self.tasks.push(Task {
+                        hook: i,
+                        pc: 0,
+                        resume_at: now,
+                    });
+                }
+            }
+        }
+
+        let mut messages = vec![];
+        let mut errors = vec![];
+        let script = &self.script;
+        self.tasks.retain_mut(|task| {
+            if task.resume_at > now {
+                return true;
+            }
+            let body = &script.hooks[task.hook].body;
+            while let Some(instr) = body.get(task.pc) {
+                task.pc += 1;
+                match instr {
+                    Instr::Command(command, line) => {
+                        if let Err(err) = execute(command, model, &mut messages) {
+                            errors.push(format!("line {}: {}", line, err));
+                        }
+                    }
+                    Instr::Wait(seconds) => {
+                        // waits add up from when they were due, not from
+                        // when the update noticed
+                        task.resume_at += seconds;
+                        if task.resume_at > now {
+                            return true;
+                        }
+                    }
+                    Instr::Unless(condition, target) => {
+                        if !condition.holds(model) {
+                            task.pc = *target;
+                        }
+                    }
+                    Instr::Jump(target) => task.pc = *target,
+                }
+            }
+            false
+        });
+        (messages, errors)
+    }
+}
+
+fn execute(command: &Command, model: &mut Model, messages: &mut Vec<ControlMessage>) -> Result<()> {
+    let id = |item: &ItemRef| {
+        item.find(model)
+            .map(|item| item.id)
+            .ok_or_else(|| anyhow!("no item {}", item))
+    };
+    match command {
+        Command::Play(items) => {
+            let mut ids = items.iter().map(id).collect::<Result<Vec<_>>>()?;
+            messages.push(match ids.len() {
+                1 => ControlMessage::Play(ids.remove(0)),
+                _ => ControlMessage::PlayTogether(ids),
+            });
+        }
+        Command::Pause(item) => messages.push(ControlMessage::Pause(id(item)?)),
+        Command::Stop(item) => messages.push(ControlMessage::Stop(id(item)?)),
+        Command::PauseAll => messages.push(ControlMessage::GlobalPause),
+        Command::StopAll => messages.push(ControlMessage::GlobalStop),
+        Command::Loop(item, looped) => {
+            let id = id(item)?;
+            if let Some(item) = model.items.iter_mut().find(|item| item.id == id) {
+                item.set_looped(*looped);
+            }
+            messages.push(ControlMessage::Loop(id, *looped));
+        }
+        Command::Volume(item, db) => {
+            let id = id(item)?;
+            let volume = db_to_amplitude(*db);
+            if let Some(item) = model.items.iter_mut().find(|item| item.id == id) {
+                item.volume = volume;
+            }
+            messages.push(ControlMessage::SetVolume(id, volume));
+        }
+        Command::Master(db) => {
+            model.master_gain_db = *db;
+            messages.push(ControlMessage::SetMasterVolume(db_to_amplitude(*db)));
+        }
+        Command::Go => {
+            messages.extend(model.fire_standby());
+        }
+        Command::Log(text) => info!("script: {}", text),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn model() -> Model {
+        let mut model = Model::default();
+        for (id, name) in [(1, "Intro"), (2, "Music")] {
+            model.items.push(Item::with_default_stem(
+                id,
+                name.to_string(),
+                String::new(),
+                ORANGE,
+                10.0,
+            ));
+        }
+        model
+    }
+
+    fn set_status(model: &mut Model, id: u64, status: ItemStatus) {
+        model.items.iter_mut().find(|i| i.id == id).unwrap().status = status;
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source: &str| Script::parse(source).unwrap_err().to_string();
+        assert_eq!(
+            error("play 1"),
+            "line 1: expected a hook such as on start \"Item\", found play"
+        );
+        assert_eq!(
+            error("on start \"Intro\"\n  dance\nend"),
+            "line 2: unknown command dance"
+        );
+        assert_eq!(error("on load\n  if playing 1\nend"), "missing end");
+        assert_eq!(error("on load\n  else\nend"), "line 2: else without if");
+        assert_eq!(
+            error("on load\n  wait -1\nend"),
+            "line 2: can't wait for -1 seconds"
+        );
+        assert_eq!(
+            error("on load\n  stop \"Intro\" now\nend"),
+            "line 2: unexpected now"
+        );
+        assert_eq!(
+            error("on load\n  volume \"Intro\" NaN\nend"),
+            "line 2: expected a number, found NaN"
+        );
+        assert_eq!(
+            error("on load\n  master inf\nend"),
+            "line 2: expected a number, found inf"
+        );
+        assert_eq!(
+            error("on load\n  wait infinity\nend"),
+            "line 2: expected a number, found infinity"
+        );
+        assert!(Script::parse("# nothing\n\non load # at startup\nend").is_ok());
+    }
+
+    #[test]
+    fn hooks() -> Result<()> {
+        let script = Script::parse(
+            r#"
+            on load
+                master -6
+            end
+            on start "Intro"
+                wait 1.5
+                if not playing "Music"
+                    volume 2 0
+                    play "Music"
+                else
+                    log already playing
+                end
+            end
+            on end 1
+                stop all
+                play 1 "Music"
+            end
+            "#,
+        )?;
+        let mut model = model();
+        let mut runner = Runner::new(script);
+
+        let (messages, errors) = runner.update(0.0, &mut model);
+        assert_eq!(
+            messages,
+            vec![ControlMessage::SetMasterVolume(db_to_amplitude(-6.0))]
+        );
+        assert!(errors.is_empty());
+        assert_eq!(model.master_gain_db, -6.0);
+
+        set_status(&mut model, 1, ItemStatus::Loading);
+        assert!(runner.update(0.05, &mut model).0.is_empty());
+        set_status(&mut model, 1, ItemStatus::Playing);
+        assert!(runner.update(0.1, &mut model).0.is_empty());
+        // resuming isn't starting
+        set_status(&mut model, 1, ItemStatus::Paused);
+        runner.update(0.2, &mut model);
+        set_status(&mut model, 1, ItemStatus::Playing);
+        runner.update(0.3, &mut model);
+
+        assert!(runner.update(1.5, &mut model).0.is_empty());
+        let (messages, _) = runner.update(1.65, &mut model);
+        assert_eq!(
+            messages,
+            vec![ControlMessage::SetVolume(2, 1.0), ControlMessage::Play(2)]
+        );
+        assert_eq!(model.items[1].volume, 1.0);
+        assert!(runner.update(5.0, &mut model).0.is_empty());
+
+        set_status(&mut model, 1, ItemStatus::Stopped);
+        assert_eq!(
+            runner.update(6.0, &mut model).0,
+            vec![
+                ControlMessage::GlobalStop,
+                ControlMessage::PlayTogether(vec![1, 2])
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn gains_stay_within_the_faders() -> Result<()> {
+        let script = Script::parse("on load\n  master 60\n  volume 2 -200\nend")?;
+        let mut model = model();
+        let (messages, _) = Runner::new(script).update(0.0, &mut model);
+        assert_eq!(model.master_gain_db, MAX_GAIN_DB);
+        assert_eq!(model.items[1].volume, db_to_amplitude(SILENCE_DB));
+        assert_eq!(
+            messages,
+            vec![
+                ControlMessage::SetMasterVolume(db_to_amplitude(MAX_GAIN_DB)),
+                ControlMessage::SetVolume(2, db_to_amplitude(SILENCE_DB)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_items() -> Result<()> {
+        let mut model = model();
+        let mut runner = Runner::new(Script::parse("on load\n  play \"Outro\"\n  go\nend")?);
+        let (messages, errors) = runner.update(0.0, &mut model);
+        assert!(messages.is_empty());
+        assert_eq!(errors, vec!["line 2: no item \"Outro\"".to_string()]);
+        Ok(())
+    }
+}
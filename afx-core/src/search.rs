@@ -0,0 +1,45 @@
+//! Matching search queries against names regardless of how they are spelled
+//! out in Unicode, so that `cafe` finds `Café` and names in other scripts
+//! can be searched for at all.
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Bring text to the form it is compared in: compatibility characters are
+/// decomposed, accents and other combining marks dropped, and the rest put
+/// in lowercase, with the letters that only fold to several lower case ones
+/// spelled out.
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.nfkd().filter(|&c| !is_combining_mark(c)) {
+        match c {
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            // the final form only differs by its position in the word
+            'ς' => folded.push('σ'),
+            _ => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+/// The words of a query, folded.
+pub fn words(query: &str) -> Vec<String> {
+    fold(query).split_whitespace().map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold("Café_Ambience.ogg"), "cafe_ambience.ogg");
+        assert_eq!(fold("ÅNGSTRÖM"), "angstrom");
+        assert_eq!(fold("Straße"), "strasse");
+        assert_eq!(fold("ΣΕΙΣΜΟΣ"), fold("σεισμος"));
+        assert_eq!(fold("ﬁre ①"), "fire 1");
+        assert_eq!(fold("雷の音"), "雷の音");
+        assert!(fold("Громкий Гром").contains(&fold("гром")));
+        assert_eq!(words(" cafe\u{3000}Noir "), vec!["cafe", "noir"]);
+    }
+}
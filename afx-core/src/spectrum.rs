@@ -0,0 +1,189 @@
+//! Live spectra of the sounds being played. An [`Analyser`] taps the mixer
+//! track of an item and divides the latest audio passing through it into
+//! frequency bands, scaled like the bars of a waveform.
+
+use kira::clock::clock_info::ClockInfoProvider;
+use kira::dsp::Frame;
+use kira::track::effect::{Effect, EffectBuilder};
+use parking_lot::Mutex;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// How many samples each spectrum is computed from. Must be a power of two.
+const WINDOW: usize = 1024;
+/// How many bands a spectrum is divided into.
+pub const BANDS: usize = 32;
+/// The range of frequencies the bands cover, in hertz.
+const FREQUENCIES: (f32, f32) = (40.0, 16_000.0);
+/// The level of an empty band, in decibels.
+const FLOOR_DB: f32 = -60.0;
+
+#[derive(Default)]
+struct Capture {
+    /// The latest samples, oldest first.
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+/// Adds an [`Analyser`] to a track.
+pub struct AnalyserBuilder;
+
+impl EffectBuilder for AnalyserBuilder {
+    type Handle = Analyser;
+
+    fn build(self) -> (Box<dyn Effect>, Analyser) {
+        let capture = Arc::new(Mutex::new(Capture::default()));
+        let effect = Tap {
+            ring: vec![0.0; WINDOW],
+            next: 0,
+            capture: capture.clone(),
+        };
+        (Box::new(effect), Analyser(capture))
+    }
+}
+
+/// Passes the audio through unchanged, keeping the latest samples for the
+/// [`Analyser`]. They are handed over once per batch, and only if the
+/// analyser isn't busy with them, so that the audio thread never waits.
+struct Tap {
+    ring: Vec<f32>,
+    next: usize,
+    capture: Arc<Mutex<Capture>>,
+}
+
+impl Effect for Tap {
+    fn init(&mut self, sample_rate: u32) {
+        self.capture.lock().sample_rate = sample_rate;
+    }
+
+    fn on_change_sample_rate(&mut self, sample_rate: u32) {
+        self.capture.lock().sample_rate = sample_rate;
+    }
+
+    fn on_start_processing(&mut self) {
+        if let Some(mut capture) = self.capture.try_lock() {
+            capture.samples.clear();
+            capture.samples.extend_from_slice(&self.ring[self.next..]);
+            capture.samples.extend_from_slice(&self.ring[..self.next]);
+        }
+    }
+
+    fn process(&mut self, input: Frame, _dt: f64, _clock_info: &ClockInfoProvider) -> Frame {
+        self.ring[self.next] = (input.left + input.right) / 2.0;
+        self.next = (self.next + 1) % WINDOW;
+        input
+    }
+}
+
+/// Reads the spectrum of the audio going through a track.
+pub struct Analyser(Arc<Mutex<Capture>>);
+
+impl Analyser {
+    /// The level of each band, empty until the track has started.
+    pub fn bands(&self) -> Vec<u8> {
+        let (samples, sample_rate) = {
+            let capture = self.0.lock();
+            (capture.samples.clone(), capture.sample_rate)
+        };
+        if samples.len() != WINDOW || sample_rate == 0 {
+            return vec![];
+        }
+        spectrum(&samples, sample_rate)
+    }
+}
+
+/// Divide the samples into [`BANDS`] logarithmically spaced frequency bands,
+/// giving the peak level of each, from 0 at [`FLOOR_DB`] to 255 at full
+/// scale. The number of samples must be a power of two.
+pub fn spectrum(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let n = samples.len();
+    // a Hann window keeps loud bands from smearing into their neighbours
+    let mut bins: Vec<_> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &sample)| {
+            let hann = 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos();
+            (sample * hann, 0.0)
+        })
+        .collect();
+    fft(&mut bins);
+
+    // the window halves the amplitude, and half of it is in the mirrored bins
+    let amplitude = |(re, im): (f32, f32)| (re * re + im * im).sqrt() * 4.0 / n as f32;
+    let (lowest, highest) = FREQUENCIES;
+    let edge = |band: usize| lowest * (highest / lowest).powf(band as f32 / BANDS as f32);
+    let bin = |frequency: f32| {
+        ((frequency * n as f32 / sample_rate as f32).round() as usize).clamp(1, n / 2)
+    };
+    (0..BANDS)
+        .map(|band| {
+            let start = bin(edge(band));
+            let end = bin(edge(band + 1)).max(start + 1);
+            let peak = bins[start..end]
+                .iter()
+                .copied()
+                .map(amplitude)
+                .fold(0.0, f32::max);
+            let db = 20.0 * peak.log10();
+            (((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0) * 255.0) as u8
+        })
+        .collect()
+}
+
+/// An in-place radix-2 fast Fourier transform of complex values.
+pub(crate) fn fft(values: &mut [(f32, f32)]) {
+    let n = values.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let step = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (step * k as f32).sin_cos();
+                let (a, b) = (values[start + k], values[start + k + len / 2]);
+                let twiddled = (b.0 * cos - b.1 * sin, b.0 * sin + b.1 * cos);
+                values[start + k] = (a.0 + twiddled.0, a.1 + twiddled.1);
+                values[start + k + len / 2] = (a.0 - twiddled.0, a.1 - twiddled.1);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bands() {
+        let sample_rate = 48_000;
+        assert!(spectrum(&[0.0; WINDOW], sample_rate)
+            .iter()
+            .all(|&band| band == 0));
+
+        let tone: Vec<_> = (0..WINDOW)
+            .map(|i| (2.0 * PI * 1000.0 * i as f32 / sample_rate as f32).sin())
+            .collect();
+        let bands = spectrum(&tone, sample_rate);
+        assert_eq!(bands.len(), BANDS);
+        let loudest = (0..BANDS).max_by_key(|&band| bands[band]).unwrap();
+        let (lowest, highest) = FREQUENCIES;
+        let edge = |band: usize| lowest * (highest / lowest).powf(band as f32 / BANDS as f32);
+        assert!((edge(loudest)..edge(loudest + 1)).contains(&1000.0));
+        // a full scale tone reaches the top, within the spread of the window
+        assert!(bands[loudest] > 240);
+        assert!(bands[0] < 64 && bands[BANDS - 1] < 64);
+    }
+}
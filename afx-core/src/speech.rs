@@ -0,0 +1,181 @@
+//! Text-to-speech, for announcements written inside afx. The text of a
+//! speech item is rendered to a WAV file by the engine chosen in the
+//! settings, and the file is cached by its text, so that the item plays like
+//! any other and is only rendered again once the text changes.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// How many characters of the text name a new speech item.
+const NAME_LENGTH: usize = 40;
+
+/// What renders speech.
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+pub enum SpeechEngine {
+    /// The speech synthesiser of the system: SAPI on Windows, `say` on macOS
+    /// and espeak-ng elsewhere.
+    #[default]
+    System,
+    /// An external program, reading the text from its standard input.
+    /// `{output}` in its command line stands for the WAV file it writes.
+    Command(String),
+}
+
+/// A name for an item speaking the text: its first line, shortened.
+pub fn speech_name(text: &str) -> String {
+    let line = text.trim().lines().next().unwrap_or_default();
+    match line.char_indices().nth(NAME_LENGTH) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Render the text, unless it was rendered before, and return the WAV file
+/// holding the speech.
+pub fn render(engine: &SpeechEngine, text: &str) -> Result<PathBuf> {
+    let dirs =
+        directories_next::ProjectDirs::from("", "", "afx").context("there's no cache directory")?;
+    render_in(engine, text, &dirs.cache_dir().join("speech"))
+}
+
+fn render_in(engine: &SpeechEngine, text: &str, dir: &Path) -> Result<PathBuf> {
+    // named after the engine too, so that changing it renders again
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(format!("{:?}", engine).as_bytes());
+    hasher.update(text.as_bytes());
+    let path = dir.join(format!("{:08x}.wav", hasher.finalize()));
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    std::fs::create_dir_all(dir)?;
+    // a render cut short mustn't be mistaken for a cached one
+    let partial = path.with_extension("partial.wav");
+    let mut command = match engine {
+        SpeechEngine::System => system_command(&partial),
+        SpeechEngine::Command(line) => {
+            let output = partial.display().to_string();
+            let mut words = line
+                .split_whitespace()
+                .map(|w| w.replace("{output}", &output));
+            let program = words.next().context("the speech command is empty")?;
+            let mut command = Command::new(program);
+            command.args(words);
+            command
+        }
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("couldn't start {}", command.get_program().to_string_lossy()))?;
+    let written = child.stdin.take().unwrap().write_all(text.as_bytes());
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "the speech engine failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    match written {
+        // programs ignoring the text may well close their input early
+        Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => {
+            return Err(err).context("couldn't pass the text on to the speech engine");
+        }
+        _ => (),
+    }
+    if !partial.is_file() {
+        bail!("the speech engine didn't write {}", partial.display());
+    }
+    std::fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+#[cfg(target_os = "windows")]
+fn system_command(output: &Path) -> Command {
+    let mut command = Command::new("powershell");
+    command.args([
+        "-NoProfile",
+        "-Command",
+        &format!(
+            "Add-Type -AssemblyName System.Speech; \
+             $voice = New-Object System.Speech.Synthesis.SpeechSynthesizer; \
+             $voice.SetOutputToWaveFile('{}'); \
+             $voice.Speak([Console]::In.ReadToEnd())",
+            output.display().to_string().replace('\'', "''")
+        ),
+    ]);
+    command
+}
+
+#[cfg(target_os = "macos")]
+fn system_command(output: &Path) -> Command {
+    let mut command = Command::new("say");
+    command
+        .args(["--data-format=LEI16@22050", "-f", "-", "-o"])
+        .arg(output);
+    command
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn system_command(output: &Path) -> Command {
+    let mut command = Command::new("espeak-ng");
+    command.args(["--stdin", "-w"]).arg(output);
+    command
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            speech_name("  Doors in five minutes\nplease"),
+            "Doors in five minutes"
+        );
+        let long = "The performance will begin shortly, please take your seats";
+        assert_eq!(
+            speech_name(long),
+            "The performance will begin shortly, plea…"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn renders_once() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let sample = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../samples/416529__inspectorj__bird-whistling-single-robin-a.wav"
+        );
+        let engine = SpeechEngine::Command(format!("cp {} {{output}}", sample));
+        let path = render_in(&engine, "doors in five minutes", dir.path())?;
+        assert_eq!(std::fs::read(&path)?, std::fs::read(sample)?);
+
+        // another engine renders the text afresh
+        let broken = SpeechEngine::Command("false".to_string());
+        assert!(render_in(&broken, "doors in five minutes", dir.path()).is_err());
+        // while the same one finds it in the cache
+        std::fs::write(&path, b"cached")?;
+        assert_eq!(
+            render_in(&engine, "doors in five minutes", dir.path())?,
+            path
+        );
+        assert_eq!(std::fs::read(&path)?, b"cached");
+        assert_ne!(
+            render_in(&engine, "doors in two minutes", dir.path())?,
+            path
+        );
+
+        let missing = SpeechEngine::Command("afx-no-such-engine {output}".to_string());
+        assert!(render_in(&missing, "hello", dir.path()).is_err());
+        let silent = SpeechEngine::Command("true".to_string());
+        assert!(render_in(&silent, "hello", dir.path()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+//! Statistics on how the items and playlists get used. The history of the
+//! session is lost on closing, so the counts that outlast it are kept with
+//! the model as they come in.
+
+use crate::model::{HistoryEntry, Model, PlaybackEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How much the items and playlists have been used over all sessions.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Usage {
+    /// By item id.
+    pub items: HashMap<u64, ItemUsage>,
+    /// How many items were started from each playlist, by playlist id.
+    pub playlists: HashMap<u64, u32>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ItemUsage {
+    pub starts: u32,
+    /// How long the item played in all, in seconds.
+    pub seconds: f64,
+}
+
+impl Usage {
+    /// Count an item starting, from the given playlist if any.
+    pub fn start(&mut self, item: u64, playlist: Option<u64>) {
+        self.items.entry(item).or_default().starts += 1;
+        if let Some(playlist) = playlist {
+            *self.playlists.entry(playlist).or_default() += 1;
+        }
+    }
+
+    /// Add to how long an item played.
+    pub fn played(&mut self, item: u64, seconds: f64) {
+        self.items.entry(item).or_default().seconds += seconds;
+    }
+}
+
+/// The statistics shown to the user, leaving out items and playlists that
+/// have since been deleted.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Summary {
+    pub session_starts: usize,
+    /// How long the items played during the session, in seconds, overlaps
+    /// counted twice.
+    pub session_seconds: f64,
+    pub total_starts: u32,
+    pub total_seconds: f64,
+    /// The items started the most, with their usage, most used first.
+    pub most_used: Vec<(u64, ItemUsage)>,
+    /// The items that haven't been started in any session, in the order of
+    /// the library.
+    pub never_played: Vec<u64>,
+    /// How many items were started from each playlist, busiest first.
+    pub playlists: Vec<(u64, u32)>,
+}
+
+/// Sum up the usage of the items and playlists of a model, listing at most
+/// `top` of the most used items.
+pub fn summary(model: &Model, top: usize) -> Summary {
+    let starts: Vec<&HistoryEntry> = model
+        .history
+        .iter()
+        .filter(|entry| entry.event == PlaybackEvent::Start && entry.item.is_some())
+        .collect();
+    // items still playing haven't been added to the usage yet
+    let ongoing: f64 = starts
+        .iter()
+        .filter(|entry| entry.duration.is_none())
+        .map(|entry| entry.time.elapsed().unwrap_or_default().as_secs_f64())
+        .sum();
+    let session_seconds = starts
+        .iter()
+        .filter_map(|entry| entry.duration)
+        .sum::<f64>()
+        + ongoing;
+
+    let usage = &model.usage;
+    let exists = |id: &u64| model.items.iter().any(|item| item.id == *id);
+    let mut most_used: Vec<(u64, ItemUsage)> = usage
+        .items
+        .iter()
+        .filter(|(id, usage)| exists(id) && usage.starts > 0)
+        .map(|(&id, &usage)| (id, usage))
+        .collect();
+    most_used.sort_by(|(a_id, a), (b_id, b)| {
+        (b.starts, b.seconds, a_id)
+            .partial_cmp(&(a.starts, a.seconds, b_id))
+            .unwrap()
+    });
+    most_used.truncate(top);
+
+    let never_played = model
+        .items
+        .iter()
+        .map(|item| item.id)
+        .filter(|id| usage.items.get(id).is_none_or(|usage| usage.starts == 0))
+        .collect();
+
+    let mut playlists: Vec<(u64, u32)> = usage
+        .playlists
+        .iter()
+        .filter(|(id, _)| model.playlists.iter().any(|p| p.id == **id))
+        .map(|(&id, &starts)| (id, starts))
+        .collect();
+    playlists.sort_by_key(|&(id, starts)| (std::cmp::Reverse(starts), id));
+
+    Summary {
+        session_starts: starts.len(),
+        session_seconds,
+        total_starts: usage.items.values().map(|usage| usage.starts).sum(),
+        total_seconds: usage.items.values().map(|usage| usage.seconds).sum::<f64>() + ongoing,
+        most_used,
+        never_played,
+        playlists,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{Item, Playlist, ORANGE};
+
+    #[test]
+    fn summaries() {
+        let item = |id| Item::with_default_stem(id, String::new(), String::new(), ORANGE, 1.0);
+        let mut model = Model {
+            items: vec![item(1), item(2), item(3)],
+            playlists: vec![Playlist::new(7, String::new(), vec![1, 2])],
+            ..Model::default()
+        };
+        model.items[0].source_playlist = Some(7);
+        // since deleted, along with its playlist
+        model.usage.start(5, Some(8));
+        model.usage.played(5, 100.0);
+
+        model.record(PlaybackEvent::Start, Some(1));
+        model.record_end(1);
+        model.record(PlaybackEvent::Start, Some(2));
+        model.record_end(2);
+        model.record(PlaybackEvent::Start, Some(1));
+
+        let summary = summary(&model, 1);
+        assert_eq!(summary.session_starts, 3);
+        assert_eq!(summary.total_starts, 4);
+        assert!(summary.total_seconds >= 100.0);
+        let most_used: Vec<_> = summary
+            .most_used
+            .iter()
+            .map(|(id, usage)| (*id, usage.starts))
+            .collect();
+        assert_eq!(most_used, vec![(1, 2)]);
+        assert_eq!(summary.never_played, vec![3]);
+        assert_eq!(summary.playlists, vec![(7, 2)]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Tempo detection, for laying a beat grid over the waveform of an item.
+//! The onsets of the sound are autocorrelated to find the beat length, and
+//! the beats are then lined up with the strongest onsets.
+
+use crate::model::Tempo;
+use kira::dsp::Frame;
+
+/// How many frames each step of the onset envelope covers.
+const HOP: usize = 512;
+/// The range of tempos considered, in beats per minute.
+const TEMPOS: (f64, f64) = (70.0, 180.0);
+/// The tempo preferred when a track could be counted in several, such as in
+/// half time.
+const PREFERRED_BPM: f64 = 120.0;
+/// How strongly the onsets must repeat at the beat length, relative to how
+/// strong they are, for the tempo to be trusted.
+const MIN_CONFIDENCE: f64 = 0.1;
+/// How many beats a sound must span at the slowest tempo to be analysed.
+const MIN_BEATS: usize = 8;
+
+/// Find the tempo of a sound, if it has a steady one.
+pub fn detect(frames: &[Frame], sample_rate: u32) -> Option<Tempo> {
+    let hops_per_second = sample_rate as f64 / HOP as f64;
+    let lag = |bpm: f64| 60.0 * hops_per_second / bpm;
+    let (min_lag, max_lag) = (
+        lag(TEMPOS.1).floor() as usize,
+        lag(TEMPOS.0).ceil() as usize,
+    );
+
+    let levels: Vec<f64> = frames
+        .chunks_exact(HOP)
+        .map(|hop| {
+            let energy: f32 = hop
+                .iter()
+                .map(|frame| (frame.left + frame.right) / 2.0)
+                .map(|sample| sample * sample)
+                .sum();
+            (energy as f64 / HOP as f64).sqrt()
+        })
+        .collect();
+    if levels.len() < MIN_BEATS * max_lag {
+        return None;
+    }
+    let mut onsets: Vec<f64> = levels
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).max(0.0))
+        .collect();
+    let mean = onsets.iter().sum::<f64>() / onsets.len() as f64;
+    onsets.iter_mut().for_each(|onset| *onset -= mean);
+
+    let correlation = |lag: usize| {
+        let sum: f64 = onsets.iter().zip(&onsets[lag..]).map(|(a, b)| a * b).sum();
+        sum / (onsets.len() - lag) as f64
+    };
+    let power = correlation(0);
+    if power <= f64::EPSILON {
+        return None;
+    }
+    let correlations: Vec<f64> = (min_lag - 1..=max_lag + 1).map(correlation).collect();
+    let weight = |lag: f64| {
+        let octaves = (60.0 * hops_per_second / lag / PREFERRED_BPM).log2();
+        (-0.5 * octaves * octaves).exp()
+    };
+    let best = (1..correlations.len() - 1)
+        .max_by(|&a, &b| {
+            let score = |i: usize| correlations[i] * weight((min_lag - 1 + i) as f64);
+            score(a).total_cmp(&score(b))
+        })
+        .unwrap();
+    if correlations[best] / power < MIN_CONFIDENCE {
+        return None;
+    }
+
+    // the peak a few beats later pins down the beat length more finely, and
+    // a parabola fitted through it finer than a hop
+    let beat = (min_lag - 1 + best) as f64;
+    let beats = (1..=MIN_BEATS)
+        .rev()
+        .find(|&beats| (beat * beats as f64) < onsets.len() as f64 / 2.0)
+        .unwrap_or(1) as f64;
+    let expected = (beat * beats).round() as usize;
+    let spread = (beats / 2.0).ceil() as usize;
+    let peak = (expected - spread..=expected + spread)
+        .max_by(|&a, &b| correlation(a).total_cmp(&correlation(b)))
+        .unwrap();
+    let (before, at, after) = (
+        correlation(peak - 1),
+        correlation(peak),
+        correlation(peak + 1),
+    );
+    let curvature = before - 2.0 * at + after;
+    let shift = if curvature < 0.0 {
+        (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    let beat = (peak as f64 + shift) / beats;
+
+    // the first beat is where the onsets a beat apart add up the most
+    let phase = (0..beat.round() as usize)
+        .max_by(|&a, &b| {
+            let strength = |phase: usize| {
+                (0..)
+                    .map(|k| (phase as f64 + k as f64 * beat).round() as usize)
+                    .take_while(|&hop| hop < onsets.len())
+                    .map(|hop| onsets[hop])
+                    .sum::<f64>()
+            };
+            strength(a).total_cmp(&strength(b))
+        })
+        .unwrap();
+    Some(Tempo {
+        bpm: 60.0 * hops_per_second / beat,
+        // onsets are the rise into the hop after the difference
+        offset: (phase + 1) as f64 / hops_per_second,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clicks() {
+        let sample_rate = 44_100;
+        let seconds = 12;
+        let (bpm, offset) = (124.0, 0.3);
+        let frames: Vec<_> = (0..sample_rate * seconds)
+            .map(|i| {
+                let time = i as f64 / sample_rate as f64;
+                let since_beat = (time - offset).rem_euclid(60.0 / bpm);
+                let click = since_beat < 0.01 && time >= offset;
+                let sample = if click {
+                    (time * 2000.0 * std::f64::consts::TAU).sin() as f32
+                } else {
+                    0.0
+                };
+                Frame::from_mono(sample)
+            })
+            .collect();
+
+        let tempo = detect(&frames, sample_rate).unwrap();
+        assert!((tempo.bpm - bpm).abs() < 0.5, "detected {}", tempo.bpm);
+        let beat = 60.0 / bpm;
+        let error = (tempo.offset - offset).rem_euclid(beat);
+        assert!(
+            error.min(beat - error) < 0.02,
+            "first beat at {}",
+            tempo.offset
+        );
+
+        let silence = vec![Frame::from_mono(0.0); frames.len()];
+        assert_eq!(detect(&silence, sample_rate), None);
+        assert_eq!(detect(&frames[..sample_rate as usize], sample_rate), None);
+    }
+}
@@ -0,0 +1,53 @@
+use afx_core::engine::{process_message, Playback};
+use afx_core::import::import_paths;
+use afx_core::model::*;
+use afx_core::persistence::{load_project, save_project};
+use anyhow::Result;
+use kira::manager::backend::mock::MockBackend;
+use kira::manager::{AudioManager, AudioManagerSettings};
+use parking_lot::RwLock;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+
+const SAMPLE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../samples/416529__inspectorj__bird-whistling-single-robin-a.wav"
+);
+
+#[test]
+fn import_save_and_play() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let project = dir.path().join("show.ron");
+
+    let mut model = Model::default();
+    let (tx, _rx) = channel();
+    let items = import_paths(
+        tx,
+        || model.fresh_id(),
+        vec![SAMPLE.into()],
+        &[],
+        ImportLimits::default(),
+    );
+    model.items.extend(items);
+    save_project(&project, &model)?;
+
+    let model = Arc::new(RwLock::new(load_project(&project)?));
+    let id = model.read().items[0].id;
+    let mut playback =
+        Playback::new(AudioManager::<MockBackend>::new(AudioManagerSettings::default()).unwrap());
+    let (tx, _rx) = channel();
+
+    process_message(ControlMessage::Play(id), &tx, &mut playback, &model)?;
+    process_message(
+        ControlMessage::SyncPlaybackStatus,
+        &tx,
+        &mut playback,
+        &model,
+    )?;
+    assert_eq!(model.read().items[0].status, ItemStatus::Playing);
+    assert_eq!(model.read().engine.voices, 1);
+
+    process_message(ControlMessage::Stop(id), &tx, &mut playback, &model)?;
+    assert_eq!(model.read().items[0].status, ItemStatus::Stopped);
+    Ok(())
+}
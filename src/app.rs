@@ -1,18 +1,104 @@
-use crate::model::*;
-use anyhow::{anyhow, Result};
+use crate::cli::Open;
+use crate::diagnostics::Diagnostics;
+use crate::remote::RemoteServer;
+use crate::sync::{Backups, SyncLink};
+use crate::ui::Interface;
+use afx_core::model::*;
+use afx_core::persistence::{
+    backup_path, default_project_path, discard_emergency, eframe_storage_path, load_emergency,
+    load_project, save_project,
+};
 use eframe::egui;
 use parking_lot::RwLock;
-use std::sync::mpsc::Sender;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, warn};
+
+pub struct ImportState {
+    pub items_in_progress: Vec<(u64, String, ItemImportStatus)>,
+    pub finished: Vec<Item>,
+    /// The file being downloaded before it's imported, and how far along.
+    pub download: Option<(String, f32)>,
+}
+
+pub type SharedImportState = Arc<RwLock<ImportState>>;
+
+pub struct SharedModel {
+    pub import_state: Option<(Receiver<ImportMessage>, SharedImportState)>,
+    pub play_channel: Sender<ControlMessage>,
+    pub model: Arc<RwLock<Model>>,
+    pub notifications: Receiver<Notification>,
+    /// Notifications not yet dismissed by the user.
+    pub toasts: Vec<Notification>,
+    pub remote: Option<RemoteServer>,
+    pub sync: Option<SyncLink>,
+    /// The backups connected while this is a primary.
+    pub backups: Backups,
+    /// The copy of the model the interface draws from, so that the shared
+    /// one is only locked briefly, and the state of the shared model it was
+    /// last brought up to date with.
+    pub view: Model,
+    pub base: Model,
+    pub interface: Interface,
+    pub diagnostics: Diagnostics,
+    /// Set by the tray icon once the window is asked for.
+    pub show_window: Arc<AtomicBool>,
+    /// Files forwarded by instances started after this one.
+    pub opens: Receiver<Open>,
+    /// The state saved when afx last crashed, until the user decides whether
+    /// to restore it.
+    pub crashed: Option<Model>,
+}
 
 impl eframe::App for SharedModel {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.render_ui(ctx);
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if let Some(cpu_usage) = frame.info().cpu_usage {
+            self.diagnostics
+                .record_frame_time(Duration::from_secs_f32(cpu_usage));
+        }
+        let opens: Vec<Open> = self.opens.try_iter().collect();
+        if self.show_window.swap(false, Ordering::Relaxed) || !opens.is_empty() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+            // briefly keeping the window on top raises it where it can't be
+            // focused directly
+            ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(
+                egui::WindowLevel::AlwaysOnTop,
+            ));
+            ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(
+                egui::WindowLevel::Normal,
+            ));
+        }
+        for open in opens {
+            self.open(open);
+        }
+        self.render_ui(ctx, ctx.native_pixels_per_point());
     }
 
-    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+    fn save(&mut self, _storage: &mut dyn eframe::Storage) {
+        // eframe overwrites its storage in place, so the model is saved to a
+        // project of its own
+        let Some(path) = default_project_path() else {
+            warn!("there's no data directory, the state can't be saved");
+            return;
+        };
         let model = self.model.read();
-        storage.set_string("model", serialize(&*model).unwrap());
+        if let Err(err) = std::fs::create_dir_all(path.parent().unwrap_or(&path))
+            .map_err(anyhow::Error::from)
+            .and_then(|()| save_project(&path, &model))
+        {
+            error!("failed to save the state: {:#}", err);
+            return;
+        }
+        // a thread may have panicked without taking the application down,
+        // in which case this save is more recent
+        if self.crashed.is_none() {
+            if let Err(err) = discard_emergency(&path) {
+                warn!("failed to discard the state saved in a crash: {:#}", err);
+            }
+        }
     }
 
     fn persist_egui_memory(&self) -> bool {
@@ -20,46 +106,62 @@ impl eframe::App for SharedModel {
     }
 }
 
-fn serialize<T: serde::Serialize + ?Sized>(value: &T) -> Result<String> {
-    Ok(base64::encode(lz4_flex::compress_prepend_size(
-        &rmp_serde::to_vec(value)?,
-    )))
-}
-
-fn deserialize<T: for<'de> serde::Deserialize<'de>>(saved: impl AsRef<[u8]>) -> Result<T> {
-    base64::decode(saved)
-        .map_err(|e| anyhow!(e))
-        .and_then(|decoded| lz4_flex::decompress_size_prepended(&decoded).map_err(|e| anyhow!(e)))
-        .and_then(|decompressed| rmp_serde::from_slice(&decompressed).map_err(|e| anyhow!(e)))
-}
-
-/// Recover saved state of the application.
-pub fn recover(
-    cc: &eframe::CreationContext,
-    tx: Sender<ControlMessage>,
-    model: Arc<RwLock<Model>>,
-) -> Option<()> {
-    let saved = cc.storage?.get_string("model")?;
-    let mut loaded: Model = match deserialize(saved) {
-        Ok(loaded) => Some(loaded),
+/// Recover saved state of the application, returning what had to be
+/// repaired in it.
+pub fn recover(tx: Sender<ControlMessage>, model: Arc<RwLock<Model>>) -> Option<Vec<String>> {
+    let saved = |path: &PathBuf| path.exists() || backup_path(path).exists();
+    // earlier versions saved the model along with eframe's state
+    let path = default_project_path()
+        .filter(saved)
+        .or_else(|| eframe_storage_path().filter(saved))?;
+    let loaded = match load_project(&path) {
+        Ok(loaded) => loaded,
         Err(err) => {
-            eprintln!("Failed to load saved model: {}", err);
-            None
+            eprintln!("Failed to load saved model: {:#}", err);
+            return None;
         }
-    }?;
+    };
+    Some(restore(loaded, &tx, &model))
+}
+
+/// Replace the shared model with a loaded one, resuming its playback and
+/// returning the repairs it needed.
+pub fn restore(
+    mut loaded: Model,
+    tx: &Sender<ControlMessage>,
+    model: &Arc<RwLock<Model>>,
+) -> Vec<String> {
+    let repairs = loaded.repair();
+    for repair in &repairs {
+        warn!("repaired the saved model: {}", repair);
+    }
 
     // taking the lock before any messages are sent so that the background
     // thread can't accidentally query the model before it's been loaded
     let mut model = model.write();
-    for item in loaded.items.iter_mut() {
-        if item.status == ItemStatus::Playing {
-            item.status = ItemStatus::Loading;
-            tx.send(ControlMessage::Play(item.id)).unwrap();
-        } else if item.status == ItemStatus::Loading {
-            item.status = ItemStatus::Stopped;
-        }
+    for msg in loaded.restore_playback() {
+        tx.send(msg).unwrap();
     }
+    let master_volume = db_to_amplitude(loaded.master_gain_db);
+    tx.send(ControlMessage::SetMasterVolume(master_volume))
+        .unwrap();
 
+    // the engine keeps running on the output it reported, whatever the
+    // loaded settings ask for
+    loaded.engine = std::mem::take(&mut model.engine);
+    loaded.revision = model.revision + 1;
     *model = loaded;
-    Some(())
+    repairs
+}
+
+/// The state saved when afx last crashed, see [`crate::crash`].
+pub fn crashed() -> Option<Model> {
+    let path = default_project_path()?;
+    match load_emergency(&path)? {
+        Ok(model) => Some(model),
+        Err(err) => {
+            warn!("failed to load the state saved in a crash: {:#}", err);
+            None
+        }
+    }
 }
@@ -0,0 +1,314 @@
+use crate::ui::format_timestamp;
+use afx_core::engine::{process_message, Playback, PLAYBACK_SYNC_INTERVAL};
+use afx_core::import::import_paths;
+use afx_core::model::*;
+use afx_core::persistence::{default_project_path, load_project, save_project};
+use anyhow::{anyhow, bail, Result};
+use kira::manager::{backend::cpal::CpalBackend, AudioManager, AudioManagerSettings};
+use parking_lot::RwLock;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+
+const USAGE: &str = "\
+usage:
+    afx                                   start the graphical interface
+    afx <files>... [--playlist <name>]    start the graphical interface and
+                                          import files into the library or
+                                          a playlist, or hand them to the
+                                          interface already running
+    afx play <project> --item <id>...     play items without the interface
+    afx list [--project <project>]        list the items of a project
+    afx import <files>... [--project <project>]
+                                          import files into a project
+
+A project is a file saved by afx, by default the state of the graphical
+interface. Close the interface before importing into its state.";
+
+/// Files to import once the graphical interface has started, into the named
+/// playlist or the library.
+#[derive(PartialEq, Debug, Default)]
+pub struct Open {
+    pub files: Vec<PathBuf>,
+    pub playlist: Option<String>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    Open(Open),
+    Play {
+        project: PathBuf,
+        items: Vec<u64>,
+    },
+    List {
+        project: Option<PathBuf>,
+    },
+    Import {
+        project: Option<PathBuf>,
+        files: Vec<PathBuf>,
+    },
+}
+
+/// Parse the command line arguments, excluding the program name. No
+/// arguments mean the graphical interface should start. Arguments that don't
+/// start with a subcommand are files to open, as passed by file managers.
+pub fn parse_args(args: &[String]) -> Result<Option<Command>> {
+    let (subcommand, rest) = match args.split_first() {
+        None => return Ok(None),
+        Some((subcommand, rest)) if ["play", "list", "import"].contains(&subcommand.as_str()) => {
+            (subcommand.as_str(), rest)
+        }
+        Some(_) => ("open", args),
+    };
+
+    let mut positional = vec![];
+    let mut project = None;
+    let mut items = vec![];
+    let mut playlist = None;
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || {
+            rest.next()
+                .ok_or_else(|| anyhow!("{} requires a value", arg))
+        };
+        match arg.as_str() {
+            "--project" => project = Some(PathBuf::from(value()?)),
+            "--item" => items.push(value()?.parse()?),
+            "--playlist" if subcommand == "open" => playlist = Some(value()?.clone()),
+            flag if flag.starts_with("--") => bail!("unknown option {}", flag),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    match subcommand {
+        "play" => {
+            let [project] = &positional[..] else {
+                bail!("play takes exactly one project");
+            };
+            if items.is_empty() {
+                bail!("play needs at least one --item");
+            }
+            Ok(Some(Command::Play {
+                project: project.clone(),
+                items,
+            }))
+        }
+        "list" if positional.is_empty() => Ok(Some(Command::List { project })),
+        "import" if !positional.is_empty() => Ok(Some(Command::Import {
+            project,
+            files: positional,
+        })),
+        "open" if !positional.is_empty() && project.is_none() && items.is_empty() => {
+            Ok(Some(Command::Open(Open {
+                files: positional,
+                playlist,
+            })))
+        }
+        "open" => bail!("expected a command or files to open"),
+        other => bail!("unexpected arguments to {}", other),
+    }
+}
+
+/// Run a command given on the command line. Continues with the files to open
+/// if the graphical interface should start instead.
+pub fn run(args: &[String]) -> ControlFlow<Result<()>, Open> {
+    if args
+        .first()
+        .is_some_and(|arg| arg == "help" || arg == "--help")
+    {
+        println!("{}", USAGE);
+        return ControlFlow::Break(Ok(()));
+    }
+    let command = match parse_args(args) {
+        Ok(Some(command)) => command,
+        Ok(None) => return ControlFlow::Continue(Open::default()),
+        Err(err) => return ControlFlow::Break(Err(anyhow!("{}\n\n{}", err, USAGE))),
+    };
+
+    ControlFlow::Break(match command {
+        Command::Open(open) => match open.files.iter().find(|file| !file.exists()) {
+            // most likely a mistyped command rather than a file
+            Some(missing) => Err(anyhow!(
+                "no such command or file: {}\n\n{}",
+                missing.display(),
+                USAGE
+            )),
+            None => return ControlFlow::Continue(open),
+        },
+        Command::Play { project, items } => play(&project, &items),
+        Command::List { project } => project_path(project).and_then(|path| list(&path)),
+        Command::Import { project, files } => {
+            project_path(project).and_then(|path| import(&path, files))
+        }
+    })
+}
+
+fn project_path(project: Option<PathBuf>) -> Result<PathBuf> {
+    project
+        .or_else(default_project_path)
+        .ok_or_else(|| anyhow!("couldn't find the default project, pass --project"))
+}
+
+/// Play the items until they all stop. Looped items play until interrupted.
+fn play(project: &Path, ids: &[u64]) -> Result<()> {
+    let mut model = load_project(project)?;
+    for item in model.items.iter_mut() {
+        item.status = ItemStatus::Stopped;
+        item.position = 0.0;
+        item.target_position = 0.0;
+    }
+    for id in ids {
+        if !model.items.iter().any(|item| item.id == *id) {
+            bail!("there's no item with id {}", id);
+        }
+    }
+
+    let model = Arc::new(RwLock::new(model));
+    let mut playback = Playback::new(AudioManager::<CpalBackend>::new(
+        AudioManagerSettings::default(),
+    )?);
+    let (tx, rx) = channel();
+    for &id in ids {
+        tx.send(ControlMessage::Play(id))?;
+    }
+
+    loop {
+        while let Ok(msg) = rx.try_recv() {
+            process_message(msg, &tx, &mut playback, &model)?;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(PLAYBACK_SYNC_INTERVAL));
+        process_message(
+            ControlMessage::SyncPlaybackStatus,
+            &tx,
+            &mut playback,
+            &model,
+        )?;
+
+        // the item positions are normally animated by the interface
+        let mut model = model.write();
+        for item in model.items.iter_mut() {
+            item.position = item.target_position;
+        }
+        let finished = model
+            .items
+            .iter()
+            .filter(|item| ids.contains(&item.id))
+            .all(|item| item.status == ItemStatus::Stopped);
+        if finished && rx.try_recv().is_err() {
+            return Ok(());
+        }
+    }
+}
+
+fn list(project: &Path) -> Result<()> {
+    let model = load_project(project)?;
+    for item in &model.items {
+        println!(
+            "{:>5}  {:>8}  {}",
+            item.id,
+            format_timestamp(item.duration),
+            item.name
+        );
+    }
+    for playlist in &model.playlists {
+        let items: Vec<_> = playlist.items.iter().map(|id| id.to_string()).collect();
+        println!("\n{} ({}): {}", playlist.name, playlist.id, items.join(" "));
+    }
+    Ok(())
+}
+
+fn import(project: &Path, files: Vec<PathBuf>) -> Result<()> {
+    let mut model = if project.exists() {
+        load_project(project)?
+    } else {
+        Model::default()
+    };
+
+    let (tx, rx) = channel();
+    let (rules, limits) = (
+        model.settings.colour_rules.clone(),
+        model.settings.import_limits,
+    );
+    let items = import_paths(tx, || model.fresh_id(), files, &rules, limits);
+    for msg in rx.try_iter() {
+        if let ImportMessage::Update(id, ItemImportStatus::Failed(err)) = msg {
+            eprintln!("failed to import item {}: {}", id, err);
+        }
+    }
+    for item in &items {
+        println!("{:>5}  {}", item.id, item.name);
+    }
+
+    model.items.extend(items);
+    save_project(project, &model)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE: &str = "samples/416529__inspectorj__bird-whistling-single-robin-a.wav";
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parsing() -> Result<()> {
+        assert_eq!(parse_args(&[])?, None);
+        assert_eq!(
+            parse_args(&args("play show.ron --item 3 --item 5"))?,
+            Some(Command::Play {
+                project: "show.ron".into(),
+                items: vec![3, 5]
+            })
+        );
+        assert_eq!(
+            parse_args(&args("list"))?,
+            Some(Command::List { project: None })
+        );
+        assert_eq!(
+            parse_args(&args("import a.wav b.ogg --project show.ron"))?,
+            Some(Command::Import {
+                project: Some("show.ron".into()),
+                files: vec!["a.wav".into(), "b.ogg".into()]
+            })
+        );
+
+        assert!(parse_args(&args("play show.ron")).is_err());
+        assert!(parse_args(&args("play --item x show.ron")).is_err());
+        assert!(parse_args(&args("import")).is_err());
+        assert_eq!(
+            parse_args(&args("a.wav --playlist Act b.ogg"))?,
+            Some(Command::Open(Open {
+                files: vec!["a.wav".into(), "b.ogg".into()],
+                playlist: Some("Act".into())
+            }))
+        );
+
+        assert!(parse_args(&args("--playlist Act")).is_err());
+        assert!(parse_args(&args("a.wav --item 3")).is_err());
+        assert!(parse_args(&args("import a.wav --playlist Act")).is_err());
+        assert!(matches!(run(&args("dance")), ControlFlow::Break(Err(_))));
+        assert!(matches!(
+            run(&args(&format!("{} --playlist Act", SAMPLE))),
+            ControlFlow::Continue(Open { files, playlist: Some(playlist) })
+                if files == [PathBuf::from(SAMPLE)] && playlist == "Act"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn import_into_project() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let project = dir.path().join("show.ron");
+        import(&project, vec![SAMPLE.into()])?;
+        import(&project, vec![SAMPLE.into(), "nonexistent.wav".into()])?;
+
+        let model = load_project(&project)?;
+        assert_eq!(model.items.len(), 2);
+        assert_ne!(model.items[0].id, model.items[1].id);
+        Ok(())
+    }
+}
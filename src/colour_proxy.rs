@@ -1,3 +1,4 @@
+use afx_core::model::Colour;
 use eframe::epaint::Color32;
 
 pub struct ColourProxy(Color32);
@@ -42,6 +43,19 @@ impl From<ColourProxy> for Color32 {
     }
 }
 
+impl From<Colour> for ColourProxy {
+    fn from(colour: Colour) -> Self {
+        let [r, g, b, a] = colour.to_array();
+        Self(Color32::from_rgba_premultiplied(r, g, b, a))
+    }
+}
+
+impl From<ColourProxy> for Colour {
+    fn from(proxy: ColourProxy) -> Self {
+        Colour(proxy.0.to_array())
+    }
+}
+
 impl From<&ColourProxy> for rgb::RGB<u8> {
     fn from(proxy: &ColourProxy) -> Self {
         let ColourProxy(colour) = proxy;
@@ -98,6 +112,18 @@ impl From<&ColourProxy> for rgb::RGBA<u8> {
     }
 }
 
+/// The colours of the model are drawn by converting them to egui's.
+pub trait DrawnColour {
+    fn drawn(self) -> Color32;
+}
+
+impl DrawnColour for Colour {
+    fn drawn(self) -> Color32 {
+        ColourProxy::from(self).into()
+    }
+}
+
+#[allow(dead_code)]
 pub trait ExtendedColourOps {
     fn via_rgb<F: FnMut(rgb::RGB<u8>) -> rgb::RGB<u8>>(&self, f: F) -> Self;
     fn via_rgba<F: FnMut(rgb::RGBA<u8>) -> rgb::RGBA<u8>>(&self, f: F) -> Self;
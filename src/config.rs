@@ -0,0 +1,86 @@
+use afx_core::config::{default_config_path, ConfigFile};
+use afx_core::model::*;
+use eframe::egui;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often the settings file is checked for edits.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Take on the settings file, before anything reads the settings it can
+/// override, e.g. the output the engine opens.
+pub fn load(model: &RwLock<Model>) -> Option<ConfigFile> {
+    let Some(path) = default_config_path() else {
+        warn!("there's no configuration directory, the settings stay with the library");
+        return None;
+    };
+    let mut file = ConfigFile::new(path);
+    sync(&mut file, model);
+    Some(file)
+}
+
+/// Keep the settings in step with the settings file on a background thread,
+/// see [`afx_core::config`].
+pub fn start(file: Option<ConfigFile>, model: Arc<RwLock<Model>>, ctx: egui::Context) {
+    let Some(mut file) = file else {
+        return;
+    };
+    std::thread::spawn(move || loop {
+        std::thread::sleep(CHECK_INTERVAL);
+        if sync(&mut file, &model) {
+            ctx.request_repaint();
+        }
+    });
+}
+
+/// Sync the settings of the model with the file, returning whether they were
+/// taken on from it.
+fn sync(file: &mut ConfigFile, model: &RwLock<Model>) -> bool {
+    // the file is read and written without holding up the interface
+    let before = model.read().settings.clone();
+    let mut settings = before.clone();
+    match file.sync(&mut settings) {
+        Ok(true) => {
+            let mut model = model.write();
+            // settings changed in the meantime win, and are written out on
+            // the next sync
+            if model.settings != before {
+                return false;
+            }
+            model.settings = settings;
+            info!("took on the settings from {}", file.path.display());
+            true
+        }
+        Ok(false) => false,
+        Err(err) => {
+            warn!("failed to sync {}: {:#}", file.path.display(), err);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn loaded_output() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("settings.toml");
+        let saved = Settings {
+            audio_host: AudioHost::Jack,
+            audio_device: Some("system".to_string()),
+            ..Settings::default()
+        };
+        afx_core::config::save(&path, &saved)?;
+
+        let model = RwLock::new(Model::default());
+        assert!(sync(&mut ConfigFile::new(path), &model));
+        let settings = &model.read().settings;
+        assert_eq!(settings.audio_host, AudioHost::Jack);
+        assert_eq!(settings.audio_device.as_deref(), Some("system"));
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use afx_core::model::*;
+use afx_core::persistence::{default_project_path, save_emergency};
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, warn};
+
+/// How long a panic waits for the model, which the panicking thread may be
+/// holding itself.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Save the model next to the default project whenever a thread panics, so
+/// that the next launch can offer to restore it.
+pub fn install(model: Arc<RwLock<Model>>) {
+    let Some(path) = default_project_path() else {
+        warn!("there's no data directory, a crash would lose the unsaved state");
+        return;
+    };
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let Some(model) = model.try_read_for(LOCK_TIMEOUT) else {
+            error!("the model is locked, the unsaved state is lost");
+            return;
+        };
+        match save_emergency(&path, &model) {
+            Ok(()) => error!("saved the unsaved state to restore on the next launch"),
+            Err(err) => error!("failed to save the unsaved state: {:#}", err),
+        }
+    }));
+}
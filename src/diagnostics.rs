@@ -0,0 +1,206 @@
+use afx_core::model::*;
+use eframe::egui;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Layer;
+use tracing_timing::{Histogram, TimingLayer};
+
+/// How many of the latest samples of each measurement are kept.
+const HISTORY: usize = 240;
+/// The longest span the histograms can record, in nanoseconds.
+const LONGEST_SPAN: u64 = 60_000_000_000;
+
+/// Logs going to stderr, along with timing histograms of the spans the
+/// subsystems run in, for the diagnostics window.
+pub fn subscriber() -> impl Subscriber {
+    let timing = tracing_timing::Builder::default()
+        .span_close_events()
+        .layer(|| Histogram::new_with_max(LONGEST_SPAN, 2).unwrap());
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(LevelFilter::from_level(Level::TRACE)),
+        )
+        .with(timing)
+}
+
+/// The latest samples of a measurement.
+#[derive(Default)]
+struct Samples(VecDeque<Duration>);
+
+impl Samples {
+    fn push(&mut self, sample: Duration) {
+        if self.0.len() == HISTORY {
+            self.0.pop_front();
+        }
+        self.0.push_back(sample);
+    }
+
+    fn summary(&self) -> Option<(Duration, Duration, Duration)> {
+        let last = *self.0.back()?;
+        let mean = self.0.iter().sum::<Duration>() / self.0.len() as u32;
+        let worst = self.0.iter().copied().max()?;
+        Some((last, mean, worst))
+    }
+}
+
+/// Measurements of how responsive afx is, so that reports of it feeling
+/// sluggish can come with numbers.
+#[derive(Default)]
+pub struct Diagnostics {
+    frame_times: Samples,
+    lock_waits: Samples,
+    latencies: Samples,
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
+
+impl Diagnostics {
+    pub fn record_frame_time(&mut self, frame_time: Duration) {
+        self.frame_times.push(frame_time);
+    }
+
+    /// How long the interface waited to lock the shared model.
+    pub fn record_lock_wait(&mut self, wait: Duration) {
+        self.lock_waits.push(wait);
+    }
+
+    /// Take note of the control latency the playback thread reported, once
+    /// per report.
+    pub fn record_engine(&mut self, engine: &EngineStatus) {
+        if let Some(latency) = engine.control_latency {
+            if self.latencies.0.back() != Some(&latency) {
+                self.latencies.push(latency);
+            }
+        }
+    }
+
+    pub fn window(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new("Performance diagnostics")
+            .open(open)
+            .show(ctx, |ui| {
+                ui.heading("Interface");
+                egui::Grid::new("diagnostics summary")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for heading in ["", "last", "mean", "worst"] {
+                            ui.strong(heading);
+                        }
+                        ui.end_row();
+                        for (label, hint, samples) in [
+                            (
+                                "Frame time",
+                                "CPU time spent on each frame of the interface",
+                                &self.frame_times,
+                            ),
+                            (
+                                "Model lock wait",
+                                "How long the interface waited for other threads to \
+                                let go of the model",
+                                &self.lock_waits,
+                            ),
+                            (
+                                "Control latency",
+                                "How long control messages took to reach the playback thread",
+                                &self.latencies,
+                            ),
+                        ] {
+                            ui.label(label).on_hover_text(hint);
+                            match samples.summary() {
+                                Some((last, mean, worst)) => {
+                                    ui.label(millis(last));
+                                    ui.label(millis(mean));
+                                    ui.label(millis(worst));
+                                }
+                                None => {
+                                    ui.weak("no samples yet");
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                ui.separator();
+                ui.heading("Subsystems").on_hover_text(
+                    "Time from the start of a span, or from its previous event, to each event",
+                );
+                span_timings(ui);
+            });
+    }
+}
+
+fn span_timings(ui: &mut egui::Ui) {
+    tracing::dispatcher::get_default(|dispatch| {
+        let Some(timing) = dispatch.downcast_ref::<TimingLayer>() else {
+            ui.weak("span timing is unavailable");
+            return;
+        };
+        timing.force_synchronize();
+        timing.with_histograms(|spans| {
+            let mut rows: Vec<_> = spans
+                .iter_mut()
+                .flat_map(|(span, events)| {
+                    events.iter_mut().map(move |(event, histogram)| {
+                        histogram.refresh_timeout(Duration::ZERO);
+                        (*span, event.clone(), histogram)
+                    })
+                })
+                .collect();
+            rows.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+            egui::Grid::new("span timings")
+                .num_columns(6)
+                .striped(true)
+                .show(ui, |ui| {
+                    for heading in ["span", "event", "count", "median", "99th %", "max"] {
+                        ui.strong(heading);
+                    }
+                    ui.end_row();
+                    for (span, event, histogram) in &rows {
+                        ui.label(*span);
+                        ui.label(event.as_str());
+                        ui.label(histogram.len().to_string());
+                        for nanos in [
+                            histogram.value_at_quantile(0.5),
+                            histogram.value_at_quantile(0.99),
+                            histogram.max(),
+                        ] {
+                            ui.label(millis(Duration::from_nanos(nanos)));
+                        }
+                        ui.end_row();
+                    }
+                });
+            if ui.button("Reset").clicked() {
+                for (_, _, histogram) in rows {
+                    histogram.reset();
+                }
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn samples() {
+        let mut samples = Samples::default();
+        assert_eq!(samples.summary(), None);
+        for millis in (1..=HISTORY as u64 + 2).rev() {
+            samples.push(Duration::from_millis(millis));
+        }
+        // the oldest, longest samples are gone
+        let (last, mean, worst) = samples.summary().unwrap();
+        assert_eq!(last, Duration::from_millis(1));
+        assert_eq!(mean, Duration::from_micros(120_500));
+        assert_eq!(worst, Duration::from_millis(HISTORY as u64));
+    }
+}
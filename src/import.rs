@@ -1,39 +1,113 @@
-use crate::model::*;
-use crate::ui::*;
+use crate::app::{ImportState, SharedModel};
+use crate::cli::Open;
+use afx_core::download::download;
+use afx_core::import::{analyse, import_paths, stem_changed};
+use afx_core::model::*;
+use afx_core::speech;
 use eframe::egui;
-use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
-use kira::sound::FromFileError;
 use parking_lot::{RwLock, RwLockWriteGuard};
 use std::path::PathBuf;
 use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 
 impl SharedModel {
-    pub fn begin_import(&mut self) {
-        let model = self.model.clone();
+    /// Ask for files to import on a background thread, colouring the imported
+    /// items according to the given rules.
+    pub fn begin_import(&mut self, colour_rules: Vec<ColourRule>) {
+        self.start_import(colour_rules, |_| {
+            rfd::FileDialog::new()
+                .set_title("Choose files to import")
+                .pick_files()
+        });
+    }
+
+    /// Import the given files on a background thread, as if they had been
+    /// picked in the import dialog.
+    pub fn import_files(&mut self, paths: Vec<PathBuf>, colour_rules: Vec<ColourRule>) {
+        self.start_import(colour_rules, move |_| Some(paths));
+    }
+
+    /// Download the audio of the video at the given address with yt-dlp and
+    /// import it on a background thread. A failed download is shown in the
+    /// import window.
+    pub fn import_video(&mut self, url: String, colour_rules: Vec<ColourRule>) {
+        let ids = self.model.read().id_counter.clone();
+        self.start_import(colour_rules, move |sender| {
+            info!("downloading {}", url);
+            let reported = download(&url, |fraction| {
+                let _ = sender.send(ImportMessage::Downloading(url.clone(), fraction));
+            });
+            match reported {
+                Ok(path) => Some(vec![path]),
+                Err(err) => {
+                    warn!("failed to download {}: {:#}", url, err);
+                    let id = ids.fresh();
+                    let _ = sender.send(ImportMessage::Update(
+                        id,
+                        ItemImportStatus::Queued(url.clone()),
+                    ));
+                    let _ = sender.send(ImportMessage::Update(
+                        id,
+                        ItemImportStatus::Failed(format!("{:#}", err)),
+                    ));
+                    Some(vec![])
+                }
+            }
+        });
+    }
+
+    /// Import the files given on the command line into the playlist of the
+    /// given name, creating it if there's none, or into the library.
+    pub fn open(&mut self, open: Open) {
+        if open.files.is_empty() {
+            return;
+        }
+        let colour_rules = {
+            let mut model = self.model.write();
+            model.revision += 1;
+            model.selected_playlist = open.playlist.map(|name| {
+                match model
+                    .playlists
+                    .iter()
+                    .find(|playlist| playlist.name == name)
+                {
+                    Some(playlist) => playlist.id,
+                    None => {
+                        let id = model.fresh_id();
+                        model.playlists.push(Playlist::new(id, name, vec![]));
+                        id
+                    }
+                }
+            });
+            model.settings.colour_rules.clone()
+        };
+        self.import_files(open.files, colour_rules);
+    }
+
+    fn start_import(
+        &mut self,
+        colour_rules: Vec<ColourRule>,
+        choose: impl FnOnce(&Sender<ImportMessage>) -> Option<Vec<PathBuf>> + Send + 'static,
+    ) {
+        let (ids, limits) = {
+            let model = self.model.read();
+            (model.id_counter.clone(), model.settings.import_limits)
+        };
         let (sender, receiver) = channel();
         self.import_state = Some((
             receiver,
             Arc::new(RwLock::new(ImportState {
                 items_in_progress: vec![],
                 finished: vec![],
+                download: None,
             })),
         ));
 
         std::thread::spawn(move || {
-            if let Some(paths) = rfd::FileDialog::new()
-                .set_title("Choose files to import")
-                .pick_files()
-            {
-                let new_items = import_paths(
-                    sender.clone(),
-                    || {
-                        let mut model = model.write();
-                        model.fresh_id()
-                    },
-                    paths,
-                );
+            if let Some(paths) = choose(&sender) {
+                let new_items =
+                    import_paths(sender.clone(), || ids.fresh(), paths, &colour_rules, limits);
                 sender.send(ImportMessage::Finished(new_items)).unwrap();
             } else {
                 sender.send(ImportMessage::Cancelled).unwrap();
@@ -42,58 +116,164 @@ impl SharedModel {
     }
 }
 
-fn import_paths(
-    tx: Sender<ImportMessage>,
-    mut fresh_id: impl FnMut() -> u64,
-    paths: Vec<PathBuf>,
-) -> Vec<Item> {
-    use rayon::prelude::*;
-
-    paths
-        .into_iter()
-        .map(|path| {
-            let name = path.file_name().unwrap().to_string_lossy().to_string();
-            let path = path.display().to_string();
-            let id = fresh_id();
-            tx.send(ImportMessage::Update(
-                id,
-                ItemImportStatus::Queued(name.clone()),
-            ))
-            .unwrap();
-
-            (name, path, id, tx.clone())
+/// Bring the duration, waveform and file details of items up to date with
+/// their files on a background thread: those of the given items, or of every
+/// item whose files changed since they were analysed if none are given.
+pub fn refresh_items(model: Arc<RwLock<Model>>, ids: Option<Vec<u64>>) {
+    std::thread::spawn(move || {
+        // the files are only looked at once the lock is let go, since they
+        // may be slow to get to
+        let stems: Vec<_> = model
+            .read()
+            .items
+            .iter()
+            .filter(|item| ids.as_ref().is_none_or(|ids| ids.contains(&item.id)))
+            .filter(|item| item.signal.is_none() && !item.is_stream())
+            .flat_map(|item| {
+                let id = item.id;
+                item.stems
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, stem)| (id, index, stem.clone()))
+            })
+            .collect();
+
+        for (id, index, stem) in stems {
+            if ids.is_none() && !stem_changed(&stem) {
+                continue;
+            }
+            info!("analysing {} again", stem.path);
+            let analysis = match analyse(&stem.path) {
+                Ok(analysis) => analysis,
+                Err(err) => {
+                    warn!("failed to analyse {}: {}", stem.path, err);
+                    continue;
+                }
+            };
+            let mut model = model.write();
+            let item = model.items.iter_mut().find(|item| {
+                item.id == id && item.stems.get(index).is_some_and(|s| s.path == stem.path)
+            });
+            if let Some(item) = item {
+                analysis.apply_to(item, index);
+                model.revision += 1;
+            }
+        }
+    });
+}
+
+/// Ask for files to add to an item as further stems on a background thread,
+/// analysing them once added.
+pub fn add_stems(model: Arc<RwLock<Model>>, id: u64) {
+    std::thread::spawn(move || {
+        let Some(paths) = rfd::FileDialog::new()
+            .set_title("Choose files to add as stems")
+            .pick_files()
+        else {
+            return;
+        };
+        {
+            let mut model = model.write();
+            model.revision += 1;
+            let Some(item) = model.items.iter_mut().find(|item| item.id == id) else {
+                return;
+            };
+            for path in paths {
+                let tag = path
+                    .file_stem()
+                    .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+                item.stems.push(Stem {
+                    tag,
+                    path: path.display().to_string(),
+                    info: None,
+                });
+            }
+        }
+        refresh_items(model, Some(vec![id]));
+    });
+}
+
+/// Render the speech of a speech item on a background thread, making it
+/// the stem of the item and analysing it once rendered.
+pub fn render_speech(model: Arc<RwLock<Model>>, id: u64) {
+    std::thread::spawn(move || {
+        let (engine, text) = {
+            let model = model.read();
+            let text = model
+                .items
+                .iter()
+                .find(|item| item.id == id)
+                .and_then(|item| item.speech.clone());
+            let Some(text) = text else {
+                return;
+            };
+            (model.settings.speech_engine.clone(), text)
+        };
+        info!("rendering speech for item {}", id);
+        let rendered = speech::render(&engine, &text);
+        {
+            let mut model = model.write();
+            model.revision += 1;
+            // a newer text is being rendered already
+            let Some(item) = model
+                .items
+                .iter_mut()
+                .find(|item| item.id == id && item.speech.as_ref() == Some(&text))
+            else {
+                return;
+            };
+            match rendered {
+                Ok(path) => {
+                    item.stems[item.current_stem].path = path.display().to_string();
+                    item.issues.clear();
+                }
+                Err(err) => {
+                    warn!("failed to render speech: {:#}", err);
+                    item.report((
+                        IssueType::OtherError,
+                        format!("the speech couldn't be rendered: {:#}", err),
+                    ));
+                    return;
+                }
+            }
+        }
+        refresh_items(model, Some(vec![id]));
+    });
+}
+
+/// The files named in pasted text, one per line, either as paths or as
+/// `file://` URIs the way file managers copy them. Anything else, like the
+/// `copy` or `cut` header some file managers put first, is skipped.
+pub fn paths_from_clipboard(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| match line.strip_prefix("file://") {
+            // the host is either empty or localhost for local files
+            Some(uri) => {
+                let path = uri.strip_prefix("localhost").unwrap_or(uri);
+                percent_decode(path).map(PathBuf::from)
+            }
+            None => Some(PathBuf::from(line)),
         })
-        .collect::<Vec<_>>()
-        .into_par_iter()
-        .flat_map(|(name, path, id, tx)| create_item(tx, id, path, name))
+        .filter(|path| path.is_absolute() && path.is_file())
         .collect()
 }
 
-fn create_item(tx: Sender<ImportMessage>, id: u64, path: String, name: String) -> Option<Item> {
-    tx.send(ImportMessage::Update(id, ItemImportStatus::InProgress))
-        .unwrap();
-    let static_sound = match StaticSoundData::from_file(&path, StaticSoundSettings::new()) {
-        Ok(sound) => sound,
-        Err(e) => {
-            let (msg, _) = classify_from_file_err(&e);
-            warn!("failed to load {}: {}", path, msg);
-            tx.send(ImportMessage::Update(id, ItemImportStatus::Failed(msg)))
-                .unwrap();
-            return None;
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
         }
-    };
-    let duration = static_sound.frames.len() as f64 / static_sound.sample_rate as f64;
-    let mut i = Item::with_default_stem(
-        id,
-        name,
-        path,
-        PALETTE[id as usize % PALETTE.len()],
-        duration,
-    );
-    i.bars = visualise_samples(&static_sound.frames);
-    tx.send(ImportMessage::Update(id, ItemImportStatus::Finished))
-        .unwrap();
-    Some(i)
+    }
+    String::from_utf8(bytes).ok()
 }
 
 pub fn process_import_message(
@@ -107,6 +287,7 @@ pub fn process_import_message(
             ui.label("Cancelled");
             *keep_window_open = false;
         }
+        ImportMessage::Downloading(name, fraction) => state.download = Some((name, fraction)),
         ImportMessage::Update(id, status) => match status {
             ItemImportStatus::Queued(name) => {
                 state
@@ -130,96 +311,38 @@ pub fn process_import_message(
     }
 }
 
-fn visualise_samples(frames: &[kira::dsp::Frame]) -> Vec<u8> {
-    // collect samples into bins
-    let mut bins = vec![0.0; BARS];
-    let mut max = 0.0f32;
-    let bin_size = frames.len() / bins.len();
-    debug!(
-        "processing {:#?} frames with bin size {}",
-        frames.len(),
-        bin_size
-    );
-
-    for (i, bin) in bins.iter_mut().enumerate() {
-        let start = i * bin_size;
-        let end = start + bin_size;
-        let mut sum = 0.0;
-        for sample in frames[start..end].iter() {
-            sum += sample.left.abs() * 0.5 + sample.right.abs() * 0.5;
-        }
-        *bin = sum / bin_size as f32;
-        max = max.max(*bin);
-    }
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    bins.into_iter()
-        .map(|bin| (255.0 * (bin / max)).round() as u8)
-        .collect()
-}
+    #[test]
+    fn clipboard_paths() {
+        let dir = std::env::temp_dir().join("afx clipboard test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("rain & thunder.ogg");
+        std::fs::write(&file, []).unwrap();
+        let uri = format!(
+            "file://{}",
+            file.display()
+                .to_string()
+                .replace('%', "%25")
+                .replace(' ', "%20")
+        );
 
-pub fn classify_from_file_err(e: &FromFileError) -> (String, IssueType) {
-    use std::io::ErrorKind;
-    use symphonia::core::errors;
-    use IssueType::*;
-
-    fn describe_io_error(kind: ErrorKind) -> (String, IssueType) {
-        match kind {
-            ErrorKind::NotFound => ("the file could not be found".to_string(), MissingFile),
-            ErrorKind::PermissionDenied => (
-                "permission to read the file was denied".to_string(),
-                InaccessibleFile,
-            ),
-            kind => (format!("an IO error occurred: {}", kind), OtherError),
-        }
-    }
+        let text = format!("copy\n{}\n\n  {}  \nnot a file\n", uri, file.display());
+        assert_eq!(
+            paths_from_clipboard(&text),
+            vec![file.clone(), file.clone()]
+        );
+        assert_eq!(
+            paths_from_clipboard(&uri.replace("file://", "file://localhost")),
+            vec![file.clone()]
+        );
+        // directories and missing files are left out
+        assert!(paths_from_clipboard(&dir.display().to_string()).is_empty());
+        assert!(paths_from_clipboard(&format!("{}x", uri)).is_empty());
+        assert!(paths_from_clipboard("file:///broken%2").is_empty());
 
-    match e {
-        FromFileError::NoDefaultTrack => (
-            "the file doesn't have a default track".to_string(),
-            PlaybackProblem,
-        ),
-        FromFileError::UnknownSampleRate => (
-            "the sample rate could not be determined".to_string(),
-            PlaybackProblem,
-        ),
-        FromFileError::UnsupportedChannelConfiguration => (
-            "the channel configuration of the file is not supported".to_string(),
-            PlaybackProblem,
-        ),
-        FromFileError::IoError(io_err) => describe_io_error(io_err.kind()),
-        FromFileError::SymphoniaError(symphonia_err) => match symphonia_err {
-            errors::Error::IoError(e) => describe_io_error(e.kind()),
-            errors::Error::DecodeError(e) => (
-                format!("symphonia could not decode the file: {}", e),
-                PlaybackProblem,
-            ),
-            errors::Error::SeekError(e) => match e {
-                errors::SeekErrorKind::Unseekable => {
-                    ("this file is not seekable".to_string(), PlaybackProblem)
-                }
-                errors::SeekErrorKind::ForwardOnly => (
-                    "this file can only be seeked forward".to_string(),
-                    PlaybackProblem,
-                ),
-                errors::SeekErrorKind::OutOfRange => (
-                    "the seek timestamp is out of range".to_string(),
-                    PlaybackProblem,
-                ),
-                errors::SeekErrorKind::InvalidTrack => {
-                    ("the track ID is invalid".to_string(), PlaybackProblem)
-                }
-            },
-            errors::Error::Unsupported(e) => (
-                format!("symphonia does not support this format: {}", e),
-                PlaybackProblem,
-            ),
-            errors::Error::LimitError(e) => {
-                (format!("a limit error occurred: {}", e), PlaybackProblem)
-            }
-            errors::Error::ResetRequired => {
-                ("symphonia requires a reset".to_string(), PlaybackProblem)
-            }
-        },
-        _ => ("an unknown error occurred".to_string(), OtherError),
+        std::fs::remove_dir_all(&dir).ok();
     }
 }
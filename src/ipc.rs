@@ -0,0 +1,464 @@
+use crate::cli::Open;
+use afx_core::model::*;
+use anyhow::{anyhow, bail, Result};
+use eframe::egui;
+use parking_lot::RwLock;
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+/// The longest fade-out accepted, in seconds.
+const MAX_FADE: f64 = 600.0;
+
+/// Where a running instance listens for commands.
+#[cfg(unix)]
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("afx.sock"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("afx-{}.sock", user))
+        }
+    }
+}
+
+/// Where a running instance listens for commands, a named pipe.
+#[cfg(windows)]
+pub fn socket_path() -> PathBuf {
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    PathBuf::from(format!(r"\\.\pipe\afx-{}", user))
+}
+
+/// Turn a command such as `{"command": "play", "item": 3}` into a control
+/// message.
+pub fn parse_command(text: &str) -> Result<ControlMessage> {
+    let object: Map<String, Value> = serde_json::from_str(text)?;
+    let number = |key: &str| match object.get(key) {
+        Some(value) => value
+            .as_f64()
+            .ok_or_else(|| anyhow!("{} must be a number", key)),
+        None => Err(anyhow!("missing {}", key)),
+    };
+    let id = |key: &str| {
+        let n = number(key)?;
+        if n < 0.0 || n.fract() != 0.0 {
+            bail!("{} must be an id", key);
+        }
+        Ok(n as u64)
+    };
+    let volume = |key: &str| number(key).map(|n| n.clamp(0.0, db_to_amplitude(MAX_GAIN_DB)));
+    let seconds = |key: &str| number(key).map(|n| n.clamp(0.0, MAX_FADE));
+    let flag = |key: &str| {
+        object
+            .get(key)
+            .and_then(Value::as_bool)
+            .ok_or_else(|| anyhow!("{} must be true or false", key))
+    };
+
+    let Some(command) = object.get("command").and_then(Value::as_str) else {
+        bail!("missing command");
+    };
+    Ok(match command {
+        "play" => ControlMessage::Play(id("item")?),
+        "pause" => ControlMessage::Pause(id("item")?),
+        "stop" => ControlMessage::Stop(id("item")?),
+        "seek" => ControlMessage::Seek(id("item")?, number("position")?.max(0.0)),
+        "skip" => ControlMessage::SeekRelative(id("item")?, number("seconds")?),
+        "loop" => ControlMessage::Loop(id("item")?, flag("enabled")?),
+        "mute" => ControlMessage::Mute(id("item")?, flag("enabled")?),
+        "volume" => ControlMessage::SetVolume(id("item")?, volume("volume")?),
+        "master_volume" => ControlMessage::SetMasterVolume(volume("volume")?),
+        "play_playlist" => ControlMessage::PlayFromPlaylist(id("playlist")?),
+        "pause_all" => ControlMessage::GlobalPause,
+        "resume_all" => ControlMessage::GlobalResume,
+        "stop_all" => ControlMessage::GlobalStop,
+        "fade_all" => ControlMessage::GlobalFadeOut(seconds("seconds")?),
+        other => bail!("unknown command {}", other),
+    })
+}
+
+/// The command another instance sends with the files it was started with,
+/// as a list of paths. An empty list only brings the window forward.
+fn open_command(open: &Open) -> String {
+    let files: Vec<_> = open
+        .files
+        .iter()
+        .map(|file| file.to_string_lossy())
+        .collect();
+    json!({
+        "command": "open",
+        "files": files,
+        "playlist": open.playlist,
+    })
+    .to_string()
+}
+
+/// Recognise the command sent by [`open_command`], leaving the others to
+/// [`parse_command`].
+fn parse_open(text: &str) -> Result<Option<Open>> {
+    let object: Map<String, Value> = serde_json::from_str(text)?;
+    if object.get("command").and_then(Value::as_str) != Some("open") {
+        return Ok(None);
+    }
+    let files = match object.get("files") {
+        Some(Value::Array(files)) => files
+            .iter()
+            .map(|file| file.as_str().map(PathBuf::from))
+            .collect::<Option<_>>()
+            .ok_or_else(|| anyhow!("files must be strings"))?,
+        _ => bail!("files must be a list"),
+    };
+    let playlist = match object.get("playlist") {
+        Some(Value::String(name)) => Some(name.clone()),
+        None | Some(Value::Null) => None,
+        Some(_) => bail!("playlist must be a string"),
+    };
+    Ok(Some(Open { files, playlist }))
+}
+
+/// Connect to the instance listening on [`socket_path`], if there is one.
+#[cfg(unix)]
+fn connect() -> Result<Option<std::os::unix::net::UnixStream>> {
+    let Ok(stream) = std::os::unix::net::UnixStream::connect(socket_path()) else {
+        return Ok(None);
+    };
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+    Ok(Some(stream))
+}
+
+/// Connect to the instance listening on [`socket_path`], if there is one.
+#[cfg(windows)]
+fn connect() -> Result<Option<std::fs::File>> {
+    use windows_sys::Win32::Foundation::ERROR_PIPE_BUSY;
+
+    // the running instance opens another end of the pipe right after a
+    // client takes one
+    for _ in 0..50 {
+        match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(socket_path())
+        {
+            Ok(pipe) => return Ok(Some(pipe)),
+            Err(err) if err.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) => {
+                std::thread::sleep(std::time::Duration::from_millis(100))
+            }
+            Err(_) => return Ok(None),
+        }
+    }
+    bail!("the running instance is busy")
+}
+
+/// Hand the files to open to an instance that's already running, so that
+/// two don't fight over the audio device and the saved state. Returns
+/// whether one took them.
+pub fn forward(open: &Open) -> Result<bool> {
+    use std::io::{BufRead, BufReader, Write};
+
+    let Some(mut stream) = connect()? else {
+        return Ok(false);
+    };
+    // the running instance resolves relative paths against its own directory
+    let open = Open {
+        files: open
+            .files
+            .iter()
+            .map(|file| std::fs::canonicalize(file).unwrap_or_else(|_| file.clone()))
+            .collect(),
+        playlist: open.playlist.clone(),
+    };
+    writeln!(stream, "{}", open_command(&open))?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    let reply: Value = serde_json::from_str(&reply)?;
+    match reply["error"].as_str() {
+        Some(err) => bail!("the running instance refused the files: {}", err),
+        None => Ok(true),
+    }
+}
+
+/// Mirror a message in the model where the interface would, then send it.
+fn dispatch(msg: ControlMessage, model: &RwLock<Model>, channel: &Sender<ControlMessage>) {
+    {
+        let mut model = model.write();
+        let mut edit = |id: u64, edit: &mut dyn FnMut(&mut Item)| {
+            if let Some(item) = model.items.iter_mut().find(|item| item.id == id) {
+                edit(item);
+            }
+        };
+        match msg {
+            ControlMessage::Loop(id, looped) => edit(id, &mut |item| item.set_looped(looped)),
+            ControlMessage::Mute(id, muted) => edit(id, &mut |item| item.muted = muted),
+            ControlMessage::SetVolume(id, volume) => edit(id, &mut |item| item.volume = volume),
+            ControlMessage::SetMasterVolume(volume) => {
+                model.master_gain_db = amplitude_to_db(volume)
+            }
+            _ => (),
+        }
+    }
+    channel.send(msg).unwrap();
+}
+
+/// Answer the newline-separated JSON commands of one client until it
+/// disconnects.
+fn serve(
+    reader: impl std::io::Read,
+    mut writer: impl std::io::Write,
+    model: &RwLock<Model>,
+    channel: &Sender<ControlMessage>,
+    opens: &Sender<Open>,
+    ctx: &egui::Context,
+) {
+    use std::io::{BufRead, BufReader};
+
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = match parse_open(&line) {
+            Ok(Some(open)) => {
+                let _ = opens.send(open);
+                ctx.request_repaint();
+                Ok(())
+            }
+            Ok(None) => parse_command(&line).map(|msg| dispatch(msg, model, channel)),
+            Err(err) => Err(err),
+        };
+        let reply = match result {
+            Ok(()) => json!({"ok": true}),
+            Err(err) => json!({"ok": false, "error": err.to_string()}),
+        };
+        if writeln!(writer, "{}", reply).is_err() {
+            break;
+        }
+    }
+}
+
+/// Listen for newline-separated JSON commands on a Unix domain socket. Each
+/// command is answered with `{"ok": true}` or an error. Files forwarded by
+/// other instances go to `opens`.
+#[cfg(unix)]
+pub fn start(
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    opens: Sender<Open>,
+    ctx: egui::Context,
+) {
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use tracing::{info, warn};
+
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        warn!("another instance is listening on {}", path.display());
+        return;
+    }
+    let _ = std::fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            warn!("failed to listen on {}: {}", path.display(), err);
+            return;
+        }
+    };
+    info!("listening for commands on {}", path.display());
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let model = model.clone();
+            let channel = channel.clone();
+            let opens = opens.clone();
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                let Ok(reader) = stream.try_clone() else {
+                    return;
+                };
+                serve(reader, stream, &model, &channel, &opens, &ctx);
+            });
+        }
+    });
+}
+
+/// Listen for commands on a named pipe, like the Unix domain socket
+/// elsewhere.
+#[cfg(windows)]
+pub fn start(
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    opens: Sender<Open>,
+    ctx: egui::Context,
+) {
+    use std::fs::File;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+    use tracing::{info, warn};
+    use windows_sys::Win32::Foundation::{
+        GetLastError, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+        PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    let path = socket_path();
+    let name: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // every client takes an end of the pipe of its own
+    let create = move |first: bool| {
+        let flags = if first {
+            FILE_FLAG_FIRST_PIPE_INSTANCE
+        } else {
+            0
+        };
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX | flags,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                std::ptr::null(),
+            )
+        };
+        (handle != INVALID_HANDLE_VALUE).then(|| unsafe { File::from_raw_handle(handle as _) })
+    };
+    // the first end can't be created while another instance is listening
+    let Some(mut pipe) = create(true) else {
+        warn!(
+            "failed to listen on {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        );
+        return;
+    };
+    info!("listening for commands on {}", path.display());
+
+    std::thread::spawn(move || loop {
+        let connected = unsafe {
+            ConnectNamedPipe(pipe.as_raw_handle() as _, std::ptr::null_mut()) != 0
+                || GetLastError() == ERROR_PIPE_CONNECTED
+        };
+        let Some(next) = create(false) else {
+            warn!(
+                "stopped listening on {}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            );
+            return;
+        };
+        let client = std::mem::replace(&mut pipe, next);
+        if !connected {
+            continue;
+        }
+        let model = model.clone();
+        let channel = channel.clone();
+        let opens = opens.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let Ok(reader) = client.try_clone() else {
+                return;
+            };
+            serve(reader, client, &model, &channel, &opens, &ctx);
+        });
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn commands() -> Result<()> {
+        assert_eq!(
+            parse_command(r#"{"command": "play", "item": 3}"#)?,
+            ControlMessage::Play(3)
+        );
+        assert_eq!(
+            parse_command(r#"{"command": "volume", "item": 3, "volume": 0.5}"#)?,
+            ControlMessage::SetVolume(3, 0.5)
+        );
+        assert_eq!(
+            parse_command(r#"{"command": "skip", "item": 3, "seconds": -5}"#)?,
+            ControlMessage::SeekRelative(3, -5.0)
+        );
+        assert_eq!(
+            parse_command(r#"{"command": "stop_all"}"#)?,
+            ControlMessage::GlobalStop
+        );
+
+        assert!(parse_command(r#"{"command": "play"}"#).is_err());
+        assert!(parse_command(r#"{"command": "play", "item": [3]}"#).is_err());
+        assert!(parse_command(r#"{"command": "play", "item": 3"#).is_err());
+        assert!(parse_command(r#"{"command": "play", "item": 3} x"#).is_err());
+        assert!(parse_command(r#"{"command": "play", "item": 1.5}"#).is_err());
+        assert!(parse_command(r#"{"command": "dance"}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_range() -> Result<()> {
+        for number in ["1e400", "-1e400", "inf", "infinity", "NaN"] {
+            let command = format!(r#"{{"command": "fade_all", "seconds": {}}}"#, number);
+            assert!(parse_command(&command).is_err(), "{} was taken", number);
+        }
+        assert_eq!(
+            parse_command(r#"{"command": "fade_all", "seconds": 1e300}"#)?,
+            ControlMessage::GlobalFadeOut(MAX_FADE)
+        );
+        assert_eq!(
+            parse_command(r#"{"command": "fade_all", "seconds": -3}"#)?,
+            ControlMessage::GlobalFadeOut(0.0)
+        );
+        assert_eq!(
+            parse_command(r#"{"command": "volume", "item": 3, "volume": -1}"#)?,
+            ControlMessage::SetVolume(3, 0.0)
+        );
+        assert_eq!(
+            parse_command(r#"{"command": "master_volume", "volume": 1e9}"#)?,
+            ControlMessage::SetMasterVolume(db_to_amplitude(MAX_GAIN_DB))
+        );
+        assert_eq!(
+            parse_command(r#"{"command": "seek", "item": 3, "position": -2}"#)?,
+            ControlMessage::Seek(3, 0.0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn forwarded_files() -> Result<()> {
+        let open = Open {
+            files: vec![
+                PathBuf::from("/music/a \"b\".ogg"),
+                PathBuf::from("/music/c\\d.wav"),
+                // both are valid in a Unix path
+                PathBuf::from("/music/e\nf.ogg\r"),
+            ],
+            playlist: Some("Act 1".to_string()),
+        };
+        assert_eq!(parse_open(&open_command(&open))?, Some(open));
+        // characters outside the basic plane are escaped as surrogate pairs
+        assert_eq!(
+            parse_open(r#"{"command": "open", "files": [], "playlist": "\ud83c\udfb5"}"#)?,
+            Some(Open {
+                files: vec![],
+                playlist: Some("🎵".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_open(&open_command(&Open::default()))?,
+            Some(Open::default())
+        );
+        assert_eq!(parse_open(r#"{"command": "stop_all"}"#)?, None);
+        assert!(parse_open(r#"{"command": "open", "files": 1}"#).is_err());
+        assert!(parse_open(r#"{"command": "open", "files": "a.ogg"}"#).is_err());
+        assert!(parse_open(r#"{"command": "open", "files": ["a.ogg", 1]}"#).is_err());
+        Ok(())
+    }
+}
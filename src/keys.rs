@@ -0,0 +1,100 @@
+//! The keyboard shortcuts of the keymap as egui sees them.
+
+use afx_core::keymap::{Action, Key, Keymap, Modifiers, Shortcut};
+use eframe::egui;
+
+macro_rules! egui_keys {
+    ($($key:ident),*; $($renamed:ident => $egui_key:ident),*) => {
+        fn egui_key(key: Key) -> egui::Key {
+            match key {
+                $(Key::$key => egui::Key::$key,)*
+                $(Key::$renamed => egui::Key::$egui_key,)*
+            }
+        }
+    };
+}
+
+#[rustfmt::skip]
+egui_keys!(
+    ArrowDown, ArrowLeft, ArrowRight, ArrowUp,
+    Escape, Tab, Backspace, Enter, Space,
+    Insert, Delete, Home, End, PageUp, PageDown,
+    Minus,
+    Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10,
+    F11, F12, F13, F14, F15, F16, F17, F18, F19, F20;
+    // egui names the key by its unshifted character, the keymap keeps the
+    // name saved profiles use
+    PlusEquals => Equals
+);
+
+fn egui_modifiers(modifiers: Modifiers) -> egui::Modifiers {
+    let Modifiers {
+        alt,
+        ctrl,
+        shift,
+        mac_cmd,
+        command,
+    } = modifiers;
+    egui::Modifiers {
+        alt,
+        ctrl,
+        shift,
+        mac_cmd,
+        command,
+    }
+}
+
+pub trait ShortcutInput {
+    fn format(&self, ctx: &egui::Context) -> String;
+}
+
+impl ShortcutInput for Shortcut {
+    fn format(&self, ctx: &egui::Context) -> String {
+        ctx.format_shortcut(&egui::KeyboardShortcut::new(
+            egui_modifiers(self.modifiers),
+            egui_key(self.key),
+        ))
+    }
+}
+
+pub trait KeymapInput {
+    /// A human-readable list of the shortcuts for the action, e.g. for
+    /// tooltips.
+    fn describe(&self, ctx: &egui::Context, action: Action) -> String;
+    /// Check whether any shortcut of the action was pressed this frame,
+    /// consuming the key press if so.
+    fn consume(&self, ctx: &egui::Context, action: Action) -> bool;
+}
+
+impl KeymapInput for Keymap {
+    fn describe(&self, ctx: &egui::Context, action: Action) -> String {
+        self.shortcuts(action)
+            .map(|shortcut| shortcut.format(ctx))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn consume(&self, ctx: &egui::Context, action: Action) -> bool {
+        ctx.input_mut(|input| {
+            self.shortcuts(action).any(|shortcut| {
+                input.consume_key(egui_modifiers(shortcut.modifiers), egui_key(shortcut.key))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_shortcuts() {
+        let keymap = Keymap::default();
+        let ctx = egui::Context::default();
+        assert_eq!(keymap.describe(&ctx, Action::Skip(5)), "Right");
+        assert_eq!(keymap.describe(&ctx, Action::Skip(30)), "Shift+Right");
+        assert_eq!(keymap.describe(&ctx, Action::SelectBank(0)), "F1");
+    }
+}
@@ -1,42 +1,42 @@
 mod app;
+mod cli;
 mod colour_proxy;
+mod config;
+mod crash;
+mod diagnostics;
 mod import;
-mod model;
+mod ipc;
+mod keys;
+mod midi;
+mod mpris;
+mod remote;
+mod script;
+mod suspend;
+mod sync;
+mod tray;
 mod ui;
 
-use kira::manager::backend::Backend;
-use model::*;
-use ui::*;
+use afx_core::engine::{process_control_messages, ENGINE_CHANNEL_CAPACITY, PLAYBACK_SYNC_INTERVAL};
+use afx_core::model::*;
+use app::SharedModel;
 
-use anyhow::Result;
-use kira::manager::backend::cpal::CpalBackend;
-use kira::manager::{AudioManager, AudioManagerSettings};
-use kira::sound::static_sound::PlaybackState;
-use kira::sound::streaming::{StreamingSoundData, StreamingSoundHandle, StreamingSoundSettings};
-use kira::sound::FromFileError;
-use kira::tween::Tween;
-use kira::LoopBehavior;
 use parking_lot::RwLock;
-use std::collections::HashMap;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::ops::ControlFlow;
+use std::sync::mpsc::{channel, sync_channel};
 use std::sync::Arc;
-use tracing::{info, warn, Level};
-use tracing_subscriber::FmtSubscriber;
-
-use crate::import::classify_from_file_err;
 
 fn main() {
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::TRACE)
-        .finish();
-
-    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+    // logs go to stderr so that they don't mix with command line output
+    tracing::subscriber::set_global_default(diagnostics::subscriber())
+        .expect("setting default subscriber failed");
 
     let options = eframe::NativeOptions {
-        drag_and_drop_support: true,
+        viewport: eframe::egui::ViewportBuilder::default().with_drag_and_drop(true),
         ..Default::default()
     };
 
+    // import workers start out low, until the engine applies the priorities
+    // from the settings
     rayon::ThreadPoolBuilder::new()
         .start_handler(|_| {
             use thread_priority::*;
@@ -45,16 +45,58 @@ fn main() {
         .build_global()
         .unwrap();
 
-    let (tx, rx) = channel();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let open = match cli::run(&args) {
+        ControlFlow::Continue(open) => open,
+        ControlFlow::Break(result) => {
+            if let Err(err) = result {
+                eprintln!("afx: {:#}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+    };
+    match ipc::forward(&open) {
+        Ok(true) => {
+            tracing::info!("handed the files to the running instance");
+            return;
+        }
+        Ok(false) => (),
+        // starting anyway would leave two instances fighting over the saved
+        // state
+        Err(err) => {
+            eprintln!("afx: failed to reach the running instance: {:#}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let (tx, relay_rx) = channel();
+    let (open_tx, open_rx) = channel();
+    let (engine_tx, rx) = sync_channel(ENGINE_CHANNEL_CAPACITY);
+    let (notification_tx, notification_rx) = channel();
     let model = Arc::new(RwLock::new(Model::default()));
+    let backups = sync::Backups::default();
+    crash::install(model.clone());
+
+    // the engine opens the output from the settings as it starts, so the
+    // saved state goes first, then the settings file which wins over the
+    // settings saved with the library
+    let repairs = app::recover(tx.clone(), model.clone()).unwrap_or_default();
+    let config = config::load(&model);
 
     {
         let model = model.clone();
-        // start a background thread for audio playback
+        // start a background thread for audio playback, fed by one mirroring
+        // the messages to backups and holding them back while it's busy
         {
-            let tx = tx.clone();
-            std::thread::spawn(move || process_control_messages(tx, rx, model));
-        }
+            let model = model.clone();
+            let backups = backups.clone();
+            std::thread::spawn(move || sync::relay(relay_rx, engine_tx, &model, backups));
+        }
+        // the engine sends messages to itself through the relay, since its
+        // own channel is bounded
+        let engine_tx = tx.clone();
+        std::thread::spawn(move || process_control_messages(engine_tx, rx, notification_tx, model));
         // sync playback status every PLAYBACK_SYNC_INTERVAL ms
         let tx = tx.clone();
         std::thread::spawn(move || loop {
@@ -63,418 +105,52 @@ fn main() {
         });
     }
 
-    eframe::run_native(
+    let result = eframe::run_native(
         "afx",
         options,
         Box::new(|cc| {
-            app::recover(cc, tx.clone(), model.clone());
-
-            Box::new(SharedModel {
+            // the keymap has zoom shortcuts of its own
+            cc.egui_ctx
+                .options_mut(|options| options.zoom_with_keyboard = false);
+            let crashed = app::crashed();
+            config::start(config, model.clone(), cc.egui_ctx.clone());
+            import::refresh_items(model.clone(), None);
+            midi::start_input(model.clone(), tx.clone());
+            ipc::start(model.clone(), tx.clone(), open_tx, cc.egui_ctx.clone());
+            mpris::start(model.clone(), tx.clone(), cc);
+            script::start(model.clone(), tx.clone());
+            suspend::start(tx.clone());
+            let show_window = tray::start(model.clone(), tx.clone(), cc.egui_ctx.clone());
+
+            let mut app = SharedModel {
                 import_state: None,
                 play_channel: tx,
                 model,
-            })
+                notifications: notification_rx,
+                toasts: repairs
+                    .into_iter()
+                    .map(|repair| Notification {
+                        message: format!("Repaired the saved state: {}", repair),
+                        item: None,
+                    })
+                    .collect(),
+                remote: None,
+                sync: None,
+                backups,
+                view: Model::default(),
+                base: Model::default(),
+                interface: Default::default(),
+                diagnostics: Default::default(),
+                show_window,
+                opens: open_rx,
+                crashed,
+            };
+            app.open(open);
+            Box::new(app)
         }),
     );
-}
-
-fn process_control_messages(
-    tx: Sender<ControlMessage>,
-    rx: Receiver<ControlMessage>,
-    model: Arc<RwLock<Model>>,
-) {
-    let manager = AudioManager::<CpalBackend>::new(AudioManagerSettings::default());
-    if let Err(err) = manager {
-        warn!("Failed to create audio manager: {}", err);
-        return;
-    }
-
-    let mut manager = manager.unwrap();
-    let mut handles = HashMap::<u64, StreamingSoundHandle<FromFileError>>::new();
-
-    while let Ok(msg) = rx.recv() {
-        let res = process_message(msg, &tx, &mut manager, &mut handles, &model);
-        if let Err(err) = res {
-            warn!("Failed to process control message: {}", err);
-        }
-    }
-}
-
-fn process_message<B: Backend>(
-    msg: ControlMessage,
-    tx: &Sender<ControlMessage>,
-    manager: &mut AudioManager<B>,
-    handles: &mut HashMap<u64, StreamingSoundHandle<FromFileError>>,
-    model: &Arc<RwLock<Model>>,
-) -> Result<()> {
-    // string return value because lol no lambda generics :(
-    let edit_item = |id: u64, f: &mut dyn FnMut(&mut Item) -> String| {
-        let mut model = model.write();
-        model.items.iter_mut().find(|item| item.id == id).map(f)
-    };
-
-    match msg {
-        ControlMessage::Play(id) => {
-            if let Some(handle) = handles.get_mut(&id) {
-                handle.resume(Tween::default())?;
-            } else {
-                let handle = begin_playback(model, id, edit_item, manager)?;
-                handles.insert(id, handle);
-            }
-            // we ignore the option here - the edit may not go through
-            // if the item was deleted in the meantime
-            edit_item(id, &mut |item| {
-                item.status = ItemStatus::Playing;
-                String::new()
-            });
-            Ok(())
-        }
-        ControlMessage::Pause(id) => {
-            if let Some(handle) = handles.get_mut(&id) {
-                handle.pause(Tween::default())?;
-                edit_item(id, &mut |item| {
-                    item.status = ItemStatus::Paused;
-                    String::new()
-                });
-            }
-            Ok(())
-        }
-        ControlMessage::ChangeStem(_, _) => todo!(),
-        ControlMessage::SyncPlaybackStatus => {
-            let mut to_remove = vec![];
-            for (&id, handle) in handles
-                .iter_mut()
-                .filter(|(_, h)| h.state() != PlaybackState::Paused)
-            {
-                edit_item(id, &mut |item| {
-                    item.target_position = handle.position();
-
-                    if item.position >= item.duration || handle.state() == PlaybackState::Stopped {
-                        item.target_position = 0.0;
-
-                        to_remove.push(id);
-                        if item.looped {
-                            // FIXME this is a hack, since looping behaviour
-                            // can't be changed via a handle
-                            item.status = ItemStatus::Loading;
-                            tx.send(ControlMessage::Play(id)).unwrap();
-                        } else {
-                            item.status = ItemStatus::Stopped;
-                            handle.stop(Tween::default()).unwrap();
-                        }
-                    }
-                    String::new()
-                });
-            }
-            for id in to_remove {
-                handles.remove(&id);
-            }
-            Ok(())
-        }
-        ControlMessage::Seek(id, target) => {
-            let mut defer_to_sync = false;
-            if let Some(handle) = handles.get_mut(&id) {
-                handle.seek_to(target)?;
-                if handle.state() == PlaybackState::Playing {
-                    defer_to_sync = true;
-                }
-            }
-
-            // FIXME there's still the issue of seeking a paused handle and then
-            // letting it play. Leads to glitchy behaviour.
-            if !defer_to_sync {
-                edit_item(id, &mut |item| {
-                    item.target_position = target;
-                    String::new()
-                });
-            }
-            Ok(())
-        }
-        ControlMessage::Loop(id, _do_loop) => {
-            if let Some(_handle) = handles.get_mut(&id) {
-                // TODO: implement looping via handles once it's supported
-            }
-            Ok(())
-        }
-        ControlMessage::Mute(id, mute) => {
-            if let Some(handle) = handles.get_mut(&id) {
-                let model = model.read();
-                let item = model.items.iter().find(|item| item.id == id).unwrap();
-                handle.set_volume(if mute { 0.0 } else { item.volume }, Tween::default())?;
-            }
-            Ok(())
-        }
-        ControlMessage::SetVolume(id, volume) => {
-            if let Some(handle) = handles.get_mut(&id) {
-                handle.set_volume(volume, Tween::default())?;
-            }
-            Ok(())
-        }
-        ControlMessage::Delete(id) => {
-            if let Some(mut handle) = handles.remove(&id) {
-                handle.stop(Tween::default())?;
-            }
-            let mut model = model.write();
-            model.items.retain(|item| item.id != id);
-            model.playlists.iter_mut().for_each(|playlist| {
-                playlist.items.retain(|item| *item != id);
-            });
-            Ok(())
-        }
-        ControlMessage::AddToPlaylist {
-            item_id,
-            playlist_id,
-        } => {
-            let mut model = model.write();
-            let playlist = model
-                .playlists
-                .iter_mut()
-                .find(|playlist| playlist.id == playlist_id)
-                .unwrap();
-            playlist.items.push(item_id);
-            Ok(())
-        }
-        ControlMessage::RemoveFromPlaylist {
-            pos_within_playlist,
-            playlist_id,
-        } => {
-            let mut model = model.write();
-            let playlist = model
-                .playlists
-                .iter_mut()
-                .find(|playlist| playlist.id == playlist_id)
-                .unwrap();
-            playlist.items.remove(pos_within_playlist);
-            Ok(())
-        }
-        ControlMessage::PlayFromPlaylist(id) => {
-            let mut model = model.write();
-            // TODO if another playlist is playing, stop it
-            // begin playback of the first item in the playlist
-            // TODO if the playlist is empty, do nothing
-
-            model.playing_playlist = Some(id);
-
-            Ok(())
-        }
-        ControlMessage::GlobalPause => {
-            let mut model = model.write();
-            for (id, handle) in handles.iter_mut() {
-                handle.pause(Tween::default())?;
-                model
-                    .items
-                    .iter_mut()
-                    .find(|item| item.id == *id)
-                    .unwrap()
-                    .status = ItemStatus::Paused;
-            }
-            Ok(())
-        }
-        ControlMessage::GlobalStop => {
-            let mut model = model.write();
-            for (id, handle) in handles.iter_mut() {
-                handle.stop(Tween::default())?;
-                let item = model.items.iter_mut().find(|item| item.id == *id).unwrap();
-                item.status = ItemStatus::Stopped;
-                item.target_position = 0.0;
-            }
-            handles.clear();
-            Ok(())
-        }
-    }
-}
-
-fn begin_playback<B: Backend>(
-    model: &Arc<RwLock<Model>>,
-    id: u64,
-    mut edit_item: impl FnMut(u64, &mut dyn FnMut(&mut Item) -> String) -> Option<String>,
-    manager: &mut AudioManager<B>,
-) -> Result<StreamingSoundHandle<FromFileError>> {
-    let (file, position, looped, muted, volume) = {
-        let model = model.read();
-        let item = model.items.iter().find(|item| item.id == id).unwrap();
-        let path = item.stems[item.current_stem].path.clone();
-        (path, item.position, item.looped, item.muted, item.volume)
-    };
-    info!("loading {}", file);
-    let settings = StreamingSoundSettings::new()
-        .start_position(position)
-        .volume(if muted { 0.0 } else { volume })
-        .loop_behavior(if looped {
-            Some(LoopBehavior {
-                start_position: 0.0,
-            })
-        } else {
-            None
-        });
-    let sound = match StreamingSoundData::from_file(&file, settings) {
-        Ok(sound) => sound,
-        Err(err) => {
-            edit_item(id, &mut |item| {
-                item.status = ItemStatus::Stopped;
-                let (msg, typ) = classify_from_file_err(&err);
-                item.issues.push((typ, msg));
-                String::new()
-            });
-            return Err(err.into());
-        }
-    };
-    info!("passing {} to manager", file);
-    Ok(manager.play(sound)?)
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-    use eframe::epaint::Color32;
-
-    fn mock_audio_manager() -> AudioManager<kira::manager::backend::mock::MockBackend> {
-        AudioManager::new(AudioManagerSettings::default()).unwrap()
-    }
-
-    fn build_test_model() -> Model {
-        let path = "samples/416529__inspectorj__bird-whistling-single-robin-a.wav".to_string();
-        Model {
-            items: vec![
-                Item::with_default_stem(
-                    0,
-                    "test 0".to_string(),
-                    path.clone(),
-                    Color32::BLACK,
-                    1.0,
-                ),
-                Item::with_default_stem(
-                    1,
-                    "test 1".to_string(),
-                    path.clone(),
-                    Color32::BLACK,
-                    1.0,
-                ),
-                Item::with_default_stem(
-                    2,
-                    "test 2".to_string(),
-                    path,
-                    Color32::BLACK,
-                    1.0,
-                ),
-            ],
-            ..Model::default()
-        }
-    }
-
-    #[test]
-    fn file_not_found() -> Result<()> {
-        // create a temporary directory and try to play a nonexistent file from it
-        let path = {
-            let tempdir = tempfile::tempdir()?;
-            let path = tempdir
-                .path()
-                .join("nonexistent.wav")
-                .to_str()
-                .unwrap()
-                .to_string();
-            tempdir.close()?;
-            path
-        };
-        let model = {
-            let mut m = build_test_model();
-            m.items[0].stems[0].path = path;
-            m
-        };
-        let mut manager = mock_audio_manager();
-        let mut handles = HashMap::new();
-
-        let msg = ControlMessage::Play(0);
-
-        let model = Arc::new(RwLock::new(model));
-        let (rx, _tx) = channel();
-        #[allow(unused_must_use)]
-        {
-            process_message(msg, &rx, &mut manager, &mut handles, &model);
-        }
-
-        let model = &*model.read();
-
-        assert_eq!(model.items[0].status, ItemStatus::Stopped);
-        assert_eq!(model.items[0].issues.len(), 1);
-        assert_eq!(model.items[0].issues[0].0, IssueType::MissingFile);
-        Ok(())
-    }
-
-    #[test]
-    fn play_and_pause() -> Result<()> {
-        let model = build_test_model();
-        let mut manager = mock_audio_manager();
-        let mut handles = HashMap::new();
-
-        let model = Arc::new(RwLock::new(model));
-        let (rx, _tx) = channel();
-
-        process_message(ControlMessage::Play(0), &rx, &mut manager, &mut handles, &model)?;
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        assert_eq!(model.read().items[0].status, ItemStatus::Playing);
-
-        process_message(ControlMessage::Pause(0), &rx, &mut manager, &mut handles, &model)?;
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        assert_eq!(model.read().items[0].status, ItemStatus::Paused);
-
-        Ok(())
-    }
-
-    #[test]
-    fn play_many() -> Result<()> {
-        let model = build_test_model();
-        let mut manager = mock_audio_manager();
-        let mut handles = HashMap::new();
-
-        let model = Arc::new(RwLock::new(model));
-        let (rx, _tx) = channel();
-
-        process_message(ControlMessage::Play(0), &rx, &mut manager, &mut handles, &model)?;
-        process_message(ControlMessage::Play(1), &rx, &mut manager, &mut handles, &model)?;
-        process_message(ControlMessage::Play(2), &rx, &mut manager, &mut handles, &model)?;
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        assert_eq!(model.read().items[0].status, ItemStatus::Playing);
-        assert_eq!(model.read().items[1].status, ItemStatus::Playing);
-        assert_eq!(model.read().items[2].status, ItemStatus::Playing);
-
-        process_message(ControlMessage::GlobalPause, &rx, &mut manager, &mut handles, &model)?;
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        assert_eq!(model.read().items[0].status, ItemStatus::Paused);
-        assert_eq!(model.read().items[1].status, ItemStatus::Paused);
-        assert_eq!(model.read().items[2].status, ItemStatus::Paused);
-
-        process_message(ControlMessage::GlobalStop, &rx, &mut manager, &mut handles, &model)?;
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        assert_eq!(model.read().items[0].status, ItemStatus::Stopped);
-        assert_eq!(model.read().items[1].status, ItemStatus::Stopped);
-        assert_eq!(model.read().items[2].status, ItemStatus::Stopped);
-
-        Ok(())
-    }
-
-    #[ignore = "requires a real audio backend, won't work in CI"]
-    #[test]
-    fn seek() -> Result<()> {
-        use approx::assert_relative_eq;
-
-        let model = build_test_model();
-        let mut manager = AudioManager::<CpalBackend>::new(AudioManagerSettings::default())?;
-        let mut handles = HashMap::new();
-
-        let model = Arc::new(RwLock::new(model));
-        let (rx, _tx) = channel();
-
-        process_message(ControlMessage::Play(0), &rx, &mut manager, &mut handles, &model)?;
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        assert_eq!(model.read().items[0].status, ItemStatus::Playing);
-
-        process_message(ControlMessage::Seek(0, 1.5), &rx, &mut manager, &mut handles, &model)?;
-        std::thread::sleep(std::time::Duration::from_millis(600));
-        process_message(ControlMessage::SyncPlaybackStatus, &rx, &mut manager, &mut handles, &model)?;
-        assert_eq!(model.read().items[0].status, ItemStatus::Playing);
-        assert_relative_eq!(model.read().items[0].target_position, 1.5, epsilon = 0.5);
-
-        Ok(())
+    if let Err(err) = result {
+        eprintln!("afx: {}", err);
+        std::process::exit(1);
     }
 }
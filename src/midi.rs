@@ -0,0 +1,62 @@
+use afx_core::midi::{handle_message, MidiParser};
+use afx_core::model::*;
+use midir::{Ignore, MidiInput};
+use parking_lot::RwLock;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Listen to all MIDI input ports. The names of the ports found are stored
+/// in the model.
+pub fn start_input(model: Arc<RwLock<Model>>, channel: Sender<ControlMessage>) {
+    let input = match MidiInput::new("afx") {
+        Ok(input) => input,
+        Err(err) => {
+            warn!("failed to set up MIDI input: {}", err);
+            return;
+        }
+    };
+    let ports: Vec<_> = input
+        .ports()
+        .into_iter()
+        .filter_map(|port| Some((port.id(), input.port_name(&port).ok()?)))
+        .collect();
+    model.write().midi_devices = ports.iter().map(|(_, name)| name.clone()).collect();
+
+    for (id, name) in ports {
+        // a client connects to a single port
+        let mut input = match MidiInput::new("afx") {
+            Ok(input) => input,
+            Err(err) => {
+                warn!("failed to open MIDI port {}: {}", name, err);
+                continue;
+            }
+        };
+        input.ignore(Ignore::All);
+        let Some(port) = input.find_port_by_id(id) else {
+            warn!("MIDI port {} disappeared", name);
+            continue;
+        };
+        let (model, channel, device) = (model.clone(), channel.clone(), name.clone());
+        let connection = input.connect(
+            &port,
+            "afx input",
+            move |_, bytes, parser: &mut MidiParser| {
+                for &byte in bytes {
+                    if let Some(message) = parser.feed(byte) {
+                        handle_message(&device, message, &mut model.write(), &channel);
+                    }
+                }
+            },
+            MidiParser::default(),
+        );
+        match connection {
+            Ok(connection) => {
+                info!("listening to MIDI port {}", name);
+                // the port is listened to for as long as afx runs
+                std::mem::forget(connection);
+            }
+            Err(err) => warn!("failed to open MIDI port {}: {}", name, err),
+        }
+    }
+}
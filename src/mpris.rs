@@ -0,0 +1,473 @@
+use afx_core::model::*;
+use parking_lot::RwLock;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use tracing::warn;
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.afx";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// The item shown as "now playing": the first playing item, or failing that
+/// the first paused one.
+fn now_playing(model: &Model) -> Option<&Item> {
+    let with_status = |status| model.items.iter().find(|item| item.status == status);
+    with_status(ItemStatus::Playing).or_else(|| with_status(ItemStatus::Paused))
+}
+
+/// What the media controls show about playback.
+#[derive(Debug, Clone, PartialEq)]
+struct Playback {
+    /// One of "Playing", "Paused" and "Stopped".
+    status: &'static str,
+    looped: bool,
+    /// The id, name and duration of the item playing.
+    track: Option<(u64, String, f64)>,
+    position: f64,
+    volume: f64,
+    can_go_next: bool,
+    can_play: bool,
+}
+
+fn playback(model: &Model) -> Playback {
+    let playing = model
+        .items
+        .iter()
+        .any(|item| matches!(item.status, ItemStatus::Playing | ItemStatus::Loading));
+    let item = now_playing(model);
+    Playback {
+        status: match item {
+            _ if playing => "Playing",
+            Some(_) => "Paused",
+            None => "Stopped",
+        },
+        looped: item.is_some_and(Item::looped),
+        track: item.map(|item| (item.id, item.name.clone(), item.duration)),
+        position: item.map_or(0.0, |item| item.position),
+        volume: db_to_amplitude(model.master_gain_db),
+        can_go_next: model.standby_cue().is_some(),
+        can_play: item.is_some() || model.standby_cue().is_some(),
+    }
+}
+
+/// Carry out a method of the player interface. Pausing pauses everything,
+/// playing resumes whatever was paused or otherwise fires the standby cue,
+/// and next always fires the standby cue.
+fn player_command(member: &str, model: &mut Model) -> Option<Vec<ControlMessage>> {
+    let playing = model
+        .items
+        .iter()
+        .any(|item| item.status == ItemStatus::Playing);
+    let resume = |model: &mut Model| {
+        let paused: Vec<_> = model
+            .items
+            .iter()
+            .filter(|item| item.status == ItemStatus::Paused)
+            .map(|item| ControlMessage::Play(item.id))
+            .collect();
+        if paused.is_empty() {
+            model.fire_standby().into_iter().collect()
+        } else {
+            paused
+        }
+    };
+
+    Some(match member {
+        "Pause" => vec![ControlMessage::GlobalPause],
+        "PlayPause" if playing => vec![ControlMessage::GlobalPause],
+        "Play" if playing => vec![],
+        "Play" | "PlayPause" => resume(model),
+        "Next" => model.fire_standby().into_iter().collect(),
+        "Stop" => vec![ControlMessage::GlobalStop],
+        "Previous" | "Seek" | "SetPosition" | "OpenUri" => vec![],
+        _ => return None,
+    })
+}
+
+#[cfg(target_os = "macos")]
+mod now_playing;
+#[cfg(target_os = "windows")]
+mod smtc;
+
+/// Expose the MPRIS interface on the session bus so that media keys and
+/// desktop media controls work with afx.
+#[cfg(target_os = "linux")]
+pub fn start(
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    _cc: &eframe::CreationContext,
+) {
+    std::thread::spawn(move || {
+        if let Err(err) = bus::serve(model, channel) {
+            warn!("media controls are unavailable: {}", err);
+        }
+    });
+}
+
+/// Take media keys and show the item playing in the media overlay of Windows
+/// through the system media transport controls of the window.
+#[cfg(target_os = "windows")]
+pub fn start(
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    cc: &eframe::CreationContext,
+) {
+    if let Err(err) = smtc::start(model, channel, cc) {
+        warn!("media controls are unavailable: {:#}", err);
+    }
+}
+
+/// Take media keys and show the item playing in Now Playing on macOS.
+#[cfg(target_os = "macos")]
+pub fn start(
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    _cc: &eframe::CreationContext,
+) {
+    now_playing::start(model, channel);
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+pub fn start(
+    _model: Arc<RwLock<Model>>,
+    _channel: Sender<ControlMessage>,
+    _cc: &eframe::CreationContext,
+) {
+    warn!("media controls aren't supported on this platform");
+}
+
+#[cfg(target_os = "linux")]
+mod bus {
+    use super::*;
+    use afx_core::engine::PLAYBACK_SYNC_INTERVAL;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use zbus::fdo;
+    use zbus::zvariant::{ObjectPath, Value};
+
+    const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+    struct Root;
+
+    #[zbus::interface(name = "org.mpris.MediaPlayer2")]
+    impl Root {
+        fn raise(&self) {}
+
+        fn quit(&self) {}
+
+        #[zbus(property)]
+        fn can_quit(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn can_raise(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn has_track_list(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn identity(&self) -> String {
+            "afx".to_string()
+        }
+
+        #[zbus(property)]
+        fn supported_uri_schemes(&self) -> Vec<String> {
+            vec![]
+        }
+
+        #[zbus(property)]
+        fn supported_mime_types(&self) -> Vec<String> {
+            vec![]
+        }
+    }
+
+    pub(super) struct Player {
+        pub(super) model: Arc<RwLock<Model>>,
+        pub(super) channel: Sender<ControlMessage>,
+    }
+
+    impl Player {
+        fn playback(&self) -> Playback {
+            playback(&self.model.read())
+        }
+
+        fn run(&self, member: &str) -> fdo::Result<()> {
+            let commands = player_command(member, &mut self.model.write())
+                .ok_or_else(|| fdo::Error::UnknownMethod(format!("{} isn't supported", member)))?;
+            for command in commands {
+                self.channel.send(command).unwrap();
+            }
+            Ok(())
+        }
+
+        /// The properties announced whenever they change. Clients poll the
+        /// position.
+        fn announced(&self) -> Vec<(&'static str, Value<'static>)> {
+            vec![
+                ("PlaybackStatus", self.playback_status().into()),
+                ("LoopStatus", self.loop_status().into()),
+                ("Metadata", self.metadata().into()),
+                ("Volume", self.volume().into()),
+                ("CanGoNext", self.can_go_next().into()),
+                ("CanPlay", self.can_play().into()),
+            ]
+        }
+    }
+
+    #[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl Player {
+        fn next(&self) -> fdo::Result<()> {
+            self.run("Next")
+        }
+
+        fn previous(&self) -> fdo::Result<()> {
+            self.run("Previous")
+        }
+
+        pub(super) fn pause(&self) -> fdo::Result<()> {
+            self.run("Pause")
+        }
+
+        fn play_pause(&self) -> fdo::Result<()> {
+            self.run("PlayPause")
+        }
+
+        fn stop(&self) -> fdo::Result<()> {
+            self.run("Stop")
+        }
+
+        fn play(&self) -> fdo::Result<()> {
+            self.run("Play")
+        }
+
+        fn seek(&self, _offset: i64) -> fdo::Result<()> {
+            self.run("Seek")
+        }
+
+        fn set_position(&self, _track: ObjectPath<'_>, _position: i64) -> fdo::Result<()> {
+            self.run("SetPosition")
+        }
+
+        fn open_uri(&self, _uri: &str) -> fdo::Result<()> {
+            self.run("OpenUri")
+        }
+
+        #[zbus(property)]
+        pub(super) fn playback_status(&self) -> String {
+            self.playback().status.to_string()
+        }
+
+        #[zbus(property)]
+        fn loop_status(&self) -> String {
+            if self.playback().looped {
+                "Track"
+            } else {
+                "None"
+            }
+            .to_string()
+        }
+
+        #[zbus(property)]
+        fn rate(&self) -> f64 {
+            1.0
+        }
+
+        #[zbus(property)]
+        pub(super) fn metadata(&self) -> HashMap<String, Value<'static>> {
+            let (path, track) = match self.playback().track {
+                Some((id, name, duration)) => (format!("/afx/item/{}", id), Some((name, duration))),
+                None => (
+                    "/org/mpris/MediaPlayer2/TrackList/NoTrack".to_string(),
+                    None,
+                ),
+            };
+            let mut metadata = HashMap::new();
+            metadata.insert(
+                "mpris:trackid".to_string(),
+                ObjectPath::from_string_unchecked(path).into(),
+            );
+            if let Some((name, duration)) = track {
+                metadata.insert("mpris:length".to_string(), micros(duration).into());
+                metadata.insert("xesam:title".to_string(), name.into());
+            }
+            metadata
+        }
+
+        #[zbus(property)]
+        fn volume(&self) -> f64 {
+            self.playback().volume
+        }
+
+        #[zbus(property)]
+        pub(super) fn set_volume(&self, volume: f64) {
+            let volume = volume.max(0.0);
+            self.model.write().master_gain_db = amplitude_to_db(volume);
+            self.channel
+                .send(ControlMessage::SetMasterVolume(volume))
+                .unwrap();
+        }
+
+        #[zbus(property)]
+        pub(super) fn position(&self) -> i64 {
+            micros(self.playback().position)
+        }
+
+        #[zbus(property)]
+        fn minimum_rate(&self) -> f64 {
+            1.0
+        }
+
+        #[zbus(property)]
+        fn maximum_rate(&self) -> f64 {
+            1.0
+        }
+
+        #[zbus(property)]
+        fn can_go_next(&self) -> bool {
+            self.playback().can_go_next
+        }
+
+        #[zbus(property)]
+        fn can_go_previous(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn can_play(&self) -> bool {
+            self.playback().can_play
+        }
+
+        #[zbus(property)]
+        fn can_pause(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_seek(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn can_control(&self) -> bool {
+            true
+        }
+    }
+
+    fn micros(seconds: f64) -> i64 {
+        (seconds * 1e6) as i64
+    }
+
+    pub(super) fn serve(
+        model: Arc<RwLock<Model>>,
+        channel: Sender<ControlMessage>,
+    ) -> zbus::Result<()> {
+        let connection = zbus::blocking::connection::Builder::session()?
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, Root)?
+            .serve_at(OBJECT_PATH, Player { model, channel })?
+            .build()?;
+        let player = connection
+            .object_server()
+            .interface::<_, Player>(OBJECT_PATH)?;
+
+        let mut reported = player.get().announced();
+        loop {
+            std::thread::sleep(Duration::from_millis(PLAYBACK_SYNC_INTERVAL * 5));
+            let properties = player.get().announced();
+            if properties != reported {
+                let changed: HashMap<_, _> = properties
+                    .iter()
+                    .filter(|property| !reported.contains(property))
+                    .cloned()
+                    .collect();
+                connection.emit_signal(
+                    None::<&str>,
+                    OBJECT_PATH,
+                    "org.freedesktop.DBus.Properties",
+                    "PropertiesChanged",
+                    &(PLAYER_INTERFACE, changed, Vec::<String>::new()),
+                )?;
+                reported = properties;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use afx_core::headless::TestModel;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn media_keys() {
+        let mut model = TestModel::new(3, 2.0)
+            .playlist(5, vec![1, 2])
+            .select(5)
+            .build();
+        assert_eq!(
+            player_command("Next", &mut model),
+            Some(vec![ControlMessage::Cue {
+                playlist_id: 5,
+                position: 0
+            }])
+        );
+        assert_eq!(model.playlists[0].standby, 1);
+
+        model.items[1].status = ItemStatus::Playing;
+        assert_eq!(
+            player_command("PlayPause", &mut model),
+            Some(vec![ControlMessage::GlobalPause])
+        );
+        model.items[1].status = ItemStatus::Paused;
+        assert_eq!(
+            player_command("PlayPause", &mut model),
+            Some(vec![ControlMessage::Play(1)])
+        );
+
+        model.items[1].status = ItemStatus::Stopped;
+        assert_eq!(
+            player_command("Play", &mut model),
+            Some(vec![ControlMessage::Cue {
+                playlist_id: 5,
+                position: 1
+            }])
+        );
+        assert_eq!(player_command("Next", &mut model), Some(vec![]));
+        assert_eq!(player_command("Dance", &mut model), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn properties() {
+        let mut model = TestModel::new(3, 2.0)
+            .playlist(5, vec![1, 2])
+            .select(5)
+            .build();
+        model.items[2].status = ItemStatus::Playing;
+        model.items[2].position = 1.5;
+        let (tx, rx) = channel();
+        let player = bus::Player {
+            model: Arc::new(RwLock::new(model)),
+            channel: tx,
+        };
+
+        assert_eq!(player.playback_status(), "Playing");
+        assert_eq!(player.position(), 1_500_000);
+        assert_eq!(
+            player.metadata()["mpris:trackid"],
+            zbus::zvariant::ObjectPath::from_static_str_unchecked("/afx/item/2").into()
+        );
+
+        player.pause().unwrap();
+        assert_eq!(rx.try_recv(), Ok(ControlMessage::GlobalPause));
+
+        player.set_volume(1.0);
+        assert_eq!(rx.try_recv(), Ok(ControlMessage::SetMasterVolume(1.0)));
+        assert_eq!(player.model.read().master_gain_db, 0.0);
+    }
+}
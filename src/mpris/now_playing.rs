@@ -0,0 +1,119 @@
+//! Now Playing on macOS, standing in for MPRIS: media keys and the media
+//! controls of the system reach afx as remote commands, and the item playing
+//! is shown in the now playing info.
+
+use super::*;
+use afx_core::engine::PLAYBACK_SYNC_INTERVAL;
+use block2::RcBlock;
+use objc2::rc::Retained;
+use objc2::runtime::AnyObject;
+use objc2::{class, msg_send, msg_send_id};
+use objc2_foundation::{NSNumber, NSString};
+use std::time::Duration;
+
+// values of MPNowPlayingPlaybackState
+const PLAYING: usize = 1;
+const PAUSED: usize = 2;
+const STOPPED: usize = 3;
+
+// values of MPRemoteCommandHandlerStatus
+const SUCCESS: isize = 0;
+const COMMAND_FAILED: isize = 200;
+
+#[link(name = "MediaPlayer", kind = "framework")]
+extern "C" {
+    static MPMediaItemPropertyTitle: &'static NSString;
+    static MPMediaItemPropertyPlaybackDuration: &'static NSString;
+    static MPNowPlayingInfoPropertyElapsedPlaybackTime: &'static NSString;
+    static MPNowPlayingInfoPropertyPlaybackRate: &'static NSString;
+}
+
+/// Handle the remote commands and keep the now playing info up to date on a
+/// thread of its own. Must be called on the main thread, which the commands
+/// are delivered on.
+pub(super) fn start(model: Arc<RwLock<Model>>, channel: Sender<ControlMessage>) {
+    let commands: [(Retained<AnyObject>, &'static str); 5] = unsafe {
+        let center: Retained<AnyObject> =
+            msg_send_id![class!(MPRemoteCommandCenter), sharedCommandCenter];
+        [
+            (msg_send_id![&center, playCommand], "Play"),
+            (msg_send_id![&center, pauseCommand], "Pause"),
+            (msg_send_id![&center, togglePlayPauseCommand], "PlayPause"),
+            (msg_send_id![&center, stopCommand], "Stop"),
+            (msg_send_id![&center, nextTrackCommand], "Next"),
+        ]
+    };
+    for (command, member) in commands {
+        let (model, channel) = (model.clone(), channel.clone());
+        let handler = RcBlock::new(move |_event: *mut AnyObject| -> isize {
+            let Some(commands) = player_command(member, &mut model.write()) else {
+                return COMMAND_FAILED;
+            };
+            for command in commands {
+                if channel.send(command).is_err() {
+                    return COMMAND_FAILED;
+                }
+            }
+            SUCCESS
+        });
+        unsafe {
+            let _: () = msg_send![&command, setEnabled: true];
+            // the command keeps the handler for as long as afx runs, the
+            // target returned only serves to remove it
+            let _: Retained<AnyObject> = msg_send_id![&command, addTargetWithHandler: &*handler];
+        }
+    }
+
+    std::thread::spawn(move || {
+        let mut reported = None;
+        loop {
+            let current = playback(&model.read());
+            // the system moves the position on by itself while playing
+            let shown = Playback {
+                position: 0.0,
+                ..current.clone()
+            };
+            if reported.as_ref() != Some(&shown) {
+                unsafe { show(&current) };
+                reported = Some(shown);
+            }
+            std::thread::sleep(Duration::from_millis(PLAYBACK_SYNC_INTERVAL * 5));
+        }
+    });
+}
+
+/// Put the state of playback into the now playing info.
+unsafe fn show(playback: &Playback) {
+    let center: Retained<AnyObject> = msg_send_id![class!(MPNowPlayingInfoCenter), defaultCenter];
+    let state = match playback.status {
+        "Playing" => PLAYING,
+        "Paused" => PAUSED,
+        _ => STOPPED,
+    };
+    let _: () = msg_send![&center, setPlaybackState: state];
+
+    let Some((_, name, duration)) = &playback.track else {
+        let _: () = msg_send![&center, setNowPlayingInfo: std::ptr::null::<AnyObject>()];
+        return;
+    };
+    let rate = if playback.status == "Playing" {
+        1.0
+    } else {
+        0.0
+    };
+    let info: Retained<AnyObject> = msg_send_id![class!(NSMutableDictionary), new];
+    let title = NSString::from_str(name);
+    let _: () = msg_send![&info, setObject: &*title, forKey: MPMediaItemPropertyTitle];
+    let duration = NSNumber::new_f64(*duration);
+    let _: () =
+        msg_send![&info, setObject: &*duration, forKey: MPMediaItemPropertyPlaybackDuration];
+    let elapsed = NSNumber::new_f64(playback.position);
+    let _: () = msg_send![
+        &info,
+        setObject: &*elapsed,
+        forKey: MPNowPlayingInfoPropertyElapsedPlaybackTime
+    ];
+    let rate = NSNumber::new_f64(rate);
+    let _: () = msg_send![&info, setObject: &*rate, forKey: MPNowPlayingInfoPropertyPlaybackRate];
+    let _: () = msg_send![&center, setNowPlayingInfo: &*info];
+}
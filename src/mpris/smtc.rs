@@ -0,0 +1,111 @@
+//! The system media transport controls of Windows, standing in for MPRIS:
+//! media keys and the media overlay control afx through them.
+
+use super::*;
+use afx_core::engine::PLAYBACK_SYNC_INTERVAL;
+use anyhow::{bail, Result};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::time::Duration;
+use windows::core::HSTRING;
+use windows::Foundation::TypedEventHandler;
+use windows::Media::{
+    MediaPlaybackAutoRepeatMode, MediaPlaybackStatus, MediaPlaybackType,
+    SystemMediaTransportControls, SystemMediaTransportControlsButton,
+    SystemMediaTransportControlsButtonPressedEventArgs,
+};
+use windows::Win32::Foundation::HWND;
+use windows::Win32::System::WinRT::{
+    ISystemMediaTransportControlsInterop, RoInitialize, RO_INIT_MULTITHREADED,
+};
+
+/// Take the controls of the window and keep them up to date on a thread of
+/// their own.
+pub(super) fn start(
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    cc: &eframe::CreationContext,
+) -> Result<()> {
+    let RawWindowHandle::Win32(window) = cc.window_handle()?.as_raw() else {
+        bail!("the window isn't a Win32 window");
+    };
+    let interop = windows::core::factory::<
+        SystemMediaTransportControls,
+        ISystemMediaTransportControlsInterop,
+    >()?;
+    let controls: SystemMediaTransportControls =
+        unsafe { interop.GetForWindow(HWND(window.hwnd.get()))? };
+    controls.SetIsEnabled(true)?;
+    controls.SetIsPauseEnabled(true)?;
+    controls.SetIsStopEnabled(true)?;
+
+    let handler_model = model.clone();
+    controls.ButtonPressed(&TypedEventHandler::<
+        SystemMediaTransportControls,
+        SystemMediaTransportControlsButtonPressedEventArgs,
+    >::new(
+        move |_, args: &Option<SystemMediaTransportControlsButtonPressedEventArgs>| {
+            let Some(args) = args else {
+                return Ok(());
+            };
+            let member = match args.Button()? {
+                SystemMediaTransportControlsButton::Play => "Play",
+                SystemMediaTransportControlsButton::Pause => "Pause",
+                SystemMediaTransportControlsButton::Stop => "Stop",
+                SystemMediaTransportControlsButton::Next => "Next",
+                SystemMediaTransportControlsButton::Previous => "Previous",
+                _ => return Ok(()),
+            };
+            let commands = player_command(member, &mut handler_model.write());
+            for command in commands.unwrap_or_default() {
+                let _ = channel.send(command);
+            }
+            Ok(())
+        },
+    ))?;
+
+    std::thread::spawn(move || {
+        // the controls can be used from any thread in a COM apartment
+        let _ = unsafe { RoInitialize(RO_INIT_MULTITHREADED) };
+        if let Err(err) = update(&controls, &model) {
+            warn!("media controls stopped updating: {}", err);
+        }
+    });
+    Ok(())
+}
+
+/// Show what's playing whenever it changes. The overlay has no use for the
+/// position.
+fn update(controls: &SystemMediaTransportControls, model: &RwLock<Model>) -> Result<()> {
+    let updater = controls.DisplayUpdater()?;
+    let mut reported = None;
+    loop {
+        let current = Playback {
+            position: 0.0,
+            ..playback(&model.read())
+        };
+        if reported.as_ref() != Some(&current) {
+            controls.SetPlaybackStatus(match current.status {
+                "Playing" => MediaPlaybackStatus::Playing,
+                "Paused" => MediaPlaybackStatus::Paused,
+                _ => MediaPlaybackStatus::Stopped,
+            })?;
+            controls.SetAutoRepeatMode(if current.looped {
+                MediaPlaybackAutoRepeatMode::Track
+            } else {
+                MediaPlaybackAutoRepeatMode::None
+            })?;
+            controls.SetIsPlayEnabled(current.can_play)?;
+            controls.SetIsNextEnabled(current.can_go_next)?;
+            match &current.track {
+                Some((_, name, _)) => {
+                    updater.SetType(MediaPlaybackType::Music)?;
+                    updater.MusicProperties()?.SetTitle(&HSTRING::from(name))?;
+                }
+                None => updater.ClearAll()?,
+            }
+            updater.Update()?;
+            reported = Some(current);
+        }
+        std::thread::sleep(Duration::from_millis(PLAYBACK_SYNC_INTERVAL * 5));
+    }
+}
@@ -0,0 +1,590 @@
+use crate::app::SharedModel;
+use afx_core::model::*;
+use anyhow::{bail, Result};
+use parking_lot::RwLock;
+use serde_json::json;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::{Role, WebSocketConfig};
+use tungstenite::{Message, WebSocket};
+
+const PAGE: &str = include_str!("remote.html");
+/// How often connected remotes are checked for changes to push.
+const STATUS_INTERVAL: Duration = Duration::from_millis(100);
+/// How many connections are served at once, page loads included.
+const MAX_CONNECTIONS: usize = 16;
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// The longest request line or header accepted, in bytes.
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+/// The longest message accepted from a remote, which only sends short
+/// commands.
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024;
+/// How often remotes are pinged, which browsers answer by themselves.
+const PING_INTERVAL: Duration = Duration::from_secs(20);
+/// How long a remote may stay silent, pongs included, before it's dropped.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// The number of columns used for playlists without a fixed grid.
+const DEFAULT_COLUMNS: u32 = 4;
+
+/// A running web remote server. Dropping it stops the server.
+pub struct RemoteServer {
+    pub address: SocketAddr,
+    /// The secret the page and its socket have to be asked for with, so
+    /// that only the devices shown the address can fire sounds.
+    pub token: String,
+    running: Arc<AtomicBool>,
+}
+
+impl Drop for RemoteServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        // wake up the listener so that it notices
+        let _ = TcpStream::connect(("127.0.0.1", self.address.port()));
+    }
+}
+
+impl SharedModel {
+    /// Start or stop the web remote server to match the settings.
+    pub fn sync_remote(&mut self, model: &mut Model) {
+        let settings = &model.settings.remote;
+        let wanted = settings.enabled.then_some(settings.port);
+        if self.remote.as_ref().map(|server| server.address.port()) == wanted {
+            return;
+        }
+
+        self.remote = None;
+        model.remote_url = None;
+        let Some(port) = wanted else {
+            return;
+        };
+        match start_server(port, self.model.clone(), self.play_channel.clone()) {
+            Ok(server) => {
+                let ip = local_ip().unwrap_or(server.address.ip());
+                let address = SocketAddr::new(ip, server.address.port());
+                model.remote_url = Some(format!("http://{}/?token={}", address, server.token));
+                self.remote = Some(server);
+            }
+            Err(err) => {
+                model.settings.remote.enabled = false;
+                self.toasts.push(Notification {
+                    message: format!("Failed to start the web remote: {}", err),
+                    item: None,
+                });
+            }
+        }
+    }
+}
+
+/// The address other devices on the network can likely reach this machine at.
+fn local_ip() -> Option<IpAddr> {
+    // connecting a UDP socket sends nothing, but picks the outgoing interface
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+    socket.connect(("192.0.2.1", 80)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// A fresh secret for the remote to be asked for with.
+fn random_token() -> Result<String> {
+    let mut bytes = [0; 16];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Compare secrets in time independent of where they differ.
+pub(crate) fn same_secret(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// A place among the connections a server counts, such as the
+/// [`MAX_CONNECTIONS`], freed once the connection ends.
+pub(crate) struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    /// Take a place if fewer than `limit` are taken.
+    pub(crate) fn take(count: &Arc<AtomicUsize>, limit: usize) -> Option<Self> {
+        if count.fetch_add(1, Ordering::Relaxed) >= limit {
+            count.fetch_sub(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(ConnectionSlot(count.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn start_server(
+    port: u16,
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+) -> Result<RemoteServer> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    let address = listener.local_addr()?;
+    let token = random_token()?;
+    let running = Arc::new(AtomicBool::new(true));
+    info!("web remote listening on {}", address);
+
+    {
+        let running = running.clone();
+        let token = token.clone();
+        let connections = Arc::new(AtomicUsize::new(0));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !running.load(Ordering::Relaxed) {
+                    break;
+                }
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let Some(slot) = ConnectionSlot::take(&connections, MAX_CONNECTIONS) else {
+                    warn!("turned away a web remote connection, too many are open");
+                    let _ = respond(&mut stream, "503 Service Unavailable");
+                    continue;
+                };
+                let model = model.clone();
+                let channel = channel.clone();
+                let running = running.clone();
+                let token = token.clone();
+                std::thread::spawn(move || {
+                    let _slot = slot;
+                    if let Err(err) = handle_connection(stream, &token, model, channel, running) {
+                        warn!("web remote connection failed: {}", err);
+                    }
+                });
+            }
+            info!("web remote on {} stopped", address);
+        });
+    }
+
+    Ok(RemoteServer {
+        address,
+        token,
+        running,
+    })
+}
+
+/// The request line and the headers of an HTTP request.
+struct Request {
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .find(|&(param, _)| param == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Read a line of at most [`MAX_LINE_LENGTH`] bytes, without its line break.
+fn read_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_LINE_LENGTH).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        bail!("request line too long or cut off");
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<Request> {
+    let request_line = read_line(reader)?;
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        path: path.to_string(),
+        query: query.to_string(),
+        headers: vec![],
+    };
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(request);
+        }
+        if request.headers.len() == MAX_HEADERS {
+            bail!("too many headers");
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            request
+                .headers
+                .push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+}
+
+/// Whether the request may use the remote: it has to carry the token, and
+/// sockets opened by web pages have to come from the remote's own page, so
+/// that other sites open in a browser on the network can't fire sounds.
+fn authorised(request: &Request, token: &str) -> bool {
+    let same_origin = match (request.header("origin"), request.header("host")) {
+        (Some(origin), Some(host)) => origin == format!("http://{}", host),
+        (Some(_), None) => false,
+        (None, _) => true,
+    };
+    request
+        .param("token")
+        .is_some_and(|given| same_secret(given, token))
+        && (request.path != "/ws" || same_origin)
+}
+
+fn respond(stream: &mut TcpStream, status: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    )?;
+    Ok(())
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    token: &str,
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    running: Arc<AtomicBool>,
+) -> Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = read_request(&mut reader)?;
+    if !matches!(request.path.as_str(), "/" | "/ws") {
+        return respond(&mut stream, "404 Not Found");
+    }
+    if !authorised(&request, token) {
+        warn!("refused a web remote request without the token or from another site");
+        return respond(&mut stream, "403 Forbidden");
+    }
+
+    match (request.path.as_str(), request.header("sec-websocket-key")) {
+        ("/ws", Some(key)) => websocket(reader, stream, key, model, channel, running),
+        ("/", _) => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\n\
+                Content-Type: text/html; charset=utf-8\r\n\
+                Content-Length: {}\r\n\
+                Connection: close\r\n\r\n{}",
+                PAGE.len(),
+                PAGE
+            )?;
+            Ok(())
+        }
+        _ => respond(&mut stream, "400 Bad Request"),
+    }
+}
+
+fn websocket(
+    reader: BufReader<TcpStream>,
+    mut stream: TcpStream,
+    key: &str,
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    running: Arc<AtomicBool>,
+) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\n\
+        Upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    )?;
+    // waiting for commands only as long as the state of the pads may go
+    // without being pushed
+    stream.set_read_timeout(Some(STATUS_INTERVAL))?;
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_LENGTH),
+        max_frame_size: Some(MAX_MESSAGE_LENGTH),
+        ..Default::default()
+    };
+    let mut socket = WebSocket::from_partially_read(
+        stream,
+        reader.buffer().to_vec(),
+        Role::Server,
+        Some(config),
+    );
+
+    let mut last = String::new();
+    let (mut pinged, mut heard) = (Instant::now(), Instant::now());
+    while running.load(Ordering::Relaxed) {
+        match socket.read() {
+            Ok(message) => {
+                heard = Instant::now();
+                if let Message::Text(command) = message {
+                    handle_command(&command, &model, &channel);
+                }
+            }
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+        // pongs count too, so a remote that stays silent is gone
+        if heard.elapsed() >= IDLE_TIMEOUT {
+            bail!("the remote stopped answering");
+        }
+
+        // push the state of the pads whenever it changes, and ping the
+        // remote now and then to tell whether it's still there
+        let status = status_json(&model.read());
+        if status != last {
+            socket.send(Message::Text(status.clone()))?;
+            last = status;
+        }
+        if pinged.elapsed() >= PING_INTERVAL {
+            socket.send(Message::Ping(vec![]))?;
+            pinged = Instant::now();
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+    Ok(())
+}
+
+/// Act on a command sent by the web page: `toggle <item id>` or `stop`.
+fn handle_command(command: &str, model: &RwLock<Model>, channel: &Sender<ControlMessage>) {
+    let mut words = command.split_whitespace();
+    match (
+        words.next(),
+        words.next().and_then(|id| id.parse::<u64>().ok()),
+    ) {
+        (Some("toggle"), Some(id)) => {
+            let mut model = model.write();
+            if let Some(item) = model.items.iter_mut().find(|item| item.id == id) {
+                item.toggle_playback(channel);
+            }
+        }
+        (Some("stop"), None) => channel.send(ControlMessage::GlobalStop).unwrap(),
+        _ => warn!("unknown web remote command: {}", command),
+    }
+}
+
+/// Describe the pads of the selected playlist (or the whole library) for the
+/// web page. Playlists with a fixed grid show the current bank.
+fn status_json(model: &Model) -> String {
+    let playlist = model
+        .selected_playlist
+        .and_then(|id| model.playlists.iter().find(|p| p.id == id));
+    let find = |id: &u64| model.items.iter().find(|item| item.id == *id);
+
+    let (title, columns, pads): (&str, _, Vec<_>) = match playlist {
+        Some(Playlist {
+            name,
+            grid: Some(grid),
+            ..
+        }) => {
+            let mut cells: Vec<_> = grid
+                .cells
+                .iter()
+                .filter(|(_, cell)| cell.bank == grid.bank)
+                .filter_map(|(id, cell)| Some((find(id)?, cell.column, cell.row)))
+                .collect();
+            cells.sort_by_key(|&(_, column, row)| (row, column));
+            (name, grid.columns, cells)
+        }
+        Some(Playlist { name, items, .. }) => (
+            name,
+            DEFAULT_COLUMNS,
+            in_rows(items.iter().filter_map(find)),
+        ),
+        None => ("Library", DEFAULT_COLUMNS, in_rows(model.items.iter())),
+    };
+
+    let pads: Vec<_> = pads
+        .into_iter()
+        .map(|(item, column, row)| {
+            let [r, g, b, _] = item.colour.to_array();
+            let status = match item.status {
+                ItemStatus::Stopped => "stopped",
+                ItemStatus::Loading => "loading",
+                ItemStatus::Playing => "playing",
+                ItemStatus::Paused => "paused",
+            };
+            json!({
+                "id": item.id,
+                "name": item.name,
+                "colour": format!("#{:02x}{:02x}{:02x}", r, g, b),
+                "status": status,
+                "column": column,
+                "row": row,
+            })
+        })
+        .collect();
+    json!({"title": title, "columns": columns, "pads": pads}).to_string()
+}
+
+fn in_rows<'a>(items: impl Iterator<Item = &'a Item>) -> Vec<(&'a Item, u32, u32)> {
+    items
+        .enumerate()
+        .map(|(i, item)| {
+            let i = i as u32;
+            (item, i % DEFAULT_COLUMNS, i / DEFAULT_COLUMNS)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::Value;
+
+    fn test_server() -> Result<RemoteServer> {
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let model = Arc::new(RwLock::new(Model::default()));
+        start_server(0, model, tx)
+    }
+
+    fn get(server: &RemoteServer, request: &str) -> Result<String> {
+        let mut stream = TcpStream::connect(("127.0.0.1", server.address.port()))?;
+        write!(stream, "{}", request)?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+
+    #[test]
+    fn socket() -> Result<()> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let server = start_server(0, Arc::new(RwLock::new(Model::default())), tx)?;
+        let url = format!(
+            "ws://127.0.0.1:{}/ws?token={}",
+            server.address.port(),
+            server.token
+        );
+        let stream = TcpStream::connect(server.address)?;
+        let (mut socket, _) = tungstenite::client(url, stream)?;
+        let Message::Text(status) = socket.read()? else {
+            panic!("expected the state of the pads");
+        };
+        assert_eq!(serde_json::from_str::<Value>(&status)?["title"], "Library");
+
+        socket.send(Message::Text("stop".to_string()))?;
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5))?,
+            ControlMessage::GlobalStop
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serves_page() -> Result<()> {
+        let server = test_server()?;
+        let request = format!(
+            "GET /?token={} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            server.token
+        );
+        let response = get(&server, &request)?;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("<title>afx remote</title>"));
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_strangers() -> Result<()> {
+        let server = test_server()?;
+        let forbidden = |request: &str| -> Result<bool> {
+            Ok(get(&server, request)?.starts_with("HTTP/1.1 403 Forbidden"))
+        };
+        assert!(forbidden("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")?);
+        assert!(forbidden(
+            "GET /?token=0123 HTTP/1.1\r\nHost: localhost\r\n\r\n"
+        )?);
+        // a page on another site opening a socket with a leaked token
+        let upgrade = |origin: &str| {
+            format!(
+                "GET /ws?token={} HTTP/1.1\r\nHost: 10.0.0.2:8340\r\nOrigin: {}\r\n\
+                Upgrade: websocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+                server.token, origin
+            )
+        };
+        assert!(forbidden(&upgrade("http://example.com"))?);
+
+        let mut stream = TcpStream::connect(("127.0.0.1", server.address.port()))?;
+        write!(stream, "{}", upgrade("http://10.0.0.2:8340"))?;
+        let mut response = [0; 12];
+        stream.read_exact(&mut response)?;
+        assert_eq!(&response, b"HTTP/1.1 101");
+        Ok(())
+    }
+
+    #[test]
+    fn limits_requests() -> Result<()> {
+        let long = format!(
+            "GET /{} HTTP/1.1\r\n\r\n",
+            "a".repeat(MAX_LINE_LENGTH as usize)
+        );
+        assert!(read_request(&mut long.as_bytes()).is_err());
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X-A: b\r\n".repeat(MAX_HEADERS + 1)
+        );
+        assert!(read_request(&mut many.as_bytes()).is_err());
+
+        let server = test_server()?;
+        // connections that never send a request hold their place until they
+        // time out
+        let idle: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(("127.0.0.1", server.address.port())))
+            .collect::<Result<_, _>>()?;
+        // turned away before it gets to send its request
+        let response = get(&server, "")?;
+        assert!(response.starts_with("HTTP/1.1 503"));
+        drop(idle);
+        Ok(())
+    }
+
+    #[test]
+    fn status() {
+        let mut model = Model {
+            items: vec![Item::with_default_stem(
+                3,
+                "say \"hi\"".to_string(),
+                "hi.wav".to_string(),
+                Colour::from_rgb(255, 0, 16),
+                1.0,
+            )],
+            ..Model::default()
+        };
+        model.items[0].status = ItemStatus::Playing;
+        assert_eq!(
+            serde_json::from_str::<Value>(&status_json(&model)).unwrap(),
+            json!({
+                "title": "Library",
+                "columns": 4,
+                "pads": [{
+                    "id": 3,
+                    "name": "say \"hi\"",
+                    "colour": "#ff0010",
+                    "status": "playing",
+                    "column": 0,
+                    "row": 0,
+                }],
+            })
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use afx_core::engine::PLAYBACK_SYNC_INTERVAL;
+use afx_core::model::*;
+use afx_core::script::{Runner, Script};
+use parking_lot::RwLock;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Run the cue script from the settings on a background thread, reloading it
+/// whenever it changes. Errors are stored in the model.
+pub fn start(model: Arc<RwLock<Model>>, channel: Sender<ControlMessage>) {
+    std::thread::spawn(move || {
+        let epoch = Instant::now();
+        let mut source = None;
+        let mut runner = None;
+        loop {
+            std::thread::sleep(Duration::from_millis(PLAYBACK_SYNC_INTERVAL));
+            let mut model = model.write();
+            if source.as_ref() != Some(&model.settings.script) {
+                source = Some(model.settings.script.clone());
+                runner = match Script::parse(&model.settings.script) {
+                    Ok(script) => {
+                        model.script_error = None;
+                        Some(Runner::new(script))
+                    }
+                    Err(err) => {
+                        warn!("failed to load the cue script: {}", err);
+                        model.script_error = Some(err.to_string());
+                        None
+                    }
+                };
+            }
+
+            // a backup leaves the script to its primary while following it
+            let Some(runner) = runner.as_mut().filter(|_| !model.mirroring) else {
+                continue;
+            };
+            let (messages, errors) = runner.update(epoch.elapsed().as_secs_f64(), &mut model);
+            for error in errors {
+                warn!("cue script: {}", error);
+                model.script_error = Some(error);
+            }
+            drop(model);
+            for msg in messages {
+                if channel.send(msg).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
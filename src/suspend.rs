@@ -0,0 +1,128 @@
+use afx_core::model::*;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+/// How often the clocks are compared to tell whether the system slept.
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How far the wall clock has to get ahead of the monotonic clock for the
+/// difference to count as sleep rather than the clock being adjusted.
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(3);
+
+/// Pause playback when the system goes to sleep and reopen the audio output
+/// once it wakes up, since the output often stays silent otherwise.
+pub fn start(channel: Sender<ControlMessage>) {
+    std::thread::spawn(move || {
+        #[cfg(target_os = "linux")]
+        if let Err(err) = watch_logind(&channel) {
+            warn!("falling back to watching the clock for sleep: {}", err);
+        }
+        watch_clock(&channel);
+    });
+}
+
+/// Tell sleep from the wall clock jumping ahead of the monotonic clock, which
+/// stops while the system is suspended. This only notices once the system
+/// is awake again.
+// TODO the monotonic clock keeps going through sleep on Windows, which would
+// need WM_POWERBROADCAST instead
+fn watch_clock(channel: &Sender<ControlMessage>) {
+    let mut last = (Instant::now(), SystemTime::now());
+    loop {
+        std::thread::sleep(CLOCK_CHECK_INTERVAL);
+        let now = (Instant::now(), SystemTime::now());
+        let monotonic = now.0 - last.0;
+        let wall = now.1.duration_since(last.1).unwrap_or_default();
+        last = now;
+        if wall > monotonic + SLEEP_THRESHOLD {
+            info!(
+                "the system slept for about {:.0} s",
+                (wall - monotonic).as_secs_f64()
+            );
+            for msg in [ControlMessage::GlobalPause, ControlMessage::RecoverOutput] {
+                if channel.send(msg).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+const LOGIN_MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+
+/// Whether a message is logind announcing that the system is about to sleep
+/// (`true`) or has just woken up (`false`).
+#[cfg(target_os = "linux")]
+fn sleep_signal(message: &zbus::Message) -> Option<bool> {
+    let header = message.header();
+    if header.message_type() != zbus::message::Type::Signal
+        || header.interface().map(|name| name.as_str()) != Some(LOGIN_MANAGER_INTERFACE)
+        || header.member().map(|name| name.as_str()) != Some("PrepareForSleep")
+    {
+        return None;
+    }
+    message.body().deserialize().ok()
+}
+
+/// Follow the sleep announcements of logind on the system bus, which come
+/// just before the system sleeps.
+#[cfg(target_os = "linux")]
+fn watch_logind(channel: &Sender<ControlMessage>) -> anyhow::Result<()> {
+    let connection = zbus::blocking::Connection::system()?;
+    let manager = zbus::blocking::Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1",
+        LOGIN_MANAGER_INTERFACE,
+    )?;
+    let signals = manager.receive_signal("PrepareForSleep")?;
+    info!("pausing playback whenever the system sleeps");
+
+    for message in signals {
+        let msg = match sleep_signal(&message) {
+            Some(true) => ControlMessage::GlobalPause,
+            Some(false) => ControlMessage::RecoverOutput,
+            None => continue,
+        };
+        info!(
+            "the system is {}",
+            if msg == ControlMessage::GlobalPause {
+                "going to sleep"
+            } else {
+                "awake"
+            }
+        );
+        if channel.send(msg).is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn logind_signals() -> zbus::Result<()> {
+        use super::*;
+        use zbus::Message;
+
+        let path = "/org/freedesktop/login1";
+        let signal = |member, sleeping: bool| {
+            Message::signal(path, LOGIN_MANAGER_INTERFACE, member)?.build(&sleeping)
+        };
+        assert_eq!(sleep_signal(&signal("PrepareForSleep", true)?), Some(true));
+        assert_eq!(
+            sleep_signal(&signal("PrepareForSleep", false)?),
+            Some(false)
+        );
+        assert_eq!(sleep_signal(&signal("PrepareForShutdown", true)?), None);
+
+        let call = Message::method_call(path, "PrepareForSleep")?
+            .interface(LOGIN_MANAGER_INTERFACE)?
+            .build(&true)?;
+        assert_eq!(sleep_signal(&call), None);
+        Ok(())
+    }
+}
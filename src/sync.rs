@@ -0,0 +1,703 @@
+use crate::app::SharedModel;
+use crate::remote::{same_secret, ConnectionSlot};
+use afx_core::engine::forward;
+use afx_core::model::*;
+use afx_core::persistence::{deserialize, serialize};
+use anyhow::{bail, Result};
+use hmac::{Hmac, Mac};
+use parking_lot::{Mutex, RwLock};
+use sha2::Sha256;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How often the primary checks the model for changes to send to backups.
+/// Backups are pinged at least this often too.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a backup waits before reconnecting to a primary it lost.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+/// How long either side waits for the other during the handshake.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+/// The longest greeting the primary reads from a backup.
+const MAX_HELLO_LENGTH: u64 = 1024;
+/// How many backups may be connected or still shaking hands at once.
+const MAX_CONNECTIONS: usize = 16;
+/// The length of the nonces exchanged in the handshake, in bytes.
+const NONCE_LENGTH: usize = 32;
+
+/// The lines waiting to be written to each connected backup.
+pub type Backups = Arc<Mutex<Vec<Sender<String>>>>;
+
+/// Pass control messages on to the playback thread, mirroring them to the
+/// connected backups on the way. Each side syncs its own playback status and
+/// looks after its own output.
+pub fn relay(
+    rx: Receiver<ControlMessage>,
+    engine: SyncSender<ControlMessage>,
+    model: &Arc<RwLock<Model>>,
+    backups: Backups,
+) {
+    forward(rx, engine, model, |msg| {
+        let local = matches!(
+            msg,
+            ControlMessage::SyncPlaybackStatus | ControlMessage::RecoverOutput
+        );
+        if local {
+            return;
+        }
+        let mut backups = backups.lock();
+        if !backups.is_empty() {
+            match serialize(msg) {
+                Ok(encoded) => {
+                    let line = format!("cmd {}", encoded);
+                    backups.retain(|backup| backup.send(line.clone()).is_ok());
+                }
+                Err(err) => warn!("failed to mirror {:?}: {}", msg, err),
+            }
+        }
+    });
+}
+
+/// A running primary server or backup client. Dropping it stops it.
+pub struct SyncLink {
+    settings: SyncSettings,
+    /// The port a primary is listening on.
+    port: u16,
+    running: Arc<AtomicBool>,
+    /// The connection of a backup to its primary.
+    connection: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl Drop for SyncLink {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(stream) = self.connection.lock().take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        if self.settings.role == SyncRole::Primary {
+            // wake up the listener so that it notices
+            let _ = TcpStream::connect(("127.0.0.1", self.port));
+        }
+    }
+}
+
+impl SharedModel {
+    /// Start or stop the primary/backup link to match the settings.
+    pub fn sync_link(&mut self, model: &mut Model) {
+        let settings = &model.settings.sync;
+        let wanted = (settings.role != SyncRole::Off).then_some(settings);
+        if self.sync.as_ref().map(|link| &link.settings) == wanted {
+            return;
+        }
+
+        self.sync = None;
+        model.sync_status = None;
+        model.mirroring = false;
+        let link = match model.settings.sync.role {
+            SyncRole::Off => return,
+            SyncRole::Primary => start_primary(
+                &model.settings.sync,
+                self.model.clone(),
+                self.backups.clone(),
+            ),
+            SyncRole::Backup => start_backup(
+                &model.settings.sync,
+                self.model.clone(),
+                self.play_channel.clone(),
+            ),
+        };
+        match link {
+            Ok(link) => self.sync = Some(link),
+            Err(err) => {
+                model.settings.sync.role = SyncRole::Off;
+                self.toasts.push(Notification {
+                    message: format!("Failed to start syncing: {}", err),
+                    item: None,
+                });
+            }
+        }
+    }
+}
+
+fn start_primary(
+    settings: &SyncSettings,
+    model: Arc<RwLock<Model>>,
+    backups: Backups,
+) -> Result<SyncLink> {
+    if settings.secret.is_empty() {
+        bail!("the shared secret is missing");
+    }
+    let listener = TcpListener::bind(("0.0.0.0", settings.port))?;
+    let port = listener.local_addr()?.port();
+    let running = Arc::new(AtomicBool::new(true));
+    let connected = Arc::new(AtomicUsize::new(0));
+    let connections = Arc::new(AtomicUsize::new(0));
+    info!("serving backups on port {}", port);
+
+    let report = move |model: &RwLock<Model>, connected: usize| {
+        model.write().sync_status = Some(match connected {
+            0 => format!("Waiting for backups on port {}", port),
+            1 => "1 backup connected".to_string(),
+            n => format!("{} backups connected", n),
+        });
+    };
+    report(&model, 0);
+
+    {
+        let running = running.clone();
+        let secret = settings.secret.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !running.load(Ordering::Relaxed) {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let Some(slot) = ConnectionSlot::take(&connections, MAX_CONNECTIONS) else {
+                    warn!("turned away a backup, too many are connected");
+                    continue;
+                };
+                let model = model.clone();
+                let backups = backups.clone();
+                let running = running.clone();
+                let connected = connected.clone();
+                let secret = secret.clone();
+                std::thread::spawn(move || {
+                    let _slot = slot;
+                    let session = match check_backup(&stream, &secret) {
+                        Ok(session) => session,
+                        Err(err) => {
+                            warn!("turned away a backup: {}", err);
+                            return;
+                        }
+                    };
+                    report(&model, connected.fetch_add(1, Ordering::Relaxed) + 1);
+                    if let Err(err) = serve_backup(stream, session, &model, &backups, &running) {
+                        warn!("lost a backup: {}", err);
+                    }
+                    if running.load(Ordering::Relaxed) {
+                        report(&model, connected.fetch_sub(1, Ordering::Relaxed) - 1);
+                    }
+                });
+            }
+            info!("stopped serving backups on port {}", port);
+        });
+    }
+
+    Ok(SyncLink {
+        settings: settings.clone(),
+        port,
+        running,
+        connection: Default::default(),
+    })
+}
+
+/// A random hex string, used once.
+fn nonce() -> Result<String> {
+    let mut bytes = [0; NONCE_LENGTH];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(hex(&bytes))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The HMAC-SHA256 of the parts put together, in hex.
+fn sign(key: &str, parts: &[&[u8]]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC takes keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    hex(&mac.finalize().into_bytes())
+}
+
+/// Seals the lines the primary sends to a backup with a key only the two of
+/// them know. The lines are numbered, so that none can be replayed or
+/// dropped unnoticed either.
+struct Session {
+    key: String,
+    sequence: u64,
+}
+
+impl Session {
+    fn new(secret: &str, challenge: &str, nonce: &str) -> Self {
+        Session {
+            key: sign(
+                secret,
+                &[b"session", challenge.as_bytes(), nonce.as_bytes()],
+            ),
+            sequence: 0,
+        }
+    }
+
+    fn tag(&mut self, line: &str) -> String {
+        let tag = sign(&self.key, &[&self.sequence.to_be_bytes(), line.as_bytes()]);
+        self.sequence += 1;
+        tag
+    }
+
+    fn seal(&mut self, line: &str) -> String {
+        format!("{} {}", line, self.tag(line))
+    }
+
+    /// The line that was sealed, if it's the one expected next.
+    fn open<'a>(&mut self, sealed: &'a str) -> Result<&'a str> {
+        match sealed.rsplit_once(' ') {
+            Some((line, tag)) if same_secret(tag, &self.tag(line)) => Ok(line),
+            _ => bail!("a message from the primary failed authentication"),
+        }
+    }
+}
+
+/// Make sure that a backup knows the shared secret before it's sent
+/// anything, by having it sign a fresh challenge, and prove in turn that
+/// this primary knows it too. The secret itself never crosses the network.
+fn check_backup(stream: &TcpStream, secret: &str) -> Result<Session> {
+    stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+    let challenge = nonce()?;
+    let mut writer = stream;
+    writeln!(writer, "challenge {}", challenge)?;
+    let mut line = String::new();
+    BufReader::new(stream.take(MAX_HELLO_LENGTH)).read_line(&mut line)?;
+    let hello = line
+        .trim_end()
+        .strip_prefix("hello ")
+        .and_then(|hello| hello.split_once(' '));
+    match hello {
+        Some((nonce, proof))
+            if same_secret(
+                proof,
+                &sign(secret, &[b"backup", challenge.as_bytes(), nonce.as_bytes()]),
+            ) =>
+        {
+            let proof = sign(
+                secret,
+                &[b"primary", nonce.as_bytes(), challenge.as_bytes()],
+            );
+            writeln!(writer, "welcome {}", proof)?;
+            Ok(Session::new(secret, &challenge, nonce))
+        }
+        _ => bail!(
+            "{} didn't prove it knows the shared secret",
+            stream.peer_addr()?
+        ),
+    }
+}
+
+/// Send the model to a backup whenever it changes, along with the commands
+/// mirrored by [`relay`].
+fn serve_backup(
+    mut stream: TcpStream,
+    mut session: Session,
+    model: &RwLock<Model>,
+    backups: &Backups,
+    running: &AtomicBool,
+) -> Result<()> {
+    info!("backup {} connected", stream.peer_addr()?);
+    stream.set_nodelay(true)?;
+    // a backup that stopped reading is as good as gone
+    stream.set_write_timeout(Some(3 * SNAPSHOT_INTERVAL))?;
+
+    let (tx, rx) = channel();
+    backups.lock().push(tx);
+    let mut sent = None;
+    let mut checked: Option<Instant> = None;
+    while running.load(Ordering::Relaxed) {
+        let mut idle = true;
+        if checked.is_none_or(|at| at.elapsed() >= SNAPSHOT_INTERVAL) {
+            checked = Some(Instant::now());
+            let snapshot = {
+                let model = model.read();
+                (sent != Some(model.revision))
+                    .then(|| serialize(&*model).map(|encoded| (model.revision, encoded)))
+                    .transpose()?
+            };
+            if let Some((revision, encoded)) = snapshot {
+                writeln!(stream, "{}", session.seal(&format!("model {}", encoded)))?;
+                sent = Some(revision);
+                idle = false;
+            }
+        }
+        match rx.recv_timeout(SNAPSHOT_INTERVAL) {
+            Ok(line) => {
+                writeln!(stream, "{}", session.seal(&line))?;
+                for line in rx.try_iter() {
+                    writeln!(stream, "{}", session.seal(&line))?;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if idle => writeln!(stream, "{}", session.seal("ping"))?,
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(())
+}
+
+fn start_backup(
+    settings: &SyncSettings,
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+) -> Result<SyncLink> {
+    let primary = settings.primary.trim();
+    if primary.is_empty() {
+        bail!("the address of the primary is missing");
+    }
+    if settings.secret.is_empty() {
+        bail!("the shared secret is missing");
+    }
+    let address = if primary.contains(':') {
+        primary.to_string()
+    } else {
+        format!("{}:{}", primary, settings.port)
+    };
+    let running = Arc::new(AtomicBool::new(true));
+    let connection: Arc<Mutex<Option<TcpStream>>> = Default::default();
+
+    {
+        let running = running.clone();
+        let connection = connection.clone();
+        let secret = settings.secret.clone();
+        std::thread::spawn(move || {
+            let report = |status: String, mirroring: bool| {
+                if running.load(Ordering::Relaxed) {
+                    let mut model = model.write();
+                    model.sync_status = Some(status);
+                    model.mirroring = mirroring;
+                }
+            };
+            while running.load(Ordering::Relaxed) {
+                match TcpStream::connect(&address) {
+                    Ok(stream) => {
+                        *connection.lock() = stream.try_clone().ok();
+                        let mut reader = BufReader::new(stream);
+                        match greet(&mut reader, &secret) {
+                            Ok(session) => {
+                                info!("following the primary at {}", address);
+                                report(format!("Mirroring the primary at {}", address), true);
+                                let err = follow(reader, session, &model, &channel).unwrap_err();
+                                warn!("lost the primary at {}: {}", address, err);
+                                report(
+                                    format!("Lost the primary at {}, carrying on alone", address),
+                                    false,
+                                );
+                            }
+                            Err(err) => {
+                                warn!("failed to join the primary at {}: {}", address, err);
+                                report(
+                                    format!("Can't join the primary at {}: {}", address, err),
+                                    false,
+                                );
+                            }
+                        }
+                    }
+                    Err(err) => report(
+                        format!("Can't reach the primary at {}: {}", address, err),
+                        false,
+                    ),
+                }
+                std::thread::sleep(RECONNECT_INTERVAL);
+            }
+        });
+    }
+
+    Ok(SyncLink {
+        settings: settings.clone(),
+        port: settings.port,
+        running,
+        connection,
+    })
+}
+
+/// Answer the challenge of the primary, see [`check_backup`], and make sure
+/// that the primary knows the shared secret too.
+fn greet(reader: &mut BufReader<TcpStream>, secret: &str) -> Result<Session> {
+    reader.get_ref().set_read_timeout(Some(HELLO_TIMEOUT))?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let Some(challenge) = line.trim_end().strip_prefix("challenge ") else {
+        bail!("unexpected greeting from the primary");
+    };
+    let challenge = challenge.to_string();
+    let nonce = nonce()?;
+    let proof = sign(secret, &[b"backup", challenge.as_bytes(), nonce.as_bytes()]);
+    let mut writer = reader.get_ref();
+    writeln!(writer, "hello {} {}", nonce, proof)?;
+
+    line.clear();
+    if reader.read_line(&mut line)? == 0 {
+        bail!("the primary turned this backup away, check the shared secret");
+    }
+    let expected = sign(
+        secret,
+        &[b"primary", nonce.as_bytes(), challenge.as_bytes()],
+    );
+    match line.trim_end().strip_prefix("welcome ") {
+        Some(proof) if same_secret(proof, &expected) => {
+            Ok(Session::new(secret, &challenge, &nonce))
+        }
+        _ => bail!("the primary doesn't know the shared secret"),
+    }
+}
+
+/// Apply what the primary sends until the connection breaks.
+fn follow(
+    reader: BufReader<TcpStream>,
+    mut session: Session,
+    model: &RwLock<Model>,
+    channel: &Sender<ControlMessage>,
+) -> Result<()> {
+    // the primary pings regularly, so silence means it's gone
+    reader
+        .get_ref()
+        .set_read_timeout(Some(3 * SNAPSHOT_INTERVAL))?;
+    for line in reader.lines() {
+        let line = line?;
+        let line = session.open(&line)?;
+        match line.split_once(' ') {
+            Some(("model", encoded)) => mirror(&mut model.write(), deserialize(encoded)?),
+            Some(("cmd", encoded)) => channel.send(deserialize(encoded)?)?,
+            None if line == "ping" => (),
+            _ => bail!("unexpected message from the primary"),
+        }
+    }
+    bail!("the primary closed the connection")
+}
+
+/// Take over the show from the primary: its items, playlists and mixer. The
+/// settings and the view stay local, and so does the status of the items
+/// already known, which is reported by the playback thread of this machine.
+fn mirror(local: &mut Model, mut primary: Model) {
+    for item in primary.items.iter_mut() {
+        match local.items.iter().find(|own| own.id == item.id) {
+            Some(own) => {
+                item.status = own.status.clone();
+                item.position = own.position;
+                item.target_position = own.target_position;
+            }
+            None => {
+                item.status = ItemStatus::Stopped;
+                item.position = 0.0;
+                item.target_position = 0.0;
+            }
+        }
+    }
+    local.items = primary.items;
+    local.playlists = primary.playlists;
+    local.playing_playlist = primary.playing_playlist;
+    local.shuffle = primary.shuffle;
+    local.id_counter.reserve(primary.id_counter.last());
+    local.crossfader = primary.crossfader;
+    local.master_gain_db = primary.master_gain_db;
+    if !local
+        .playlists
+        .iter()
+        .any(|playlist| Some(playlist.id) == local.selected_playlist)
+    {
+        local.selected_playlist = primary.selected_playlist;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use afx_core::engine::ENGINE_CHANNEL_CAPACITY;
+    use afx_core::headless::TestModel;
+    use std::sync::mpsc::sync_channel;
+
+    #[test]
+    fn relay_mirrors_commands() -> Result<()> {
+        let backups = Backups::default();
+        let (backup_tx, backup_rx) = channel();
+        backups.lock().push(backup_tx);
+        let (tx, rx) = channel();
+        let (engine_tx, engine_rx) = sync_channel(ENGINE_CHANNEL_CAPACITY);
+
+        tx.send(ControlMessage::Play(3))?;
+        tx.send(ControlMessage::SyncPlaybackStatus)?;
+        drop(tx);
+        relay(
+            rx,
+            engine_tx,
+            &Arc::new(RwLock::new(Model::default())),
+            backups,
+        );
+
+        let processed: Vec<_> = engine_rx.try_iter().collect();
+        assert_eq!(
+            processed,
+            vec![ControlMessage::Play(3), ControlMessage::SyncPlaybackStatus]
+        );
+        let lines: Vec<_> = backup_rx.try_iter().collect();
+        assert_eq!(lines.len(), 1);
+        let msg: ControlMessage = deserialize(lines[0].strip_prefix("cmd ").unwrap())?;
+        assert_eq!(msg, ControlMessage::Play(3));
+        Ok(())
+    }
+
+    #[test]
+    fn mirror_keeps_local_state() {
+        let mut primary = TestModel::new(2, 2.0).playlist(5, vec![0, 1]).build();
+        primary.items[0].status = ItemStatus::Playing;
+        primary.items[1].status = ItemStatus::Playing;
+        primary.items.push(primary.items[1].clone());
+        primary.items[2].id = 2;
+        primary.master_gain_db = -3.0;
+        primary.selected_playlist = Some(5);
+        primary.settings.touch_mode = true;
+
+        let mut local = TestModel::new(2, 2.0).playlist(5, vec![0, 1]).build();
+        local.items[1].status = ItemStatus::Paused;
+        local.items[1].position = 1.5;
+        local.settings.sync.role = SyncRole::Backup;
+        local.mirroring = true;
+        mirror(&mut local, primary);
+
+        assert_eq!(local.items.len(), 3);
+        assert_eq!(local.items[0].status, ItemStatus::Stopped);
+        assert_eq!(local.items[1].status, ItemStatus::Paused);
+        assert_eq!(local.items[1].position, 1.5);
+        assert_eq!(local.items[2].status, ItemStatus::Stopped);
+        assert_eq!(local.master_gain_db, -3.0);
+        assert_eq!(local.selected_playlist, Some(5));
+        assert!(!local.settings.touch_mode);
+        assert_eq!(local.settings.sync.role, SyncRole::Backup);
+        assert!(local.mirroring);
+    }
+
+    #[test]
+    fn backup_follows_primary() -> Result<()> {
+        let primary_model = Arc::new(RwLock::new(
+            TestModel::new(2, 2.0).playlist(5, vec![0, 1]).build(),
+        ));
+        let backups = Backups::default();
+        let primary = start_primary(
+            &SyncSettings {
+                role: SyncRole::Primary,
+                port: 0,
+                primary: String::new(),
+                secret: "open sesame".to_string(),
+            },
+            primary_model.clone(),
+            backups.clone(),
+        )?;
+
+        let backup_model = Arc::new(RwLock::new(Model::default()));
+        let (tx, rx) = channel();
+        let _backup = start_backup(
+            &SyncSettings {
+                role: SyncRole::Backup,
+                port: primary.port,
+                primary: "127.0.0.1".to_string(),
+                secret: "open sesame".to_string(),
+            },
+            backup_model.clone(),
+            tx,
+        )?;
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backup_model.read().items.len() < 2 || backups.lock().is_empty() {
+            assert!(Instant::now() < deadline, "the backup didn't catch up");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(backup_model.read().mirroring);
+        assert_eq!(
+            primary_model.read().sync_status.as_deref(),
+            Some("1 backup connected")
+        );
+
+        let (relay_tx, relay_rx) = channel();
+        let (engine_tx, _engine_rx) = sync_channel(ENGINE_CHANNEL_CAPACITY);
+        relay_tx.send(ControlMessage::Stop(1))?;
+        drop(relay_tx);
+        relay(relay_rx, engine_tx, &primary_model, backups);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5))?,
+            ControlMessage::Stop(1)
+        );
+
+        // changes to the show are sent along once counted
+        {
+            let mut model = primary_model.write();
+            let mut extra = model.items[1].clone();
+            extra.id = 2;
+            model.items.push(extra);
+            model.revision += 1;
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backup_model.read().items.len() < 3 {
+            assert!(Instant::now() < deadline, "the backup missed a change");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // the backup carries on alone once the primary is gone
+        drop(primary);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backup_model.read().mirroring {
+            assert!(Instant::now() < deadline, "the backup didn't notice");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_strangers() -> Result<()> {
+        let settings = SyncSettings {
+            role: SyncRole::Primary,
+            port: 0,
+            primary: String::new(),
+            secret: "open sesame".to_string(),
+        };
+        let model = Arc::new(RwLock::new(
+            TestModel::new(2, 2.0).playlist(5, vec![0, 1]).build(),
+        ));
+        let backups = Backups::default();
+        let primary = start_primary(&settings, model.clone(), backups.clone())?;
+
+        let stranger = TcpStream::connect(("127.0.0.1", primary.port))?;
+        stranger.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(&stranger);
+        let mut challenge = String::new();
+        reader.read_line(&mut challenge)?;
+        assert!(challenge.starts_with("challenge "));
+        let proof = sign(
+            "open says me",
+            &[b"backup", challenge[10..].trim_end().as_bytes()],
+        );
+        writeln!(&stranger, "hello  {}", proof)?;
+        let mut received = String::new();
+        reader.read_to_string(&mut received)?;
+        assert_eq!(received, "");
+        assert!(backups.lock().is_empty());
+
+        let unprotected = SyncSettings {
+            secret: String::new(),
+            ..settings
+        };
+        assert!(start_primary(&unprotected, model, backups).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sealed_lines() -> Result<()> {
+        let mut primary = Session::new("open sesame", "ab", "cd");
+        let mut backup = Session::new("open sesame", "ab", "cd");
+        let (ping, model) = (primary.seal("ping"), primary.seal("model x"));
+        assert_eq!(backup.open(&ping)?, "ping");
+        assert_eq!(backup.open(&model)?, "model x");
+
+        // replayed, tampered with or sealed with another key
+        assert!(backup.open(&ping).is_err());
+        let fresh = || Session::new("open sesame", "ab", "cd");
+        assert!(fresh().open(&ping.replace("ping", "pong")).is_err());
+        let mut stranger = Session::new("open says me", "ab", "cd");
+        assert!(fresh().open(&stranger.seal("ping")).is_err());
+        Ok(())
+    }
+}
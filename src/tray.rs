@@ -0,0 +1,276 @@
+use afx_core::model::*;
+use parking_lot::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use tracing::warn;
+
+/// How long fading everything out from the tray takes, in seconds.
+const FADE_OUT_SECONDS: f64 = 3.0;
+
+// the entries of the menu
+const NOW_PLAYING: i32 = 1;
+const SHOW_WINDOW: i32 = 2;
+const PAUSE_ALL: i32 = 3;
+const RESUME_ALL: i32 = 4;
+const FADE_ALL: i32 = 5;
+const STOP_ALL: i32 = 6;
+const SEPARATOR: i32 = 7;
+
+/// What the icon and its tooltip say about playback: a summary and the
+/// names of the items playing.
+fn playing(model: &Model) -> (&'static str, Vec<&str>) {
+    let names = |status| -> Vec<&str> {
+        model
+            .items
+            .iter()
+            .filter(|item| item.status == status)
+            .map(|item| item.name.as_str())
+            .collect()
+    };
+    let playing = names(ItemStatus::Playing);
+    if !playing.is_empty() {
+        return ("Playing", playing);
+    }
+    let paused = names(ItemStatus::Paused);
+    if !paused.is_empty() {
+        return ("Paused", paused);
+    }
+    ("Nothing playing", vec![])
+}
+
+/// The themed icon standing for the state of playback.
+fn icon(model: &Model) -> &'static str {
+    match playing(model).0 {
+        "Playing" => "media-playback-start",
+        "Paused" => "media-playback-pause",
+        _ => "audio-x-generic",
+    }
+}
+
+/// The entries of the menu, each with its label and whether it's enabled, in
+/// order.
+fn menu_entries(model: &Model) -> Vec<(i32, String, bool)> {
+    let (summary, names) = playing(model);
+    let any = |status| model.items.iter().any(|item| item.status == status);
+    let now_playing = match &names[..] {
+        [] => summary.to_string(),
+        [name] => format!("{}: {}", summary, name),
+        [name, rest @ ..] => format!("{}: {} and {} more", summary, name, rest.len()),
+    };
+    vec![
+        (NOW_PLAYING, now_playing, false),
+        (SHOW_WINDOW, "Show window".to_string(), true),
+        (SEPARATOR, String::new(), false),
+        (PAUSE_ALL, "Pause all".to_string(), any(ItemStatus::Playing)),
+        (RESUME_ALL, "Resume all".to_string(), model.resumable() > 0),
+        (
+            FADE_ALL,
+            format!("Fade out all over {} s", FADE_OUT_SECONDS),
+            any(ItemStatus::Playing),
+        ),
+        (
+            STOP_ALL,
+            "Stop all".to_string(),
+            any(ItemStatus::Playing) || any(ItemStatus::Paused),
+        ),
+    ]
+}
+
+/// Carry out a click on an entry of the menu.
+fn menu_command(id: i32) -> Option<ControlMessage> {
+    match id {
+        PAUSE_ALL => Some(ControlMessage::GlobalPause),
+        RESUME_ALL => Some(ControlMessage::GlobalResume),
+        FADE_ALL => Some(ControlMessage::GlobalFadeOut(FADE_OUT_SECONDS)),
+        STOP_ALL => Some(ControlMessage::GlobalStop),
+        _ => None,
+    }
+}
+
+/// The tray icon, as served by ksni. Activating the icon or the menu entry
+/// shows the window.
+#[cfg(target_os = "linux")]
+struct TrayIcon {
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    show: Arc<AtomicBool>,
+    ctx: eframe::egui::Context,
+}
+
+#[cfg(target_os = "linux")]
+impl TrayIcon {
+    fn show_window(&self) {
+        self.show.store(true, Ordering::Relaxed);
+        self.ctx.request_repaint();
+    }
+
+    fn click(&self, id: i32) {
+        if id == SHOW_WINDOW {
+            self.show_window();
+        } else if let Some(command) = menu_command(id) {
+            self.channel.send(command).unwrap();
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ksni::Tray for TrayIcon {
+    fn id(&self) -> String {
+        "afx".to_string()
+    }
+
+    fn title(&self) -> String {
+        "afx".to_string()
+    }
+
+    fn icon_name(&self) -> String {
+        icon(&self.model.read()).to_string()
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        let model = self.model.read();
+        let (summary, names) = playing(&model);
+        ksni::ToolTip {
+            icon_name: icon(&model).to_string(),
+            icon_pixmap: vec![],
+            title: format!("afx: {}", summary.to_lowercase()),
+            description: names.join("\n"),
+        }
+    }
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        self.show_window();
+    }
+
+    fn secondary_activate(&mut self, _x: i32, _y: i32) {
+        self.show_window();
+    }
+
+    fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+        menu_entries(&self.model.read())
+            .into_iter()
+            .map(|(id, label, enabled)| match id {
+                SEPARATOR => ksni::MenuItem::Separator,
+                _ => ksni::menu::StandardItem {
+                    label,
+                    enabled,
+                    activate: Box::new(move |tray: &mut Self| tray.click(id)),
+                    ..Default::default()
+                }
+                .into(),
+            })
+            .collect()
+    }
+}
+
+/// Show an icon in the system tray with quick controls for playback, so that
+/// the window can be out of the way during a show. The returned flag is set
+/// whenever the window is asked for, with a repaint requested to notice it.
+#[cfg(target_os = "linux")]
+pub fn start(
+    model: Arc<RwLock<Model>>,
+    channel: Sender<ControlMessage>,
+    ctx: eframe::egui::Context,
+) -> Arc<AtomicBool> {
+    let show = Arc::new(AtomicBool::new(false));
+    let tray = TrayIcon {
+        model: model.clone(),
+        channel,
+        show: show.clone(),
+        ctx,
+    };
+    std::thread::spawn(move || {
+        if let Err(err) = serve(tray, &model) {
+            warn!("the tray icon is unavailable: {}", err);
+        }
+    });
+    show
+}
+
+// TODO a notification area icon on Windows and a status item on macOS,
+// offering the entries of menu_entries
+#[cfg(not(target_os = "linux"))]
+pub fn start(
+    _model: Arc<RwLock<Model>>,
+    _channel: Sender<ControlMessage>,
+    _ctx: eframe::egui::Context,
+) -> Arc<AtomicBool> {
+    warn!("the tray icon isn't supported on this platform yet");
+    Arc::new(AtomicBool::new(false))
+}
+
+/// Register the icon and refresh it whenever what it shows changes.
+#[cfg(target_os = "linux")]
+fn serve(tray: TrayIcon, model: &RwLock<Model>) -> Result<(), ksni::Error> {
+    use afx_core::engine::PLAYBACK_SYNC_INTERVAL;
+    use ksni::blocking::TrayMethods;
+    use std::time::Duration;
+
+    let shown = |model: &Model| (playing(model).1.join("\n"), menu_entries(model));
+    let mut reported = shown(&model.read());
+    let handle = tray.spawn()?;
+    while !handle.is_closed() {
+        std::thread::sleep(Duration::from_millis(PLAYBACK_SYNC_INTERVAL * 5));
+        let current = shown(&model.read());
+        if current != reported {
+            handle.update(|_| ());
+            reported = current;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use afx_core::headless::TestModel;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn menu() {
+        use ksni::{MenuItem, Tray};
+        use std::sync::mpsc::channel;
+
+        let (tx, rx) = channel();
+        let mut tray = TrayIcon {
+            model: Arc::new(RwLock::new(TestModel::new(3, 2.0).build())),
+            channel: tx,
+            show: Arc::new(AtomicBool::new(false)),
+            ctx: Default::default(),
+        };
+        let menu = tray.menu();
+        assert_eq!(menu.len(), 7);
+        let mut click = |index: usize| match &menu[index] {
+            MenuItem::Standard(item) => (item.activate)(&mut tray),
+            _ => panic!("entry {} can't be clicked", index),
+        };
+
+        click(5);
+        assert_eq!(
+            rx.try_recv(),
+            Ok(ControlMessage::GlobalFadeOut(FADE_OUT_SECONDS))
+        );
+        click(0);
+        assert!(rx.try_recv().is_err());
+        click(1);
+        assert!(tray.show.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn playing_indicator() {
+        let mut model = TestModel::new(3, 2.0).build();
+        let label = |model: &Model| menu_entries(model)[0].1.clone();
+        assert_eq!(icon(&model), "audio-x-generic");
+        assert_eq!(label(&model), "Nothing playing");
+
+        model.items[1].status = ItemStatus::Playing;
+        model.items[2].status = ItemStatus::Playing;
+        assert_eq!(icon(&model), "media-playback-start");
+        assert_eq!(label(&model), "Playing: test 1 and 1 more");
+        model.items[1].status = ItemStatus::Paused;
+        model.items[2].status = ItemStatus::Stopped;
+        assert_eq!(icon(&model), "media-playback-pause");
+        assert_eq!(label(&model), "Paused: test 1");
+    }
+}
@@ -1,217 +1,159 @@
-use crate::colour_proxy::ExtendedColourOps;
-use crate::model::*;
-use eframe::egui::plot::{Bar, BarChart, Plot};
+mod grid;
+mod library;
+mod settings;
+mod waveform;
+mod windows;
+
+use crate::app::{SharedImportState, SharedModel};
+use crate::colour_proxy::{ColourProxy, DrawnColour, ExtendedColourOps};
+use crate::import::{add_stems, refresh_items, render_speech};
+use crate::keys::{KeymapInput, ShortcutInput};
+use afx_core::engine::{PLAYBACK_SYNC_INTERVAL, VOLUME_UPDATE_INTERVAL};
+use afx_core::generator::DEFAULT_FREQUENCY;
+use afx_core::keymap::{Action, QUICK_TRIGGERS};
+use afx_core::merge::Merge;
+use afx_core::midi::{MidiBinding, MidiTarget, MidiTrigger};
+use afx_core::model::*;
+use afx_core::persistence::{default_project_path, discard_emergency};
+use afx_core::profile::Profile;
+use afx_core::radio::is_stream_url;
+use afx_core::speech::SpeechEngine;
 use eframe::egui::{Button, RichText, Slider};
 use eframe::epaint::{vec2, Color32, Stroke};
 use eframe::{egui, egui::Frame};
-use std::sync::mpsc::{Receiver, Sender};
-use tracing::info;
+use parking_lot::RwLock;
+use settings::{apply_touch_style, apply_ui_scale};
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, trace_span, warn};
+use waveform::render_waveform;
 
-#[rustfmt::skip]
-mod colours {
-    use super::*;
-    pub const ORANGE   : Color32 = Color32::from_rgb(240, 135, 35);
-    pub const YELLOW   : Color32 = Color32::from_rgb(230, 200, 50);
-    pub const PURPLE   : Color32 = Color32::from_rgb(110, 60,  200);
-    pub const PINK     : Color32 = Color32::from_rgb(240, 140, 170);
-    pub const BURGUNDY : Color32 = Color32::from_rgb(119, 51,  85);
-    pub const SALMON   : Color32 = Color32::from_rgb(220, 130, 140);
-    pub const TEAL     : Color32 = Color32::from_rgb(40,  150, 190);
-    pub const BROWN    : Color32 = Color32::from_rgb(102, 51,  46);
-    pub const CREAM    : Color32 = Color32::from_rgb(238, 221, 170);
-    pub const RED      : Color32 = Color32::from_rgb(230, 70,  70);
-    pub const GREEN    : Color32 = Color32::from_rgb(70,  175, 70);
-    pub const BLUE     : Color32 = Color32::from_rgb(40,  120, 220);
-}
-
-pub use colours::*;
-pub const PALETTE: [Color32; 12] = [
-    ORANGE, YELLOW, PURPLE, PINK, BURGUNDY, SALMON, TEAL, BROWN, CREAM, RED, GREEN, BLUE,
-];
-
-pub const BARS: usize = 128;
 pub const BAR_PLOT_WIDTH: f32 = 360.0;
-pub const PLAYBACK_SYNC_INTERVAL: u64 = 50;
+/// How long a press has to be held to open a context menu in touch mode.
+pub const LONG_PRESS_DURATION: f64 = 0.6;
+/// Emoji can be made of several characters, such as flags.
+const ICON_CHAR_LIMIT: usize = 8;
+/// How many pairs of output channels can be picked, enough for interfaces
+/// with 16 outputs.
+const MAX_CHANNEL_PAIRS: usize = 8;
+
+/// The state of the interface itself, which lasts only as long as the window
+/// and isn't saved.
+#[derive(Default)]
+pub struct Interface {
+    /// The item whose position label is being edited, along with the text
+    /// typed so far.
+    timestamp_edit: Option<(u64, String)>,
+    /// The item being dragged to a cell of a soundboard grid or onto a
+    /// playlist, along with the window the drag started in.
+    dragged_item: Option<(u64, egui::ViewportId)>,
+    /// The area the dragged item would be dropped on, and its window.
+    drop_highlight: Option<(egui::ViewportId, egui::Rect)>,
+    /// Whether the window is reduced to the pads of the selected playlist.
+    performance_mode: bool,
+    /// The item whose context menu was opened by a long press, along with its
+    /// position within the playlist and the position of the press.
+    touch_menu: Option<(u64, usize, egui::Pos2)>,
+    settings_open: bool,
+    /// Whether the keyboard shortcut cheat-sheet is shown.
+    help_open: bool,
+    /// The item shown in the details window.
+    item_details: Option<u64>,
+    /// Whether MIDI learn buttons are shown next to controls.
+    midi_learning: bool,
+    /// Whether the library lists its items in the order of their keys.
+    sort_by_key: bool,
+    /// Whether the performance diagnostics window is shown.
+    diagnostics_open: bool,
+    /// Whether the usage statistics are shown.
+    stats_open: bool,
+}
 
 /// This is an ephemeral struct only alive during a single call to
 /// [`SharedModel::render_ui`].
 struct UIState<'a> {
     model: &'a mut Model,
+    interface: &'a mut Interface,
     channel: Sender<ControlMessage>,
+    /// The model shared with the other threads, for handing out ids and for
+    /// work done in the background.
+    shared: &'a Arc<RwLock<Model>>,
+    /// Where the dragged item can be dropped, collected while drawing.
+    drop_targets: Vec<DropTarget>,
 }
 
-impl<'a> UIState<'a> {
-    fn new(model: &'a mut Model, channel: Sender<ControlMessage>) -> Self {
-        Self { model, channel }
-    }
-
-    fn playlist_menu(&mut self, ui: &mut egui::Ui) {
-        ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
-            self.library_button(ui);
-            ui.separator();
-            self.playlist_list(ui);
-            if !self.model.playlists.is_empty() {
-                ui.separator();
-            }
-            self.add_playlist_button(ui);
-        });
-    }
-
-    fn add_playlist_button(&mut self, ui: &mut egui::Ui) {
-        let button = Button::new("➕ Add playlist").fill(GREEN.linear_multiply(0.1));
-        if ui.add(button).clicked() && self.model.playlist_creation_state.is_none() {
-            self.model.playlist_creation_state = Some(Playlist {
-                id: self.model.fresh_id(),
-                name: "New playlist".to_string(),
-                description: "".to_string(),
-                items: vec![],
-            });
-        }
-    }
-
-    fn playlist_list(&mut self, ui: &mut egui::Ui) {
-        let mut to_delete = vec![];
-        for playlist in self.model.playlists.iter() {
-            let resp = ui.selectable_label(
-                Some(playlist.id) == self.model.selected_playlist,
-                &playlist.name,
-            );
-            if resp.clicked() {
-                self.model.selected_playlist = Some(playlist.id);
-            }
-            resp.context_menu(|ui| {
-                if ui.button(RichText::new("Delete").color(RED)).clicked() {
-                    to_delete.push(playlist.id);
-                    if Some(playlist.id) == self.model.selected_playlist {
-                        self.model.selected_playlist = None;
-                    }
-                    ui.close_menu();
-                }
-            });
-        }
-        self.model.playlists.retain(|p| !to_delete.contains(&p.id));
-    }
+/// An area adding the item dropped on it to a playlist, or moving it to a
+/// cell of the playlist's grid.
+#[derive(Clone, Copy)]
+struct DropTarget {
+    /// The window the area is in.
+    viewport: egui::ViewportId,
+    layer: egui::LayerId,
+    rect: egui::Rect,
+    playlist: u64,
+    cell: Option<Cell>,
+}
 
-    fn library_button(&mut self, ui: &mut egui::Ui) {
-        let lib = ui.selectable_label(
-            self.model.selected_playlist.is_none(),
-            RichText::new("📚 library").heading(),
-        );
-        if lib.clicked() {
-            self.model.selected_playlist = None;
+impl DropTarget {
+    fn new(resp: &egui::Response, playlist: u64) -> Self {
+        Self {
+            viewport: resp.ctx.viewport_id(),
+            layer: resp.layer_id,
+            // parts scrolled out of sight don't count
+            rect: resp.interact_rect,
+            playlist,
+            cell: None,
         }
     }
+}
 
-    fn search_bar(&mut self, ui: &mut egui::Ui) {
-        let search_field =
-            egui::TextEdit::singleline(&mut self.model.search_query).hint_text("type to search");
-        let resp = ui.add(search_field);
-        if !self.model.search_query.is_empty() {
-            let button = Button::new("❌").frame(false);
-            if ui.add(button).clicked()
-                || (resp.lost_focus() && ui.ctx().input().key_pressed(egui::Key::Escape))
-            {
-                self.model.search_query.clear();
-                resp.request_focus();
-            }
-        }
-        if ui
-            .ctx()
-            .input_mut()
-            .consume_key(egui::Modifiers::CTRL, egui::Key::F)
-        {
-            resp.request_focus();
+impl<'a> UIState<'a> {
+    fn new(
+        model: &'a mut Model,
+        interface: &'a mut Interface,
+        channel: Sender<ControlMessage>,
+        shared: &'a Arc<RwLock<Model>>,
+    ) -> Self {
+        Self {
+            model,
+            interface,
+            channel,
+            shared,
+            drop_targets: vec![],
         }
     }
 
-    fn items(&mut self, ui: &mut egui::Ui) {
-        let filtered_ids = self.process_search();
-        self.items_scroll_area(ui, filtered_ids);
+    /// An id no other thread hands out as well.
+    fn fresh_id(&self) -> u64 {
+        self.model.fresh_id()
     }
 
     // TODO rename
-    fn process_search(&mut self) -> Vec<(usize, u64)> {
-        let lowercase_query = self.model.search_query.to_lowercase();
-        let pat: Vec<_> = lowercase_query.split_ascii_whitespace().collect();
-        let selected_playlist = self.model.selected_playlist.map(|id| {
-            self.model
-                .playlists
-                .iter()
-                .find(|p| p.id == id)
-                .expect("selected playlist not found")
-        });
 
-        self.search_in_playlist(selected_playlist, pat)
+    fn item_index(&self, item_id: u64) -> usize {
+        // FIXME ugly data model
+        // we should really decide whether to handle
+        // mutations via message passing or whether to
+        // use mutable references. The latter is more
+        // convenient but the borrow checker doesn't
+        // like it, the former is more verbose but less
+        // error-prone and leads to more modular code.
+        self.model
+            .items
+            .iter()
+            .position(|i| i.id == item_id)
+            .unwrap()
     }
 
-    fn search_in_playlist(
-        &self,
-        selected_playlist: Option<&Playlist>,
-        pat: Vec<&str>,
-    ) -> Vec<(usize, u64)> {
-        let items = selected_playlist
-            .map(|p| {
-                p.items
-                    .iter()
-                    .map(|id| self.model.items.iter().find(|i| i.id == *id).unwrap())
-                    .collect()
-            })
-            .unwrap_or(self.model.items.iter().collect::<Vec<_>>());
-        items
-            .into_iter()
-            .enumerate()
-            .filter(|(_, item)| {
-                pat.iter()
-                    .find(|w| "playing".starts_with(**w))
-                    .filter(|_| item.status == ItemStatus::Playing)
-                    .is_some()
-                    || pat.iter().all(|w| item.name.to_lowercase().contains(w))
-            })
-            .map(|(pos_within_playlist, item)| (pos_within_playlist, item.id))
-            .collect::<Vec<_>>()
-    }
-
-    fn items_scroll_area(&mut self, ui: &mut egui::Ui, filtered_ids: Vec<(usize, u64)>) {
-        let items_per_row = (ui.available_width() / BAR_PLOT_WIDTH).floor() as usize;
-        egui::ScrollArea::vertical()
-            .auto_shrink([false; 2])
-            .show_rows(
-                ui,
-                100.0,
-                filtered_ids.len() / items_per_row + 1,
-                |ui, row_range| {
-                    for row in row_range {
-                        ui.horizontal(|ui| {
-                            for i in 0..items_per_row {
-                                let index = row * items_per_row + i;
-                                if index >= filtered_ids.len() {
-                                    break;
-                                }
-                                let (position_within_playlist, item_id) = filtered_ids[index];
-                                // FIXME ugly data model
-                                // we should really decide whether to handle
-                                // mutations via message passing or whether to
-                                // use mutable references. The latter is more
-                                // convenient but the borrow checker doesn't
-                                // like it, the former is more verbose but less
-                                // error-prone and leads to more modular code.
-                                let item_index = self
-                                    .model
-                                    .items
-                                    .binary_search_by_key(&item_id, |i| i.id)
-                                    .unwrap();
-                                let item = &mut self.model.items[item_index];
-                                item.position = ui.ctx().animate_value_with_time(
-                                    egui::Id::new(item.id),
-                                    item.target_position as f32,
-                                    0.06,
-                                ) as f64;
-                                self.item_frame(position_within_playlist, ui, item_index);
-                            }
-                        });
-                    }
-                },
-            );
+    fn animate_position(&mut self, ui: &egui::Ui, item_index: usize) {
+        let item = &mut self.model.items[item_index];
+        item.position = ui.ctx().animate_value_with_time(
+            egui::Id::new(item.id),
+            item.target_position as f32,
+            0.06,
+        ) as f64;
     }
 
     fn item_frame(
@@ -220,31 +162,101 @@ impl<'a> UIState<'a> {
         ui: &mut egui::Ui,
         item_index: usize,
     ) {
-        let Item { status, colour, .. } = &self.model.items[item_index];
-
-        Frame::group(ui.style())
-            .stroke(if matches!(status, ItemStatus::Playing) {
-                Stroke::new(1.0, Color32::WHITE)
+        let Item {
+            id, status, colour, ..
+        } = &self.model.items[item_index];
+        let id = *id;
+        let on_standby =
+            self.selected_playlist().map(|p| p.standby) == Some(position_within_playlist);
+        let follow = self
+            .selected_playlist()
+            .map(|p| p.follow(position_within_playlist))
+            .unwrap_or_default();
+        let touch_mode = self.model.settings.touch_mode;
+        let split_channels = self.model.settings.split_channels;
+        let colour_blind = self.model.settings.colour_blind;
+        let compact = self.compact_view();
+        let pinned =
+            self.model.selected_playlist.is_none() && self.model.library_pins.contains(&id);
+        // the cells of a grid have a handle of their own
+        let draggable = !self.interface.performance_mode
+            && self.selected_playlist().is_none_or(|p| p.grid.is_none());
+        let (colour, _) = display_colour(*colour, colour_blind);
+
+        let resp = Frame::group(ui.style())
+            .stroke(if on_standby {
+                Stroke::new(2.0, YELLOW.drawn())
+            } else if matches!(status, ItemStatus::Playing) {
+                // told apart by more than the shade of the outline
+                Stroke::new(if colour_blind { 3.0 } else { 1.0 }, Color32::WHITE)
             } else {
                 ui.style().visuals.widgets.noninteractive.bg_stroke
             })
             .fill(colour.linear_multiply(0.03))
             .show(ui, |ui| {
+                if compact {
+                    ui.horizontal(|ui| {
+                        let item = &self.model.items[item_index];
+                        render_item_name(ui, item, pinned);
+                        if let Some(follow) = describe_follow(follow) {
+                            ui.label(RichText::new(follow).small().weak());
+                        }
+                        issues_label(ui, item);
+                        if draggable {
+                            self.drag_handle(ui, id);
+                        }
+                        self.item_controls(ui, item_index);
+                    });
+                    return;
+                }
                 ui.vertical(|ui| {
                     let item = &self.model.items[item_index];
 
-                    render_item_name(ui, item);
-                    render_bar_chart(position_within_playlist, &self.channel, ui, item);
+                    render_item_name(ui, item, pinned);
+                    if let Some(follow) = describe_follow(follow) {
+                        ui.label(RichText::new(follow).small().weak());
+                    }
+                    issues_label(ui, item);
+                    render_waveform(
+                        &self.channel,
+                        ui,
+                        item,
+                        touch_mode,
+                        split_channels,
+                        colour_blind,
+                    );
 
                     ui.horizontal(|ui| {
+                        if draggable {
+                            self.drag_handle(ui, id);
+                        }
                         self.item_controls(ui, item_index);
                     });
                 });
             })
-            .response
-            .context_menu(|ui| {
-                self.item_context_menu(position_within_playlist, item_index, ui);
-            });
+            .response;
+
+        let located = ui.data(|d| d.get_temp::<Located>(egui::Id::new(LOCATED)));
+        if let Some(located) = located.filter(|located| located.item == id) {
+            let elapsed = ui.input(|i| i.time) - located.since;
+            if elapsed < LOCATE_FLASH {
+                // pulsing twice a second while fading out
+                let pulse = (elapsed * std::f64::consts::TAU * 2.0).cos() * 0.5 + 0.5;
+                let alpha = (pulse * (1.0 - elapsed / LOCATE_FLASH)) as f32;
+                let stroke = Stroke::new(3.0, YELLOW.drawn().linear_multiply(alpha));
+                ui.painter().rect_stroke(resp.rect, 4.0, stroke);
+                ui.ctx().request_repaint();
+            }
+        }
+
+        if touch_mode {
+            if let Some(pos) = long_press(ui, resp.rect) {
+                self.interface.touch_menu = Some((id, position_within_playlist, pos));
+            }
+        }
+        resp.context_menu(|ui| {
+            self.item_context_menu(position_within_playlist, item_index, ui);
+        });
     }
 
     fn item_context_menu(
@@ -263,10 +275,60 @@ impl<'a> UIState<'a> {
                             playlist_id: playlist.id,
                         })
                         .unwrap();
-                    ui.close_menu();
+                    close_menu(ui, &mut self.interface.touch_menu);
                 }
             }
         });
+        let selected_playlist = self.model.selected_playlist;
+        if let Some(playlist) = self
+            .model
+            .playlists
+            .iter_mut()
+            .find(|p| Some(p.id) == selected_playlist)
+        {
+            if ui.button("Set as standby cue").clicked() {
+                playlist.standby = pos_within_playlist;
+                close_menu(ui, &mut self.interface.touch_menu);
+            }
+            ui.menu_button("Then", |ui| {
+                let current = playlist.follow(pos_within_playlist);
+                let mut follow = current;
+                ui.radio_value(&mut follow, Follow::Manual, "Wait for GO");
+                ui.radio_value(&mut follow, Follow::After(0.0), "Fire the next cue along");
+                ui.horizontal(|ui| {
+                    let delayed = matches!(follow, Follow::After(seconds) if seconds > 0.0);
+                    let mut seconds = match follow {
+                        Follow::After(seconds) if delayed => seconds,
+                        _ => 5.0,
+                    };
+                    if ui.radio(delayed, "Fire the next cue after").clicked() {
+                        follow = Follow::After(seconds);
+                    }
+                    let drag = egui::DragValue::new(&mut seconds)
+                        .speed(0.1)
+                        .clamp_range(0.1..=3600.0)
+                        .suffix(" s");
+                    if ui.add_enabled(delayed, drag).changed() {
+                        follow = Follow::After(seconds);
+                    }
+                });
+                ui.radio_value(&mut follow, Follow::End, "Fire the next cue when this ends");
+                if follow != current {
+                    playlist.set_follow(pos_within_playlist, follow);
+                }
+            });
+            if let Some(grid) = &mut playlist.grid {
+                ui.menu_button("Move to bank", |ui| {
+                    let current = grid.cells.get(&item.id).map(|cell| cell.bank);
+                    for bank in (0..BANKS).filter(|&b| Some(b) != current) {
+                        if ui.button(format!("{}", bank + 1)).clicked() {
+                            grid.move_to_bank(item.id, bank);
+                            close_menu(ui, &mut self.interface.touch_menu);
+                        }
+                    }
+                });
+            }
+        }
         if let Some(playlist_id) = self.model.selected_playlist {
             if ui.button("Remove from playlist").clicked() {
                 self.channel
@@ -275,45 +337,157 @@ impl<'a> UIState<'a> {
                         playlist_id,
                     })
                     .unwrap();
-                ui.close_menu();
+                close_menu(ui, &mut self.interface.touch_menu);
             }
         }
-        if ui.button(RichText::new("Delete").color(RED)).clicked() {
-            self.channel.send(ControlMessage::Delete(item.id)).unwrap();
-            ui.close_menu();
+        let pinned = self.model.library_pins.contains(&item.id);
+        if ui
+            .button(if pinned { "Unpin" } else { "Pin to top" })
+            .on_hover_text("Keep the item at the top of the library, even while searching")
+            .clicked()
+        {
+            match pinned {
+                true => self.model.library_pins.retain(|&id| id != item.id),
+                false => self.model.library_pins.push(item.id),
+            }
+            close_menu(ui, &mut self.interface.touch_menu);
         }
-    }
-
-    fn item_controls(&mut self, ui: &mut egui::Ui, item_index: usize) {
-        let item = &mut self.model.items[item_index];
-        match item.status {
-            ItemStatus::Stopped | ItemStatus::Paused => {
-                if ui.button(RichText::new("▶").heading()).clicked() {
-                    item.status = ItemStatus::Loading;
-                    self.channel.send(ControlMessage::Play(item.id)).unwrap();
+        let mut variation = None;
+        if item.signal.is_none() && !item.is_stream() {
+            ui.menu_button("Duplicate with variation", |ui| {
+                let id = egui::Id::new("variation");
+                let mut range = ui.data(|d| d.get_temp::<Variation>(id)).unwrap_or_default();
+                ui.horizontal(|ui| {
+                    ui.label("Pitch ±");
+                    ui.add(
+                        egui::DragValue::new(&mut range.pitch)
+                            .clamp_range(0.0..=MAX_PITCH)
+                            .speed(0.05)
+                            .suffix(" semitones"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Gain ±");
+                    ui.add(
+                        egui::DragValue::new(&mut range.gain_db)
+                            .clamp_range(0.0..=MAX_GAIN_TRIM)
+                            .speed(0.1)
+                            .suffix(" dB"),
+                    );
+                });
+                ui.checkbox(&mut range.trim, "Shorten and move the trim")
+                    .on_hover_text("Play a different part of the file");
+                ui.data_mut(|d| d.insert_temp(id, range));
+                if ui.button("Duplicate").clicked() {
+                    variation = Some(range);
+                    close_menu(ui, &mut self.interface.touch_menu);
                 }
+            });
+        }
+        if ui.button("Details").clicked() {
+            self.interface.item_details = Some(item.id);
+            close_menu(ui, &mut self.interface.touch_menu);
+        }
+        if let Some(key) = item.key {
+            if ui
+                .button("Find compatible keys")
+                .on_hover_text(format!("Search for items that mix well with {}", key))
+                .clicked()
+            {
+                self.model.search_query = key
+                    .compatible()
+                    .iter()
+                    .map(|key| {
+                        let (number, letter) = key.camelot();
+                        format!("key:{}{}", number, letter)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                close_menu(ui, &mut self.interface.touch_menu);
             }
-            ItemStatus::Loading => {
-                ui.spinner();
+        }
+        if item.signal.is_none()
+            && ui
+                .button("Copy path")
+                .on_hover_text("Copy the path of the current stem, or the address of a stream")
+                .clicked()
+        {
+            ui.output_mut(|o| o.copied_text = stem_location(item));
+            close_menu(ui, &mut self.interface.touch_menu);
+        }
+        if ui
+            .button("Copy summary")
+            .on_hover_text("Copy the name, duration, file and details, for cue sheets")
+            .clicked()
+        {
+            ui.output_mut(|o| o.copied_text = item_summary(item));
+            close_menu(ui, &mut self.interface.touch_menu);
+        }
+        if (item.trim.is_some() || item.loop_region.is_some())
+            && ui
+                .button("Export labels")
+                .on_hover_text("Save the trim points and loop region as Audacity labels")
+                .clicked()
+        {
+            save_labels(item.name.clone(), afx_core::report::item_labels(item));
+            close_menu(ui, &mut self.interface.touch_menu);
+        }
+        if ui
+            .button("Reload from file")
+            .on_hover_text("Update the duration and waveform after editing the file")
+            .clicked()
+        {
+            refresh_items(self.shared.clone(), Some(vec![item.id]));
+            close_menu(ui, &mut self.interface.touch_menu);
+        }
+        if !item.issues.is_empty() {
+            if ui
+                .button("Retry")
+                .on_hover_text("Load the file again, after plugging a drive back in for example")
+                .clicked()
+            {
+                self.channel.send(ControlMessage::Retry(item.id)).unwrap();
+                close_menu(ui, &mut self.interface.touch_menu);
             }
-            ItemStatus::Playing => {
-                if ui.button(RichText::new("⏸").heading()).clicked() {
-                    item.status = ItemStatus::Paused;
-                    self.channel.send(ControlMessage::Pause(item.id)).unwrap();
-                }
+            if ui.button("Clear issues").clicked() {
+                self.model.items[item_index].issues.clear();
+                close_menu(ui, &mut self.interface.touch_menu);
+                return;
             }
-        };
+        }
+        if ui
+            .button(RichText::new("Delete").color(RED.drawn()))
+            .clicked()
+        {
+            self.channel.send(ControlMessage::Delete(item.id)).unwrap();
+            close_menu(ui, &mut self.interface.touch_menu);
+        }
+        if let Some(variation) = variation {
+            let copy = self.model.items[item_index].varied(self.fresh_id(), &variation);
+            self.add_imported_items(vec![copy]);
+        }
+    }
 
-        let loop_button = Button::new(if item.looped { "🔁" } else { "🔂" }).frame(item.looped);
-        let resp = ui.add(loop_button).on_hover_text(if item.looped {
-            "Disable looping"
+    fn item_controls(&mut self, ui: &mut egui::Ui, item_index: usize) {
+        let source_playlist = self.model.selected_playlist;
+        let item = &mut self.model.items[item_index];
+        play_pause_button(ui, item, source_playlist, &self.channel);
+        stop_button(ui, item, &self.channel);
+
+        let looped = item.looped();
+        let loop_button = Button::new(if looped { "🔁" } else { "🔂" }).frame(looped);
+        let resp = ui.add(loop_button).on_hover_text(if looped {
+            "Disable looping\n\
+            Alt+drag on the waveform to move the loop markers, \
+            which snap to the beats if the tempo is known unless Shift is held, \
+            Alt+double-click to loop the whole track"
         } else {
             "Enable looping"
         });
         if resp.clicked() {
-            item.looped = !item.looped;
+            item.set_looped(!looped);
             self.channel
-                .send(ControlMessage::Loop(item.id, item.looped))
+                .send(ControlMessage::Loop(item.id, !looped))
                 .unwrap();
         }
 
@@ -324,64 +498,216 @@ impl<'a> UIState<'a> {
                 .unwrap();
         }
 
-        let original_volume = item.volume;
-        ui.add(Slider::new(&mut item.volume, 0.0001..=1.0).show_value(false));
-        if original_volume != item.volume {
-            self.channel
-                .send(ControlMessage::SetVolume(item.id, item.volume))
-                .unwrap();
+        volume_slider(ui, item, &self.channel);
+
+        if item.status != ItemStatus::Stopped && !item.is_stream() {
+            let keymap = &self.model.settings.keymap;
+            for seconds in SKIPS {
+                let label = if seconds < 0 {
+                    format!("⏪{}", -seconds)
+                } else {
+                    format!("{}⏩", seconds)
+                };
+                let action = Action::Skip(seconds);
+                let mut hover = action.description().replace(" the focused item", "");
+                let shortcuts = keymap.describe(ui.ctx(), action);
+                if !shortcuts.is_empty() {
+                    hover += &format!(
+                        "\n{} skips the item whose details are open, or the one started last",
+                        shortcuts
+                    );
+                }
+                let resp = ui.small_button(label).on_hover_text(hover);
+                if resp.clicked() {
+                    self.channel
+                        .send(ControlMessage::SeekRelative(item.id, seconds as f64))
+                        .unwrap();
+                }
+            }
         }
 
-        let minutes = (item.position / 60.0).floor() as u32;
-        let seconds = item.position % 60.0;
-        ui.label(format!("{:01}:{:05.2}", minutes, seconds));
+        self.position_label(ui, item_index);
+
+        if self.interface.midi_learning {
+            let id = self.model.items[item_index].id;
+            self.midi_learn_button(ui, MidiTarget::Item(id), "▶");
+            self.midi_learn_button(ui, MidiTarget::ItemVolume(id), "🔈");
+        }
     }
 
-    fn add_imported_items(&mut self, items: Vec<Item>) {
-        if let Some(playlist_id) = self.model.selected_playlist {
-            for item in items.iter() {
+    /// Arm the target in MIDI learn mode, so that the next fitting control
+    /// pressed on a MIDI device gets bound to it.
+    fn midi_learn_button(&mut self, ui: &mut egui::Ui, target: MidiTarget, label: &str) {
+        let armed = self.model.midi_learn == Some(target);
+        let bound: Vec<_> = self
+            .model
+            .settings
+            .midi
+            .bindings
+            .iter()
+            .filter(|binding| binding.target == target)
+            .map(|binding| binding.trigger.to_string())
+            .collect();
+
+        let text = if armed {
+            "🎹 waiting…".to_string()
+        } else {
+            format!("🎹 {}", label)
+        };
+        let hover = if bound.is_empty() {
+            "Click, then press a control on your MIDI device".to_string()
+        } else {
+            format!("Mapped to {}, click to remap", bound.join(", "))
+        };
+        let mut text = RichText::new(text).small();
+        if !bound.is_empty() {
+            text = text.color(PURPLE.drawn());
+        }
+        if ui
+            .selectable_label(armed, text)
+            .on_hover_text(hover)
+            .clicked()
+        {
+            self.model.midi_learn = if armed { None } else { Some(target) };
+        }
+    }
+
+    /// Skip through the focused item with the keyboard, unless typing.
+    fn skip_hotkeys(&mut self, ctx: &egui::Context) {
+        if ctx.memory(|m| m.focused()).is_some() {
+            return;
+        }
+        let focused = self.interface.item_details;
+        let Some(id) = focused.or_else(|| self.model.focused_item()) else {
+            return;
+        };
+        for seconds in SKIPS {
+            if self
+                .model
+                .settings
+                .keymap
+                .consume(ctx, Action::Skip(seconds))
+            {
                 self.channel
-                    .send(ControlMessage::AddToPlaylist {
-                        item_id: item.id,
-                        playlist_id,
-                    })
+                    .send(ControlMessage::SeekRelative(id, seconds as f64))
                     .unwrap();
             }
         }
-        self.model.items.extend(items);
     }
 
-    fn playlist_creation_window(&mut self, ui: &mut egui::Ui) {
-        if let Some(playlist) = &self.model.playlist_creation_state {
-            let mut playlist = playlist.clone();
+    /// Show the playback position of an item. Clicking the label turns it into
+    /// a text field which seeks to the typed timestamp on Enter.
+    fn position_label(&mut self, ui: &mut egui::Ui, item_index: usize) {
+        let Item {
+            id,
+            position,
+            duration,
+            ..
+        } = self.model.items[item_index];
+
+        match &mut self.interface.timestamp_edit {
+            Some((edited_id, text)) if *edited_id == id => {
+                let resp = ui.add(egui::TextEdit::singleline(text).desired_width(60.0));
+                if resp.lost_focus() {
+                    if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        if let Some(target) = parse_timestamp(text) {
+                            self.channel
+                                .send(ControlMessage::Seek(id, target.min(duration)))
+                                .unwrap();
+                        }
+                    }
+                    self.interface.timestamp_edit = None;
+                } else if !resp.has_focus() {
+                    resp.request_focus();
+                }
+            }
+            _ => {
+                let label =
+                    egui::Label::new(format_timestamp(position)).sense(egui::Sense::click());
+                if ui.add(label).on_hover_text("Click to seek").clicked() {
+                    self.interface.timestamp_edit = Some((id, format_timestamp(position)));
+                }
+            }
+        }
+    }
 
-            egui::Window::new("Create playlist")
-                .resizable(false)
-                .show(ui.ctx(), |ui| {
-                    ui.horizontal(|ui| {
-                        ui.label("Name:");
-                        ui.text_edit_singleline(&mut playlist.name);
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Description:");
-                        egui::TextEdit::multiline(&mut playlist.description)
-                            .desired_rows(3)
-                            .show(ui);
-                    });
+    /// Show every item that is currently playing or paused, with a compact
+    /// set of transport controls for each.
+    fn now_playing_bar(&mut self, ui: &mut egui::Ui) {
+        let active: Vec<_> = self
+            .model
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.status != ItemStatus::Stopped)
+            .map(|(index, _)| index)
+            .collect();
+
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Master").strong());
+                let resp = db_fader(ui, &mut self.model.master_gain_db);
+                if fader_settled(ui, &resp) {
+                    let volume = db_to_amplitude(self.model.master_gain_db);
+                    self.channel
+                        .send(ControlMessage::SetMasterVolume(volume))
+                        .unwrap();
+                }
+                if self.interface.midi_learning {
+                    self.midi_learn_button(ui, MidiTarget::MasterVolume, "master");
+                }
+                ui.separator();
 
-                    self.model.playlist_creation_state = Some(playlist.clone());
-                    ui.horizontal(|ui| {
-                        if ui.button(RichText::new("Discard").heading()).clicked() {
-                            self.model.playlist_creation_state = None;
-                        } else if ui.button(RichText::new("Create").heading()).clicked() {
-                            self.model.playlists.push(playlist.clone());
-                            self.model.playlist_creation_state = None;
-                        }
-                    });
-                })
-        } else {
-            None
-        };
+                let countdown = self.model.playing_playlist.and_then(|id| {
+                    let playlist = self.model.playlists.iter().find(|p| p.id == id)?;
+                    Some((&playlist.name, self.model.remaining(id)?))
+                });
+                if let Some((name, left)) = countdown {
+                    ui.label(format!("⏳ {}: {} left", name, format_runtime(left)))
+                        .on_hover_text(
+                            "What's left of the item playing from the playlist \
+                             and of the items after it",
+                        );
+                    ui.separator();
+                }
+
+                ui.label(RichText::new("Now playing:").strong());
+                if active.is_empty() {
+                    ui.label(RichText::new("nothing").weak());
+                }
+                let locate = Action::Locate;
+                let hover = format!(
+                    "{} ({})",
+                    locate.description(),
+                    self.model.settings.keymap.describe(ui.ctx(), locate)
+                );
+                let playing = active
+                    .iter()
+                    .any(|&index| self.model.items[index].status == ItemStatus::Playing);
+                if ui
+                    .add_enabled(playing, Button::new("🔎"))
+                    .on_hover_text(hover)
+                    .clicked()
+                {
+                    self.locate_playing(&ui.ctx().clone());
+                }
+                ui.spacing_mut().slider_width = 80.0;
+
+                let colour_blind = self.model.settings.colour_blind;
+                for item_index in active {
+                    let item = &mut self.model.items[item_index];
+                    let (colour, _) = display_colour(item.colour, colour_blind);
+                    Frame::group(ui.style())
+                        .fill(colour.linear_multiply(0.03))
+                        .show(ui, |ui| {
+                            truncated_label(ui, &item.name, egui::TextStyle::Body, 120.0);
+                            play_pause_button(ui, item, item.source_playlist, &self.channel);
+                            stop_button(ui, item, &self.channel);
+                            volume_slider(ui, item, &self.channel);
+                        });
+                }
+            });
+        });
     }
 
     fn render_import_progress(
@@ -428,7 +754,17 @@ impl<'a> UIState<'a> {
 
                 ui.vertical(|ui| {
                     if state.items_in_progress.is_empty() {
-                        ui.vertical_centered(|ui| ui.heading("Waiting for file selection..."));
+                        match &state.download {
+                            Some((url, fraction)) => {
+                                ui.label(format!("Downloading {}", url));
+                                ui.add(egui::ProgressBar::new(*fraction).show_percentage());
+                            }
+                            None => {
+                                ui.vertical_centered(|ui| {
+                                    ui.heading("Waiting for file selection...")
+                                });
+                            }
+                        }
                         return;
                     }
 
@@ -439,7 +775,7 @@ impl<'a> UIState<'a> {
 
                     ui.horizontal(|ui| {
                         if ui
-                            .button(RichText::new("Discard").heading().color(RED))
+                            .button(RichText::new("Discard").heading().color(RED.drawn()))
                             .clicked()
                         {
                             keep_window_open = false;
@@ -448,7 +784,7 @@ impl<'a> UIState<'a> {
                         let import_action =
                             RichText::new(format!("Add {} tracks to {}", finished, target))
                                 .heading()
-                                .color(GREEN);
+                                .color(GREEN.drawn());
                         if ui.button(import_action).clicked() {
                             keep_window_open = false;
                             imported = Some(state.finished.drain(..).collect());
@@ -459,21 +795,17 @@ impl<'a> UIState<'a> {
         (keep_window_open, imported)
     }
 
-    fn get_selected_playlist_name(&self) -> &str {
-        if let Some(playlist_id) = self.model.selected_playlist {
-            &self
-                .model
-                .playlists
-                .iter()
-                .find(|p| p.id == playlist_id)
-                .unwrap()
-                .name
-        } else {
-            "library"
-        }
+    fn selected_playlist(&self) -> Option<&Playlist> {
+        let id = self.model.selected_playlist?;
+        self.model.playlists.iter().find(|p| p.id == id)
     }
 
-    fn render_top_button_bar(&mut self, ui: &mut egui::Ui) -> [egui::Response; 5] {
+    fn selected_playlist_mut(&mut self) -> Option<&mut Playlist> {
+        let id = self.model.selected_playlist?;
+        self.model.playlists.iter_mut().find(|p| p.id == id)
+    }
+
+    fn render_top_button_bar(&mut self, ui: &mut egui::Ui) -> [egui::Response; 6] {
         let import_button = Button::new(RichText::new("Import").heading().color(Color32::BLACK))
             .fill(Color32::GOLD);
         let import_button_resp = ui.add(import_button);
@@ -490,6 +822,18 @@ impl<'a> UIState<'a> {
         let pause_resp = ui.add(
             Button::new(RichText::new("⏸").heading().color(Color32::BLACK)).fill(Color32::YELLOW),
         );
+        let resumable = self.model.resumable();
+        let resume_resp = ui
+            .add_enabled(
+                resumable > 0,
+                Button::new(RichText::new("⏯").heading().color(Color32::BLACK))
+                    .fill(Color32::YELLOW),
+            )
+            .on_hover_text(format!(
+                "Resume the {} {} paused with ⏸",
+                resumable,
+                if resumable == 1 { "item" } else { "items" }
+            ));
         let stop_resp = ui.add(
             Button::new(RichText::new("⏹").heading().color(Color32::BLACK)).fill(Color32::RED),
         );
@@ -499,6 +843,7 @@ impl<'a> UIState<'a> {
             import_button_resp,
             play_resp,
             pause_resp,
+            resume_resp,
             stop_resp,
             search_to_playlist_resp,
         ]
@@ -508,9 +853,10 @@ impl<'a> UIState<'a> {
         &mut self,
         play_resp: egui::Response,
         pause_resp: egui::Response,
+        resume_resp: egui::Response,
         stop_resp: egui::Response,
     ) {
-        if let Some(id) = self.model.selected_playlist {
+        if let Some(id) = self.model.selected_playlist.filter(|_| play_resp.clicked()) {
             self.channel
                 .send(ControlMessage::PlayFromPlaylist(id))
                 .unwrap();
@@ -518,35 +864,241 @@ impl<'a> UIState<'a> {
         if pause_resp.clicked() {
             self.channel.send(ControlMessage::GlobalPause).unwrap();
         }
+        if resume_resp.clicked() {
+            self.channel.send(ControlMessage::GlobalResume).unwrap();
+        }
         if stop_resp.clicked() {
             self.channel.send(ControlMessage::GlobalStop).unwrap();
         }
     }
+}
 
-    /// Create a new playlist from the current search.
-    fn playlist_from_search(&mut self) {
-        if self.model.playlist_creation_state.is_none() {
-            self.model.playlist_creation_state = Some(Playlist {
-                id: self.model.fresh_id(),
-                name: "new playlist".to_string(),
-                description: "".to_string(),
-                items: self
-                    .process_search()
-                    .into_iter()
-                    .map(|(_, item_id)| item_id)
-                    .collect(),
-            });
+/// How long ago something happened, to the minute.
+fn format_ago(elapsed: std::time::Duration) -> String {
+    let minutes = elapsed.as_secs() / 60;
+    match minutes {
+        0 => "just now".to_string(),
+        1..=59 => format!("{} min ago", minutes),
+        _ => format!("{} h {} min ago", minutes / 60, minutes % 60),
+    }
+}
+
+/// A length of time in whole seconds, like `20:05` or `1:02:03`.
+fn format_runtime(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    match hours {
+        0 => format!("{}:{:02}", minutes, seconds),
+        _ => format!("{}:{:02}:{:02}", hours, minutes, seconds),
+    }
+}
+
+/// How many items a playlist has and how long they take to play through.
+fn runtime_summary(count: usize, seconds: f64, streams: usize) -> String {
+    let items = match count {
+        1 => "1 item".to_string(),
+        _ => format!("{} items", count),
+    };
+    match streams {
+        0 => format!("{}, {}", items, format_runtime(seconds)),
+        1 => format!("{}, {} plus a stream", items, format_runtime(seconds)),
+        _ => format!(
+            "{}, {} plus {} streams",
+            items,
+            format_runtime(seconds),
+            streams
+        ),
+    }
+}
+
+pub fn format_timestamp(position: f64) -> String {
+    let minutes = (position / 60.0).floor() as u32;
+    let seconds = position % 60.0;
+    format!("{:01}:{:05.2}", minutes, seconds)
+}
+
+/// Parse a timestamp like `12:34.5`, `1:02:03` or `95` into seconds.
+fn parse_timestamp(text: &str) -> Option<f64> {
+    let parts: Vec<_> = text.trim().rsplit(':').map(str::trim).collect();
+    if parts.len() > 3 {
+        return None;
+    }
+
+    let seconds: f64 = parts[0].parse().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+
+    let mut total = seconds;
+    for (part, multiplier) in parts[1..].iter().zip([60.0, 3600.0]) {
+        total += part.parse::<u32>().ok()? as f64 * multiplier;
+    }
+    Some(total)
+}
+
+/// Close the menu `ui` belongs to, be it a regular context menu or one opened
+/// by a long press.
+fn close_menu(ui: &mut egui::Ui, touch_menu: &mut Option<(u64, usize, egui::Pos2)>) {
+    *touch_menu = None;
+    ui.close_menu();
+}
+
+/// The position of a press held still over `rect` for longer than a click.
+fn long_press(ui: &egui::Ui, rect: egui::Rect) -> Option<egui::Pos2> {
+    ui.input(|input| {
+        let pointer = &input.pointer;
+        let origin = pointer.press_origin()?;
+        let held_for = input.time - pointer.press_start_time()?;
+        let moved = pointer.interact_pos()?.distance(origin);
+
+        (pointer.primary_down()
+            && rect.contains(origin)
+            && moved < 8.0
+            && held_for > LONG_PRESS_DURATION)
+            .then_some(origin)
+    })
+}
+
+/// Ask where to save a show report and write it there, in the background.
+fn save_report(history: Vec<HistoryEntry>) {
+    std::thread::spawn(move || {
+        let path = rfd::FileDialog::new()
+            .set_title("Save the show report")
+            .set_file_name("show-report.csv")
+            .add_filter("CSV", &["csv"])
+            .add_filter("JSON", &["json"])
+            .add_filter("Audacity labels", &["txt"])
+            .save_file();
+        if let Some(path) = path {
+            match afx_core::report::save(&path, &history) {
+                Ok(()) => info!("saved the show report to {}", path.display()),
+                Err(err) => warn!("failed to save the show report: {}", err),
+            }
+        }
+    });
+}
+
+/// Ask where to save an Audacity label track, named after what it marks.
+fn save_labels(name: String, labels: String) {
+    std::thread::spawn(move || {
+        let path = rfd::FileDialog::new()
+            .set_title("Export labels for Audacity")
+            .set_file_name(format!("{} labels.txt", name))
+            .add_filter("Audacity labels", &["txt"])
+            .save_file();
+        if let Some(path) = path {
+            match std::fs::write(&path, labels) {
+                Ok(()) => info!("saved labels to {}", path.display()),
+                Err(err) => warn!("failed to save labels: {}", err),
+            }
         }
+    });
+}
+
+/// The absolute path of the current stem of an item, or the address of its
+/// stream.
+fn stem_location(item: &Item) -> String {
+    let path = &item.stems[item.current_stem].path;
+    if item.is_stream() {
+        return path.clone();
+    }
+    std::path::absolute(path).map_or_else(|_| path.clone(), |path| path.display().to_string())
+}
+
+/// A few lines describing an item, for pasting into cue sheets and bug
+/// reports.
+fn item_summary(item: &Item) -> String {
+    let mut lines = vec![item.labelled_name()];
+    lines.push(if item.is_stream() {
+        "Duration: live stream".to_string()
+    } else {
+        format!("Duration: {}", format_timestamp(item.duration))
+    });
+    if let Some((start, end)) = item.trim {
+        lines.push(format!(
+            "Trim: {} to {}",
+            format_timestamp(start),
+            format_timestamp(end)
+        ));
+    }
+    if item.signal.is_none() {
+        lines.push(format!("File: {}", stem_location(item)));
+    }
+    if item.stems.len() > 1 {
+        let tags: Vec<_> = item.stems.iter().map(|stem| stem.tag.as_str()).collect();
+        lines.push(format!("Stems: {}", tags.join(", ")));
+    }
+    if let Some(key) = item.key {
+        lines.push(format!("Key: {}", key));
+    }
+    if let Some(tempo) = &item.tempo {
+        lines.push(format!("Tempo: {:.1} BPM", tempo.bpm));
+    }
+    if let Some(loudness) = item.loudness {
+        lines.push(format!("Loudness: {:.1} LUFS", loudness));
+    }
+    lines.join("\n")
+}
+
+/// Count the issues of an item, listing them on hover, unless it has none.
+fn issues_label(ui: &mut egui::Ui, item: &Item) {
+    if item.issues.is_empty() {
+        return;
+    }
+    let count = item.issues.len();
+    let messages: Vec<_> = item.issues.iter().map(|(_, msg)| msg.as_str()).collect();
+    ui.colored_label(
+        RED.drawn(),
+        RichText::new(format!(
+            "⚠ {} {}",
+            count,
+            if count == 1 { "issue" } else { "issues" }
+        ))
+        .small(),
+    )
+    .on_hover_text(format!(
+        "{}\nRetry or clear them from the context menu",
+        messages.join("\n")
+    ));
+}
+
+/// Describe what a cue list does after a cue, unless it waits for GO.
+fn describe_follow(follow: Follow) -> Option<String> {
+    match follow {
+        Follow::Manual => None,
+        Follow::After(seconds) if seconds <= 0.0 => Some("then the next cue along".to_string()),
+        Follow::After(seconds) => Some(format!("then the next cue after {:.1} s", seconds)),
+        Follow::End => Some("then the next cue when done".to_string()),
     }
 }
 
-fn render_item_name(ui: &mut egui::Ui, item: &Item) {
+fn render_item_name(ui: &mut egui::Ui, item: &Item, pinned: bool) {
+    const KEY_WIDTH: f32 = 70.0;
+    let name = match pinned {
+        true => format!("📌 {}", item.labelled_name()),
+        false => item.labelled_name(),
+    };
+    let Some(key) = item.key else {
+        truncated_label(ui, &name, egui::TextStyle::Heading, BAR_PLOT_WIDTH);
+        return;
+    };
+    ui.horizontal(|ui| {
+        let name_width = BAR_PLOT_WIDTH - KEY_WIDTH;
+        truncated_label(ui, &name, egui::TextStyle::Heading, name_width);
+        ui.label(RichText::new(key.to_string()).weak())
+            .on_hover_text("The detected key, with its position on the Camelot wheel");
+    });
+}
+
+/// Show a single line of text, cutting it off at `max_width`. The full text is
+/// shown on hover.
+fn truncated_label(ui: &mut egui::Ui, text: &str, style: egui::TextStyle, max_width: f32) {
     ui.vertical(|ui| {
-        ui.set_max_size(vec2(BAR_PLOT_WIDTH, 0.0));
+        ui.set_max_size(vec2(max_width, 0.0));
 
-        let font_id = egui::TextStyle::Heading.resolve(ui.style());
+        let font_id = style.resolve(ui.style());
         let mut job = eframe::epaint::text::LayoutJob::single_section(
-            item.name.clone(),
+            text.to_string(),
             egui::TextFormat {
                 font_id,
                 color: Color32::WHITE,
@@ -561,10 +1113,169 @@ fn render_item_name(ui: &mut egui::Ui, item: &Item) {
             ..Default::default()
         };
 
-        ui.label(job).on_hover_text_at_pointer(&item.name);
+        ui.label(job).on_hover_text_at_pointer(text);
     });
 }
 
+/// Show a button toggling playback of the item. `source_playlist` is the
+/// playlist the item gets started from when clicked.
+fn play_pause_button(
+    ui: &mut egui::Ui,
+    item: &mut Item,
+    source_playlist: Option<u64>,
+    channel: &Sender<ControlMessage>,
+) {
+    match item.status {
+        ItemStatus::Stopped | ItemStatus::Paused => {
+            if ui.button(RichText::new("▶").heading()).clicked() {
+                toggle_playback(item, source_playlist, channel);
+            }
+        }
+        ItemStatus::Loading => {
+            ui.spinner();
+        }
+        ItemStatus::Playing => {
+            if ui.button(RichText::new("⏸").heading()).clicked() {
+                toggle_playback(item, source_playlist, channel);
+            }
+        }
+    };
+}
+
+/// Start an item from the given playlist, or pause it if it's playing. Items
+/// still loading are left alone.
+fn toggle_playback(
+    item: &mut Item,
+    source_playlist: Option<u64>,
+    channel: &Sender<ControlMessage>,
+) {
+    match item.status {
+        ItemStatus::Stopped | ItemStatus::Paused => {
+            item.status = ItemStatus::Loading;
+            item.source_playlist = source_playlist;
+            channel.send(ControlMessage::Play(item.id)).unwrap();
+        }
+        ItemStatus::Loading => (),
+        ItemStatus::Playing => {
+            item.status = ItemStatus::Paused;
+            channel.send(ControlMessage::Pause(item.id)).unwrap();
+        }
+    }
+}
+
+fn stop_button(ui: &mut egui::Ui, item: &mut Item, channel: &Sender<ControlMessage>) {
+    let button = ui.add_enabled(
+        item.status != ItemStatus::Stopped,
+        Button::new(RichText::new("⏹").heading()),
+    );
+    if button.on_hover_text("Stop").clicked() {
+        item.status = ItemStatus::Stopped;
+        item.position = 0.0;
+        item.target_position = 0.0;
+        channel.send(ControlMessage::Stop(item.id)).unwrap();
+    }
+}
+
+fn volume_slider(ui: &mut egui::Ui, item: &mut Item, channel: &Sender<ControlMessage>) {
+    let mut db = amplitude_to_db(item.volume);
+    let resp = db_fader(ui, &mut db);
+    if resp.changed() {
+        item.volume = db_to_amplitude(db);
+    }
+    if fader_settled(ui, &resp) {
+        channel
+            .send(ControlMessage::SetVolume(item.id, item.volume))
+            .unwrap();
+    }
+}
+
+/// Pick the output device to route to, `None` being the default output.
+fn output_picker(
+    ui: &mut egui::Ui,
+    id_source: impl std::hash::Hash,
+    output: &mut Option<String>,
+    outputs: &[String],
+) {
+    egui::ComboBox::from_id_source(id_source)
+        .selected_text(output.as_deref().unwrap_or("Default"))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(output, None, "Default");
+            for device in outputs {
+                ui.selectable_value(output, Some(device.clone()), device);
+            }
+        });
+}
+
+/// Pick a pair of channels of an output, for interfaces with more than two.
+fn channel_pair_picker(ui: &mut egui::Ui, id_source: impl std::hash::Hash, pair: &mut usize) {
+    let describe = |pair: usize| format!("{}/{}", 2 * pair + 1, 2 * pair + 2);
+    egui::ComboBox::from_id_source(id_source)
+        .width(60.0)
+        .selected_text(describe(*pair))
+        .show_ui(ui, |ui| {
+            for option in 0..MAX_CHANNEL_PAIRS {
+                ui.selectable_value(pair, option, describe(option));
+            }
+        })
+        .response
+        .on_hover_text(
+            "The channels played through. Fails to play if the output has fewer channels.",
+        );
+}
+
+/// A fader in decibels. Double-click resets it to unity gain. Returns whether
+/// the value was changed.
+fn db_fader(ui: &mut egui::Ui, db: &mut f64) -> egui::Response {
+    let mut resp = ui.add(
+        Slider::new(db, SILENCE_DB..=MAX_GAIN_DB)
+            .step_by(0.5)
+            .custom_formatter(|db, _| {
+                if db <= SILENCE_DB {
+                    "-∞ dB".to_string()
+                } else {
+                    format!("{:+.1} dB", db)
+                }
+            })
+            .custom_parser(|text| {
+                let text = text.trim().trim_end_matches("dB").trim();
+                match text {
+                    "-∞" | "-inf" => Some(SILENCE_DB),
+                    _ => text.parse().ok(),
+                }
+            }),
+    );
+    if resp.double_clicked() {
+        *db = 0.0;
+        resp.mark_changed();
+    }
+    resp
+}
+
+/// Whether the value of a fader should be sent to the engine. While it's
+/// dragged, changes go out at most every [`VOLUME_UPDATE_INTERVAL`] ms, with
+/// the final value sent on release.
+fn fader_settled(ui: &egui::Ui, resp: &egui::Response) -> bool {
+    let interval = Duration::from_millis(VOLUME_UPDATE_INTERVAL);
+    let now = Instant::now();
+    // when the last value was sent and whether a newer one is waiting
+    let (last_sent, pending) = ui
+        .data(|d| d.get_temp::<(Option<Instant>, bool)>(resp.id))
+        .unwrap_or_default();
+    let pending = pending || resp.changed();
+    let due = last_sent.is_none_or(|sent| now.duration_since(sent) >= interval);
+
+    if pending && (due || !resp.dragged()) {
+        ui.data_mut(|d| d.insert_temp(resp.id, (Some(now), false)));
+        true
+    } else {
+        if pending {
+            ui.ctx().request_repaint_after(interval);
+        }
+        ui.data_mut(|d| d.insert_temp(resp.id, (last_sent, pending)));
+        false
+    }
+}
+
 fn show_import_progress_indicator(
     ui: &mut egui::Ui,
     status: &ItemImportStatus,
@@ -582,12 +1293,13 @@ fn show_import_progress_indicator(
                 ui.spinner().on_hover_text_at_pointer("processing…");
             }
             ItemImportStatus::Finished => {
-                ui.colored_label(GREEN, "✔")
+                ui.colored_label(GREEN.drawn(), "✔")
                     .on_hover_text_at_pointer("finished");
                 *finished += 1;
             }
             ItemImportStatus::Failed(err) => {
-                ui.colored_label(RED, "🗙").on_hover_text_at_pointer(err);
+                ui.colored_label(RED.drawn(), "🗙")
+                    .on_hover_text_at_pointer(err);
             }
         }
         ui.label(name);
@@ -595,22 +1307,92 @@ fn show_import_progress_indicator(
 }
 
 impl SharedModel {
-    pub fn render_ui(&mut self, ctx: &egui::Context) {
-        let model = self.model.clone();
-        let mut model = model.write();
+    /// Draw a frame from the copy of the model. The shared model is only
+    /// locked to bring the copy up to date and to carry the changes made while
+    /// drawing back. Control messages are held back until then, so that the
+    /// engine sees the changes they rely on.
+    pub fn render_ui(&mut self, ctx: &egui::Context, native_pixels_per_point: Option<f32>) {
+        let _span = trace_span!("interface").entered();
+        self.crash_prompt(ctx);
+        let shared = self.model.clone();
+        {
+            let waiting = Instant::now();
+            let shared = shared.read();
+            self.diagnostics.record_lock_wait(waiting.elapsed());
+            self.base.refresh_from(&shared);
+            self.view.refresh_from(&shared);
+        }
+        self.diagnostics.record_engine(&self.view.engine);
+
+        let mut model = std::mem::take(&mut self.view);
+        let mut interface = std::mem::take(&mut self.interface);
+        let (tx, rx) = channel();
+        self.render_frame(
+            ctx,
+            native_pixels_per_point,
+            &mut model,
+            &mut interface,
+            tx,
+            &shared,
+        );
+        self.view = model;
+        self.interface = interface;
+
+        let waiting = Instant::now();
+        let mut shared = shared.write();
+        self.diagnostics.record_lock_wait(waiting.elapsed());
+        shared.merge(&self.base, &self.view);
+        drop(shared);
+        for msg in rx.try_iter() {
+            self.play_channel.send(msg).unwrap();
+        }
+    }
+
+    fn render_frame(
+        &mut self,
+        ctx: &egui::Context,
+        native_pixels_per_point: Option<f32>,
+        model: &mut Model,
+        interface: &mut Interface,
+        channel: Sender<ControlMessage>,
+        shared: &Arc<RwLock<Model>>,
+    ) {
         ctx.request_repaint_after(std::time::Duration::from_millis(PLAYBACK_SYNC_INTERVAL));
+        self.toasts.extend(self.notifications.try_iter());
+
+        apply_touch_style(ctx, model.settings.touch_mode);
+        apply_ui_scale(ctx, &mut model.settings, native_pixels_per_point);
+        self.sync_remote(model);
+        self.sync_link(model);
+        let mut state = UIState::new(model, interface, channel, shared);
+
+        if !state.interface.performance_mode {
+            egui::SidePanel::left("playlist menu")
+                .resizable(true)
+                .default_width(150.0)
+                .width_range(120.0..=400.0)
+                .show(ctx, |ui| {
+                    state.playlist_menu(ui);
+                });
+        }
 
-        let mut state = UIState::new(&mut model, self.play_channel.clone());
+        self.diagnostics
+            .window(ctx, &mut state.interface.diagnostics_open);
 
-        egui::SidePanel::left("playlist menu")
-            .resizable(true)
-            .default_width(150.0)
-            .width_range(120.0..=400.0)
-            .show(ctx, |ui| {
-                state.playlist_menu(ui);
-            });
+        egui::TopBottomPanel::bottom("engine status").show(ctx, |ui| {
+            engine_status_bar(ui, &state.model.engine);
+        });
+
+        egui::TopBottomPanel::bottom("now playing").show(ctx, |ui| {
+            state.now_playing_bar(ui);
+        });
+
+        let central = egui::CentralPanel::default().show(ctx, |ui| {
+            if state.interface.performance_mode {
+                state.performance_view(ui);
+                return;
+            }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
             ui.allocate_ui_with_layout(
                 vec2(ui.available_size_before_wrap().x, 0.0),
                 egui::Layout::left_to_right(egui::Align::Center),
@@ -618,16 +1400,148 @@ impl SharedModel {
                     state.search_bar(ui);
                     state.playlist_creation_window(ui);
 
-                    let [import_button_response, play_resp, pause_resp, stop_resp, into_playlist_resp] =
+                    let [import_button_response, play_resp, pause_resp, resume_resp, stop_resp, into_playlist_resp] =
                         state.render_top_button_bar(ui);
 
-                    state.handle_playback_control_buttons(play_resp, pause_resp, stop_resp);
+                    state.handle_playback_control_buttons(
+                        play_resp,
+                        pause_resp,
+                        resume_resp,
+                        stop_resp,
+                    );
+                    state.go_button(ui);
+                    let learn = ui
+                        .selectable_label(state.interface.midi_learning, "🎹 Learn")
+                        .on_hover_text("Map controls to a MIDI device by example");
+                    if learn.clicked() {
+                        state.interface.midi_learning = !state.interface.midi_learning;
+                        state.model.midi_learn = None;
+                    }
+                    if ui.button("⚙").on_hover_text("Settings").clicked() {
+                        state.interface.settings_open = !state.interface.settings_open;
+                    }
+                    if ui
+                        .button("🎭 Perform")
+                        .on_hover_text("Show only the pads of the selected playlist")
+                        .clicked()
+                    {
+                        state.interface.performance_mode = true;
+                    }
+                    if ui
+                        .button("📋 Report")
+                        .on_hover_text("Save what was played during the session as CSV or JSON")
+                        .clicked()
+                    {
+                        save_report(state.model.history.clone());
+                    }
+                    if ui
+                        .button("📊 Stats")
+                        .on_hover_text("How much the items and playlists get used")
+                        .clicked()
+                    {
+                        state.interface.stats_open = !state.interface.stats_open;
+                    }
+                    if ui
+                        .button("🗗 Window")
+                        .on_hover_text(
+                            "Show the selected playlist in another window, \
+                            to drag items between them",
+                        )
+                        .clicked()
+                    {
+                        let selected = state.model.selected_playlist;
+                        state.open_view(selected);
+                    }
+                    ui.menu_button("Generate", |ui| {
+                        let signals = [
+                            Signal::Sine(DEFAULT_FREQUENCY),
+                            Signal::PinkNoise,
+                            Signal::WhiteNoise,
+                        ];
+                        for signal in signals {
+                            if ui.button(signal.to_string()).clicked() {
+                                state.add_generated_item(signal);
+                                ui.close_menu();
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("Add a tone or noise, for checking lines and tuning the room");
+                    ui.menu_button("Stream", |ui| {
+                        let url_id = egui::Id::new("stream url");
+                        let mut url = ui.data(|d| d.get_temp::<String>(url_id)).unwrap_or_default();
+                        ui.label("Address of an Icecast or Shoutcast stream:");
+                        let edit = ui.add(
+                            egui::TextEdit::singleline(&mut url).hint_text("http://"),
+                        );
+                        let valid = is_stream_url(&url);
+                        let entered = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        let add = ui.add_enabled(valid, Button::new("Add stream"));
+                        if valid && (entered || add.clicked()) {
+                            state.add_stream_item(url.trim().to_string());
+                            url.clear();
+                            ui.close_menu();
+                        }
+                        ui.data_mut(|d| d.insert_temp(url_id, url));
+                    })
+                    .response
+                    .on_hover_text("Add an internet radio stream, for background music");
+                    let mut video_url = None;
+                    ui.menu_button("Video", |ui| {
+                        let url_id = egui::Id::new("video url");
+                        let mut url = ui.data(|d| d.get_temp::<String>(url_id)).unwrap_or_default();
+                        ui.label("Address of a video to take the audio of:");
+                        let edit = ui.add(
+                            egui::TextEdit::singleline(&mut url).hint_text("https://"),
+                        );
+                        let valid = is_stream_url(&url) && self.import_state.is_none();
+                        let entered = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        let add = ui.add_enabled(valid, Button::new("Download and import"));
+                        if valid && (entered || add.clicked()) {
+                            video_url = Some(url.trim().to_string());
+                            url.clear();
+                            ui.close_menu();
+                        }
+                        ui.data_mut(|d| d.insert_temp(url_id, url));
+                    })
+                    .response
+                    .on_hover_text("Import the audio of an online video, downloaded with yt-dlp");
+                    ui.menu_button("Speech", |ui| {
+                        let text_id = egui::Id::new("speech text");
+                        let mut text = ui.data(|d| d.get_temp::<String>(text_id)).unwrap_or_default();
+                        ui.label("Text of the announcement:");
+                        ui.add(
+                            egui::TextEdit::multiline(&mut text)
+                                .hint_text("Doors in five minutes")
+                                .desired_rows(3),
+                        );
+                        let valid = !text.trim().is_empty();
+                        if ui
+                            .add_enabled(valid, Button::new("Add announcement"))
+                            .clicked()
+                        {
+                            state.add_speech_item(text.trim().to_string());
+                            text.clear();
+                            ui.close_menu();
+                        }
+                        ui.data_mut(|d| d.insert_temp(text_id, text));
+                    })
+                    .response
+                    .on_hover_text("Add an announcement spoken by the speech engine");
                     if into_playlist_resp.clicked() {
                         state.playlist_from_search();
                     }
 
-                    if import_button_response.clicked() && self.import_state.is_none() {
-                        self.begin_import();
+                    if let Some(url) = video_url {
+                        self.import_video(url, state.model.settings.colour_rules.clone());
+                    } else if import_button_response.clicked() && self.import_state.is_none() {
+                        self.begin_import(state.model.settings.colour_rules.clone());
+                    } else if self.import_state.is_none() {
+                        let paths = files_to_import(ctx);
+                        if !paths.is_empty() {
+                            info!("importing {} dropped or pasted files", paths.len());
+                            self.import_files(paths, state.model.settings.colour_rules.clone());
+                        }
                     }
                     if let Some((rx, import_state)) = &self.import_state {
                         let (keep_win_open, imported) =
@@ -644,91 +1558,119 @@ impl SharedModel {
             );
 
             ui.vertical(|ui| {
+                state.playlist_header(ui);
                 state.items(ui);
-            })
+            });
         });
-
+        if let Some(playlist) = state.model.selected_playlist {
+            state
+                .drop_targets
+                .push(DropTarget::new(&central.response, playlist));
+        }
+        state.views(ctx);
+
+        state.touch_context_menu(ctx);
+        state.settings_window(ctx);
+        state.stats_window(ctx);
+        state.item_details_window(ctx);
+        state.skip_hotkeys(ctx);
+        if ctx.memory(|m| m.focused()).is_none()
+            && state.model.settings.keymap.consume(ctx, Action::Locate)
+        {
+            state.locate_playing(ctx);
+        }
+        state.help_overlay(ctx);
+        state.resume_prompt(ctx);
+        state.drop_dragged_item(ctx);
+        self.toasts(ctx, &state.model.items);
         preview_files_being_dropped(ctx);
     }
 }
 
-fn render_bar_chart(
-    unique_id: usize,
-    channel: &Sender<ControlMessage>,
-    ui: &mut egui::Ui,
-    item: &Item,
-) {
-    let id = format!("frequency graph for {}, {}", item.id, unique_id);
-    let bg = ui.style().visuals.window_fill();
-    let dimmed = bg.mix(0.4, &item.colour);
-
-    let plot_x = ui.cursor().left();
-    let resp = Plot::new(id)
-        .height(30.0)
-        .width(BAR_PLOT_WIDTH)
-        .include_y(1.0)
-        .include_y(-1.0)
-        .set_margin_fraction(vec2(0.0, 0.0))
-        .allow_boxed_zoom(false)
-        .allow_drag(false)
-        .allow_scroll(false)
-        .allow_zoom(false)
-        .show_axes([false; 2])
-        .show_background(false)
-        .show_x(false)
-        .show_y(false)
-        .show(ui, |plot| {
-            let mut data = Vec::with_capacity(item.bars.len() * 2);
-            for (i, height) in item.bars.iter().copied().enumerate() {
-                let height = height as f64 / 255.0;
-                for direction in [-1.0, 1.0] {
-                    let muted_modifier = if item.muted { 0.0001 } else { 1.0 };
-                    let mut bar =
-                        Bar::new(i as f64, muted_modifier * item.volume * direction * height);
-                    bar.bar_width = 0.4;
-                    bar.stroke = Stroke::none();
-                    let fill_level = ((item.position / item.duration) * item.bars.len() as f64
-                        - i as f64)
-                        .clamp(0.0, 1.0);
-                    bar.fill = dimmed.mix(fill_level as f32, &item.colour);
-                    data.push(bar);
-                }
-            }
-            let chart = BarChart::new(data);
-            plot.bar_chart(chart);
-        });
+fn engine_status_bar(ui: &mut egui::Ui, engine: &EngineStatus) {
+    ui.horizontal(|ui| {
+        let small = |text: String| RichText::new(text).small();
+        ui.label(small(engine.backend.clone()).weak());
+        ui.separator();
+        match &engine.device {
+            Some(device) => ui.label(small(device.clone())),
+            None => ui.colored_label(RED.drawn(), small("no output device".to_string())),
+        };
+        ui.separator();
+        if let Some(sample_rate) = engine.sample_rate {
+            ui.label(small(format!("{:.1} kHz", sample_rate as f64 / 1000.0)));
+            ui.separator();
+        }
+        ui.label(small(format!("{} voices", engine.voices)));
+        ui.separator();
+        ui.label(small(format!(
+            "{} queued",
+            engine.queue_depth + engine.backlog
+        )))
+        .on_hover_text("Control messages waiting for the playback thread");
+        if engine.dropped_messages > 0 {
+            ui.separator();
+            ui.colored_label(
+                RED.drawn(),
+                small(format!("{} dropped", engine.dropped_messages)),
+            )
+            .on_hover_text(
+                "Control messages dropped since the playback thread fell too far behind",
+            );
+        }
+    });
+}
 
-    handle_bar_chart_interaction(channel, resp.response, plot_x, item);
+/// How long the card of a located item flashes, in seconds.
+const LOCATE_FLASH: f64 = 1.5;
+/// Where the item located last is kept in the egui memory.
+const LOCATED: &str = "located item";
+
+/// The item located last, see [`UIState::locate_playing`].
+#[derive(Clone, Copy)]
+struct Located {
+    item: u64,
+    /// When it was located, in the time of the egui input.
+    since: f64,
+    /// Whether the view has yet to be scrolled to it.
+    scroll: bool,
 }
 
-fn handle_bar_chart_interaction(
-    channel: &Sender<ControlMessage>,
-    response: egui::Response,
-    plot_x: f32,
-    item: &Item,
-) {
-    let drag_distance = response.drag_delta().x;
-    if drag_distance != 0.0 {
-        let duration = item.duration as f32;
-        let new_position = item.position as f32 + drag_distance * duration / BAR_PLOT_WIDTH;
-        let new_position = new_position.clamp(0.0, duration) as f64;
+/// The skip buttons of items, in seconds.
+const SKIPS: [i16; 4] = [-30, -5, 5, 30];
+
+/// The colour to draw an item in, and the texture of its played part, from
+/// the accessible palette if the settings ask for it.
+fn display_colour(colour: Colour, colour_blind: bool) -> (Color32, usize) {
+    let (colour, texture) = if colour_blind {
+        accessible_colour(colour)
+    } else {
+        (colour, 0)
+    };
+    (colour.drawn(), texture)
+}
 
-        channel
-            .send(ControlMessage::Seek(item.id, new_position))
-            .unwrap();
-        return;
-    }
-    if let Some(pos) = response
-        .interact_pointer_pos()
-        .filter(|_| response.clicked())
-    {
-        let duration = item.duration as f32;
-        let new_position = (pos.x - plot_x) * duration / BAR_PLOT_WIDTH;
-        let new_position = new_position.clamp(0.0, duration) as f64;
-        channel
-            .send(ControlMessage::Seek(item.id, new_position))
-            .unwrap();
-    }
+/// Files dropped onto the window or pasted while no text field has focus.
+fn files_to_import(ctx: &egui::Context) -> Vec<std::path::PathBuf> {
+    let focused = ctx.memory(|m| m.focused()).is_some();
+    ctx.input(|input| {
+        let dropped = input
+            .raw
+            .dropped_files
+            .iter()
+            .filter_map(|file| file.path.clone());
+        let pasted = input
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                egui::Event::Paste(text) if !focused => {
+                    Some(crate::import::paths_from_clipboard(text))
+                }
+                _ => None,
+            })
+            .flatten();
+        dropped.chain(pasted).collect()
+    })
 }
 
 /// Preview hovering files:
@@ -736,22 +1678,25 @@ fn preview_files_being_dropped(ctx: &egui::Context) {
     use egui::*;
     use std::fmt::Write as _;
 
-    if !ctx.input().raw.hovered_files.is_empty() {
-        let mut text = "Dropping files:\n".to_owned();
-        for file in &ctx.input().raw.hovered_files {
-            if let Some(path) = &file.path {
-                write!(text, "\n{}", path.display()).ok();
-            } else if !file.mime.is_empty() {
-                write!(text, "\n{}", file.mime).ok();
-            } else {
-                text += "\n???";
+    if !ctx.input(|i| i.raw.hovered_files.is_empty()) {
+        let text = ctx.input(|i| {
+            let mut text = "Dropping files:\n".to_owned();
+            for file in &i.raw.hovered_files {
+                if let Some(path) = &file.path {
+                    write!(text, "\n{}", path.display()).ok();
+                } else if !file.mime.is_empty() {
+                    write!(text, "\n{}", file.mime).ok();
+                } else {
+                    text += "\n???";
+                }
             }
-        }
+            text
+        });
 
         let painter =
             ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file_drop_target")));
 
-        let screen_rect = ctx.input().screen_rect();
+        let screen_rect = ctx.screen_rect();
         painter.rect_filled(screen_rect, 0.0, Color32::from_black_alpha(192));
         painter.text(
             screen_rect.center(),
@@ -762,3 +1707,70 @@ fn preview_files_being_dropped(ctx: &egui::Context) {
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn runtimes() {
+        assert_eq!(format_runtime(0.0), "0:00");
+        assert_eq!(format_runtime(1205.4), "20:05");
+        assert_eq!(format_runtime(3723.0), "1:02:03");
+        assert_eq!(runtime_summary(1, 59.6, 0), "1 item, 1:00");
+        assert_eq!(runtime_summary(3, 90.0, 1), "3 items, 1:30 plus a stream");
+    }
+
+    #[test]
+    fn ago() {
+        let ago = |seconds| format_ago(std::time::Duration::from_secs(seconds));
+        assert_eq!(ago(59), "just now");
+        assert_eq!(ago(600), "10 min ago");
+        assert_eq!(ago(3 * 3600 + 125), "3 h 2 min ago");
+    }
+
+    #[test]
+    fn summaries() {
+        let mut item = Item::with_default_stem(
+            1,
+            "Rain".to_string(),
+            "/sounds/rain.ogg".to_string(),
+            BLUE,
+            83.25,
+        );
+        item.icon = Some("🌧".to_string());
+        item.tempo = Some(Tempo {
+            bpm: 120.0,
+            offset: 0.0,
+        });
+        item.loudness = Some(-14.25);
+        assert_eq!(stem_location(&item), "/sounds/rain.ogg");
+        assert_eq!(
+            item_summary(&item),
+            "🌧 Rain\n\
+            Duration: 1:23.25\n\
+            File: /sounds/rain.ogg\n\
+            Tempo: 120.0 BPM\n\
+            Loudness: -14.2 LUFS"
+        );
+
+        let stream = Item::stream(2, "http://radio.example/live".to_string(), BLUE);
+        assert_eq!(stem_location(&stream), "http://radio.example/live");
+        assert!(item_summary(&stream).contains("Duration: live stream"));
+        // relative paths are resolved against the working directory
+        item.stems[0].path = "rain.ogg".to_string();
+        assert!(std::path::Path::new(&stem_location(&item)).is_absolute());
+    }
+
+    #[test]
+    fn timestamps_round_trip() {
+        assert_eq!(parse_timestamp("12:34.5"), Some(754.5));
+        assert_eq!(parse_timestamp(" 1:02:03 "), Some(3723.0));
+        assert_eq!(parse_timestamp("95"), Some(95.0));
+        assert_eq!(parse_timestamp(&format_timestamp(83.25)), Some(83.25));
+        assert_eq!(parse_timestamp("1:2:3:4"), None);
+        assert_eq!(parse_timestamp("-5"), None);
+        assert_eq!(parse_timestamp("abc"), None);
+        assert_eq!(parse_timestamp(""), None);
+    }
+}
@@ -0,0 +1,351 @@
+//! Soundboard grids of pads, and performance mode, which reduces the window
+//! to them.
+
+use super::*;
+
+pub const GRID_CELL_SIZE: egui::Vec2 = vec2(BAR_PLOT_WIDTH + 40.0, 110.0);
+
+impl<'a> UIState<'a> {
+    /// Lay out the items of the selected playlist on the current bank of its
+    /// soundboard grid. Outside performance mode, items are moved between cells
+    /// by dragging their handle.
+    pub(super) fn items_grid(&mut self, ui: &mut egui::Ui, filtered_ids: Vec<(usize, u64)>) {
+        let Some(grid) = self.selected_playlist_mut().and_then(|playlist| {
+            let grid = playlist.grid.as_mut()?;
+            grid.arrange(&playlist.items);
+            Some(grid.clone())
+        }) else {
+            return;
+        };
+
+        self.bank_selector(ui, &grid);
+
+        let occupants: HashMap<_, _> = filtered_ids
+            .into_iter()
+            .filter_map(|(position_within_playlist, item_id)| {
+                let cell = grid.cells.get(&item_id)?;
+                Some((*cell, (position_within_playlist, item_id)))
+            })
+            .collect();
+        // the first pads of the bank in reading order answer to number keys
+        let mut quick: Vec<_> = occupants
+            .iter()
+            .filter(|(cell, _)| cell.bank == grid.bank)
+            .map(|(cell, &(_, item_id))| (cell.row, cell.column, item_id))
+            .collect();
+        quick.sort_unstable();
+        quick.truncate(if self.interface.performance_mode {
+            QUICK_TRIGGERS
+        } else {
+            0
+        });
+        let quick: Vec<_> = quick.into_iter().map(|(_, _, item_id)| item_id).collect();
+        if ui.ctx().memory(|m| m.focused()).is_none() {
+            for (pad, &item_id) in quick.iter().enumerate() {
+                let action = Action::TriggerPad(pad as u8);
+                if self.model.settings.keymap.consume(ui.ctx(), action) {
+                    let item_index = self.item_index(item_id);
+                    let source_playlist = self.model.selected_playlist;
+                    toggle_playback(
+                        &mut self.model.items[item_index],
+                        source_playlist,
+                        &self.channel,
+                    );
+                }
+            }
+        }
+
+        let playlist = self.model.selected_playlist;
+        egui::ScrollArea::both()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                // a spare row to drag items into
+                for row in 0..grid.rows(grid.bank) + 1 {
+                    ui.horizontal(|ui| {
+                        for column in 0..grid.columns {
+                            let cell = Cell {
+                                bank: grid.bank,
+                                column,
+                                row,
+                            };
+                            let occupant = occupants.get(&cell).copied();
+                            let pad = occupant.and_then(|(_, item_id)| {
+                                quick.iter().position(|&id| id == item_id)
+                            });
+                            let resp = self.grid_cell(ui, occupant, pad);
+                            if let Some(playlist) = playlist {
+                                self.drop_targets.push(DropTarget {
+                                    cell: Some(cell),
+                                    ..DropTarget::new(&resp, playlist)
+                                });
+                            }
+                        }
+                    });
+                }
+            });
+    }
+
+    /// Show a cell of the grid and the item in it, if any. Pads triggered
+    /// from the keyboard are numbered.
+    fn grid_cell(
+        &mut self,
+        ui: &mut egui::Ui,
+        occupant: Option<(usize, u64)>,
+        pad: Option<usize>,
+    ) -> egui::Response {
+        let resp = ui.allocate_ui(GRID_CELL_SIZE, |ui| {
+            ui.set_min_size(GRID_CELL_SIZE);
+            let Some((position_within_playlist, item_id)) = occupant else {
+                return;
+            };
+            let action = pad.map(|pad| Action::TriggerPad(pad as u8));
+            let keys = action.map(|action| self.model.settings.keymap.describe(ui.ctx(), action));
+            if let (Some(action), Some(keys)) = (action, keys.filter(|keys| !keys.is_empty())) {
+                ui.label(RichText::new(keys).heading().weak())
+                    .on_hover_text(action.description());
+            }
+            if !self.interface.performance_mode {
+                self.drag_handle(ui, item_id);
+            }
+            let item_index = self.item_index(item_id);
+            self.animate_position(ui, item_index);
+            self.item_frame(position_within_playlist, ui, item_index);
+        });
+        let located = ui.data(|d| d.get_temp::<Located>(egui::Id::new(LOCATED)));
+        if let Some(located) = located.filter(|located| located.scroll) {
+            if occupant.is_some_and(|(_, item_id)| item_id == located.item) {
+                ui.scroll_to_rect(resp.response.rect, Some(egui::Align::Center));
+                ui.data_mut(|d| {
+                    d.insert_temp(
+                        egui::Id::new(LOCATED),
+                        Located {
+                            scroll: false,
+                            ..located
+                        },
+                    )
+                });
+            }
+        }
+        resp.response
+    }
+
+    /// Show a handle for dragging an item to another cell of a grid or onto
+    /// a playlist, see [`UIState::drop_dragged_item`].
+    pub(super) fn drag_handle(&mut self, ui: &mut egui::Ui, item_id: u64) {
+        let handle = egui::Label::new("✥").sense(egui::Sense::drag());
+        let hover = "Drag to move, or onto a playlist or another window to add it there";
+        if ui.add(handle).on_hover_text(hover).drag_started() {
+            self.interface.dragged_item = Some((item_id, ui.ctx().viewport_id()));
+        }
+    }
+
+    /// Show the item being dragged at the pointer and the area it would be
+    /// dropped on, as far as they're in the window `ctx` draws.
+    pub(super) fn paint_drag(&self, ctx: &egui::Context) {
+        let Some((item_id, _)) = self.interface.dragged_item else {
+            return;
+        };
+        if let Some(item) = self.model.items.iter().find(|i| i.id == item_id) {
+            egui::show_tooltip_at_pointer(ctx, egui::Id::new("dragged item"), |ui| {
+                ui.label(item.labelled_name());
+            });
+        }
+        let highlight = self.interface.drop_highlight;
+        if let Some((_, rect)) = highlight.filter(|(viewport, _)| *viewport == ctx.viewport_id()) {
+            let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop target"));
+            ctx.layer_painter(layer)
+                .rect_stroke(rect, 4.0, Stroke::new(2.0, YELLOW.drawn()));
+        }
+    }
+
+    /// Add the dragged item to the playlist it's dropped on, be it in the
+    /// sidebar, in a cell of a grid or shown in another window. The window the
+    /// drag started in keeps getting the pointer, so where it's released is
+    /// worked out from there, on the screen when it's over another window.
+    /// Called once all the windows are drawn.
+    pub(super) fn drop_dragged_item(&mut self, ctx: &egui::Context) {
+        let Some((item_id, source)) = self.interface.dragged_item else {
+            return;
+        };
+        let (pos, released) = ctx.input_for(source, |input| {
+            (input.pointer.interact_pos(), input.pointer.any_released())
+        });
+        let origin = |viewport| {
+            ctx.input_for(viewport, |input| input.viewport().inner_rect)
+                .map(|rect| rect.min.to_vec2())
+        };
+        let on_screen = pos.zip(origin(source)).map(|(pos, origin)| pos + origin);
+
+        // the other windows are usually in front of the main one
+        let mut targets = self.drop_targets.clone();
+        targets.sort_by_key(|target| target.viewport == egui::ViewportId::ROOT);
+        let target = targets.into_iter().find(|target| {
+            let pos = match target.viewport == source {
+                true => pos,
+                false => on_screen
+                    .zip(origin(target.viewport))
+                    .map(|(pos, origin)| pos - origin),
+            };
+            // a floating window may be covering the area
+            pos.is_some_and(|pos| {
+                target.rect.contains(pos)
+                    && (target.viewport != ctx.viewport_id()
+                        || ctx.layer_id_at(pos) == Some(target.layer))
+            })
+        });
+        self.interface.drop_highlight = target.map(|target| (target.viewport, target.rect));
+        self.paint_drag(ctx);
+        if !released {
+            return;
+        }
+
+        self.interface.dragged_item = None;
+        self.interface.drop_highlight = None;
+        let playlist = target.and_then(|target| {
+            let playlist = self
+                .model
+                .playlists
+                .iter_mut()
+                .find(|playlist| playlist.id == target.playlist)?;
+            Some((playlist, target.cell))
+        });
+        if let Some((playlist, cell)) = playlist {
+            if !playlist.items.contains(&item_id) {
+                playlist.items.push(item_id);
+            }
+            if let (Some(cell), Some(grid)) = (cell, playlist.grid.as_mut()) {
+                grid.arrange(&playlist.items);
+                grid.move_to(item_id, cell);
+            }
+        }
+    }
+
+    /// Show tabs for switching between the banks of a grid, which can also be
+    /// switched from the keyboard.
+    fn bank_selector(&mut self, ui: &mut egui::Ui, grid: &Grid) {
+        let keymap = &self.model.settings.keymap;
+
+        let mut bank = grid.bank;
+        ui.horizontal(|ui| {
+            ui.label("Bank:");
+            for i in 0..BANKS {
+                let action = Action::SelectBank(i);
+                let occupied = grid.cells.values().any(|cell| cell.bank == i);
+                let mut text = RichText::new(format!("{}", i + 1)).heading();
+                if !occupied {
+                    text = text.weak();
+                }
+                let resp = ui
+                    .selectable_label(bank == i, text)
+                    .on_hover_text(keymap.describe(ui.ctx(), action));
+                if resp.clicked() || keymap.consume(ui.ctx(), action) {
+                    bank = i;
+                }
+            }
+        });
+
+        if self.interface.midi_learning {
+            ui.horizontal(|ui| {
+                for i in 0..BANKS {
+                    self.midi_learn_button(ui, MidiTarget::Bank(i), &format!("{}", i + 1));
+                }
+            });
+        }
+
+        if bank != grid.bank {
+            if let Some(grid) = self.selected_playlist_mut().and_then(|p| p.grid.as_mut()) {
+                grid.bank = bank;
+            }
+        }
+    }
+
+    /// Show the pads of the selected playlist's grid with nothing but playback
+    /// controls around them.
+    pub(super) fn performance_view(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .button(RichText::new("✖").heading())
+                .on_hover_text("Leave performance mode")
+                .clicked()
+            {
+                self.interface.performance_mode = false;
+            }
+            if let Some(playlist) = self.selected_playlist() {
+                ui.heading(&playlist.name);
+            }
+            self.go_button(ui);
+        });
+        ui.separator();
+
+        if self.selected_playlist().is_some_and(|p| p.grid.is_some()) {
+            let all_ids = self.search_in_playlist(self.selected_playlist(), vec![]);
+            self.items_grid(ui, all_ids);
+        } else {
+            ui.vertical_centered(|ui| {
+                ui.heading("Select a playlist with a fixed grid to perform with it.");
+            });
+        }
+    }
+
+    /// Show the GO button along with the name of the cue on standby. GO fires
+    /// the standby cue of the selected playlist and moves the standby pointer
+    /// on to the next one. Space acts as GO while no widget has focus.
+    pub(super) fn go_button(&mut self, ui: &mut egui::Ui) {
+        let standby = self.model.standby_cue();
+
+        let go = Button::new(RichText::new("GO").heading().strong().color(Color32::BLACK)).fill(
+            if standby.is_some() {
+                GREEN.drawn()
+            } else {
+                Color32::GRAY
+            },
+        );
+        let keymap = &self.model.settings.keymap;
+        let clicked = ui
+            .add_enabled(standby.is_some(), go)
+            .on_hover_text(format!(
+                "Fire the standby cue ({})",
+                keymap.describe(ui.ctx(), Action::Go)
+            ))
+            .clicked();
+        let ctx = ui.ctx().clone();
+        let hotkey = ctx.memory(|m| m.focused()).is_none() && keymap.consume(&ctx, Action::Go);
+        if self.interface.midi_learning {
+            self.midi_learn_button(ui, MidiTarget::Go, "GO");
+        }
+
+        let Some((_, _, item_id)) = standby else {
+            if self.model.selected_playlist.is_some() {
+                ui.label(RichText::new("end of cue list").weak());
+            }
+            return;
+        };
+
+        if let Some(item) = self.model.items.iter().find(|i| i.id == item_id) {
+            ui.label(format!("Standby: {}", item.labelled_name()));
+        }
+        let follow = self
+            .model
+            .engine
+            .follows
+            .iter()
+            .find(|(playlist_id, _)| Some(*playlist_id) == self.model.selected_playlist);
+        match follow {
+            Some((_, Some(seconds))) => {
+                ui.label(
+                    RichText::new(format!("follows in {:.1} s", seconds)).color(YELLOW.drawn()),
+                );
+            }
+            Some((_, None)) => {
+                ui.label(RichText::new("follows when the current cue ends").color(YELLOW.drawn()));
+            }
+            None => (),
+        }
+
+        if clicked || hotkey {
+            if let Some(cue) = self.model.fire_standby() {
+                self.channel.send(cue).unwrap();
+            }
+        }
+    }
+}
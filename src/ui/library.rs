@@ -0,0 +1,694 @@
+//! The library, the playlists and the search, in the side panel and the
+//! middle of the window.
+
+use super::*;
+
+/// The loudness target playlists start with once normalised, in LUFS.
+pub const DEFAULT_LOUDNESS_TARGET: f64 = -18.0;
+/// How many items the recently played list shows.
+const RECENTLY_PLAYED: usize = 10;
+/// The height of an item listed as a single row, without its waveform.
+const COMPACT_ROW_HEIGHT: f32 = 30.0;
+const COMPACT_HINT: &str =
+    "List the items as single rows without their waveforms, to fit more of them";
+
+impl<'a> UIState<'a> {
+    pub(super) fn playlist_menu(&mut self, ui: &mut egui::Ui) {
+        ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
+            self.library_button(ui);
+            ui.separator();
+            self.playlist_list(ui);
+            if !self.model.playlists.is_empty() {
+                ui.separator();
+            }
+            self.add_playlist_button(ui);
+            ui.separator();
+            self.crossfader(ui);
+            self.recently_played(ui);
+        });
+    }
+
+    /// List the items started last, to play them again with a click.
+    fn recently_played(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("🕘 Recently played").show(ui, |ui| {
+            let recent = self.model.recently_played(RECENTLY_PLAYED);
+            if recent.is_empty() {
+                ui.label(RichText::new("nothing yet").weak());
+            }
+            for (id, time) in recent {
+                let item_index = self.item_index(id);
+                let item = &mut self.model.items[item_index];
+                ui.horizontal(|ui| {
+                    play_pause_button(ui, item, item.source_playlist, &self.channel);
+                    ui.vertical(|ui| {
+                        truncated_label(ui, &item.labelled_name(), egui::TextStyle::Body, 120.0);
+                        let elapsed = time.elapsed().unwrap_or_default();
+                        ui.label(RichText::new(format_ago(elapsed)).small().weak())
+                            .on_hover_text(afx_core::report::timestamp(time));
+                    });
+                });
+            }
+        });
+    }
+
+    fn crossfader(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("🎚 Crossfader").show(ui, |ui| {
+            let mut crossfader = self.model.crossfader.clone();
+            for (label, deck) in [("A", &mut crossfader.deck_a), ("B", &mut crossfader.deck_b)] {
+                ui.horizontal(|ui| {
+                    ui.label(label);
+                    let selected = deck
+                        .and_then(|id| self.model.playlists.iter().find(|p| p.id == id))
+                        .map_or("none", |p| p.name.as_str());
+                    egui::ComboBox::from_id_source(("crossfader deck", label))
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(deck, None, "none");
+                            for playlist in self.model.playlists.iter() {
+                                ui.selectable_value(deck, Some(playlist.id), &playlist.name);
+                            }
+                        });
+                });
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("A");
+                ui.add(Slider::new(&mut crossfader.position, 0.0..=1.0).show_value(false));
+                ui.label("B");
+            });
+
+            egui::ComboBox::from_label("curve")
+                .selected_text(format!("{:?}", crossfader.curve))
+                .show_ui(ui, |ui| {
+                    for curve in [
+                        CrossfadeCurve::Linear,
+                        CrossfadeCurve::EqualPower,
+                        CrossfadeCurve::Cut,
+                    ] {
+                        ui.selectable_value(&mut crossfader.curve, curve, format!("{:?}", curve));
+                    }
+                });
+
+            if crossfader != self.model.crossfader {
+                self.model.crossfader = crossfader.clone();
+                self.channel
+                    .send(ControlMessage::Crossfade(crossfader))
+                    .unwrap();
+            }
+        });
+    }
+
+    fn add_playlist_button(&mut self, ui: &mut egui::Ui) {
+        let button = Button::new("➕ Add playlist").fill(GREEN.drawn().linear_multiply(0.1));
+        if ui.add(button).clicked() && self.model.playlist_creation_state.is_none() {
+            self.model.playlist_creation_state = Some(Playlist::new(
+                self.fresh_id(),
+                "New playlist".to_string(),
+                vec![],
+            ));
+        }
+    }
+
+    fn playlist_list(&mut self, ui: &mut egui::Ui) {
+        let mut to_delete = vec![];
+        let mut to_open = vec![];
+        for playlist in self.model.playlists.iter() {
+            let (seconds, streams) = self.model.runtime(&playlist.items);
+            let mut label = egui::text::LayoutJob::default();
+            let body = egui::TextStyle::Body.resolve(ui.style());
+            let text_colour = ui.visuals().text_color();
+            label.append(
+                &playlist.name,
+                0.0,
+                egui::TextFormat::simple(body, text_colour),
+            );
+            label.append(
+                &format!("{} · {}", playlist.items.len(), format_runtime(seconds)),
+                8.0,
+                egui::TextFormat::simple(
+                    egui::TextStyle::Small.resolve(ui.style()),
+                    ui.visuals().weak_text_color(),
+                ),
+            );
+            let mut resp =
+                ui.selectable_label(Some(playlist.id) == self.model.selected_playlist, label);
+            let mut hover = runtime_summary(playlist.items.len(), seconds, streams);
+            if !playlist.description.is_empty() {
+                hover = format!("{}\n\n{}", playlist.description, hover);
+            }
+            resp = resp.on_hover_text(hover);
+            if resp.clicked() {
+                self.model.selected_playlist = Some(playlist.id);
+            }
+            self.drop_targets.push(DropTarget::new(&resp, playlist.id));
+            resp.context_menu(|ui| {
+                if ui.button("Edit").clicked() {
+                    if self.model.playlist_creation_state.is_none() {
+                        self.model.playlist_creation_state = Some(playlist.clone());
+                    }
+                    ui.close_menu();
+                }
+                if ui.button("Open in a window").clicked() {
+                    to_open.push(playlist.id);
+                    ui.close_menu();
+                }
+                if ui
+                    .button("Export labels")
+                    .on_hover_text("Save the items played one after another as Audacity labels")
+                    .clicked()
+                {
+                    let items = playlist
+                        .items
+                        .iter()
+                        .filter_map(|id| self.model.items.iter().find(|item| item.id == *id));
+                    save_labels(playlist.name.clone(), afx_core::report::run_labels(items));
+                    ui.close_menu();
+                }
+                if ui
+                    .button(RichText::new("Delete").color(RED.drawn()))
+                    .clicked()
+                {
+                    to_delete.push(playlist.id);
+                    if Some(playlist.id) == self.model.selected_playlist {
+                        self.model.selected_playlist = None;
+                    }
+                    ui.close_menu();
+                }
+            });
+        }
+        self.model.playlists.retain(|p| !to_delete.contains(&p.id));
+        for playlist in to_open {
+            self.open_view(Some(playlist));
+        }
+    }
+
+    fn library_button(&mut self, ui: &mut egui::Ui) {
+        let lib = ui.selectable_label(
+            self.model.selected_playlist.is_none(),
+            RichText::new("📚 library").heading(),
+        );
+        if lib.clicked() {
+            self.model.selected_playlist = None;
+        }
+    }
+
+    pub(super) fn search_bar(&mut self, ui: &mut egui::Ui) {
+        let search_field =
+            egui::TextEdit::singleline(&mut self.model.search_query).hint_text("type to search");
+        let resp = ui.add(search_field).on_hover_text(
+            "Words match item names, \"playing\" matches playing items \
+            and key:Am or key:8A matches items in that key",
+        );
+        if !self.model.search_query.is_empty() {
+            let button = Button::new("❌").frame(false);
+            if ui.add(button).clicked()
+                || (resp.lost_focus() && ui.ctx().input(|i| i.key_pressed(egui::Key::Escape)))
+            {
+                self.model.search_query.clear();
+                resp.request_focus();
+            }
+        }
+        if self
+            .model
+            .settings
+            .keymap
+            .consume(ui.ctx(), Action::FocusSearch)
+        {
+            resp.request_focus();
+        }
+        ui.toggle_value(&mut self.interface.sort_by_key, "♯ by key")
+            .on_hover_text(
+                "List the items in the order of their keys on the Camelot wheel, \
+                so that neighbours mix well",
+            );
+    }
+
+    pub(super) fn items(&mut self, ui: &mut egui::Ui) {
+        let filtered_ids = self.process_search();
+        if self.selected_playlist().is_some_and(|p| p.grid.is_some()) {
+            self.items_grid(ui, filtered_ids);
+        } else {
+            self.items_scroll_area(ui, filtered_ids);
+        }
+    }
+
+    fn process_search(&mut self) -> Vec<(usize, u64)> {
+        let words = afx_core::search::words(&self.model.search_query);
+        let pat: Vec<_> = words.iter().map(String::as_str).collect();
+        let selected_playlist = self.model.selected_playlist.map(|id| {
+            self.model
+                .playlists
+                .iter()
+                .find(|p| p.id == id)
+                .expect("selected playlist not found")
+        });
+
+        self.search_in_playlist(selected_playlist, pat)
+    }
+
+    pub(super) fn search_in_playlist(
+        &self,
+        selected_playlist: Option<&Playlist>,
+        pat: Vec<&str>,
+    ) -> Vec<(usize, u64)> {
+        let items = selected_playlist
+            .map(|p| {
+                p.items
+                    .iter()
+                    .map(|id| self.model.items.iter().find(|i| i.id == *id).unwrap())
+                    .collect()
+            })
+            .unwrap_or(self.model.items.iter().collect::<Vec<_>>());
+        // any of the keys asked for will do, unfinished ones are ignored
+        let (keys, words): (Vec<_>, Vec<_>) = pat.iter().partition(|w| w.starts_with("key:"));
+        let keys: Vec<_> = keys
+            .iter()
+            .filter_map(|w: &&str| Key::parse(&w["key:".len()..]))
+            .collect();
+        let mut found = items
+            .into_iter()
+            .enumerate()
+            .filter(|(_, item)| keys.is_empty() || item.key.is_some_and(|k| keys.contains(&k)))
+            .filter(|(_, item)| {
+                words
+                    .iter()
+                    .find(|w| "playing".starts_with(**w))
+                    .filter(|_| item.status == ItemStatus::Playing)
+                    .is_some()
+                    || {
+                        let name = afx_core::search::fold(&item.name);
+                        words.iter().all(|w| name.contains(*w))
+                    }
+            })
+            .collect::<Vec<_>>();
+        if self.interface.sort_by_key {
+            // items without a key go last
+            found.sort_by_key(|(_, item)| item.key.map(|key| key.camelot()).ok_or(()));
+        }
+        if selected_playlist.is_none() {
+            // pinned items stay on top of the library whatever the search
+            let pins = &self.model.library_pins;
+            found.retain(|(_, item)| !pins.contains(&item.id));
+            let pinned = pins.iter().filter_map(|id| {
+                let index = self.model.items.iter().position(|item| item.id == *id)?;
+                Some((index, &self.model.items[index]))
+            });
+            found.splice(0..0, pinned);
+        }
+        found
+            .into_iter()
+            .map(|(pos_within_playlist, item)| (pos_within_playlist, item.id))
+            .collect()
+    }
+
+    /// Whether the selected view lists its items as single rows. Grids keep
+    /// their waveforms, since their cells have room for them.
+    pub(super) fn compact_view(&self) -> bool {
+        match self.selected_playlist() {
+            Some(playlist) => playlist.compact && playlist.grid.is_none(),
+            None => self.model.compact_library,
+        }
+    }
+
+    fn items_scroll_area(&mut self, ui: &mut egui::Ui, filtered_ids: Vec<(usize, u64)>) {
+        let compact = self.compact_view();
+        let items_per_row = if compact {
+            1
+        } else {
+            (ui.available_width() / BAR_PLOT_WIDTH).floor() as usize
+        };
+        let row_height = if compact { COMPACT_ROW_HEIGHT } else { 100.0 };
+        let mut area = egui::ScrollArea::vertical().auto_shrink([false; 2]);
+        let located = ui.data(|d| d.get_temp::<Located>(egui::Id::new(LOCATED)));
+        if let Some(located) = located.filter(|located| located.scroll) {
+            // the rows are only laid out once visible, so the offset is worked
+            // out the way they're placed
+            if let Some(index) = filtered_ids.iter().position(|&(_, id)| id == located.item) {
+                let row = (index / items_per_row.max(1)) as f32;
+                let spaced_height = row_height + ui.spacing().item_spacing.y;
+                let offset = (row + 0.5) * spaced_height - ui.available_height() / 2.0;
+                area = area.vertical_scroll_offset(offset.max(0.0));
+            }
+            ui.data_mut(|d| {
+                d.insert_temp(
+                    egui::Id::new(LOCATED),
+                    Located {
+                        scroll: false,
+                        ..located
+                    },
+                )
+            });
+        }
+        area.show_rows(
+            ui,
+            row_height,
+            filtered_ids.len() / items_per_row + 1,
+            |ui, row_range| {
+                for row in row_range {
+                    ui.horizontal(|ui| {
+                        for i in 0..items_per_row {
+                            let index = row * items_per_row + i;
+                            if index >= filtered_ids.len() {
+                                break;
+                            }
+                            let (position_within_playlist, item_id) = filtered_ids[index];
+                            let item_index = self.item_index(item_id);
+                            self.animate_position(ui, item_index);
+                            self.item_frame(position_within_playlist, ui, item_index);
+                        }
+                    });
+                }
+            },
+        );
+    }
+
+    /// Bring the playing item into view, showing the library if the selected
+    /// playlist doesn't have it, and flash its card. Locating again goes on to
+    /// the item started before it.
+    pub(super) fn locate_playing(&mut self, ctx: &egui::Context) {
+        let playing = self.model.playing();
+        let last = ctx
+            .data(|d| d.get_temp::<Located>(egui::Id::new(LOCATED)))
+            .map(|located| located.item);
+        let id = match last.and_then(|last| playing.iter().position(|&id| id == last)) {
+            Some(index) => playing[(index + 1) % playing.len()],
+            None => match playing.first() {
+                Some(&id) => id,
+                None => return,
+            },
+        };
+
+        if self
+            .selected_playlist()
+            .is_some_and(|playlist| !playlist.items.contains(&id))
+        {
+            let source = self.model.items[self.item_index(id)].source_playlist;
+            self.model.selected_playlist = source.filter(|source| {
+                self.model
+                    .playlists
+                    .iter()
+                    .any(|playlist| playlist.id == *source && playlist.items.contains(&id))
+            });
+        }
+        if !self.process_search().iter().any(|&(_, shown)| shown == id) {
+            self.model.search_query.clear();
+        }
+        if let Some(grid) = self.selected_playlist_mut().and_then(|p| p.grid.as_mut()) {
+            if let Some(cell) = grid.cells.get(&id) {
+                grid.bank = cell.bank;
+            }
+        }
+        ctx.data_mut(|d| {
+            d.insert_temp(
+                egui::Id::new(LOCATED),
+                Located {
+                    item: id,
+                    since: ctx.input(|i| i.time),
+                    scroll: true,
+                },
+            )
+        });
+    }
+
+    /// Add an item playing a test signal, as if it had been imported.
+    pub(super) fn add_generated_item(&mut self, signal: Signal) {
+        let id = self.fresh_id();
+        let item = Item::generated(id, signal, PALETTE[id as usize % PALETTE.len()]);
+        self.add_imported_items(vec![item]);
+    }
+
+    /// Add an item speaking the given text, rendering it in the background.
+    pub(super) fn add_speech_item(&mut self, text: String) {
+        let id = self.fresh_id();
+        let item = Item::speech(id, text, PALETTE[id as usize % PALETTE.len()]);
+        self.add_imported_items(vec![item]);
+        render_speech(self.shared.clone(), id);
+    }
+
+    /// Add an item playing the internet radio stream at the given address.
+    pub(super) fn add_stream_item(&mut self, url: String) {
+        let id = self.fresh_id();
+        let item = Item::stream(id, url, PALETTE[id as usize % PALETTE.len()]);
+        self.add_imported_items(vec![item]);
+    }
+
+    pub(super) fn add_imported_items(&mut self, items: Vec<Item>) {
+        if let Some(playlist_id) = self.model.selected_playlist {
+            for item in items.iter() {
+                self.channel
+                    .send(ControlMessage::AddToPlaylist {
+                        item_id: item.id,
+                        playlist_id,
+                    })
+                    .unwrap();
+            }
+        }
+        self.model.items.extend(items);
+    }
+
+    /// Show the playlist creation window. This doubles as the editor for
+    /// existing playlists, in which case only the name, description, output,
+    /// loudness target and fades of the stored playlist are updated.
+    pub(super) fn playlist_creation_window(&mut self, ui: &mut egui::Ui) {
+        if let Some(playlist) = &self.model.playlist_creation_state {
+            let mut playlist = playlist.clone();
+            let editing = self.model.playlists.iter().any(|p| p.id == playlist.id);
+
+            egui::Window::new(if editing {
+                "Edit playlist"
+            } else {
+                "Create playlist"
+            })
+            .id(egui::Id::new("playlist creation window"))
+            .resizable(false)
+            .show(ui.ctx(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut playlist.name);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Description:");
+                    egui::TextEdit::multiline(&mut playlist.description)
+                        .desired_rows(3)
+                        .show(ui);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Output:");
+                    output_picker(
+                        ui,
+                        "playlist output",
+                        &mut playlist.output,
+                        &self.model.engine.outputs,
+                    );
+                    channel_pair_picker(ui, "playlist channels", &mut playlist.output_pair);
+                });
+                ui.horizontal(|ui| {
+                    let mut normalised = playlist.loudness_target.is_some();
+                    ui.checkbox(&mut normalised, "Loudness target:")
+                        .on_hover_text(
+                            "Bring the items started from this playlist to the same loudness, \
+                            on top of their own volume",
+                        );
+                    match (normalised, &mut playlist.loudness_target) {
+                        (true, Some(target)) => {
+                            ui.add(
+                                egui::DragValue::new(target)
+                                    .clamp_range(-40.0..=-5.0)
+                                    .speed(0.1)
+                                    .suffix(" LUFS"),
+                            );
+                        }
+                        (true, target) => *target = Some(DEFAULT_LOUDNESS_TARGET),
+                        (false, target) => *target = None,
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Fades:").on_hover_text(
+                        "Taken on by the items started from this playlist \
+                         that have no fades of their own",
+                    );
+                    for (label, fade) in [
+                        ("in", &mut playlist.fade_in),
+                        ("out", &mut playlist.fade_out),
+                    ] {
+                        ui.label(label);
+                        ui.add(
+                            egui::DragValue::new(fade)
+                                .clamp_range(0.0..=60.0)
+                                .speed(0.01)
+                                .suffix(" s"),
+                        );
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Crossfade:").on_hover_text(
+                        "Starting an item fades out the others playing from this playlist, \
+                         instead of playing over them",
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut playlist.crossfade)
+                            .clamp_range(0.0..=60.0)
+                            .speed(0.01)
+                            .suffix(" s"),
+                    );
+                    if playlist.crossfade <= 0.0 {
+                        ui.label(RichText::new("off").weak());
+                    }
+                });
+                let unmeasured: Vec<_> = playlist
+                    .items
+                    .iter()
+                    .copied()
+                    .filter(|id| {
+                        self.model
+                            .items
+                            .iter()
+                            .any(|item| item.id == *id && item.loudness.is_none())
+                    })
+                    .collect();
+                if playlist.loudness_target.is_some() && !unmeasured.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "{} {} no measured loudness.",
+                            unmeasured.len(),
+                            if unmeasured.len() == 1 {
+                                "item has"
+                            } else {
+                                "items have"
+                            }
+                        ));
+                        if ui
+                            .button("Measure")
+                            .on_hover_text("Analyse their files again in the background")
+                            .clicked()
+                        {
+                            refresh_items(self.shared.clone(), Some(unmeasured));
+                        }
+                    });
+                }
+
+                self.model.playlist_creation_state = Some(playlist.clone());
+                ui.horizontal(|ui| {
+                    let confirm = if editing { "Save" } else { "Create" };
+                    if ui.button(RichText::new("Discard").heading()).clicked() {
+                        self.model.playlist_creation_state = None;
+                    } else if ui.button(RichText::new(confirm).heading()).clicked() {
+                        if let Some(existing) = self
+                            .model
+                            .playlists
+                            .iter_mut()
+                            .find(|p| p.id == playlist.id)
+                        {
+                            existing.name = playlist.name.clone();
+                            existing.description = playlist.description.clone();
+                            existing.output = playlist.output.clone();
+                            existing.output_pair = playlist.output_pair;
+                            existing.loudness_target = playlist.loudness_target;
+                            existing.fade_in = playlist.fade_in;
+                            existing.fade_out = playlist.fade_out;
+                            existing.crossfade = playlist.crossfade;
+                        } else {
+                            self.model.playlists.push(playlist.clone());
+                        }
+                        self.model.playlist_creation_state = None;
+                    }
+                });
+            })
+        } else {
+            None
+        };
+    }
+
+    /// Show the name and description of the selected playlist above its items.
+    pub(super) fn playlist_header(&mut self, ui: &mut egui::Ui) {
+        let Some(playlist) = self
+            .model
+            .selected_playlist
+            .and_then(|id| self.model.playlists.iter().find(|p| p.id == id))
+        else {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.model.compact_library, "compact")
+                    .on_hover_text(COMPACT_HINT);
+            });
+            return;
+        };
+
+        let mut grid = playlist.grid.as_ref().map(|g| g.columns);
+        let mut compact = playlist.compact;
+        let (seconds, streams) = self.model.runtime(&playlist.items);
+        ui.horizontal(|ui| {
+            ui.heading(&playlist.name);
+            ui.label(RichText::new(runtime_summary(playlist.items.len(), seconds, streams)).weak());
+            if ui
+                .small_button("✏")
+                .on_hover_text("Edit playlist")
+                .clicked()
+                && self.model.playlist_creation_state.is_none()
+            {
+                self.model.playlist_creation_state = Some(playlist.clone());
+            }
+
+            let mut enabled = grid.is_some();
+            ui.checkbox(&mut enabled, "fixed grid")
+                .on_hover_text("Place items on a grid that stays put between sessions");
+            match (enabled, &mut grid) {
+                (true, None) => grid = Some(4),
+                (false, Some(_)) => grid = None,
+                (true, Some(columns)) => {
+                    ui.add(
+                        egui::DragValue::new(columns)
+                            .clamp_range(1..=16)
+                            .suffix(" columns"),
+                    );
+                }
+                (false, None) => (),
+            }
+            if grid.is_none() {
+                ui.checkbox(&mut compact, "compact")
+                    .on_hover_text(COMPACT_HINT);
+            }
+        });
+        if !playlist.description.is_empty() {
+            ui.label(RichText::new(&playlist.description).weak());
+        }
+        ui.separator();
+
+        let grid_changed = grid != playlist.grid.as_ref().map(|g| g.columns);
+        let playlist = self.selected_playlist_mut().unwrap();
+        playlist.compact = compact;
+        if grid_changed {
+            match (grid, &mut playlist.grid) {
+                (Some(columns), Some(existing)) => existing.columns = columns,
+                (columns, existing) => *existing = columns.map(Grid::new),
+            }
+        }
+    }
+
+    pub(super) fn get_selected_playlist_name(&self) -> &str {
+        if let Some(playlist_id) = self.model.selected_playlist {
+            &self
+                .model
+                .playlists
+                .iter()
+                .find(|p| p.id == playlist_id)
+                .unwrap()
+                .name
+        } else {
+            "library"
+        }
+    }
+
+    /// Create a new playlist from the current search.
+    pub(super) fn playlist_from_search(&mut self) {
+        if self.model.playlist_creation_state.is_none() {
+            let id = self.fresh_id();
+            let items = self
+                .process_search()
+                .into_iter()
+                .map(|(_, item_id)| item_id)
+                .collect();
+            self.model.playlist_creation_state =
+                Some(Playlist::new(id, "new playlist".to_string(), items));
+        }
+    }
+}
@@ -0,0 +1,620 @@
+//! The settings window, and applying the settings about the looks of the
+//! interface.
+
+use super::*;
+
+pub const UI_SCALE_STEP: f32 = 0.1;
+pub const UI_SCALE_RANGE: std::ops::RangeInclusive<f32> = 0.5..=4.0;
+
+impl<'a> UIState<'a> {
+    pub(super) fn settings_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.interface.settings_open;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let settings = &mut self.model.settings;
+                ui.checkbox(&mut settings.touch_mode, "Touch-friendly mode")
+                    .on_hover_text(
+                        "Larger controls, press and hold for context menus, \
+                        swipe over items to scroll",
+                    );
+                ui.checkbox(
+                    &mut settings.stop_pads_with_playlists,
+                    "Starting a playlist stops soundboards too",
+                )
+                .on_hover_text(
+                    "Other playlists always fade out when one is started, \
+                    this also fades out sounds fired from soundboards and the library",
+                );
+                ui.checkbox(&mut settings.split_channels, "Split channels")
+                    .on_hover_text(
+                        "Waveforms show the left channel above the right one, \
+                        to spot one-sided recordings. Items imported before \
+                        need to be refreshed first.",
+                    );
+                ui.checkbox(&mut settings.colour_blind, "Colour-blind palette")
+                    .on_hover_text(
+                        "Items are drawn in colours told apart with colour blindness, \
+                        the played part of those sharing a colour is striped differently \
+                        and playing items get a bolder outline",
+                    );
+                ui.checkbox(&mut settings.live_spectrum, "Live spectrum")
+                    .on_hover_text(
+                        "Sounds started from now on show the frequencies they're playing \
+                        instead of their waveform, except those routed to other outputs",
+                    );
+
+                let engine = &self.model.engine;
+                ui.horizontal(|ui| {
+                    ui.label("Audio output").on_hover_text(
+                        "The audio API sounds play through. Other than the system's own, \
+                         they need afx built with the cargo feature of the same name.",
+                    );
+                    for &host in &engine.hosts {
+                        let label = match host {
+                            AudioHost::Default => "System",
+                            AudioHost::Jack => "JACK",
+                            AudioHost::Asio => "ASIO",
+                        };
+                        if ui
+                            .radio_value(&mut settings.audio_host, host, label)
+                            .changed()
+                        {
+                            // the devices of one API mean nothing to another
+                            settings.audio_device = None;
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Output device")
+                        .on_hover_text("ASIO drivers are listed as devices");
+                    // only the devices of the API played through are known
+                    ui.add_enabled_ui(settings.audio_host == engine.host, |ui| {
+                        egui::ComboBox::from_id_source("audio device")
+                            .selected_text(settings.audio_device.as_deref().unwrap_or("Default"))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut settings.audio_device, None, "Default");
+                                for device in &engine.outputs {
+                                    ui.selectable_value(
+                                        &mut settings.audio_device,
+                                        Some(device.clone()),
+                                        device,
+                                    );
+                                }
+                            });
+                    });
+                    if (settings.audio_host, &settings.audio_device)
+                        != (engine.host, &engine.output_device)
+                    {
+                        ui.label("Restart afx to switch");
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Speech engine").on_hover_text(
+                        "What renders the speech of announcements. A command reads \
+                         the text from its input and writes a WAV file to {output}.",
+                    );
+                    let mut command = matches!(settings.speech_engine, SpeechEngine::Command(_));
+                    ui.radio_value(&mut command, false, "System");
+                    ui.radio_value(&mut command, true, "Command");
+                    match (command, &mut settings.speech_engine) {
+                        (true, SpeechEngine::Command(line)) => {
+                            ui.add(
+                                egui::TextEdit::singleline(line)
+                                    .hint_text("piper --output_file {output}"),
+                            );
+                        }
+                        (true, engine) => *engine = SpeechEngine::Command(String::new()),
+                        (false, engine) => *engine = SpeechEngine::System,
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Sounds playing when afx closed")
+                        .on_hover_text("What to do with them when afx opens again");
+                    for (resume, label) in [
+                        (ResumePlayback::Ask, "Ask"),
+                        (ResumePlayback::Resume, "Resume"),
+                        (ResumePlayback::Pause, "Pause"),
+                        (ResumePlayback::Reset, "Reset"),
+                    ] {
+                        ui.radio_value(&mut settings.resume_playback, resume, label);
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    let mut scale = settings
+                        .ui_scale
+                        .unwrap_or_else(|| ui.ctx().pixels_per_point());
+                    ui.label("UI scale");
+                    let slider = ui
+                        .add(egui::Slider::new(&mut scale, UI_SCALE_RANGE).step_by(0.05))
+                        .on_hover_text("See the keyboard shortcuts (?) for zooming");
+                    // rescaling would move the slider from under the pointer,
+                    // so drags only apply once released, unlike the arrow keys
+                    // and typed values
+                    if (slider.changed() && !slider.dragged()) || slider.drag_stopped() {
+                        settings.ui_scale = Some(scale);
+                    }
+                    if ui
+                        .add_enabled(settings.ui_scale.is_some(), egui::Button::new("Reset"))
+                        .clicked()
+                    {
+                        settings.ui_scale = None;
+                    }
+                });
+
+                let pinned = self.model.items.iter().filter(|item| item.pinned).count();
+                ui.label(format!(
+                    "{} {} kept in memory, taking {:.1} MB",
+                    pinned,
+                    if pinned == 1 { "item" } else { "items" },
+                    self.model.engine.pinned_memory as f64 / 1_000_000.0
+                ))
+                .on_hover_text("Pick items to keep in memory in their details");
+
+                ui.separator();
+                self.priority_settings(ui);
+
+                ui.separator();
+                self.import_settings(ui);
+
+                ui.separator();
+                self.colour_rules(ui);
+
+                ui.separator();
+                self.midi_settings(ui);
+                self.mapping_profile(ui);
+
+                ui.separator();
+                self.remote_settings(ui);
+
+                ui.separator();
+                self.sync_settings(ui);
+
+                ui.separator();
+                self.script_settings(ui);
+            });
+        self.interface.settings_open = open;
+    }
+
+    fn remote_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Web remote");
+        let remote = &mut self.model.settings.remote;
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut remote.enabled, "Serve the pads on port")
+                .on_hover_text("Fire sounds from a browser on another device");
+            // changing the port restarts the server, so it's only editable
+            // while the remote is off
+            ui.add_enabled(
+                !remote.enabled,
+                egui::DragValue::new(&mut remote.port).clamp_range(1024..=65535),
+            );
+        });
+        if let Some(url) = &self.model.remote_url {
+            ui.label(format!("Open {} on your phone", url))
+                .on_hover_text("Only devices given this address, token included, can fire sounds");
+        }
+    }
+
+    fn priority_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Thread priorities")
+            .on_hover_text("Raise playback or lower imports if you hear dropouts while importing");
+        let priorities = &mut self.model.settings.priorities;
+        egui::Grid::new("thread priorities").show(ui, |ui| {
+            for (label, priority) in [
+                ("Playback", &mut priorities.playback),
+                ("Imports", &mut priorities.import),
+            ] {
+                ui.label(label);
+                ui.radio_value(priority, Priority::Low, "Low");
+                ui.radio_value(priority, Priority::Normal, "Normal");
+                ui.radio_value(priority, Priority::High, "High");
+                ui.end_row();
+            }
+        });
+        if let Some(err) = &self.model.engine.priority_error {
+            ui.colored_label(RED.drawn(), err);
+        }
+        if ui
+            .button("Performance diagnostics")
+            .on_hover_text("Frame times, waits and the time spent in each part of afx")
+            .clicked()
+        {
+            self.interface.diagnostics_open = true;
+        }
+    }
+
+    fn import_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Imports")
+            .on_hover_text("Lower these when importing from slow or network storage");
+        let limits = &mut self.model.settings.import_limits;
+        egui::Grid::new("import limits").show(ui, |ui| {
+            ui.label("Files at once");
+            ui.add(egui::DragValue::new(&mut limits.concurrency).clamp_range(1..=64));
+            ui.end_row();
+            ui.label("Give up after");
+            ui.add(
+                egui::DragValue::new(&mut limits.timeout)
+                    .clamp_range(1..=3600)
+                    .suffix(" s"),
+            )
+            .on_hover_text("Files that take longer to load are reported as failed");
+            ui.end_row();
+        });
+        if ui
+            .button("Check files for changes")
+            .on_hover_text("Update the items whose files were edited or replaced since")
+            .clicked()
+        {
+            refresh_items(self.shared.clone(), None);
+        }
+    }
+
+    fn sync_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Backup machine");
+        let sync = &mut self.model.settings.sync;
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut sync.role, SyncRole::Off, "Off");
+            ui.radio_value(&mut sync.role, SyncRole::Primary, "Primary")
+                .on_hover_text("Keep backups in sync with this machine");
+            ui.radio_value(&mut sync.role, SyncRole::Backup, "Backup")
+                .on_hover_text(
+                    "Mirror a primary, ready to take over if it fails. \
+                    The audio files have to be at the same paths on both machines",
+                );
+        });
+        // the link restarts on any change, so the address is only editable
+        // while it's off
+        let off = sync.role == SyncRole::Off;
+        ui.horizontal(|ui| {
+            ui.label("Primary");
+            ui.add_enabled(
+                off,
+                egui::TextEdit::singleline(&mut sync.primary)
+                    .hint_text("address")
+                    .desired_width(120.0),
+            );
+            ui.label("port");
+            ui.add_enabled(
+                off,
+                egui::DragValue::new(&mut sync.port).clamp_range(1024..=65535),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Password");
+            ui.add_enabled(
+                off,
+                egui::TextEdit::singleline(&mut sync.secret)
+                    .password(true)
+                    .desired_width(120.0),
+            )
+            .on_hover_text("The same on the primary and its backups");
+        });
+        if let Some(status) = &self.model.sync_status {
+            ui.label(status);
+        }
+    }
+
+    fn script_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Cue script");
+        let model = &mut *self.model;
+        let draft = model
+            .script_draft
+            .get_or_insert_with(|| model.settings.script.clone());
+        ui.add(
+            egui::TextEdit::multiline(draft)
+                .code_editor()
+                .desired_rows(6)
+                .hint_text("on end \"Intro\"\n    play \"Applause\"\nend"),
+        );
+        let changed = *draft != model.settings.script;
+        let (mut apply, mut revert) = (false, false);
+        ui.horizontal(|ui| {
+            // the script restarts when applied, so it isn't reloaded on every
+            // keystroke
+            apply = ui
+                .add_enabled(changed, egui::Button::new("Apply"))
+                .clicked();
+            revert = ui
+                .add_enabled(changed, egui::Button::new("Revert"))
+                .clicked();
+        });
+        if apply {
+            model.settings.script = draft.clone();
+        }
+        if revert {
+            model.script_draft = None;
+        }
+        if let Some(error) = &model.script_error {
+            ui.colored_label(RED.drawn(), error);
+        }
+    }
+
+    fn midi_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("MIDI");
+        if self.model.midi_devices.is_empty() {
+            ui.label(RichText::new("No MIDI input devices found").weak());
+        } else {
+            ui.label(format!("Inputs: {}", self.model.midi_devices.join(", ")));
+        }
+
+        let model = &mut *self.model;
+        let name_of = |id: u64| {
+            model
+                .items
+                .iter()
+                .find(|item| item.id == id)
+                .map_or("(deleted)".to_string(), |item| item.name.clone())
+        };
+        let describe_target = |target: MidiTarget| match target {
+            MidiTarget::Item(id) => format!("▶ {}", name_of(id)),
+            MidiTarget::ItemVolume(id) => format!("🔈 {}", name_of(id)),
+            MidiTarget::MasterVolume => "Master volume".to_string(),
+            MidiTarget::Bank(bank) => format!("Bank {}", bank + 1),
+            MidiTarget::Go => "GO".to_string(),
+        };
+
+        let mut to_delete = None;
+        for (i, binding) in model.settings.midi.bindings.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source(("midi binding device", i))
+                    .selected_text(binding.device.as_deref().unwrap_or("Any device"))
+                    .width(100.0)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut binding.device, None, "Any device");
+                        for device in &model.midi_devices {
+                            ui.selectable_value(&mut binding.device, Some(device.clone()), device);
+                        }
+                    });
+
+                let (kind, channel, number) = match &mut binding.trigger {
+                    MidiTrigger::Note { channel, note } => ("Note", channel, note),
+                    MidiTrigger::Control {
+                        channel,
+                        controller,
+                    } => ("CC", channel, controller),
+                };
+                let mut midi_channel = *channel + 1;
+                let mut number = *number;
+                let mut is_note = kind == "Note";
+                egui::ComboBox::from_id_source(("midi binding kind", i))
+                    .selected_text(kind)
+                    .width(50.0)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut is_note, true, "Note");
+                        ui.selectable_value(&mut is_note, false, "CC");
+                    });
+                ui.label("ch");
+                ui.add(egui::DragValue::new(&mut midi_channel).clamp_range(1..=16));
+                ui.add(egui::DragValue::new(&mut number).clamp_range(0..=127));
+                binding.trigger = if is_note {
+                    MidiTrigger::Note {
+                        channel: midi_channel - 1,
+                        note: number,
+                    }
+                } else {
+                    MidiTrigger::Control {
+                        channel: midi_channel - 1,
+                        controller: number,
+                    }
+                };
+
+                ui.label("→");
+                egui::ComboBox::from_id_source(("midi binding target", i))
+                    .selected_text(describe_target(binding.target))
+                    .width(140.0)
+                    .show_ui(ui, |ui| {
+                        let target = &mut binding.target;
+                        ui.selectable_value(target, MidiTarget::MasterVolume, "Master volume");
+                        ui.selectable_value(target, MidiTarget::Go, "GO");
+                        for bank in 0..BANKS {
+                            let bank = MidiTarget::Bank(bank);
+                            ui.selectable_value(target, bank, describe_target(bank));
+                        }
+                        for item in &model.items {
+                            let play = MidiTarget::Item(item.id);
+                            ui.selectable_value(target, play, describe_target(play));
+                            let volume = MidiTarget::ItemVolume(item.id);
+                            ui.selectable_value(target, volume, describe_target(volume));
+                        }
+                    });
+
+                if ui.button("🗑").clicked() {
+                    to_delete = Some(i);
+                }
+            });
+        }
+        if let Some(i) = to_delete {
+            model.settings.midi.bindings.remove(i);
+        }
+
+        if ui.button("➕ Add mapping").clicked() {
+            model.settings.midi.bindings.push(MidiBinding {
+                device: None,
+                trigger: MidiTrigger::Note {
+                    channel: 0,
+                    note: 36,
+                },
+                target: MidiTarget::MasterVolume,
+            });
+        }
+    }
+
+    /// Save the shortcuts and MIDI mappings to a file of their own, or load
+    /// them from one, in the background.
+    fn mapping_profile(&mut self, ui: &mut egui::Ui) {
+        let status = egui::Id::new("mapping profile status");
+        ui.horizontal(|ui| {
+            ui.label("Mapping profile").on_hover_text(
+                "The keyboard shortcuts and MIDI mappings, to share a controller layout \
+                 between machines. Items are matched up by name.",
+            );
+            if ui.button("Export…").clicked() {
+                let profile = Profile::of(self.model);
+                let ctx = ui.ctx().clone();
+                std::thread::spawn(move || {
+                    let path = rfd::FileDialog::new()
+                        .set_title("Export the mapping profile")
+                        .set_file_name("mappings.ron")
+                        .add_filter("Mapping profile", &["ron"])
+                        .save_file();
+                    let Some(path) = path else {
+                        return;
+                    };
+                    let message = match profile.save(&path) {
+                        Ok(()) => format!("Exported to {}", path.display()),
+                        Err(err) => format!("Couldn't export the mappings: {}", err),
+                    };
+                    ctx.data_mut(|d| d.insert_temp(status, message));
+                    ctx.request_repaint();
+                });
+            }
+            if ui.button("Import…").clicked() {
+                let (model, ctx) = (self.shared.clone(), ui.ctx().clone());
+                std::thread::spawn(move || {
+                    let path = rfd::FileDialog::new()
+                        .set_title("Import a mapping profile")
+                        .add_filter("Mapping profile", &["ron"])
+                        .pick_file();
+                    let Some(path) = path else {
+                        return;
+                    };
+                    let message = match Profile::load(&path) {
+                        Ok(profile) => match profile.apply(&mut model.write())[..] {
+                            [] => "Imported the mappings".to_string(),
+                            ref missing => format!(
+                                "Imported the mappings, except those of {}, \
+                                 which aren't in the library",
+                                missing.join(", ")
+                            ),
+                        },
+                        Err(err) => format!("Couldn't import the mappings: {}", err),
+                    };
+                    ctx.data_mut(|d| d.insert_temp(status, message));
+                    ctx.request_repaint();
+                });
+            }
+        });
+        let message = ui.data(|d| d.get_temp::<String>(status));
+        if let Some(message) = message {
+            ui.label(RichText::new(message).weak());
+        }
+    }
+
+    fn colour_rules(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Colour rules");
+        ui.label("Imported items are coloured by the first rule they match.");
+
+        let model = &mut *self.model;
+        let rules = &mut model.settings.colour_rules;
+        let mut to_delete = None;
+        for (i, rule) in rules.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source(("colour rule field", i))
+                    .selected_text(format!("{:?}", rule.field))
+                    .width(70.0)
+                    .show_ui(ui, |ui| {
+                        for field in [RuleField::Name, RuleField::Folder, RuleField::Tag] {
+                            ui.selectable_value(&mut rule.field, field, format!("{:?}", field));
+                        }
+                    });
+                ui.label("contains");
+                ui.add(
+                    egui::TextEdit::singleline(&mut rule.pattern)
+                        .desired_width(100.0)
+                        .hint_text("rain"),
+                );
+                let mut colour = rule.colour.drawn();
+                egui::color_picker::color_edit_button_srgba(
+                    ui,
+                    &mut colour,
+                    egui::color_picker::Alpha::Opaque,
+                );
+                rule.colour = ColourProxy::from(colour).into();
+                if ui.button("🗑").clicked() {
+                    to_delete = Some(i);
+                }
+            });
+        }
+        if let Some(i) = to_delete {
+            rules.remove(i);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("➕ Add rule").clicked() {
+                rules.push(ColourRule {
+                    field: RuleField::Name,
+                    pattern: String::new(),
+                    colour: BLUE,
+                });
+            }
+            if ui
+                .button("Apply to library")
+                .on_hover_text("Recolour all items matching a rule")
+                .clicked()
+            {
+                for item in model.items.iter_mut() {
+                    apply_colour_rules(rules, item);
+                }
+            }
+        });
+    }
+}
+
+fn touch_spacing() -> egui::style::Spacing {
+    egui::style::Spacing {
+        item_spacing: vec2(12.0, 8.0),
+        button_padding: vec2(12.0, 8.0),
+        interact_size: vec2(56.0, 44.0),
+        slider_width: 150.0,
+        icon_width: 24.0,
+        icon_width_inner: 14.0,
+        icon_spacing: 8.0,
+        scroll: egui::style::ScrollStyle {
+            bar_width: 16.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+pub(super) fn apply_touch_style(ctx: &egui::Context, touch_mode: bool) {
+    let spacing = if touch_mode {
+        touch_spacing()
+    } else {
+        Default::default()
+    };
+    if ctx.style().spacing != spacing {
+        let mut style = (*ctx.style()).clone();
+        style.spacing = spacing;
+        ctx.set_style(style);
+    }
+}
+
+/// Handle the zoom hotkeys and override the system UI scale if a custom one is
+/// set.
+pub(super) fn apply_ui_scale(ctx: &egui::Context, settings: &mut Settings, native: Option<f32>) {
+    let keymap = &settings.keymap;
+    let ui_scale = &mut settings.ui_scale;
+    let zoom = if keymap.consume(ctx, Action::ZoomIn) {
+        UI_SCALE_STEP
+    } else if keymap.consume(ctx, Action::ZoomOut) {
+        -UI_SCALE_STEP
+    } else {
+        0.0
+    };
+
+    if keymap.consume(ctx, Action::ResetZoom) {
+        *ui_scale = None;
+    } else if zoom != 0.0 {
+        let scale = ctx.pixels_per_point() + zoom;
+        *ui_scale = Some(scale.clamp(*UI_SCALE_RANGE.start(), *UI_SCALE_RANGE.end()));
+    }
+
+    if let Some(scale) = ui_scale.or(native) {
+        ctx.set_pixels_per_point(scale);
+    }
+}
@@ -0,0 +1,390 @@
+//! Drawing waveforms and seeking through them.
+
+use super::*;
+
+const WAVEFORM_HEIGHT: f32 = 30.0;
+/// The shortest loop region that can be set on the waveform, in seconds.
+pub const MIN_LOOP_LENGTH: f64 = 0.05;
+/// How far the arrow keys seek a waveform with keyboard focus, in seconds,
+/// without and with Shift.
+const KEY_SEEK: f64 = 5.0;
+const FINE_KEY_SEEK: f64 = 1.0;
+/// Beyond this many bars, they're drawn touching each other, like a
+/// continuous waveform, rather than with gaps in between.
+const SPARSE_BARS: usize = 128;
+
+/// Merge neighbouring bars of a long item so that there's at most one for
+/// each pixel of the waveform. Peaks keep the loudest of theirs, RMS levels
+/// combine.
+fn fit_bars(bars: &[u8], count: usize, rms: bool) -> Vec<u8> {
+    if bars.len() <= count {
+        return bars.to_vec();
+    }
+    (0..count)
+        .map(|i| {
+            let group = &bars[i * bars.len() / count..(i + 1) * bars.len() / count];
+            if rms {
+                let squares: f32 = group.iter().map(|&bar| (bar as f32).powi(2)).sum();
+                (squares / group.len() as f32).sqrt().round() as u8
+            } else {
+                group.iter().copied().max().unwrap_or(0)
+            }
+        })
+        .collect()
+}
+
+/// The bars of a waveform tessellated once, four vertices per bar, in a unit
+/// square centered vertically on zero. Only the colours change as an item
+/// plays. The RMS levels, if there are any, are layered inside the peaks,
+/// making for another four vertices per bar.
+fn waveform_mesh(bars: &[u8], rms: &[u8], gain: f32) -> egui::Mesh {
+    let mut mesh = egui::Mesh::default();
+    let slot = 1.0 / bars.len().max(1) as f32;
+    let half_width = if bars.len() > SPARSE_BARS { 0.5 } else { 0.2 } * slot;
+    let half_height = |height: u8| (gain * height as f32 / 255.0).min(1.0) * 0.5;
+    for (i, peak) in bars.iter().copied().enumerate() {
+        let centre = (i as f32 + 0.5) * slot;
+        let x_range = centre - half_width..=centre + half_width;
+        for height in std::iter::once(peak).chain(rms.get(i).copied()) {
+            mesh.add_colored_rect(
+                egui::Rect::from_x_y_ranges(
+                    x_range.clone(),
+                    -half_height(height)..=half_height(height),
+                ),
+                Color32::WHITE,
+            );
+        }
+    }
+    mesh
+}
+
+/// Like [`waveform_mesh`], with the left channel rising above the middle and
+/// the right one hanging below it. Each layer of a bar is made of two
+/// rectangles.
+fn split_waveform_mesh(
+    [left, right]: &[Vec<u8>; 2],
+    [left_rms, right_rms]: &[Vec<u8>; 2],
+    gain: f32,
+) -> egui::Mesh {
+    let mut mesh = egui::Mesh::default();
+    let slot = 1.0 / left.len().max(1) as f32;
+    let half_width = if left.len() > SPARSE_BARS { 0.5 } else { 0.2 } * slot;
+    let half_height = |height: u8| (gain * height as f32 / 255.0).min(1.0) * 0.5;
+    for (i, (&top, &bottom)) in left.iter().zip(right).enumerate() {
+        let centre = (i as f32 + 0.5) * slot;
+        let x_range = centre - half_width..=centre + half_width;
+        let rms = left_rms.get(i).zip(right_rms.get(i));
+        for (&top, &bottom) in std::iter::once((&top, &bottom)).chain(rms) {
+            for y_range in [-half_height(top)..=0.0, 0.0..=half_height(bottom)] {
+                mesh.add_colored_rect(
+                    egui::Rect::from_x_y_ranges(x_range.clone(), y_range),
+                    Color32::WHITE,
+                );
+            }
+        }
+    }
+    mesh
+}
+
+/// Render the waveform of an item, seeking on click and drag, which also gives
+/// it keyboard focus for [`seek_with_keys`]. In touch mode, dragging scrolls
+/// the surrounding area instead, so that swipes over items aren't mistaken
+/// for seeks. Split waveforms show each channel on its own, if the item has
+/// bars for them. For colour-blind operators, the waveform takes its colour
+/// from the accessible palette and a texture.
+pub(super) fn render_waveform(
+    channel: &Sender<ControlMessage>,
+    ui: &mut egui::Ui,
+    item: &Item,
+    touch_mode: bool,
+    split_channels: bool,
+    colour_blind: bool,
+) {
+    let (rect, response) = ui.allocate_exact_size(
+        vec2(BAR_PLOT_WIDTH, WAVEFORM_HEIGHT),
+        egui::Sense::click_and_drag(),
+    );
+
+    if ui.is_rect_visible(rect) {
+        let gain = if item.muted {
+            0.0
+        } else {
+            (item.volume * db_to_amplitude(item.gain_db)) as f32
+        };
+        let split = split_channels && item.channel_bars.iter().all(|bars| !bars.is_empty());
+        let layered = if split {
+            item.channel_rms_bars.iter().all(|bars| !bars.is_empty())
+        } else {
+            !item.rms_bars.is_empty()
+        };
+        let key = {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            (&item.bars, &item.rms_bars, gain.to_bits(), split).hash(&mut hasher);
+            hasher.finish()
+        };
+        // shared by every place the item is shown in
+        let cache_id = egui::Id::new(("waveform", item.id));
+        let cached = ui.data(|d| d.get_temp::<(u64, egui::Mesh)>(cache_id));
+        // the spectrum changes with every sync, so it isn't worth caching
+        let live = !item.spectrum.is_empty();
+        // one bar per pixel at most
+        let shown = item.bars.len().min(rect.width() as usize);
+        let mut mesh = match cached {
+            _ if live => waveform_mesh(&item.spectrum, &[], 1.0),
+            Some((cached_key, mesh)) if cached_key == key => mesh,
+            _ => {
+                let fit = |bars: &[Vec<u8>; 2], rms| bars.clone().map(|b| fit_bars(&b, shown, rms));
+                let mesh = if split {
+                    split_waveform_mesh(
+                        &fit(&item.channel_bars, false),
+                        &fit(&item.channel_rms_bars, true),
+                        gain,
+                    )
+                } else {
+                    waveform_mesh(
+                        &fit_bars(&item.bars, shown, false),
+                        &fit_bars(&item.rms_bars, shown, true),
+                        gain,
+                    )
+                };
+                ui.data_mut(|d| d.insert_temp(cache_id, (key, mesh.clone())));
+                mesh
+            }
+        };
+
+        let (item_colour, texture) = display_colour(item.colour, colour_blind);
+        let bg = ui.style().visuals.window_fill();
+        let dimmed = bg.mix(0.4, &item_colour);
+        // the whole spectrum is lit, being what plays right now
+        let progress = if live {
+            f64::INFINITY
+        } else {
+            item.position / item.duration * shown as f64
+        };
+        let vertices_per_layer = if split && !live { 8 } else { 4 };
+        let layers = if layered && !live { 2 } else { 1 };
+        for (i, bar) in mesh
+            .vertices
+            .chunks_mut(vertices_per_layer * layers)
+            .enumerate()
+        {
+            let fill_level = (progress - i as f64).clamp(0.0, 1.0);
+            let mut colour = dimmed.mix(fill_level as f32, &item_colour);
+            // every other or every fourth played bar is lighter
+            if texture > 0 && fill_level > 0.0 && i % (2 * texture) == 0 {
+                colour = colour.mix(0.5, &Color32::WHITE);
+            }
+            // the RMS level is drawn darker, inside the peak
+            let (peak, rms) = bar.split_at_mut(vertices_per_layer);
+            for (vertices, colour) in [(peak, colour), (rms, colour.mix(0.35, &Color32::BLACK))] {
+                for vertex in vertices {
+                    vertex.pos =
+                        rect.left_center() + vec2(vertex.pos.x, vertex.pos.y) * rect.size();
+                    vertex.color = colour;
+                }
+            }
+        }
+        ui.painter().add(mesh);
+        if item.is_stream() && !live {
+            ui.painter().text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "live stream",
+                egui::FontId::default(),
+                item_colour,
+            );
+        }
+
+        if item.looped() {
+            if let Some(tempo) = &item.tempo {
+                beat_grid(ui, rect, item.duration, tempo);
+            }
+            let (start, end) = item.loop_bounds();
+            for marker in [start, end] {
+                let x = rect.left() + (marker / item.duration) as f32 * rect.width();
+                ui.painter()
+                    .vline(x, rect.y_range(), Stroke::new(2.0, YELLOW.drawn()));
+            }
+        }
+    }
+
+    if response.clicked() || response.drag_started() {
+        response.request_focus();
+    }
+    if response.has_focus() {
+        ui.painter()
+            .rect_stroke(rect.expand(1.0), 2.0, ui.visuals().selection.stroke);
+        seek_with_keys(channel, &response, item);
+    }
+
+    if touch_mode && response.dragged() {
+        ui.scroll_with_delta(response.drag_delta());
+        return;
+    }
+    handle_waveform_interaction(channel, response, rect.left(), item);
+}
+
+/// Seek the item of a waveform with keyboard focus by [`KEY_SEEK`] with the
+/// arrow keys, or by [`FINE_KEY_SEEK`] with Shift held. Escape lets go of the
+/// focus, handing the arrow keys back to the skip shortcuts.
+fn seek_with_keys(channel: &Sender<ControlMessage>, response: &egui::Response, item: &Item) {
+    use egui::{Key, Modifiers};
+
+    let mut offset = 0.0;
+    let escaped = response.ctx.input_mut(|input| {
+        if input.consume_key(Modifiers::NONE, Key::Escape) {
+            return true;
+        }
+        for (key, direction) in [(Key::ArrowLeft, -1.0), (Key::ArrowRight, 1.0)] {
+            if input.consume_key(Modifiers::NONE, key) {
+                offset += direction * KEY_SEEK;
+            }
+            if input.consume_key(Modifiers::SHIFT, key) {
+                offset += direction * FINE_KEY_SEEK;
+            }
+        }
+        false
+    });
+    if escaped {
+        response.surrender_focus();
+        return;
+    }
+    // streams can't be sought through
+    if offset == 0.0 || item.is_stream() {
+        return;
+    }
+    let position = (item.target_position + offset).clamp(0.0, item.duration);
+    channel
+        .send(ControlMessage::Seek(item.id, position))
+        .unwrap();
+}
+
+/// Draw a line for every beat, stronger at the start of each bar, leaving out
+/// the beats or even the bars when they'd be too dense to tell apart.
+fn beat_grid(ui: &egui::Ui, rect: egui::Rect, duration: f64, tempo: &Tempo) {
+    const MIN_SPACING: f32 = 4.0;
+    let spacing = (tempo.beat_length() / duration) as f32 * rect.width();
+    let show_beats = spacing >= MIN_SPACING;
+    if !show_beats && spacing * (Tempo::BEATS_PER_BAR as f32) < MIN_SPACING {
+        return;
+    }
+    let colour = ui.style().visuals.text_color();
+    for (time, bar) in tempo.beats(duration) {
+        if time < 0.0 || !(bar || show_beats) {
+            continue;
+        }
+        let x = rect.left() + (time / duration) as f32 * rect.width();
+        let stroke = Stroke::new(1.0, colour.linear_multiply(if bar { 0.5 } else { 0.15 }));
+        ui.painter().vline(x, rect.y_range(), stroke);
+    }
+}
+
+/// Move whichever loop marker is closer to the pointer, snapping it to the
+/// beats unless Shift is held, or reset the region on a double click.
+fn edit_loop_region(
+    channel: &Sender<ControlMessage>,
+    response: egui::Response,
+    plot_x: f32,
+    item: &Item,
+) {
+    if response.double_clicked() {
+        channel
+            .send(ControlMessage::SetLoopRegion(item.id, None))
+            .unwrap();
+        return;
+    }
+    if let Some(pos) = response
+        .interact_pointer_pos()
+        .filter(|_| response.dragged() || response.clicked())
+    {
+        let duration = item.duration;
+        let mut time = (pos.x - plot_x) as f64 * duration / BAR_PLOT_WIDTH as f64;
+        if let Some(tempo) = item
+            .tempo
+            .filter(|_| !response.ctx.input(|i| i.modifiers.shift))
+        {
+            time = tempo.snap(time);
+        }
+        let time = time.clamp(0.0, duration);
+        let (start, end) = item.loop_bounds();
+        let region = if (time - start).abs() < (time - end).abs() {
+            (time.min(end - MIN_LOOP_LENGTH).max(0.0), end)
+        } else {
+            (start, time.max(start + MIN_LOOP_LENGTH).min(duration))
+        };
+        channel
+            .send(ControlMessage::SetLoopRegion(item.id, Some(region)))
+            .unwrap();
+    }
+}
+
+fn handle_waveform_interaction(
+    channel: &Sender<ControlMessage>,
+    response: egui::Response,
+    plot_x: f32,
+    item: &Item,
+) {
+    // streams can't be sought through
+    if item.is_stream() {
+        return;
+    }
+    if item.looped() && response.ctx.input(|i| i.modifiers.alt) {
+        edit_loop_region(channel, response, plot_x, item);
+        return;
+    }
+
+    let drag_distance = response.drag_delta().x;
+    if drag_distance != 0.0 {
+        let duration = item.duration as f32;
+        let new_position = item.position as f32 + drag_distance * duration / BAR_PLOT_WIDTH;
+        let new_position = new_position.clamp(0.0, duration) as f64;
+
+        channel
+            .send(ControlMessage::Seek(item.id, new_position))
+            .unwrap();
+        return;
+    }
+    if let Some(pos) = response
+        .interact_pointer_pos()
+        .filter(|_| response.clicked())
+    {
+        let duration = item.duration as f32;
+        let new_position = (pos.x - plot_x) * duration / BAR_PLOT_WIDTH;
+        let new_position = new_position.clamp(0.0, duration) as f64;
+        channel
+            .send(ControlMessage::Seek(item.id, new_position))
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn waveform_tessellation() {
+        let heights = |rms: &[u8], gain| {
+            let mesh = waveform_mesh(&[0, 255], rms, gain);
+            mesh.vertices
+                .chunks(4)
+                .map(|bar| bar.iter().map(|v| v.pos.y).fold(0.0, f32::max))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(heights(&[], 0.5), vec![0.0, 0.25]);
+        // loud bars are clipped to the height of the waveform
+        assert_eq!(heights(&[], 2.0), vec![0.0, 0.5]);
+        // the RMS level follows each peak
+        assert_eq!(heights(&[0, 51], 1.0), vec![0.0, 0.0, 0.5, 0.1]);
+        let mesh = waveform_mesh(&[0, 255], &[0, 51], 1.0);
+        assert_eq!((mesh.vertices.len(), mesh.indices.len()), (16, 24));
+    }
+
+    #[test]
+    fn fitting_bars() {
+        let bars = [0, 10, 20, 30, 40, 50, 60, 70, 80];
+        assert_eq!(fit_bars(&bars, 16, false), bars);
+        assert_eq!(fit_bars(&bars, 3, false), vec![20, 50, 80]);
+        assert_eq!(fit_bars(&[30, 40, 0, 0], 2, true), vec![35, 0]);
+    }
+}
@@ -0,0 +1,821 @@
+//! The windows opened over the main one: item details, views, statistics,
+//! prompts and notifications.
+
+use super::*;
+
+/// The icons offered for items, though any text of up to
+/// [`ICON_CHAR_LIMIT`] characters will do.
+const ICONS: [&str; 24] = [
+    "🔔", "🚪", "📞", "🚗", "🔫", "💥", "⚡", "🌧", "🌊", "🔥", "🐦", "🐕", "👣", "👏", "🎵", "🎹",
+    "🎺", "🥁", "📻", "📺", "⏰", "💡", "❤", "⭐",
+];
+/// How many seconds of silence stop an item once it is set to stop on silence.
+pub const DEFAULT_SILENCE_WAIT: f64 = 3.0;
+/// How many items the statistics list as the most used.
+const MOST_USED: usize = 10;
+
+impl<'a> UIState<'a> {
+    /// Open a window showing a playlist, or the library, next to the main
+    /// one.
+    pub(super) fn open_view(&mut self, playlist: Option<u64>) {
+        let view = View {
+            id: self.fresh_id(),
+            playlist,
+            search_query: String::new(),
+        };
+        self.model.views.push(view);
+    }
+
+    /// Show the windows open next to the main one, each a native window of
+    /// its own unless the platform only has the one, in which case they float
+    /// over it. Each is drawn the way the central panel is, with its playlist
+    /// and search swapped in for the selected ones.
+    pub(super) fn views(&mut self, ctx: &egui::Context) {
+        let mut views = std::mem::take(&mut self.model.views);
+        views.retain_mut(|view| {
+            let exists = |id| self.model.playlists.iter().any(|p| p.id == id);
+            if view.playlist.is_some_and(|id| !exists(id)) {
+                view.playlist = None;
+            }
+            let selected = std::mem::replace(&mut self.model.selected_playlist, view.playlist);
+            let search_query = std::mem::take(&mut view.search_query);
+            let search_query = std::mem::replace(&mut self.model.search_query, search_query);
+
+            let title = self.get_selected_playlist_name().to_string();
+            let builder = egui::ViewportBuilder::default()
+                .with_title(format!("{} – afx", title))
+                .with_inner_size(vec2(BAR_PLOT_WIDTH * 3.0, 400.0))
+                .with_drag_and_drop(true);
+            let viewport = egui::ViewportId::from_hash_of(("view", view.id));
+            let open = ctx.show_viewport_immediate(viewport, builder, |ctx, class| {
+                if class == egui::ViewportClass::Embedded {
+                    return self.floating_view(ctx, view.id, title);
+                }
+                let panel = egui::CentralPanel::default().show(ctx, |ui| {
+                    self.view_contents(ui, view.id);
+                });
+                if let Some(playlist) = self.model.selected_playlist {
+                    self.drop_targets
+                        .push(DropTarget::new(&panel.response, playlist));
+                }
+                self.paint_drag(ctx);
+                !ctx.input(|input| input.viewport().close_requested())
+            });
+
+            view.playlist = std::mem::replace(&mut self.model.selected_playlist, selected);
+            view.search_query = std::mem::replace(&mut self.model.search_query, search_query);
+            open
+        });
+        views.append(&mut self.model.views);
+        self.model.views = views;
+    }
+
+    /// Show a view floating over the main window, returning whether it's
+    /// still open.
+    fn floating_view(&mut self, ctx: &egui::Context, view_id: u64, title: String) -> bool {
+        let mut open = true;
+        let window = egui::Window::new(title)
+            .id(egui::Id::new(("view", view_id)))
+            .open(&mut open)
+            .default_size(vec2(BAR_PLOT_WIDTH * 3.0, 400.0))
+            .show(ctx, |ui| {
+                self.view_contents(ui, view_id);
+            });
+        if let (Some(window), Some(playlist)) = (window, self.model.selected_playlist) {
+            self.drop_targets
+                .push(DropTarget::new(&window.response, playlist));
+        }
+        open
+    }
+
+    fn view_contents(&mut self, ui: &mut egui::Ui, view_id: u64) {
+        ui.horizontal(|ui| {
+            self.view_header(ui, view_id);
+        });
+        ui.separator();
+        self.items(ui);
+    }
+
+    /// Pick what a window shows and search it.
+    fn view_header(&mut self, ui: &mut egui::Ui, view_id: u64) {
+        egui::ComboBox::from_id_source(("view playlist", view_id))
+            .selected_text(self.get_selected_playlist_name().to_string())
+            .show_ui(ui, |ui| {
+                let selected = &mut self.model.selected_playlist;
+                ui.selectable_value(selected, None, "📚 library");
+                for playlist in &self.model.playlists {
+                    ui.selectable_value(selected, Some(playlist.id), &playlist.name);
+                }
+            });
+        let search_field =
+            egui::TextEdit::singleline(&mut self.model.search_query).hint_text("type to search");
+        ui.add(search_field);
+    }
+
+    /// Show the context menu opened by a long press in touch mode. Tapping
+    /// anywhere outside of it dismisses it.
+    pub(super) fn touch_context_menu(&mut self, ctx: &egui::Context) {
+        let Some((item_id, position_within_playlist, pos)) = self.interface.touch_menu else {
+            return;
+        };
+        let Some(item_index) = self.model.items.iter().position(|i| i.id == item_id) else {
+            self.interface.touch_menu = None;
+            return;
+        };
+
+        let resp = egui::Area::new(egui::Id::new("touch context menu"))
+            .order(egui::Order::Foreground)
+            .fixed_pos(pos)
+            .show(ctx, |ui| {
+                Frame::menu(ui.style()).show(ui, |ui| {
+                    ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
+                        self.item_context_menu(position_within_playlist, item_index, ui);
+                    });
+                });
+            })
+            .response;
+
+        let tapped_outside = ctx.input(|input| {
+            input.pointer.any_click()
+                && input
+                    .pointer
+                    .interact_pos()
+                    .is_some_and(|pos| !resp.rect.contains(pos))
+        });
+        if tapped_outside {
+            self.interface.touch_menu = None;
+        }
+    }
+
+    /// Show the technical details of the item picked from its context menu.
+    pub(super) fn item_details_window(&mut self, ctx: &egui::Context) {
+        let shared = self.shared.clone();
+        let channel = &self.channel;
+        let outputs = &self.model.engine.outputs;
+        let names: Vec<_> = self
+            .model
+            .items
+            .iter()
+            .map(|item| (item.id, item.labelled_name()))
+            .collect();
+        let Some(item) = self
+            .interface
+            .item_details
+            .and_then(|id| self.model.items.iter_mut().find(|i| i.id == id))
+        else {
+            self.interface.item_details = None;
+            return;
+        };
+
+        let mut open = true;
+        egui::Window::new(format!("Details of {}", item.name))
+            .id(egui::Id::new("item details"))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("item details grid")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Duration:");
+                        if item.signal.is_some() {
+                            ui.add(
+                                egui::DragValue::new(&mut item.duration)
+                                    .clamp_range(1.0..=3600.0)
+                                    .speed(1.0)
+                                    .suffix(" s"),
+                            );
+                        } else if item.is_stream() {
+                            ui.label("live stream");
+                        } else {
+                            ui.label(format_timestamp(item.duration));
+                        }
+                        ui.end_row();
+
+                        if let Some(text) = &mut item.speech {
+                            ui.label("Speech:").on_hover_text(
+                                "The text spoken by the item. Rendering it again \
+                                 takes the edits in, and the speech engine from \
+                                 the settings.",
+                            );
+                            ui.vertical(|ui| {
+                                ui.add(egui::TextEdit::multiline(text).desired_rows(3));
+                                if ui.button("Render").clicked() {
+                                    render_speech(shared.clone(), item.id);
+                                }
+                            });
+                            ui.end_row();
+                        }
+
+                        if let Some(signal) = item.signal {
+                            ui.label("Signal:");
+                            let mut edited = signal;
+                            ui.horizontal(|ui| {
+                                let tone = match signal {
+                                    Signal::Sine(frequency) => Signal::Sine(frequency),
+                                    _ => Signal::Sine(DEFAULT_FREQUENCY),
+                                };
+                                egui::ComboBox::from_id_source("signal")
+                                    .selected_text(match signal {
+                                        Signal::Sine(_) => "Tone",
+                                        Signal::WhiteNoise => "White noise",
+                                        Signal::PinkNoise => "Pink noise",
+                                    })
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(&mut edited, tone, "Tone");
+                                        ui.selectable_value(
+                                            &mut edited,
+                                            Signal::WhiteNoise,
+                                            "White noise",
+                                        );
+                                        ui.selectable_value(
+                                            &mut edited,
+                                            Signal::PinkNoise,
+                                            "Pink noise",
+                                        );
+                                    });
+                                if let Signal::Sine(frequency) = &mut edited {
+                                    ui.add(
+                                        egui::DragValue::new(frequency)
+                                            .clamp_range(20.0..=20_000.0)
+                                            .speed(1.0)
+                                            .suffix(" Hz"),
+                                    );
+                                }
+                            });
+                            if edited != signal {
+                                // items still named after their signal follow it
+                                if item.name == signal.to_string() {
+                                    item.name = edited.to_string();
+                                }
+                                item.signal = Some(edited);
+                            }
+                            ui.end_row();
+                        }
+
+                        ui.label("Icon:").on_hover_text(
+                            "Shown before the name, to tell items apart at a glance",
+                        );
+                        ui.horizontal(|ui| {
+                            let mut icon = item.icon.clone().unwrap_or_default();
+                            ui.add(egui::TextEdit::singleline(&mut icon).desired_width(40.0));
+                            ui.menu_button("⏷", |ui| {
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.set_max_width(200.0);
+                                    for suggestion in ICONS {
+                                        if ui.button(suggestion).clicked() {
+                                            icon = suggestion.to_string();
+                                            ui.close_menu();
+                                        }
+                                    }
+                                });
+                            });
+                            let icon: String = icon.trim().chars().take(ICON_CHAR_LIMIT).collect();
+                            item.icon = (!icon.is_empty()).then_some(icon);
+                        });
+                        ui.end_row();
+
+                        ui.label("Gain trim:").on_hover_text(
+                            "Balances the sound against the rest of the library, \
+                             leaving the volume for riding the level during the show",
+                        );
+                        let trim = ui.add(
+                            egui::DragValue::new(&mut item.gain_db)
+                                .clamp_range(-MAX_GAIN_TRIM..=MAX_GAIN_TRIM)
+                                .speed(0.1)
+                                .suffix(" dB"),
+                        );
+                        if trim.changed() {
+                            // the trim reaches a playing sound along with its volume
+                            channel
+                                .send(ControlMessage::SetVolume(item.id, item.volume))
+                                .unwrap();
+                        }
+                        ui.end_row();
+
+                        if item.signal.is_none() && !item.is_stream() {
+                            ui.label("Pitch:").on_hover_text(
+                                "Plays the sound faster or slower, like a tape, \
+                                 which shortens or lengthens it too",
+                            );
+                            let pitch = ui.add(
+                                egui::DragValue::new(&mut item.pitch)
+                                    .clamp_range(-MAX_PITCH..=MAX_PITCH)
+                                    .speed(0.05)
+                                    .max_decimals(2)
+                                    .suffix(" semitones"),
+                            );
+                            if pitch.changed() {
+                                channel
+                                    .send(ControlMessage::SetPitch(item.id, item.pitch))
+                                    .unwrap();
+                            }
+                            ui.end_row();
+                        }
+
+                        ui.label("Volume:");
+                        volume_slider(ui, item, channel);
+                        ui.end_row();
+
+                        ui.label("Loudness:");
+                        match item.loudness {
+                            Some(loudness) => ui.label(format!("{:.1} LUFS", loudness)),
+                            None => ui.label(RichText::new("not measured").weak()),
+                        };
+                        ui.end_row();
+
+                        ui.label("Tempo:").on_hover_text(
+                            "Detected from the sound, the loop markers snap to its beats",
+                        );
+                        match &mut item.tempo {
+                            Some(tempo) => ui.add(
+                                egui::DragValue::new(&mut tempo.bpm)
+                                    .clamp_range(20.0..=300.0)
+                                    .speed(0.1)
+                                    .suffix(" BPM"),
+                            ),
+                            None => ui.label(RichText::new("no steady beat").weak()),
+                        };
+                        ui.end_row();
+
+                        ui.label("Output:").on_hover_text(
+                            "Takes effect the next time the item starts. \
+                             Overrides the output of its playlist.",
+                        );
+                        ui.horizontal(|ui| {
+                            output_picker(ui, "item output", &mut item.output, outputs);
+                            channel_pair_picker(ui, "item channels", &mut item.output_pair);
+                        });
+                        ui.end_row();
+
+                        ui.label("Trim:").on_hover_text(
+                            "Play only this part of the sound. Takes effect the next \
+                             time the item starts.",
+                        );
+                        ui.horizontal(|ui| {
+                            let mut trimmed = item.trim.is_some();
+                            ui.checkbox(&mut trimmed, "");
+                            let duration = item.duration;
+                            match (trimmed, &mut item.trim) {
+                                (true, Some((start, end))) => {
+                                    ui.add(
+                                        egui::DragValue::new(start)
+                                            .clamp_range(0.0..=*end)
+                                            .speed(0.01)
+                                            .suffix(" s"),
+                                    );
+                                    ui.label("to");
+                                    ui.add(
+                                        egui::DragValue::new(end)
+                                            .clamp_range(*start..=duration)
+                                            .speed(0.01)
+                                            .suffix(" s"),
+                                    );
+                                }
+                                (true, trim) => *trim = Some((0.0, duration)),
+                                (false, trim) => *trim = None,
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Fades:").on_hover_text(
+                            "Items without fades of their own take on those of the playlist \
+                             they're started from",
+                        );
+                        ui.horizontal(|ui| {
+                            let (start, end) = item.trim_bounds();
+                            let length = (end - start).max(0.0);
+                            ui.label("in");
+                            ui.add(
+                                egui::DragValue::new(&mut item.fade_in)
+                                    .clamp_range(0.0..=length)
+                                    .speed(0.01)
+                                    .suffix(" s"),
+                            );
+                            ui.label("out");
+                            ui.add(
+                                egui::DragValue::new(&mut item.fade_out)
+                                    .clamp_range(0.0..=length)
+                                    .speed(0.01)
+                                    .suffix(" s"),
+                            );
+                        });
+                        ui.end_row();
+
+                        ui.label("");
+                        match item.suggestion() {
+                            Some(suggestion) => {
+                                let trim = match suggestion.trim {
+                                    Some((start, end)) => format!(
+                                        "trim to {} - {}",
+                                        format_timestamp(start),
+                                        format_timestamp(end)
+                                    ),
+                                    None => "no trim".to_string(),
+                                };
+                                let button = ui
+                                    .add_enabled(
+                                        suggestion.trim != item.trim
+                                            || suggestion.fade_in != item.fade_in
+                                            || suggestion.fade_out != item.fade_out,
+                                        egui::Button::new("Apply suggested trim and fades"),
+                                    )
+                                    .on_hover_text(format!(
+                                        "Worked out from the silences of the sound: {}, \
+                                         {:.2} s fade in, {:.2} s fade out",
+                                        trim, suggestion.fade_in, suggestion.fade_out
+                                    ));
+                                if button.clicked() {
+                                    item.apply(suggestion);
+                                }
+                            }
+                            None => {
+                                ui.label(RichText::new("no suggested trim").weak());
+                            }
+                        }
+                        ui.end_row();
+
+                        ui.label("On end:").on_hover_text(
+                            "What happens once the item plays to the end. The next \
+                             and random items are taken from the playlist it was \
+                             started from.",
+                        );
+                        ui.horizontal(|ui| {
+                            let describe = |on_end: OnEnd| match on_end {
+                                OnEnd::Stop => "Stop",
+                                OnEnd::Loop => "Loop",
+                                OnEnd::Next => "Play the next item",
+                                OnEnd::Random => "Play a random item",
+                                OnEnd::Play(_) => "Play another item",
+                            };
+                            egui::ComboBox::from_id_source("on end")
+                                .selected_text(describe(item.on_end))
+                                .show_ui(ui, |ui| {
+                                    for on_end in
+                                        [OnEnd::Stop, OnEnd::Loop, OnEnd::Next, OnEnd::Random]
+                                    {
+                                        ui.selectable_value(
+                                            &mut item.on_end,
+                                            on_end,
+                                            describe(on_end),
+                                        );
+                                    }
+                                    let other = matches!(item.on_end, OnEnd::Play(_));
+                                    let first = names.iter().find(|(id, _)| *id != item.id);
+                                    if let (false, Some((id, _))) = (other, first) {
+                                        if ui
+                                            .selectable_label(false, describe(OnEnd::Play(*id)))
+                                            .clicked()
+                                        {
+                                            item.on_end = OnEnd::Play(*id);
+                                        }
+                                    }
+                                });
+                            if let OnEnd::Play(next) = &mut item.on_end {
+                                let name = names
+                                    .iter()
+                                    .find(|(id, _)| id == next)
+                                    .map_or("a deleted item", |(_, name)| name);
+                                egui::ComboBox::from_id_source("on end item")
+                                    .selected_text(name)
+                                    .show_ui(ui, |ui| {
+                                        for (id, name) in &names {
+                                            ui.selectable_value(next, *id, name);
+                                        }
+                                    });
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Stop on silence:").on_hover_text(
+                            "Stop the item once it has been nearly silent for this long, \
+                            as if it had ended, for recordings with long silent tails",
+                        );
+                        ui.horizontal(|ui| {
+                            let mut stops = item.stop_on_silence.is_some();
+                            ui.checkbox(&mut stops, "");
+                            match (stops, &mut item.stop_on_silence) {
+                                (true, Some(seconds)) => {
+                                    ui.add(
+                                        egui::DragValue::new(seconds)
+                                            .clamp_range(0.5..=60.0)
+                                            .speed(0.1)
+                                            .suffix(" s"),
+                                    );
+                                }
+                                (true, seconds) => *seconds = Some(DEFAULT_SILENCE_WAIT),
+                                (false, seconds) => *seconds = None,
+                            }
+                        });
+                        ui.end_row();
+
+                        if item.signal.is_some() {
+                            return;
+                        }
+
+                        ui.label("Keep in memory:").on_hover_text(
+                            "Load the whole sound into memory so that it starts \
+                             without reading from disk, for critical stingers",
+                        );
+                        ui.checkbox(&mut item.pinned, "");
+                        ui.end_row();
+
+                        ui.label("Round robin:").on_hover_text(
+                            "Each start plays the next stem, going round them as \
+                             variations of the same sound",
+                        );
+                        ui.add_enabled(
+                            item.stems.len() > 1,
+                            egui::Checkbox::new(&mut item.round_robin, ""),
+                        );
+                        ui.end_row();
+
+                        let mut removed = None;
+                        let removable = item.stems.len() > 1;
+                        for (index, stem) in item.stems.iter().enumerate() {
+                            ui.label(format!("Stem \"{}\":", stem.tag));
+                            ui.horizontal(|ui| {
+                                ui.label(&stem.path);
+                                if removable && ui.small_button("🗑").clicked() {
+                                    removed = Some(index);
+                                }
+                            });
+                            ui.end_row();
+
+                            ui.label("");
+                            match &stem.info {
+                                Some(info) => ui.label(info.to_string()),
+                                None => ui.label(RichText::new("no file info").weak()),
+                            };
+                            ui.end_row();
+                        }
+                        if let Some(index) = removed {
+                            item.stems.remove(index);
+                            if item.current_stem >= index && item.current_stem > 0 {
+                                item.current_stem -= 1;
+                            }
+                        }
+
+                        ui.label("");
+                        if ui.button("Add stems…").clicked() {
+                            add_stems(shared.clone(), item.id);
+                        }
+                        ui.end_row();
+                    });
+            });
+        if !open {
+            self.interface.item_details = None;
+        }
+    }
+
+    /// List the keyboard shortcuts from the keymap. Pressing `?` while no text
+    /// field has focus toggles the list.
+    pub(super) fn help_overlay(&mut self, ctx: &egui::Context) {
+        let toggled = ctx.memory(|m| m.focused()).is_none()
+            && ctx.input(|input| {
+                input
+                    .events
+                    .iter()
+                    .any(|event| matches!(event, egui::Event::Text(text) if text == "?"))
+            });
+        if toggled {
+            self.interface.help_open = !self.interface.help_open;
+        }
+
+        let mut open = self.interface.help_open;
+        egui::Window::new("Keyboard shortcuts")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                egui::Grid::new("keyboard shortcuts")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (action, shortcut) in &self.model.settings.keymap.bindings {
+                            ui.label(RichText::new(shortcut.format(ctx)).monospace().strong());
+                            ui.label(action.description());
+                            ui.end_row();
+                        }
+                        ui.label(RichText::new("?").monospace().strong());
+                        ui.label("Show or hide this list");
+                        ui.end_row();
+                    });
+            });
+        self.interface.help_open = open;
+    }
+
+    /// Ask what to do with the items that were playing when afx closed.
+    pub(super) fn resume_prompt(&mut self, ctx: &egui::Context) {
+        if self.model.interrupted.is_empty() {
+            return;
+        }
+        egui::Window::new("Resume playback?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                let count = self.model.interrupted.len();
+                ui.label(format!(
+                    "{} {} playing when afx closed, {} now paused.",
+                    count,
+                    if count == 1 {
+                        "sound was"
+                    } else {
+                        "sounds were"
+                    },
+                    if count == 1 { "it is" } else { "they are" },
+                ));
+                ui.horizontal(|ui| {
+                    let interrupted = &self.model.interrupted;
+                    let items = self
+                        .model
+                        .items
+                        .iter_mut()
+                        .filter(|item| interrupted.contains(&item.id));
+                    if ui.button("▶ Resume").clicked() {
+                        for item in items {
+                            item.status = ItemStatus::Loading;
+                            self.channel.send(ControlMessage::Play(item.id)).unwrap();
+                        }
+                        self.model.interrupted.clear();
+                    } else if ui.button("⏸ Leave paused").clicked() {
+                        self.model.interrupted.clear();
+                    } else if ui.button("⏹ Reset").clicked() {
+                        for item in items {
+                            item.status = ItemStatus::Stopped;
+                            item.position = 0.0;
+                            item.target_position = 0.0;
+                        }
+                        self.model.interrupted.clear();
+                    }
+                });
+            });
+    }
+
+    /// Sum up the session and the sessions before it from the usage counts.
+    pub(super) fn stats_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.interface.stats_open;
+        egui::Window::new("Statistics")
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                let summary = afx_core::stats::summary(self.model, MOST_USED);
+                let name = |id: u64| self.model.items[self.item_index(id)].labelled_name();
+                egui::Grid::new("play time").num_columns(3).show(ui, |ui| {
+                    ui.label("");
+                    ui.label(RichText::new("Starts").strong());
+                    ui.label(RichText::new("Play time").strong());
+                    ui.end_row();
+                    ui.label("This session");
+                    ui.label(summary.session_starts.to_string());
+                    ui.label(format_runtime(summary.session_seconds));
+                    ui.end_row();
+                    ui.label("All sessions");
+                    ui.label(summary.total_starts.to_string());
+                    ui.label(format_runtime(summary.total_seconds));
+                    ui.end_row();
+                });
+                ui.separator();
+
+                ui.collapsing("Most used items", |ui| {
+                    if summary.most_used.is_empty() {
+                        ui.label(RichText::new("nothing played yet").weak());
+                    }
+                    egui::Grid::new("most used")
+                        .num_columns(3)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (id, usage) in &summary.most_used {
+                                ui.label(name(*id));
+                                ui.label(format!("{}×", usage.starts));
+                                ui.label(format_runtime(usage.seconds));
+                                ui.end_row();
+                            }
+                        });
+                });
+                ui.collapsing(
+                    format!("Never played ({})", summary.never_played.len()),
+                    |ui| {
+                        egui::ScrollArea::vertical()
+                            .max_height(200.0)
+                            .show(ui, |ui| {
+                                for id in &summary.never_played {
+                                    ui.label(name(*id));
+                                }
+                            });
+                    },
+                );
+                ui.collapsing("Playlists", |ui| {
+                    if summary.playlists.is_empty() {
+                        ui.label(RichText::new("nothing started from a playlist yet").weak());
+                    }
+                    egui::Grid::new("playlist usage")
+                        .num_columns(2)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (id, starts) in &summary.playlists {
+                                let playlist = self.model.playlists.iter().find(|p| p.id == *id);
+                                ui.label(playlist.map_or("", |p| p.name.as_str()));
+                                ui.label(format!("{} starts", starts));
+                                ui.end_row();
+                            }
+                        });
+                });
+                ui.separator();
+                if ui
+                    .button("Reset")
+                    .on_hover_text("Forget the counts of past sessions")
+                    .clicked()
+                {
+                    self.model.usage = Default::default();
+                }
+            });
+        self.interface.stats_open = open;
+    }
+}
+
+impl SharedModel {
+    /// Offer to restore the state saved when afx last crashed. This happens
+    /// before the frame is drawn, so that the copy of the model is brought up
+    /// to date with the restored one.
+    pub(super) fn crash_prompt(&mut self, ctx: &egui::Context) {
+        if self.crashed.is_none() {
+            return;
+        }
+        let mut decision = None;
+        egui::Window::new("Restore after a crash?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label(
+                    "afx crashed last time, after the library was last saved. \
+                     Its state at the time of the crash was kept.",
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("♻ Restore").clicked() {
+                        decision = Some(true);
+                    }
+                    if ui
+                        .button("🗑 Discard")
+                        .on_hover_text("Keep the library as it was last saved")
+                        .clicked()
+                    {
+                        decision = Some(false);
+                    }
+                });
+            });
+        let Some(restore) = decision else {
+            return;
+        };
+        let crashed = self.crashed.take().unwrap();
+        if restore {
+            info!("restoring the state saved in a crash");
+            self.play_channel.send(ControlMessage::GlobalStop).unwrap();
+            let repairs = crate::app::restore(crashed, &self.play_channel, &self.model);
+            self.toasts
+                .extend(repairs.into_iter().map(|repair| Notification {
+                    message: format!("Repaired the restored state: {}", repair),
+                    item: None,
+                }));
+        }
+        if let Some(Err(err)) = default_project_path().map(|path| discard_emergency(&path)) {
+            warn!("failed to discard the state saved in a crash: {:#}", err);
+        }
+    }
+
+    /// Show notifications from background threads in the bottom right corner
+    /// until they're dismissed.
+    pub(super) fn toasts(&mut self, ctx: &egui::Context, items: &[Item]) {
+        let mut dismissed = None;
+        egui::Area::new(egui::Id::new("toasts"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, vec2(-10.0, -50.0))
+            .show(ctx, |ui| {
+                for (i, toast) in self.toasts.iter().enumerate() {
+                    Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(300.0);
+                        ui.horizontal(|ui| {
+                            ui.colored_label(RED.drawn(), "⚠");
+                            ui.vertical(|ui| {
+                                let item = toast
+                                    .item
+                                    .and_then(|id| items.iter().find(|item| item.id == id));
+                                if let Some(item) = item {
+                                    ui.strong(&item.name);
+                                }
+                                ui.label(&toast.message);
+                            });
+                            let dismiss = Button::new("❌").frame(false);
+                            if ui.add(dismiss).on_hover_text("Dismiss").clicked() {
+                                dismissed = Some(i);
+                            }
+                        });
+                    });
+                }
+            });
+        if let Some(i) = dismissed {
+            self.toasts.remove(i);
+        }
+    }
+}